@@ -0,0 +1,291 @@
+//! Generates the `pub const ODEntry` items of `od/mod.rs` from the reviewed EDS file, see
+//! `eds/pd4c.eds`.
+
+use std::{collections::BTreeSet, env, fmt::Write, fs, path::Path};
+
+#[allow(dead_code)]
+#[path = "src/od/eds/parser.rs"]
+mod parser;
+
+use parser::{EdsObject, parse_integer_with_node_id, parse_object_reference};
+
+const EDS_PATH: &str = "eds/pd4c.eds";
+const PARSER_PATH: &str = "src/od/eds/parser.rs";
+
+fn main() {
+    println!("cargo:rerun-if-changed={EDS_PATH}");
+    println!("cargo:rerun-if-changed={PARSER_PATH}");
+
+    let source = fs::read_to_string(EDS_PATH).expect("Unable to read EDS file");
+    let file = parser::parse(&source).unwrap_or_else(|err| panic!("{EDS_PATH}: {err}"));
+
+    // Containers whose sub-index 0 is described explicitly get no entry of their own
+    let described_sub_zero: BTreeSet<u16> = file
+        .objects
+        .iter()
+        .filter(|object| !object.is_container() && object.sub_index == 0)
+        .map(|object| object.index)
+        .collect();
+
+    let mut out = String::new();
+    let mut typed = String::new();
+    let mut names = Vec::new();
+    for object in file.objects.iter() {
+        if object.is_container() && described_sub_zero.contains(&object.index) {
+            continue;
+        }
+
+        let name = const_name(object);
+        if names.contains(&name) {
+            panic!("{EDS_PATH}: duplicate constant name {name}");
+        }
+
+        if let Some(rust_type) = write_entry(&mut out, object, &name) {
+            writeln!(typed, "/// Typed view of [`super::{name}`]").unwrap();
+            writeln!(
+                typed,
+                "pub const {name}: TypedEntry<{rust_type}> = crate::typed_entry!({rust_type}, super::{name});"
+            )
+            .unwrap();
+        }
+        names.push(name);
+    }
+
+    writeln!(out, "/// All entries of the reviewed object dictionary").unwrap();
+    writeln!(out, "pub const FULL_OBJECT_DICTIONARY: &[ODEntry] = &[").unwrap();
+    for name in names.iter() {
+        writeln!(out, "    {name},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set");
+    fs::write(Path::new(&out_dir).join("od_entries.rs"), out)
+        .expect("Unable to write generated object dictionary");
+    fs::write(Path::new(&out_dir).join("od_typed_entries.rs"), typed)
+        .expect("Unable to write generated typed object dictionary");
+}
+
+/// Constant name from the `Denotation` key, or derived from the parameter name
+fn const_name(object: &EdsObject) -> String {
+    if let Some(denotation) = object.denotation.as_deref() {
+        return denotation.to_string();
+    }
+
+    let mut name = String::new();
+    for c in object.name.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_uppercase());
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_matches('_').to_string();
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("OD_{:04X}_{:02X}_{name}", object.index, object.sub_index)
+    } else {
+        name
+    }
+}
+
+/// Write the entry constant, returns the Rust type of fixed width entries
+fn write_entry(out: &mut String, object: &EdsObject, name: &str) -> Option<&'static str> {
+    let location = format!("{EDS_PATH} [{:04X}sub{}]", object.index, object.sub_index);
+
+    // Keep these rules in sync with `od::eds::object_to_entry`
+    let access_raw = object.access_type.as_deref();
+    let access = match access_raw {
+        None | Some("ro") => "ReadOnly",
+        Some("wo") => "WriteOnly",
+        Some("rw") | Some("rwr") | Some("rww") => "ReadWrite",
+        Some("const") => "Const",
+        Some(other) => panic!("{location}: invalid AccessType {other:?}"),
+    };
+    let mappable = match (object.pdo_mapping, access_raw) {
+        (false, _) => "None",
+        (true, Some("rww") | Some("wo")) => "RPDO",
+        (true, Some("rwr") | Some("ro") | Some("const")) => "TPDO",
+        (true, _) => "Both",
+    };
+
+    let (type_name, value, rust_type) = if object.is_container() {
+        let count = object.sub_number.unwrap_or(0);
+        ("ARRAY/RECORD", format!("Array({count})"), None)
+    } else {
+        let code = object
+            .data_type
+            .unwrap_or_else(|| panic!("{location}: missing DataType"));
+        value_literal(code, object.value()).unwrap_or_else(|err| panic!("{location}: {err}"))
+    };
+
+    let meta = meta_literal(object).unwrap_or_else(|err| panic!("{location}: {err}"));
+
+    writeln!(out, "/// {}", object.name).unwrap();
+    writeln!(out, "///").unwrap();
+    if let Some(description) = object.description.as_deref() {
+        writeln!(out, "/// {description}").unwrap();
+        writeln!(out, "///").unwrap();
+    }
+    write!(
+        out,
+        "/// `{:#06X}:{:02X}`, {type_name}, {}",
+        object.index,
+        object.sub_index,
+        access_raw.unwrap_or("ro")
+    )
+    .unwrap();
+    match object.unit.as_deref() {
+        Some(unit) => writeln!(out, ", [{unit}]").unwrap(),
+        None => writeln!(out).unwrap(),
+    }
+    match meta {
+        Some(_) => writeln!(out, "pub const {name}: ODEntry = ODEntry::new_with_meta(").unwrap(),
+        None => writeln!(out, "pub const {name}: ODEntry = ODEntry::new(").unwrap(),
+    }
+    writeln!(out, "    {:#06X},", object.index).unwrap();
+    writeln!(out, "    {:#04X},", object.sub_index).unwrap();
+    writeln!(out, "    {:?},", object.name).unwrap();
+    writeln!(out, "    AccessType::{access},").unwrap();
+    writeln!(out, "    MappableType::{mappable},").unwrap();
+    writeln!(out, "    ODValue::{value},").unwrap();
+    if let Some(meta) = meta {
+        writeln!(out, "{meta}").unwrap();
+    }
+    writeln!(out, ");").unwrap();
+    writeln!(out).unwrap();
+
+    rust_type
+}
+
+/// `ODMeta` literal of the limits, unit and description, None if the object has none of them
+fn meta_literal(object: &EdsObject) -> Result<Option<String>, String> {
+    if object.low_limit.is_none()
+        && object.high_limit.is_none()
+        && object.high_limit_object.is_none()
+        && object.unit.is_none()
+        && object.description.is_none()
+    {
+        return Ok(None);
+    }
+
+    let limit = |raw: Option<&str>| -> Result<String, String> {
+        let Some(raw) = raw else {
+            return Ok(String::from("None"));
+        };
+        let code = object.data_type.ok_or("limits need a DataType")?;
+        let (_, literal, _) = value_literal(code, Some(raw))?;
+        Ok(format!("Some(ODValue::{literal})"))
+    };
+    let max_object = match object.high_limit_object.as_deref() {
+        None => String::from("None"),
+        Some(raw) => {
+            let (index, sub_index) =
+                parse_object_reference(raw).ok_or(format!("invalid HighLimitObject {raw:?}"))?;
+            format!("Some(ODIdx {{ index: {index:#06X}, sub_index: {sub_index:#04X} }})")
+        }
+    };
+    let text = |value: Option<&str>| match value {
+        Some(value) => format!("Some(Cow::Borrowed({value:?}))"),
+        None => String::from("None"),
+    };
+
+    let mut meta = String::new();
+    writeln!(meta, "    ODMeta {{").unwrap();
+    writeln!(
+        meta,
+        "        min: {},",
+        limit(object.low_limit.as_deref())?
+    )
+    .unwrap();
+    writeln!(
+        meta,
+        "        max: {},",
+        limit(object.high_limit.as_deref())?
+    )
+    .unwrap();
+    writeln!(meta, "        max_object: {max_object},").unwrap();
+    writeln!(meta, "        unit: {},", text(object.unit.as_deref())).unwrap();
+    writeln!(
+        meta,
+        "        description: {},",
+        text(object.description.as_deref())
+    )
+    .unwrap();
+    write!(meta, "    }},").unwrap();
+
+    Ok(Some(meta))
+}
+
+/// CiA 301 type name, `ODValue` variant literal and Rust type (if fixed width) of the given data
+/// type and raw EDS value
+type Literal = (&'static str, String, Option<&'static str>);
+
+fn value_literal(code: u16, raw: Option<&str>) -> Result<Literal, String> {
+    let parse = |name: &'static str| match raw {
+        None => Ok(0),
+        Some(raw) => {
+            parse_integer_with_node_id(raw, None).ok_or(format!("invalid {name} value {raw:?}"))
+        }
+    };
+    let integer =
+        |name: &'static str, rust_type: &'static str, variant: &str, min: i128, max: i128| {
+            let value = parse(name)?;
+            if !(min..=max).contains(&value) {
+                return Err(format!("{value} is out of range for {name}"));
+            }
+            Ok((name, format!("{variant}({value})"), Some(rust_type)))
+        };
+    // Integers without a Rust type of their own, these have no typed view
+    let wide = |name: &'static str, variant: &str, signed: bool, bits: u32| {
+        let (min, max) = match signed {
+            true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+            false => (0, (1i128 << bits) - 1),
+        };
+        integer(name, "", variant, min, max).map(|(name, literal, _)| (name, literal, None))
+    };
+    let real = |name: &'static str, rust_type: &'static str, variant: &str| {
+        let value = match raw {
+            None => 0.0,
+            Some(raw) => raw
+                .parse::<f64>()
+                .map_err(|_| format!("invalid {name} value {raw:?}"))?,
+        };
+        Ok((name, format!("{variant}({value:?})"), Some(rust_type)))
+    };
+    // Owned values can not be built in a const context, only empty defaults are supported
+    let empty = |name: &'static str, literal: &str| match raw {
+        None => Ok((name, literal.to_string(), None)),
+        Some(raw) => Err(format!("non-empty {name} default {raw:?} is not supported")),
+    };
+
+    match code {
+        0x0001 => Ok((
+            "BOOLEAN",
+            format!("Bool({})", parse("BOOLEAN")? != 0),
+            Some("bool"),
+        )),
+        0x0002 => integer("INTEGER8", "i8", "I8", i8::MIN.into(), i8::MAX.into()),
+        0x0003 => integer("INTEGER16", "i16", "I16", i16::MIN.into(), i16::MAX.into()),
+        0x0010 => wide("INTEGER24", "I24", true, 24),
+        0x0004 => integer("INTEGER32", "i32", "I32", i32::MIN.into(), i32::MAX.into()),
+        0x0012 => wide("INTEGER40", "I40", true, 40),
+        0x0013 => wide("INTEGER48", "I48", true, 48),
+        0x0014 => wide("INTEGER56", "I56", true, 56),
+        0x0015 => integer("INTEGER64", "i64", "I64", i64::MIN.into(), i64::MAX.into()),
+        0x0005 => integer("UNSIGNED8", "u8", "U8", 0, u8::MAX.into()),
+        0x0006 => integer("UNSIGNED16", "u16", "U16", 0, u16::MAX.into()),
+        0x0016 => wide("UNSIGNED24", "U24", false, 24),
+        0x0007 => integer("UNSIGNED32", "u32", "U32", 0, u32::MAX.into()),
+        0x0018 => wide("UNSIGNED40", "U40", false, 40),
+        0x0019 => wide("UNSIGNED48", "U48", false, 48),
+        0x001A => wide("UNSIGNED56", "U56", false, 56),
+        0x001B => integer("UNSIGNED64", "u64", "U64", 0, u64::MAX.into()),
+        0x0008 => real("REAL32", "f32", "F32"),
+        0x0011 => real("REAL64", "f64", "F64"),
+        0x0009 => empty("VISIBLE_STRING", "VisibleString(String::new())"),
+        0x000A => empty("OCTET_STRING", "OctetString(Vec::new())"),
+        0x000B => empty("UNICODE_STRING", "UnicodeString(Vec::new())"),
+        0x000F => empty("DOMAIN", "Domain(Vec::new())"),
+        _ => Err(format!("unsupported DataType {code:#06x}")),
+    }
+}
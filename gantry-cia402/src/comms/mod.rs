@@ -1,2 +1,4 @@
 pub mod pdo;
 pub mod sdo;
+pub mod sync;
+pub mod time;
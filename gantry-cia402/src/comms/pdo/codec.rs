@@ -0,0 +1,377 @@
+use std::borrow::Cow;
+
+use crate::{
+    comms::pdo::{
+        frame::PdoFrame,
+        mapping::{BitRange, PDO_BITS, PdoMapping, PdoMappingSource},
+    },
+    error::DriveError,
+    od::{
+        data_type::DataType,
+        entry::ODEntry,
+        typed::{ODType, TypedEntry},
+        value::ODValue,
+    },
+};
+
+/// Value of a single entry mapped into a PDO
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdoValue {
+    pub entry: Cow<'static, ODEntry>,
+    pub value: ODValue,
+}
+
+impl PdoValue {
+    /// The value if it belongs to the given entry
+    pub fn get<T: ODType>(&self, entry: TypedEntry<T>) -> Option<T> {
+        match *self.entry == *entry.entry() {
+            true => T::from_value(&self.value),
+            false => None,
+        }
+    }
+
+    /// Name and value of the entry including its unit, e.g. `Position actual value = 100 steps`
+    pub fn fmt_pretty(&self) -> String {
+        format!(
+            "{} = {}",
+            self.entry.name,
+            self.entry.meta.format_value(&self.value)
+        )
+    }
+}
+
+/// Packs and unpacks the values of the mapped entries, PDO data is the little endian encoding of
+/// every entry at the bit range it is mapped to
+impl PdoMapping {
+    /// Number of bytes the PDO carries
+    pub fn dlc(&self) -> usize {
+        self.sources
+            .iter()
+            .map(|source| {
+                (source.bit_range.start as usize + source.bit_range.len as usize).div_ceil(8)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn contains(&self, entry: &ODEntry) -> bool {
+        self.source(entry).is_ok()
+    }
+
+    /// Values of every mapped entry, in mapping order, dummy entries carry no value
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<PdoValue>, DriveError> {
+        self.sources
+            .iter()
+            .filter(|source| !source.entry.is_dummy())
+            .map(|source| {
+                let bytes = source.read(data)?;
+                let data_type = source.data_type()?;
+
+                Ok(PdoValue {
+                    entry: source.entry.clone(),
+                    value: ODValue::decode(data_type, &bytes)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Encode the value of the given entry into the frame of this PDO
+    pub fn encode(
+        &self,
+        frame: &mut PdoFrame,
+        entry: &ODEntry,
+        value: &ODValue,
+    ) -> Result<(), DriveError> {
+        let source = self.source(entry)?;
+        if !value.same_type(&entry.default) {
+            return Err(DriveError::ValueTypeMismatch {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                value: value.clone(),
+            });
+        }
+
+        source.write(&mut frame.data, &value.encode()?)
+    }
+
+    /// Decode the value of the given entry from the data of this PDO
+    pub fn get<T: ODType>(&self, data: &[u8], entry: TypedEntry<T>) -> Result<T, DriveError> {
+        let source = self.source(entry.entry())?;
+        entry.decode(&source.read(data)?)
+    }
+
+    /// Encode the value of the given entry into the frame of this PDO
+    pub fn set<T: ODType>(
+        &self,
+        frame: &mut PdoFrame,
+        entry: TypedEntry<T>,
+        value: T,
+    ) -> Result<(), DriveError> {
+        self.encode(frame, entry.entry(), &value.into_value())
+    }
+
+    fn source(&self, entry: &ODEntry) -> Result<&PdoMappingSource, DriveError> {
+        self.sources
+            .iter()
+            .find(|source| !source.entry.is_dummy() && *source.entry == *entry)
+            .ok_or_else(|| {
+                DriveError::ViolatedInvariant(format!(
+                    "{} is not mapped in {:?}",
+                    entry.name, self.pdo
+                ))
+            })
+    }
+}
+
+/// PDO data is read as one little endian number, a source occupies its bit range of that number
+impl PdoMappingSource {
+    fn data_type(&self) -> Result<DataType, DriveError> {
+        self.entry.default.data_type().ok_or_else(|| {
+            DriveError::ViolatedInvariant(format!("{} has no data type", self.entry.name))
+        })
+    }
+
+    /// Size in bytes of the value of this source
+    fn size(&self) -> Result<usize, DriveError> {
+        self.data_type()?
+            .size()
+            .filter(|size| *size * 8 >= self.bit_range.len as usize)
+            .ok_or_else(|| {
+                DriveError::ViolatedInvariant(format!(
+                    "{} can not be mapped with {} bits",
+                    self.entry.name, self.bit_range.len
+                ))
+            })
+    }
+
+    fn is_signed(&self) -> Result<bool, DriveError> {
+        Ok(matches!(
+            self.data_type()?,
+            DataType::Integer8
+                | DataType::Integer16
+                | DataType::Integer24
+                | DataType::Integer32
+                | DataType::Integer40
+                | DataType::Integer48
+                | DataType::Integer56
+                | DataType::Integer64
+        ))
+    }
+
+    /// The bits of this source, shifted down to bit 0
+    fn mask(&self) -> u64 {
+        1u64.checked_shl(self.bit_range.len as u32)
+            .map_or(u64::MAX, |bit| bit - 1)
+    }
+
+    /// Fails on data that does not hold the bit range, or an empty bit range
+    fn check_bounds(&self, data: &[u8]) -> Result<(), DriveError> {
+        let BitRange { start, len } = self.bit_range;
+        let end = start as usize + len as usize;
+        if len == 0 || end > data.len() * 8 || end > PDO_BITS as usize {
+            return Err(DriveError::Conversion(data.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Little endian value of this source, extended to the size of its data type
+    fn read(&self, data: &[u8]) -> Result<Vec<u8>, DriveError> {
+        self.check_bounds(data)?;
+        let size = self.size()?;
+
+        let mut value = (data_word(data) >> self.bit_range.start) & self.mask();
+        // Sign extend values mapped with less bits than their type
+        let sign = 1 << (self.bit_range.len - 1);
+        if self.is_signed()? && value & sign != 0 {
+            value |= !self.mask();
+        }
+
+        Ok(value.to_le_bytes()[..size].to_vec())
+    }
+
+    /// Write the little endian encoded value of this source into its bit range of the data, the
+    /// value has to fit in the mapped bits
+    fn write(&self, data: &mut [u8], bytes: &[u8]) -> Result<(), DriveError> {
+        self.check_bounds(data)?;
+        let mut word = [0; 8];
+        word[..bytes.len()].copy_from_slice(bytes);
+        let mut value = u64::from_le_bytes(word);
+
+        // Compare sign extended values, so negative values fit in less bits
+        let unused = 64 - bytes.len() as u32 * 8;
+        let mut truncated = value & self.mask();
+        if self.is_signed()? {
+            value = ((value << unused) as i64 >> unused) as u64;
+            let sign = 1 << (self.bit_range.len - 1);
+            if truncated & sign != 0 {
+                truncated |= !self.mask();
+            }
+        }
+        if truncated != value {
+            return Err(DriveError::ViolatedInvariant(format!(
+                "{bytes:02x?} does not fit in the {} bits {} is mapped with",
+                self.bit_range.len, self.entry.name
+            )));
+        }
+
+        let start = self.bit_range.start;
+        let word = (data_word(data) & !(self.mask() << start)) | ((value & self.mask()) << start);
+        let len = data.len().min(8);
+        data[..len].copy_from_slice(&word.to_le_bytes()[..len]);
+
+        Ok(())
+    }
+}
+
+/// The first 8 bytes of PDO data as a little endian number
+fn data_word(data: &[u8]) -> u64 {
+    let mut word = [0; 8];
+    let len = data.len().min(8);
+    word[..len].copy_from_slice(&data[..len]);
+    u64::from_le_bytes(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::pdo::mapping::{PdoType, custom, default},
+        driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+        od::{self, typed},
+    };
+
+    #[test]
+    fn test_roundtrip() {
+        let mapping = &custom::RPDO_TARGET_POS;
+        let mut frame = PdoFrame::with_dlc(mapping.dlc());
+        mapping
+            .set(&mut frame, typed::SET_TARGET_POSITION, -1000)
+            .unwrap();
+        mapping
+            .set(&mut frame, typed::PROFILE_VELOCITY, 300)
+            .unwrap();
+
+        assert_eq!(frame.dlc, 8);
+        assert_eq!(frame.data[..4], (-1000i32).to_le_bytes());
+        assert_eq!(
+            mapping.get(&frame.data, typed::PROFILE_VELOCITY).unwrap(),
+            300
+        );
+        let values = mapping.decode(&frame.data).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].entry.index, od::SET_TARGET_POSITION.index);
+        assert_eq!(values[0].value, ODValue::I32(-1000));
+        assert_eq!(values[1].value, ODValue::U32(300));
+
+        // Entries of other PDOs and values of the wrong type are refused
+        assert!(
+            mapping
+                .set(&mut frame, typed::SET_TARGET_VELOCITY, 10)
+                .is_err()
+        );
+        assert!(matches!(
+            mapping.encode(&mut frame, &od::PROFILE_VELOCITY, &ODValue::I16(1)),
+            Err(DriveError::ValueTypeMismatch { index: 0x6081, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_by_mapping() {
+        let data = [0x10, 0x27, 0x00, 0x00, 0xF4, 0x01, 0x00, 0x00];
+
+        // The same data means something else depending on the mapping
+        let custom = custom::TPDO_POS_VEL_ACTUAL.decode(&data).unwrap();
+        assert_eq!(custom.len(), 2);
+        assert_eq!(custom[1].get(typed::VELOCITY_ACTUAL_VALUE), Some(500));
+
+        let default = default::TPDO_DEFAULT_2.decode(&data[..4]).unwrap();
+        assert_eq!(default.len(), 1);
+        assert_eq!(default[0].get(typed::POSITION_ACTUAL_VALUE), Some(10_000));
+        assert_eq!(default[0].get(typed::VELOCITY_ACTUAL_VALUE), None);
+
+        // Too short for the mapping
+        assert!(matches!(
+            custom::TPDO_POS_VEL_ACTUAL.decode(&data[..4]),
+            Err(DriveError::Conversion(_))
+        ));
+    }
+
+    #[test]
+    fn test_bit_packing() {
+        const PACKED: PdoMapping = PdoMapping {
+            pdo: PdoType::TPDO(1),
+            sources: Cow::Borrowed(&[
+                PdoMappingSource {
+                    entry: Cow::Borrowed(&od::GET_OPERATION_MODE),
+                    bit_range: BitRange { start: 0, len: 4 },
+                },
+                PdoMappingSource {
+                    entry: Cow::Borrowed(&od::DUMMY_BOOLEAN),
+                    bit_range: BitRange { start: 4, len: 1 },
+                },
+                PdoMappingSource {
+                    entry: Cow::Borrowed(&od::STATUS_WORD),
+                    bit_range: BitRange { start: 5, len: 16 },
+                },
+                PdoMappingSource {
+                    entry: Cow::Borrowed(&od::DUMMY_UNSIGNED8),
+                    bit_range: BitRange { start: 21, len: 8 },
+                },
+                PdoMappingSource {
+                    entry: Cow::Borrowed(&od::POSITION_ACTUAL_VALUE),
+                    bit_range: BitRange { start: 29, len: 32 },
+                },
+            ]),
+            communication: PdoCommunication::new(TransmissionType::OnChange),
+        };
+        assert!(PACKED.validate().is_ok());
+        assert_eq!(PACKED.dlc(), 8);
+
+        let mut frame = PdoFrame::with_dlc(PACKED.dlc());
+        frame.data = [0xFF; 8];
+        PACKED
+            .set(&mut frame, typed::GET_OPERATION_MODE, -3)
+            .unwrap();
+        PACKED.set(&mut frame, typed::STATUS_WORD, 0xBEEF).unwrap();
+        PACKED
+            .set(&mut frame, typed::POSITION_ACTUAL_VALUE, -123_456)
+            .unwrap();
+
+        // Bits outside of the written sources are left as they were
+        let word = u64::from_le_bytes(frame.data);
+        assert_eq!(word & 0xF, 0xD);
+        assert_eq!((word >> 4) & 1, 1);
+        assert_eq!((word >> 5) & 0xFFFF, 0xBEEF);
+        assert_eq!((word >> 21) & 0xFF, 0xFF);
+        assert_eq!(word >> 61, 0b111);
+
+        let values = PACKED.decode(&frame.data).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].value, ODValue::I8(-3));
+        assert_eq!(values[1].value, ODValue::U16(0xBEEF));
+        assert_eq!(values[2].get(typed::POSITION_ACTUAL_VALUE), Some(-123_456));
+
+        // Values have to fit in the mapped bits
+        for opmode in [-9, 8] {
+            assert!(
+                PACKED
+                    .set(&mut frame, typed::GET_OPERATION_MODE, opmode)
+                    .is_err()
+            );
+        }
+        assert_eq!(
+            PACKED.get(&frame.data, typed::GET_OPERATION_MODE).unwrap(),
+            -3
+        );
+        assert!(
+            PACKED
+                .encode(&mut frame, &od::DUMMY_UNSIGNED8, &ODValue::U8(0))
+                .is_err()
+        );
+        assert!(matches!(
+            PACKED.decode(&frame.data[..7]),
+            Err(DriveError::Conversion(_))
+        ));
+    }
+}
@@ -1,17 +1,16 @@
 use crate::{
-    comms::pdo::mapping::{BitRange, PdoMapping, PdoMappingSource, PdoType},
-    driver::startup::pdo_mapping::TransmissionType,
-    od,
+    comms::pdo::mapping::{PdoMapping, profile::PdoProfile},
+    driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+    od, pdo_mapping,
 };
 
-// TODO: Making T/RPDO mapping generic + encoding into type system
-// I didn't know of a better const method to do this, seems rust const fn are lacking compared
-// to c++ templates, this never changes anyway
-pub const RPDO_IDX_CONTROL_WORD: usize = 0;
-pub const RPDO_IDX_OPMODE: usize = 0;
-pub const RPDO_IDX_TARGET_POS: usize = 1;
-pub const RPDO_IDX_TARGET_VEL: usize = 2;
-pub const RPDO_IDX_TARGET_TORQUE: usize = 3;
+/// Event timer of the TPDOs, a periodic event continously synchronises the driver with the
+/// latest device state
+pub const SYNCHRONISATION_PERIOD_MS: u16 = 500;
+
+const RPDO_COMMUNICATION: PdoCommunication = PdoCommunication::new(TransmissionType::OnChange);
+const TPDO_COMMUNICATION: PdoCommunication =
+    PdoCommunication::new(TransmissionType::OnChange).with_event_timer(SYNCHRONISATION_PERIOD_MS);
 
 pub const CUSTOM_RPDOS: &[PdoMapping; 4] = &[
     RPDO_CONTROL_OPMODE,
@@ -20,112 +19,65 @@ pub const CUSTOM_RPDOS: &[PdoMapping; 4] = &[
     RPDO_TARGET_TORQUE,
 ];
 
-pub const CUSTOM_TPDOS: &[PdoMapping; 4] = &[
-    TPDO_STATUS_OPMODE,
-    TPDO_POS_VEL_ACTUAL,
-    TPDO_TORQUE_ACTUAL,
-    TPDO_EMPTY, // Required to avoid default TPDO4 generating warnings, TODO: remove this when
-                // adding invalidate all PDO step in configure_pdo_mappings
-];
-
-pub fn get_dlc(mapping: &PdoMapping) -> usize {
-    let mut dlc = 0u8;
-    for source in mapping.sources {
-        dlc += source.bit_range.len / 8;
-    }
+pub const CUSTOM_TPDOS: &[PdoMapping; 3] =
+    &[TPDO_STATUS_OPMODE, TPDO_POS_VEL_ACTUAL, TPDO_TORQUE_ACTUAL];
 
-    dlc as usize
-}
-
-pub const RPDO_CONTROL_OPMODE: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(1),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::CONTROL_WORD,
-            bit_range: BitRange { start: 0, len: 16 },
-        },
-        PdoMappingSource {
-            entry: &od::SET_OPERATION_MODE,
-            bit_range: BitRange { start: 16, len: 8 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
+/// Targets of the profile position, velocity and torque modes
+pub const PROFILE_MODES: PdoProfile = PdoProfile {
+    name: "profile modes",
+    rpdos: CUSTOM_RPDOS,
+    tpdos: CUSTOM_TPDOS,
 };
 
-pub const RPDO_TARGET_POS: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(2),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::SET_TARGET_POSITION,
-            bit_range: BitRange { start: 0, len: 32 },
-        },
-        PdoMappingSource {
-            entry: &od::PROFILE_VELOCITY,
-            bit_range: BitRange { start: 32, len: 32 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
+/// Position setpoints of the cyclic synchronous position mode, the setpoint and actual values are
+/// exchanged at every SYNC, so this needs a SYNC producer on the bus
+pub const CYCLIC_SYNCHRONOUS_POSITION: PdoProfile = PdoProfile {
+    name: "cyclic synchronous position",
+    rpdos: &[RPDO_CONTROL_OPMODE, CSP_RPDO_TARGET_POS],
+    tpdos: &[TPDO_STATUS_OPMODE, CSP_TPDO_POS_VEL_ACTUAL],
 };
 
-pub const RPDO_TARGET_VEL: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(3),
-    sources: &[PdoMappingSource {
-        entry: &od::SET_TARGET_VELOCITY,
-        bit_range: BitRange { start: 0, len: 32 },
-    }],
-    transmission_type: TransmissionType::OnChange,
-};
+const CYCLIC_COMMUNICATION: PdoCommunication = PdoCommunication::new(TransmissionType::ON_SYNC);
 
-pub const RPDO_TARGET_TORQUE: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(4),
-    sources: &[PdoMappingSource {
-        entry: &od::SET_TARGET_TORQUE,
-        bit_range: BitRange { start: 0, len: 16 },
-    }],
-    transmission_type: TransmissionType::OnChange,
-};
+pdo_mapping! {
+    pub RPDO_CONTROL_OPMODE = RPDO(1), RPDO_COMMUNICATION => {
+        pub RPDO_CONTROL_WORD: u16 = od::CONTROL_WORD,
+        pub RPDO_SET_OPERATION_MODE: i8 = od::SET_OPERATION_MODE,
+    }
 
-pub const TPDO_STATUS_OPMODE: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(1),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::STATUS_WORD,
-            bit_range: BitRange { start: 0, len: 16 },
-        },
-        PdoMappingSource {
-            entry: &od::GET_OPERATION_MODE,
-            bit_range: BitRange { start: 16, len: 8 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub RPDO_TARGET_POS = RPDO(2), RPDO_COMMUNICATION => {
+        pub RPDO_SET_TARGET_POSITION: i32 = od::SET_TARGET_POSITION,
+        pub RPDO_PROFILE_VELOCITY: u32 = od::PROFILE_VELOCITY,
+    }
 
-pub const TPDO_POS_VEL_ACTUAL: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(2),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::POSITION_ACTUAL_VALUE,
-            bit_range: BitRange { start: 0, len: 32 },
-        },
-        PdoMappingSource {
-            entry: &od::VELOCITY_ACTUAL_VALUE,
-            bit_range: BitRange { start: 32, len: 32 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub RPDO_TARGET_VEL = RPDO(3), RPDO_COMMUNICATION => {
+        pub RPDO_SET_TARGET_VELOCITY: i32 = od::SET_TARGET_VELOCITY,
+    }
 
-pub const TPDO_TORQUE_ACTUAL: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(3),
-    sources: &[PdoMappingSource {
-        entry: &od::TORQUE_ACTUAL_VALUE,
-        bit_range: BitRange { start: 0, len: 16 },
-    }],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub RPDO_TARGET_TORQUE = RPDO(4), RPDO_COMMUNICATION => {
+        pub RPDO_SET_TARGET_TORQUE: i16 = od::SET_TARGET_TORQUE,
+    }
 
-pub const TPDO_EMPTY: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(4),
-    sources: &[],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub TPDO_STATUS_OPMODE = TPDO(1), TPDO_COMMUNICATION => {
+        pub TPDO_STATUS_WORD: u16 = od::STATUS_WORD,
+        pub TPDO_GET_OPERATION_MODE: i8 = od::GET_OPERATION_MODE,
+    }
+
+    pub TPDO_POS_VEL_ACTUAL = TPDO(2), TPDO_COMMUNICATION => {
+        pub TPDO_POSITION_ACTUAL_VALUE: i32 = od::POSITION_ACTUAL_VALUE,
+        pub TPDO_VELOCITY_ACTUAL_VALUE: i32 = od::VELOCITY_ACTUAL_VALUE,
+    }
+
+    pub TPDO_TORQUE_ACTUAL = TPDO(3), TPDO_COMMUNICATION => {
+        pub TPDO_TORQUE_ACTUAL_VALUE: i16 = od::TORQUE_ACTUAL_VALUE,
+    }
+
+    pub CSP_RPDO_TARGET_POS = RPDO(2), CYCLIC_COMMUNICATION => {
+        pub CSP_RPDO_SET_TARGET_POSITION: i32 = od::SET_TARGET_POSITION,
+    }
+
+    pub CSP_TPDO_POS_VEL_ACTUAL = TPDO(2), CYCLIC_COMMUNICATION => {
+        pub CSP_TPDO_POSITION_ACTUAL_VALUE: i32 = od::POSITION_ACTUAL_VALUE,
+        pub CSP_TPDO_VELOCITY_ACTUAL_VALUE: i32 = od::VELOCITY_ACTUAL_VALUE,
+    }
+}
@@ -1,62 +1,31 @@
 use crate::{
-    comms::pdo::mapping::{BitRange, PdoMapping, PdoMappingSource, PdoType},
-    driver::startup::pdo_mapping::TransmissionType,
-    od,
+    comms::pdo::mapping::PdoMapping,
+    driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+    od, pdo_mapping,
 };
 
+const COMMUNICATION: PdoCommunication = PdoCommunication::new(TransmissionType::OnChange);
+
 pub const DEFAULT_RPDOS: &[PdoMapping] = &[RPDO_DEFAULT_1, RPDO_DEFAULT_2];
 pub const DEFAULT_TPDOS: &[PdoMapping] = &[TPDO_DEFAULT_1, TPDO_DEFAULT_2];
 
-pub const RPDO_DEFAULT_1: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(1),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::CONTROL_WORD,
-            bit_range: BitRange { start: 0, len: 16 },
-        },
-        PdoMappingSource {
-            entry: &od::SET_OPERATION_MODE,
-            bit_range: BitRange { start: 16, len: 8 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
-};
+pdo_mapping! {
+    pub RPDO_DEFAULT_1 = RPDO(1), COMMUNICATION => {
+        pub DEFAULT_RPDO_CONTROL_WORD: u16 = od::CONTROL_WORD,
+        pub DEFAULT_RPDO_SET_OPERATION_MODE: i8 = od::SET_OPERATION_MODE,
+    }
 
-pub const RPDO_DEFAULT_2: PdoMapping = PdoMapping {
-    pdo: PdoType::RPDO(2),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::SET_TARGET_POSITION,
-            bit_range: BitRange { start: 0, len: 32 },
-        },
-        PdoMappingSource {
-            entry: &od::PROFILE_VELOCITY,
-            bit_range: BitRange { start: 32, len: 32 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub RPDO_DEFAULT_2 = RPDO(2), COMMUNICATION => {
+        pub DEFAULT_RPDO_SET_TARGET_POSITION: i32 = od::SET_TARGET_POSITION,
+        pub DEFAULT_RPDO_PROFILE_VELOCITY: u32 = od::PROFILE_VELOCITY,
+    }
 
-pub const TPDO_DEFAULT_1: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(1),
-    sources: &[
-        PdoMappingSource {
-            entry: &od::STATUS_WORD,
-            bit_range: BitRange { start: 0, len: 16 },
-        },
-        PdoMappingSource {
-            entry: &od::GET_OPERATION_MODE,
-            bit_range: BitRange { start: 16, len: 8 },
-        },
-    ],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub TPDO_DEFAULT_1 = TPDO(1), COMMUNICATION => {
+        pub DEFAULT_TPDO_STATUS_WORD: u16 = od::STATUS_WORD,
+        pub DEFAULT_TPDO_GET_OPERATION_MODE: i8 = od::GET_OPERATION_MODE,
+    }
 
-pub const TPDO_DEFAULT_2: PdoMapping = PdoMapping {
-    pdo: PdoType::TPDO(2),
-    sources: &[PdoMappingSource {
-        entry: &od::POSITION_ACTUAL_VALUE,
-        bit_range: BitRange { start: 0, len: 32 },
-    }],
-    transmission_type: TransmissionType::OnChange,
-};
+    pub TPDO_DEFAULT_2 = TPDO(2), COMMUNICATION => {
+        pub DEFAULT_TPDO_POSITION_ACTUAL_VALUE: i32 = od::POSITION_ACTUAL_VALUE,
+    }
+}
@@ -1,16 +1,25 @@
 pub mod custom;
 pub mod default;
+pub mod profile;
+pub mod registry;
+pub mod typed;
 
-use crate::driver::startup::pdo_mapping::TransmissionType;
+use std::borrow::Cow;
+
+use crate::driver::startup::pdo_mapping::{PREDEFINED_PDOS, PdoCommunication};
+use crate::error::DriveError;
 use crate::od::entry::ODEntry;
 
+/// Maximum number of bits mapped into a single PDO
+pub const PDO_BITS: u16 = 64;
+
 #[derive(Debug, Clone, Copy)]
 pub struct BitRange {
     pub start: u8,
     pub len: u8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PdoType {
     RPDO(u8),
     TPDO(u8),
@@ -25,31 +34,131 @@ impl PdoType {
     }
 }
 
-#[derive(Debug)]
-/// Represents a single T/RPDO mapping
+#[derive(Debug, Clone)]
+/// Represents a single T/RPDO mapping, borrowed for the mappings declared in code and owned for
+/// the mappings read from a device
 pub struct PdoMapping {
     // PDO type and number
     pub pdo: PdoType,
     // Values to map
-    pub sources: &'static [PdoMappingSource],
+    pub sources: Cow<'static, [PdoMappingSource]>,
     // When to transmit this PDO
-    pub transmission_type: TransmissionType,
+    pub communication: PdoCommunication,
 }
 
-#[derive(Debug)]
+impl PdoMapping {
+    /// The sources as slice, unlike dereferencing them this is usable in const contexts
+    pub const fn sources(&self) -> &[PdoMappingSource] {
+        match &self.sources {
+            Cow::Borrowed(sources) => sources,
+            Cow::Owned(sources) => sources.as_slice(),
+        }
+    }
+
+    /// COB-ID this PDO uses on the given node, the explicit one of its communication parameter or
+    /// else the one of the predefined connection set
+    pub fn cob_id(&self, node_id: u8) -> Option<u16> {
+        self.communication
+            .cob_id
+            .or_else(|| self.pdo.get_pdo_cob_id(node_id))
+    }
+
+    /// Check that every source may be mapped into this kind of PDO, RPDOs can only hold
+    /// entries the network may write and TPDOs only entries the network may read
+    /// The device packs the sources in order, so every source has to start where the previous
+    /// one ended, gaps are filled with dummy entries
+    /// The communication parameters have to fit the kind of PDO as well
+    pub fn validate(&self) -> Result<(), DriveError> {
+        self.communication.validate(&self.pdo)?;
+
+        let mut end = 0;
+        for source in self.sources.iter() {
+            let entry = &source.entry;
+            let allowed = match self.pdo {
+                PdoType::RPDO(_) => entry.pdo_mappable.allows_rpdo(),
+                PdoType::TPDO(_) => entry.pdo_mappable.allows_tpdo(),
+            };
+
+            if !allowed {
+                return Err(DriveError::NotPdoMappable {
+                    index: entry.index,
+                    sub_index: entry.sub_index,
+                    pdo: self.pdo.clone(),
+                    mappable: entry.pdo_mappable.clone(),
+                });
+            }
+
+            let invalid = |reason| {
+                Err(DriveError::InvalidMapping {
+                    index: entry.index,
+                    sub_index: entry.sub_index,
+                    pdo: self.pdo.clone(),
+                    reason,
+                })
+            };
+            let BitRange { start, len } = source.bit_range;
+            let bits = entry
+                .default
+                .data_type()
+                .and_then(|data_type| data_type.size());
+            if len == 0 || bits.is_none_or(|size| len as usize > size * 8) {
+                return invalid("mapped with no bits or more bits than its data type has");
+            }
+            if (start as u16) < end {
+                return invalid("overlaps the previous entry");
+            }
+            if (start as u16) > end {
+                return invalid("leaves a gap after the previous entry, map a dummy entry instead");
+            }
+
+            end = start as u16 + len as u16;
+            if end > PDO_BITS {
+                return invalid("does not fit in the 64 bits of a PDO");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 /// Values to map onto T/RPDO
 pub struct PdoMappingSource {
     // The entry to map
-    pub entry: &'static ODEntry,
+    pub entry: Cow<'static, ODEntry>,
 
     // The T/RPDO bits to map the above entry to
     pub bit_range: BitRange,
 }
 
+impl PdoMappingSource {
+    /// The entry, unlike dereferencing it this is usable in const contexts
+    pub const fn entry(&self) -> &ODEntry {
+        match &self.entry {
+            Cow::Borrowed(entry) => entry,
+            Cow::Owned(entry) => entry,
+        }
+    }
+
+    /// Value of the mapping parameter subindex describing this source: 2 bytes of OD entry to be
+    /// mapped, 1 byte subindex, 1 byte with number of bits to be mapped
+    pub fn mapping_value(&self) -> u32 {
+        ((self.entry.index as u32) << 16)
+            | ((self.entry.sub_index as u32) << 8)
+            | self.bit_range.len as u32
+    }
+}
+
 impl PdoType {
     /// Returns the COB Id for the given pdo num and type
     /// See https://en.wikipedia.org/wiki/CANopen#Process_Data_Object_(PDO)_protocol
+    /// None for PDOs outside of the predefined connection set, those need an explicit COB-ID
     pub fn get_pdo_cob_id(&self, node_id: u8) -> Option<u16> {
+        let (Self::TPDO(num) | Self::RPDO(num)) = self;
+        if !(1..=PREDEFINED_PDOS).contains(num) {
+            return None;
+        }
+
         Some(match self {
             Self::TPDO(num) => {
                 const BASE: u16 = 0x80;
@@ -65,4 +174,213 @@ impl PdoType {
             }
         })
     }
+
+    /// Node and PDO the given COB-ID belongs to in the predefined connection set, e.g. 0x283 is
+    /// TPDO2 of node 3. None outside of the PDO ranges of the predefined connection set
+    pub fn from_predefined_cob_id(cob_id: u16) -> Option<(u8, PdoType)> {
+        let node_id = (cob_id & 0x7F) as u8;
+        if node_id == 0 {
+            return None;
+        }
+
+        let pdo = match cob_id & !0x7F {
+            0x180 => Self::TPDO(1),
+            0x200 => Self::RPDO(1),
+            0x280 => Self::TPDO(2),
+            0x300 => Self::RPDO(2),
+            0x380 => Self::TPDO(3),
+            0x400 => Self::RPDO(3),
+            0x480 => Self::TPDO(4),
+            0x500 => Self::RPDO(4),
+            _ => return None,
+        };
+
+        Some((node_id, pdo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::startup::pdo_mapping::TransmissionType, od};
+
+    #[test]
+    fn test_custom_mappings_are_valid() {
+        for mapping in custom::CUSTOM_RPDOS.iter().chain(custom::CUSTOM_TPDOS) {
+            assert!(mapping.validate().is_ok(), "{mapping:?}");
+        }
+    }
+
+    #[test]
+    fn test_rpdo_only_entry_in_tpdo() {
+        const MAPPING: PdoMapping = PdoMapping {
+            pdo: PdoType::TPDO(1),
+            sources: Cow::Borrowed(&[PdoMappingSource {
+                entry: Cow::Borrowed(&od::CONTROL_WORD),
+                bit_range: BitRange { start: 0, len: 16 },
+            }]),
+            communication: PdoCommunication::new(TransmissionType::OnChange),
+        };
+
+        assert!(matches!(
+            MAPPING.validate(),
+            Err(DriveError::NotPdoMappable { index: 0x6040, .. })
+        ));
+    }
+
+    #[test]
+    fn test_packing() {
+        macro_rules! source {
+            ($entry:expr, $start:expr, $len:expr) => {
+                PdoMappingSource {
+                    entry: Cow::Borrowed($entry),
+                    bit_range: BitRange {
+                        start: $start,
+                        len: $len,
+                    },
+                }
+            };
+        }
+        macro_rules! tpdo {
+            ($($source:expr),+ $(,)?) => {
+                PdoMapping {
+                    pdo: PdoType::TPDO(1),
+                    sources: Cow::Borrowed(&[$($source),+]),
+                    communication: PdoCommunication::new(TransmissionType::OnChange),
+                }
+            };
+        }
+
+        const PACKED: PdoMapping = tpdo!(
+            source!(&od::GET_OPERATION_MODE, 0, 4),
+            source!(&od::DUMMY_BOOLEAN, 4, 1),
+            source!(&od::STATUS_WORD, 5, 16),
+        );
+        assert!(PACKED.validate().is_ok());
+
+        const OVERLAP: PdoMapping = tpdo!(
+            source!(&od::STATUS_WORD, 0, 16),
+            source!(&od::GET_OPERATION_MODE, 8, 8),
+        );
+        const GAP: PdoMapping = tpdo!(
+            source!(&od::STATUS_WORD, 0, 16),
+            source!(&od::GET_OPERATION_MODE, 20, 8),
+        );
+        const TOO_WIDE: PdoMapping = tpdo!(source!(&od::STATUS_WORD, 0, 17));
+        const EMPTY: PdoMapping = tpdo!(source!(&od::STATUS_WORD, 0, 0));
+        const TOO_LONG: PdoMapping = tpdo!(
+            source!(&od::POSITION_ACTUAL_VALUE, 0, 32),
+            source!(&od::VELOCITY_ACTUAL_VALUE, 32, 32),
+            source!(&od::DUMMY_BOOLEAN, 64, 1),
+        );
+        let invalid = [OVERLAP, GAP, TOO_WIDE, EMPTY, TOO_LONG];
+        for mapping in invalid {
+            assert!(
+                matches!(mapping.validate(), Err(DriveError::InvalidMapping { .. })),
+                "{mapping:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_communication() {
+        let pdo = PdoType::RPDO(1);
+        let valid = [
+            PdoCommunication::new(TransmissionType::SyncAcyclic),
+            PdoCommunication::new(TransmissionType::SyncCyclic(240)),
+            PdoCommunication::new(TransmissionType::OnChange).with_event_timer(100),
+            PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x281),
+        ];
+        for communication in valid {
+            assert!(communication.validate(&pdo).is_ok(), "{communication:?}");
+        }
+
+        let invalid = [
+            PdoCommunication::new(TransmissionType::SyncCyclic(0)),
+            PdoCommunication::new(TransmissionType::SyncCyclic(241)),
+            PdoCommunication::new(TransmissionType::RtrEvent),
+            PdoCommunication::new(TransmissionType::OnChange).with_inhibit_time(10),
+            PdoCommunication::new(TransmissionType::ON_SYNC).with_sync_start(2),
+            // SYNC, an SDO and more than 11 bits
+            PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x080),
+            PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x5A3),
+            PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x800),
+        ];
+        for communication in invalid {
+            assert!(
+                matches!(
+                    communication.validate(&pdo),
+                    Err(DriveError::InvalidCommunication { .. })
+                ),
+                "{communication:?}"
+            );
+        }
+
+        // Beyond the predefined connection set only with an explicit COB-ID
+        let implicit = PdoCommunication::new(TransmissionType::OnChange);
+        let explicit = PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x381);
+        assert!(implicit.validate(&PdoType::TPDO(5)).is_err());
+        assert!(implicit.validate(&PdoType::RPDO(0)).is_err());
+        assert!(explicit.validate(&PdoType::TPDO(0)).is_err());
+        assert!(explicit.validate(&PdoType::TPDO(5)).is_ok());
+
+        // TPDOs may be requested remotely, and start at a given SYNC
+        let pdo = PdoType::TPDO(1);
+        let sync = PdoCommunication::new(TransmissionType::SyncCyclic(4)).with_sync_start(2);
+        assert!(sync.validate(&pdo).is_ok());
+        assert!(
+            PdoCommunication::new(TransmissionType::RtrSync)
+                .with_inhibit_time(10)
+                .validate(&pdo)
+                .is_ok()
+        );
+        assert!(
+            PdoCommunication::new(TransmissionType::OnChange)
+                .with_sync_start(2)
+                .validate(&pdo)
+                .is_err()
+        );
+
+        for value in 0..=u8::MAX {
+            if let Some(transmission_type) = TransmissionType::from_od_value(value) {
+                assert_eq!(transmission_type.od_value(), value);
+            } else {
+                assert!((0xF1..=0xFB).contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_predefined_cob_ids() {
+        for node_id in [1, 3, 0x7F] {
+            for num in 1..=4 {
+                for pdo in [PdoType::RPDO(num), PdoType::TPDO(num)] {
+                    let cob_id = pdo.get_pdo_cob_id(node_id).unwrap();
+                    assert_eq!(
+                        PdoType::from_predefined_cob_id(cob_id),
+                        Some((node_id, pdo))
+                    );
+                }
+            }
+        }
+
+        // SYNC, node 0 and SDOs
+        for cob_id in [0x080, 0x200, 0x603] {
+            assert_eq!(PdoType::from_predefined_cob_id(cob_id), None);
+        }
+        // TPDO5 would collide with the SDO responses, TPDO0 with EMCY
+        for pdo in [PdoType::TPDO(5), PdoType::TPDO(0), PdoType::RPDO(0)] {
+            assert_eq!(pdo.get_pdo_cob_id(3), None);
+        }
+
+        // An explicit COB-ID takes precedence
+        const TARGET_POS: &PdoMapping = &custom::RPDO_TARGET_POS;
+        const LINKED: PdoMapping = PdoMapping {
+            pdo: PdoType::RPDO(3),
+            sources: Cow::Borrowed(TARGET_POS.sources()),
+            communication: PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x281),
+        };
+        assert_eq!(LINKED.cob_id(4), Some(0x281));
+        assert_eq!(custom::RPDO_TARGET_POS.cob_id(4), Some(0x304));
+    }
 }
@@ -0,0 +1,79 @@
+use crate::{
+    comms::pdo::{
+        Pdo,
+        mapping::{PdoMapping, PdoType},
+    },
+    error::DriveError,
+};
+
+/// Named set of RPDO and TPDO mappings, the driver switches between profiles at runtime to map
+/// the objects the operation mode in use needs, e.g. profile targets or cyclic setpoints
+#[derive(Debug)]
+pub struct PdoProfile {
+    pub name: &'static str,
+    pub rpdos: &'static [PdoMapping],
+    pub tpdos: &'static [PdoMapping],
+}
+
+impl PdoProfile {
+    /// Every mapping of this profile, RPDOs first
+    pub fn mappings(&self) -> impl Iterator<Item = &'static PdoMapping> {
+        self.rpdos.iter().chain(self.tpdos)
+    }
+
+    /// Check the mappings of this profile before any of it is applied to a device, the RPDOs
+    /// have to satisfy [`Pdo::check_rpdo_mappings`]
+    pub fn validate(&self) -> Result<(), DriveError> {
+        Pdo::check_rpdo_mappings(&self.rpdos.iter().collect::<Vec<_>>())?;
+        for mapping in self.tpdos {
+            if !matches!(mapping.pdo, PdoType::TPDO(_)) {
+                return Err(DriveError::ViolatedInvariant(format!(
+                    "{:?} of PDO profile {} is not a TPDO",
+                    mapping.pdo, self.name
+                )));
+            }
+            mapping.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How the driver moves a device to another [`PdoProfile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemapStrategy {
+    /// Put the device in NMT PreOperational, remap, and start it again. No PDOs are exchanged
+    /// while the mappings change
+    #[default]
+    PreOperational,
+    /// Stay in NMT Operational and disable, remap and re-enable every PDO that changes. Only for
+    /// devices that allow mapping changes while Operational
+    Operational,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::pdo::mapping::custom;
+
+    #[test]
+    fn test_validate() {
+        assert!(custom::PROFILE_MODES.validate().is_ok());
+        assert!(custom::CYCLIC_SYNCHRONOUS_POSITION.validate().is_ok());
+
+        // Profiles without a controlword can not drive the device
+        const TARGETS_ONLY: PdoProfile = PdoProfile {
+            name: "targets only",
+            rpdos: &[custom::RPDO_TARGET_POS],
+            tpdos: custom::CUSTOM_TPDOS,
+        };
+        assert!(TARGETS_ONLY.validate().is_err());
+
+        let swapped = PdoProfile {
+            name: "swapped",
+            rpdos: custom::CUSTOM_RPDOS,
+            tpdos: custom::CUSTOM_RPDOS,
+        };
+        assert!(swapped.validate().is_err());
+    }
+}
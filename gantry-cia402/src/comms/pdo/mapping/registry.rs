@@ -0,0 +1,122 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use oze_canopen::canopen::NodeId;
+
+use crate::comms::pdo::mapping::{PdoMapping, PdoType};
+
+/// PDO mappings of the nodes on a bus, used to decode received PDOs with the mapping configured
+/// on the node that sent or is addressed by them
+#[derive(Debug, Clone, Default)]
+pub struct MappingRegistry {
+    nodes: BTreeMap<NodeId, Vec<Cow<'static, PdoMapping>>>,
+    // Node and position in its mappings of every COB-ID in use, rebuilt whenever the mappings
+    // change
+    routes: BTreeMap<u16, (NodeId, usize)>,
+}
+
+impl MappingRegistry {
+    /// Register mappings of a node, replacing earlier mappings of the same PDOs
+    pub fn insert(
+        &mut self,
+        node_id: NodeId,
+        mappings: impl IntoIterator<Item = Cow<'static, PdoMapping>>,
+    ) {
+        let known = self.nodes.entry(node_id).or_default();
+        for mapping in mappings {
+            known.retain(|known| known.pdo != mapping.pdo);
+            known.push(mapping);
+        }
+        self.route();
+    }
+
+    pub fn remove(&mut self, node_id: NodeId) -> Option<Vec<Cow<'static, PdoMapping>>> {
+        let removed = self.nodes.remove(&node_id);
+        self.route();
+        removed
+    }
+
+    /// Mapping of the given PDO of a node, None if it is not registered
+    pub fn get(&self, node_id: NodeId, pdo: &PdoType) -> Option<&PdoMapping> {
+        self.nodes
+            .get(&node_id)?
+            .iter()
+            .find(|mapping| &mapping.pdo == pdo)
+            .map(|mapping| &**mapping)
+    }
+
+    /// Node and mapping of the PDO sent with the given COB-ID, None if no registered PDO uses it
+    /// A TPDO linked to the RPDO of another node resolves to the TPDO, its producer defines what
+    /// the data means
+    pub fn lookup(&self, cob_id: u16) -> Option<(NodeId, &PdoMapping)> {
+        let (node_id, position) = self.routes.get(&cob_id)?;
+        Some((*node_id, &self.nodes[node_id][*position]))
+    }
+
+    fn route(&mut self) {
+        self.routes.clear();
+        for (&node_id, mappings) in &self.nodes {
+            for (position, mapping) in mappings.iter().enumerate() {
+                let Some(cob_id) = mapping.cob_id(node_id) else {
+                    continue;
+                };
+                let is_tpdo = matches!(mapping.pdo, PdoType::TPDO(_));
+                let routed_tpdo = self.routes.get(&cob_id).is_some_and(|(node_id, position)| {
+                    matches!(self.nodes[node_id][*position].pdo, PdoType::TPDO(_))
+                });
+                if is_tpdo || !routed_tpdo {
+                    self.routes.insert(cob_id, (node_id, position));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::pdo::mapping::{BitRange, PdoMappingSource, custom, default},
+        driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+        od,
+    };
+
+    #[test]
+    fn test_per_node_lookup() {
+        let mut registry = MappingRegistry::default();
+        registry.insert(3, default::DEFAULT_TPDOS.iter().map(Cow::Borrowed));
+        registry.insert(3, [Cow::Owned(custom::TPDO_POS_VEL_ACTUAL)]);
+
+        let tpdo2 = registry.get(3, &PdoType::TPDO(2)).unwrap();
+        assert_eq!(tpdo2.sources.len(), 2);
+        assert!(registry.get(3, &PdoType::TPDO(1)).is_some());
+        assert!(registry.get(3, &PdoType::TPDO(3)).is_none());
+        assert!(registry.get(4, &PdoType::TPDO(1)).is_none());
+    }
+
+    #[test]
+    fn test_linked_pdos() {
+        // Node 5 follows the actual position of node 3 as its target position
+        const FOLLOWER: PdoMapping = PdoMapping {
+            pdo: PdoType::RPDO(2),
+            sources: Cow::Borrowed(&[PdoMappingSource {
+                entry: Cow::Borrowed(&od::SET_TARGET_POSITION),
+                bit_range: BitRange { start: 0, len: 32 },
+            }]),
+            communication: PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x283),
+        };
+
+        let mut registry = MappingRegistry::default();
+        registry.insert(5, [Cow::Owned(FOLLOWER)]);
+        let (node_id, mapping) = registry.lookup(0x283).unwrap();
+        assert_eq!((node_id, &mapping.pdo), (5, &PdoType::RPDO(2)));
+        assert!(registry.lookup(0x305).is_none());
+
+        // The producer takes precedence once it is known
+        registry.insert(3, [Cow::Owned(custom::TPDO_POS_VEL_ACTUAL)]);
+        let (node_id, mapping) = registry.lookup(0x283).unwrap();
+        assert_eq!((node_id, &mapping.pdo), (3, &PdoType::TPDO(2)));
+
+        registry.remove(3);
+        assert_eq!(registry.lookup(0x283).unwrap().0, 5);
+    }
+}
@@ -0,0 +1,246 @@
+use std::{borrow::Cow, mem};
+
+use crate::{
+    comms::pdo::{
+        frame::PdoFrame,
+        mapping::{BitRange, PDO_BITS, PdoMapping, PdoMappingSource, PdoType},
+    },
+    error::DriveError,
+    od::{
+        self,
+        entry::ODEntry,
+        typed::{ODType, TypedEntry},
+    },
+};
+
+/// Declare [`PdoMapping`] consts, the bit range of every entry follows from the data types of the
+/// entries before it. Every mapped entry gets a [`MappedEntry`] const to read and write it, e.g.
+/// ```
+/// use gantry_cia402::{
+///     comms::pdo::{frame::PdoFrame, mapping::PdoType},
+///     driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+///     od, pdo_mapping,
+/// };
+///
+/// pdo_mapping! {
+///     /// Statusword followed by the operation mode
+///     pub TPDO_STATUS = TPDO(1), PdoCommunication::new(TransmissionType::OnChange) => {
+///         pub STATUS_WORD: u16 = od::STATUS_WORD,
+///         pub OPERATION_MODE: i8 = od::GET_OPERATION_MODE,
+///     }
+/// }
+///
+/// assert_eq!(TPDO_STATUS.pdo, PdoType::TPDO(1));
+/// assert_eq!(TPDO_STATUS.sources[1].bit_range.start, 16);
+///
+/// let mut frame = PdoFrame::with_dlc(TPDO_STATUS.dlc());
+/// OPERATION_MODE.set(&mut frame, 3).unwrap();
+/// assert_eq!(OPERATION_MODE.get(&frame.data).unwrap(), 3);
+/// ```
+/// Entries that can not be mapped in the direction of the PDO, or more than 64 bits of entries
+/// are a compile error:
+/// ```compile_fail
+/// use gantry_cia402::{
+///     driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+///     od, pdo_mapping,
+/// };
+///
+/// pdo_mapping! {
+///     // The statusword is only sent by the device
+///     pub RPDO_STATUS = RPDO(1), PdoCommunication::new(TransmissionType::OnChange) => {
+///         pub STATUS_WORD: u16 = od::STATUS_WORD,
+///     }
+/// }
+/// let _ = RPDO_STATUS;
+/// ```
+#[macro_export]
+macro_rules! pdo_mapping {
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis $mapping:ident = $kind:ident($num:literal), $communication:expr => {
+            $(
+                $(#[$field_attr:meta])*
+                $field_vis:vis $field:ident: $ty:ty = $entry:path
+            ),+ $(,)?
+        }
+    )*) => {
+        $(
+            $(#[$attr])*
+            $vis const $mapping: $crate::comms::pdo::mapping::PdoMapping =
+                $crate::comms::pdo::mapping::PdoMapping {
+                    pdo: $crate::comms::pdo::mapping::PdoType::$kind($num),
+                    sources: ::std::borrow::Cow::Borrowed(
+                        &$crate::comms::pdo::mapping::typed::pack(
+                            &$crate::comms::pdo::mapping::PdoType::$kind($num),
+                            [$($crate::typed_entry!($ty, $entry).entry()),+],
+                        ),
+                    ),
+                    communication: $communication,
+                };
+
+            $(
+                $(#[$field_attr])*
+                $field_vis const $field: $crate::comms::pdo::mapping::typed::MappedEntry<$ty> =
+                    $crate::comms::pdo::mapping::typed::MappedEntry::new(
+                        {
+                            const MAPPING: &$crate::comms::pdo::mapping::PdoMapping = &$mapping;
+                            MAPPING
+                        },
+                        $crate::typed_entry!($ty, $entry),
+                    );
+            )+
+        )*
+    };
+}
+
+/// Sources of a PDO mapping the given entries in order, each as wide as its data type
+/// Panics, a compile error in const contexts, if an entry can not be mapped in the direction of
+/// the PDO or the entries do not fit in a single PDO
+pub const fn pack<const N: usize>(
+    pdo: &PdoType,
+    entries: [&'static ODEntry; N],
+) -> [PdoMappingSource; N] {
+    let mut sources = [const {
+        PdoMappingSource {
+            entry: Cow::Borrowed(&od::DUMMY_BOOLEAN),
+            bit_range: BitRange { start: 0, len: 0 },
+        }
+    }; N];
+
+    let mut start = 0;
+    let mut i = 0;
+    while i < N {
+        let entry = entries[i];
+        let allowed = match pdo {
+            PdoType::RPDO(_) => entry.pdo_mappable.allows_rpdo(),
+            PdoType::TPDO(_) => entry.pdo_mappable.allows_tpdo(),
+        };
+        if !allowed {
+            panic!("Entry can not be mapped in this direction");
+        }
+
+        let len = match entry.default.data_type() {
+            Some(data_type) => match data_type.size() {
+                Some(size) => size as u16 * 8,
+                None => panic!("Variable length entries can not be mapped"),
+            },
+            None => panic!("Entry without data type can not be mapped"),
+        };
+        if start + len > PDO_BITS {
+            panic!("Entries do not fit in a single PDO");
+        }
+
+        // Assigning would drop the placeholder, which const fns can not
+        mem::forget(mem::replace(&mut sources[i].entry, Cow::Borrowed(entry)));
+        sources[i].bit_range = BitRange {
+            start: start as u8,
+            len: len as u8,
+        };
+        start += len;
+        i += 1;
+    }
+
+    sources
+}
+
+/// A [`TypedEntry`] mapped into a PDO, reads and writes the entry in the data of that PDO
+#[derive(Debug)]
+pub struct MappedEntry<T> {
+    mapping: &'static PdoMapping,
+    entry: TypedEntry<T>,
+}
+
+// Manual impls, derives would require T: Clone/Copy
+impl<T> Clone for MappedEntry<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for MappedEntry<T> {}
+
+impl<T: ODType> MappedEntry<T> {
+    /// Panics, a compile error in const contexts, if the entry is not mapped in the mapping
+    pub const fn new(mapping: &'static PdoMapping, entry: TypedEntry<T>) -> Self {
+        let mut i = 0;
+        while i < mapping.sources().len() {
+            let source = mapping.sources()[i].entry();
+            if source.index == entry.entry().index && source.sub_index == entry.entry().sub_index {
+                return Self { mapping, entry };
+            }
+            i += 1;
+        }
+
+        panic!("Entry is not mapped in the PDO mapping");
+    }
+
+    pub const fn mapping(&self) -> &'static PdoMapping {
+        self.mapping
+    }
+
+    pub const fn entry(&self) -> TypedEntry<T> {
+        self.entry
+    }
+
+    /// Decode the entry from the data of its PDO
+    pub fn get(&self, data: &[u8]) -> Result<T, DriveError> {
+        self.mapping.get(data, self.entry)
+    }
+
+    /// Encode the entry into the frame of its PDO
+    pub fn set(&self, frame: &mut PdoFrame, value: T) -> Result<(), DriveError> {
+        self.mapping.set(frame, self.entry, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::pdo::mapping::custom,
+        driver::startup::pdo_mapping::{PdoCommunication, TransmissionType},
+        od,
+    };
+
+    pdo_mapping! {
+        PACKED = TPDO(4), PdoCommunication::new(TransmissionType::ON_SYNC) => {
+            POSITION: i32 = od::POSITION_ACTUAL_VALUE,
+            STATUS_WORD: u16 = od::STATUS_WORD,
+            OPERATION_MODE: i8 = od::GET_OPERATION_MODE,
+        }
+    }
+
+    #[test]
+    fn test_offsets() {
+        let ranges: Vec<_> = PACKED
+            .sources
+            .iter()
+            .map(|source| (source.bit_range.start, source.bit_range.len))
+            .collect();
+        assert_eq!(ranges, [(0, 32), (32, 16), (48, 8)]);
+        assert!(PACKED.validate().is_ok());
+        assert_eq!(PACKED.dlc(), 7);
+
+        let mut frame = PdoFrame::with_dlc(PACKED.dlc());
+        POSITION.set(&mut frame, -5).unwrap();
+        OPERATION_MODE.set(&mut frame, -1).unwrap();
+        assert_eq!(POSITION.get(&frame.data).unwrap(), -5);
+        assert_eq!(STATUS_WORD.get(&frame.data).unwrap(), 0);
+        assert_eq!(frame.data[6], 0xFF);
+        assert_eq!(OPERATION_MODE.mapping().pdo, PdoType::TPDO(4));
+    }
+
+    #[test]
+    fn test_custom_accessors() {
+        assert_eq!(
+            custom::RPDO_PROFILE_VELOCITY.mapping().pdo,
+            custom::RPDO_TARGET_POS.pdo
+        );
+        let range = custom::RPDO_TARGET_POS.sources[1].bit_range;
+        assert_eq!((range.start, range.len), (32, 32));
+
+        let mut frame = PdoFrame::with_dlc(custom::RPDO_CONTROL_OPMODE.dlc());
+        custom::RPDO_SET_OPERATION_MODE.set(&mut frame, 6).unwrap();
+        assert_eq!(frame.data[..3], [0, 0, 6]);
+    }
+}
@@ -1,17 +1,9 @@
+pub mod codec;
 pub mod frame;
 pub mod mapping;
 
 use crate::comms::pdo::mapping::PdoMapping;
 use crate::comms::pdo::mapping::PdoType;
-use crate::comms::pdo::mapping::custom::RPDO_CONTROL_OPMODE;
-use crate::comms::pdo::mapping::custom::RPDO_IDX_CONTROL_WORD;
-use crate::comms::pdo::mapping::custom::RPDO_IDX_TARGET_POS;
-use crate::comms::pdo::mapping::custom::RPDO_IDX_TARGET_TORQUE;
-use crate::comms::pdo::mapping::custom::RPDO_IDX_TARGET_VEL;
-use crate::comms::pdo::mapping::custom::RPDO_TARGET_POS;
-use crate::comms::pdo::mapping::custom::RPDO_TARGET_TORQUE;
-use crate::comms::pdo::mapping::custom::RPDO_TARGET_VEL;
-use crate::comms::pdo::mapping::custom::get_dlc;
 use crate::driver::oms::home::*;
 use crate::driver::oms::position::*;
 use crate::driver::oms::setpoint::Setpoint;
@@ -19,7 +11,10 @@ use crate::driver::oms::torque::*;
 use crate::driver::oms::velocity::*;
 use crate::driver::receiver::setpoint_manager::SetpointManager;
 use crate::od;
+use crate::od::typed::{self, ODType, TypedEntry};
+use std::borrow::Cow;
 use std::time::Duration;
+use tokio::time::Instant;
 
 /// PDO based Cia402Transport impl for oze-canopen
 use oze_canopen::{
@@ -32,7 +27,7 @@ use crate::{
     comms::pdo::frame::PdoFrame,
     driver::{oms::OperationMode, state::Cia402Flags, update::ControlWord},
     error::DriveError,
-    od::entry::ODEntry,
+    od::{entry::ODEntry, meta::KnownValues},
 };
 
 /// Low level CANopen PDO transport implementation
@@ -41,8 +36,17 @@ use crate::{
 pub struct Pdo {
     canopen: CanOpenInterface,
     node_id: u8,
-    rpdo_mapping_set: &'static [PdoMapping],
-    rpdo_frames: [PdoFrame; 4],
+    rpdo_mapping_set: Vec<Cow<'static, PdoMapping>>,
+    // Last data of every RPDO, in the order of the mapping set
+    rpdo_frames: Vec<PdoFrame>,
+    // When every RPDO was last sent, in the order of the mapping set
+    rpdo_sent: Vec<Option<Instant>>,
+    // Synchronous RPDOs held back until the next flush, in the order of the mapping set
+    rpdo_pending: Vec<bool>,
+    // Whether synchronous RPDOs are held back, see Pdo::defer_synchronous
+    defer_synchronous: bool,
+    // Values on the device, bounding the values we send, e.g. the profile velocity
+    known_values: KnownValues,
 }
 
 impl Pdo {
@@ -50,21 +54,125 @@ impl Pdo {
         canopen: CanOpenInterface,
         node_id: u8,
         rpdo_mapping_set: &'static [PdoMapping],
+        known_values: KnownValues,
     ) -> Result<Self, DriveError> {
+        let mut pdo = Self {
+            canopen,
+            node_id,
+            rpdo_mapping_set: Vec::new(),
+            rpdo_frames: Vec::new(),
+            rpdo_sent: Vec::new(),
+            rpdo_pending: Vec::new(),
+            defer_synchronous: false,
+            known_values,
+        };
+        pdo.remap(rpdo_mapping_set.iter().map(Cow::Borrowed).collect())?;
+
+        Ok(pdo)
+    }
+
+    /// Use the given RPDO mappings from now on, e.g. the mappings adopted from the device at
+    /// startup or those of another [`mapping::profile::PdoProfile`]
+    /// Entries that stay mapped keep their current value, e.g. the controlword, the data of
+    /// everything else starts out zeroed
+    pub fn remap(
+        &mut self,
+        rpdo_mapping_set: Vec<Cow<'static, PdoMapping>>,
+    ) -> Result<(), DriveError> {
+        Pdo::check_rpdo_mappings(&rpdo_mapping_set.iter().map(|m| &**m).collect::<Vec<_>>())?;
+
+        let mut rpdo_frames = Vec::with_capacity(rpdo_mapping_set.len());
+        for mapping in &rpdo_mapping_set {
+            let mut frame = PdoFrame::with_dlc(mapping.dlc());
+            for (previous, previous_frame) in self.rpdo_mapping_set.iter().zip(&self.rpdo_frames) {
+                for value in previous.decode(&previous_frame.data)? {
+                    if !mapping.contains(&value.entry) {
+                        continue;
+                    }
+                    if let Err(err) = mapping.encode(&mut frame, &value.entry, &value.value) {
+                        warn!(
+                            "Unable to keep {} after remapping: {err}",
+                            value.fmt_pretty()
+                        );
+                    }
+                }
+            }
+            rpdo_frames.push(frame);
+        }
+
+        self.rpdo_frames = rpdo_frames;
+        self.rpdo_sent = vec![None; rpdo_mapping_set.len()];
+        self.rpdo_pending = vec![false; rpdo_mapping_set.len()];
+        self.rpdo_mapping_set = rpdo_mapping_set;
+
+        Ok(())
+    }
+
+    /// Check that the given mappings are valid RPDO mappings, and map everything the driver needs
+    /// to control the device
+    pub fn check_rpdo_mappings(rpdo_mapping_set: &[&PdoMapping]) -> Result<(), DriveError> {
         // Check if all required mappings are present
         Pdo::check_required_rpdo_mappings(rpdo_mapping_set)?;
 
-        let mut dlcs = [0usize; 8];
-        for (idx, mappings) in rpdo_mapping_set.iter().enumerate() {
-            dlcs[idx] = get_dlc(mappings);
+        for mapping in rpdo_mapping_set {
+            if !matches!(mapping.pdo, PdoType::RPDO(_)) {
+                return Err(DriveError::ViolatedInvariant(format!(
+                    "{:?} is not an RPDO",
+                    mapping.pdo
+                )));
+            }
+            mapping.validate()?;
         }
 
-        Ok(Self {
-            canopen,
-            node_id,
-            rpdo_mapping_set,
-            rpdo_frames: core::array::from_fn(|idx| PdoFrame::with_dlc(dlcs[idx])),
-        })
+        Ok(())
+    }
+
+    /// When the next RPDO has to be sent again to stay within its event timer, None if no RPDO
+    /// with an event timer was sent yet
+    pub fn next_refresh(&self) -> Option<Instant> {
+        self.rpdo_mapping_set
+            .iter()
+            .zip(&self.rpdo_sent)
+            .filter_map(|(mapping, sent)| Some((*sent)? + mapping.communication.refresh_period()?))
+            .min()
+    }
+
+    /// Send every RPDO that is due to stay within its event timer, the device raises an error
+    /// once an RPDO is not received within it
+    pub async fn refresh(&mut self) -> Result<(), DriveError> {
+        let now = Instant::now();
+        for idx in 0..self.rpdo_mapping_set.len() {
+            let period = self.rpdo_mapping_set[idx].communication.refresh_period();
+            if let (Some(sent), Some(period)) = (self.rpdo_sent[idx], period)
+                && sent + period <= now
+            {
+                self.send_rpdo(idx).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hold back RPDOs with a synchronous transmission type until [`Pdo::flush_synchronous`],
+    /// e.g. to send them in the window before a SYNC. Stopping to defer sends what is held back
+    pub async fn defer_synchronous(&mut self, defer: bool) -> Result<(), DriveError> {
+        self.defer_synchronous = defer;
+        if !defer {
+            self.flush_synchronous().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send every held back synchronous RPDO with its latest data
+    pub async fn flush_synchronous(&mut self) -> Result<(), DriveError> {
+        for idx in 0..self.rpdo_mapping_set.len() {
+            if self.rpdo_pending[idx] {
+                self.transmit_rpdo(idx).await?;
+            }
+        }
+
+        Ok(())
     }
 
     // Perform the given cia402 state transition by writing the corresponding controlword flags and
@@ -80,11 +188,11 @@ impl Pdo {
         trace!("Fetched current controlword: {cw:?}");
 
         cw = cw.with_cia402_flags(flags);
-        self.set_controlword_rpdo(cw);
+        self.set_controlword_rpdo(cw)?;
 
-        match self.send_rpdo(RPDO_CONTROL_OPMODE).await {
+        match self.send_rpdos_with(&[&od::CONTROL_WORD]).await {
             Ok(_) => {
-                trace!("RPDO1 sent to effect cia402 transition");
+                trace!("Controlword sent to effect cia402 transition");
             }
             Err(err) => {
                 error!("ERR: {err}");
@@ -110,8 +218,6 @@ impl Pdo {
         }
     }
 
-    // TODO: cleanup all the hardcoded addresses and offsets when you have time... I will have time
-    // for that, right?
     pub async fn write_position_setpoint(
         &mut self,
         PositionSetpoint {
@@ -120,39 +226,36 @@ impl Pdo {
             profile_velocity,
         }: &PositionSetpoint,
     ) -> Result<(), DriveError> {
-        // 1. Construct RPDO1: Set opmode to position and toggle control_word OMS bits
+        // 1. Set opmode to position and toggle control_word OMS bits
 
         trace!(
             "Writing position setpoint - target: {target} - profile_velocity: {profile_velocity} = flags: {flags:?}"
         );
 
+        // Check the targets before the controlword announces the new setpoint
+        self.check_limits(typed::SET_TARGET_POSITION, *target)?;
+        self.check_limits(typed::PROFILE_VELOCITY, *profile_velocity)?;
+
         // Set Controlword
         let mut cw = self.get_current_controlword();
 
         trace!("Writing position setpoint - current Controlword: {cw:?}");
         cw = cw.with_position_flags(flags);
         trace!("Writing position setpoint - with position flags: {cw:?}");
-        self.set_controlword_rpdo(cw);
+        self.set_controlword_rpdo(cw)?;
 
         // Set Position Mode
-        self.set_operational_mode(OperationMode::ProfilePosition);
+        self.set_operational_mode(OperationMode::ProfilePosition)?;
 
-        // Send RPDO1
-        self.send_rpdo(RPDO_CONTROL_OPMODE).await?;
+        self.send_rpdos_with(&[&od::CONTROL_WORD, &od::SET_OPERATION_MODE])
+            .await?;
 
-        // 2. Construct RPDO2: Set position and velocity target
-        // TODO: hardcoded offsets
-        self.rpdo_frames[RPDO_IDX_TARGET_POS].set(
-            (RPDO_TARGET_POS.sources[0].bit_range.start / 8) as usize,
-            &(*target as u32).to_le_bytes(),
-        );
-        self.rpdo_frames[RPDO_IDX_TARGET_POS].set(
-            (RPDO_TARGET_POS.sources[1].bit_range.start / 8) as usize,
-            &(profile_velocity.to_le_bytes()),
-        );
+        // 2. Set position and velocity target
+        self.set_mapped(typed::SET_TARGET_POSITION, *target)?;
+        self.set_mapped(typed::PROFILE_VELOCITY, *profile_velocity)?;
 
-        // Send RPDO2
-        self.send_rpdo(RPDO_TARGET_POS).await?;
+        self.send_rpdos_with(&[&od::SET_TARGET_POSITION, &od::PROFILE_VELOCITY])
+            .await?;
 
         Ok(())
     }
@@ -163,20 +266,17 @@ impl Pdo {
             target_velocity: target,
         }: &VelocitySetpoint,
     ) -> Result<(), DriveError> {
+        self.check_limits(typed::SET_TARGET_VELOCITY, *target)?;
+
         // Set Velocity Mode
-        self.set_operational_mode(OperationMode::ProfileVelocity);
+        self.set_operational_mode(OperationMode::ProfileVelocity)?;
 
-        self.send_rpdo(RPDO_CONTROL_OPMODE).await?;
+        self.send_rpdos_with(&[&od::SET_OPERATION_MODE]).await?;
 
-        // Set position and torque target
-        self.rpdo_frames[RPDO_IDX_TARGET_VEL]
-            // TODO: hardcoded offset
-            .set(
-                (RPDO_TARGET_VEL.sources[0].bit_range.start / 8) as usize,
-                &target.to_le_bytes(),
-            );
+        // Set velocity target
+        self.set_mapped(typed::SET_TARGET_VELOCITY, *target)?;
 
-        self.send_rpdo(RPDO_TARGET_VEL).await?;
+        self.send_rpdos_with(&[&od::SET_TARGET_VELOCITY]).await?;
 
         Ok(())
     }
@@ -187,20 +287,17 @@ impl Pdo {
             target_torque: target,
         }: &TorqueSetpoint,
     ) -> Result<(), DriveError> {
+        self.check_limits(typed::SET_TARGET_TORQUE, *target)?;
+
         // Set Torque Mode
-        self.set_operational_mode(OperationMode::ProfileTorque);
+        self.set_operational_mode(OperationMode::ProfileTorque)?;
 
-        self.send_rpdo(RPDO_CONTROL_OPMODE).await?;
+        self.send_rpdos_with(&[&od::SET_OPERATION_MODE]).await?;
 
-        // Set position and torque target
-        self.rpdo_frames[RPDO_IDX_TARGET_TORQUE]
-            // TODO: hardcoded offset
-            .set(
-                (RPDO_TARGET_TORQUE.sources[0].bit_range.start / 8) as usize,
-                &target.to_le_bytes(),
-            );
+        // Set torque target
+        self.set_mapped(typed::SET_TARGET_TORQUE, *target)?;
 
-        self.send_rpdo(RPDO_TARGET_TORQUE).await?;
+        self.send_rpdos_with(&[&od::SET_TARGET_TORQUE]).await?;
 
         Ok(())
     }
@@ -211,34 +308,32 @@ impl Pdo {
     ) -> Result<(), DriveError> {
         trace!("Writing homing setpoint with flags {flags:?}");
 
-        // 1. Construct RPDO1: Set opmode to homing and toggle control_word Homing bits
+        // 1. Set opmode to homing and toggle control_word Homing bits
         // 1.A Set Position Mode
-        self.set_operational_mode(OperationMode::Homing);
+        self.set_operational_mode(OperationMode::Homing)?;
 
-        trace!("Set Operation Mode Homing in RPDO1");
+        trace!("Set Operation Mode Homing");
 
         // 1.B Set controlword homing bits
         let mut cw = self.get_current_controlword();
         trace!("Fetched current controlword: {cw:?}");
 
         cw = cw.with_home_flags(flags);
-        self.set_controlword_rpdo(cw);
+        self.set_controlword_rpdo(cw)?;
 
-        trace!("Added homing flags to controlword: {cw:?} - sending RPDO1");
+        trace!("Added homing flags to controlword: {cw:?} - sending it");
 
-        // Send RPDO1
-        self.send_rpdo(RPDO_CONTROL_OPMODE).await?;
+        self.send_rpdos_with(&[&od::CONTROL_WORD, &od::SET_OPERATION_MODE])
+            .await?;
 
-        trace!("RPDO1 sent succesfully to effect homing setpoint");
+        trace!("Controlword sent succesfully to effect homing setpoint");
 
         Ok(())
     }
 
     // Check if these rpdo mappings contain a controlword
     // TODO: move this into type system
-    fn check_required_rpdo_mappings(
-        rpdo_mapping_set: &'static [PdoMapping],
-    ) -> Result<(), DriveError> {
+    fn check_required_rpdo_mappings(rpdo_mapping_set: &[&PdoMapping]) -> Result<(), DriveError> {
         if Self::check_if_mapped(rpdo_mapping_set, &od::CONTROL_WORD)
             && Self::check_if_mapped(rpdo_mapping_set, &od::SET_OPERATION_MODE)
         {
@@ -253,10 +348,10 @@ impl Pdo {
         }
     }
 
-    fn check_if_mapped(rpdo_mapping_set: &'static [PdoMapping], entry: &ODEntry) -> bool {
+    fn check_if_mapped(rpdo_mapping_set: &[&PdoMapping], entry: &ODEntry) -> bool {
         for rpdo in rpdo_mapping_set {
-            for source in rpdo.sources {
-                if source.entry == entry {
+            for source in rpdo.sources.iter() {
+                if *source.entry == *entry {
                     return true;
                 }
             }
@@ -264,107 +359,128 @@ impl Pdo {
         false
     }
 
-    async fn send_rpdo(&mut self, pdo_mapping: PdoMapping) -> Result<(), DriveError> {
-        let PdoType::RPDO(num) = pdo_mapping.pdo else {
-            return Err(DriveError::ViolatedInvariant(
-                "Attempting to send a TPDO".to_string(),
-            ));
-        };
+    /// Send every RPDO that has any of the given entries mapped, in mapping order
+    async fn send_rpdos_with(&mut self, entries: &[&ODEntry]) -> Result<(), DriveError> {
+        for idx in 0..self.rpdo_mapping_set.len() {
+            let mapping = &self.rpdo_mapping_set[idx];
+            if entries.iter().any(|entry| mapping.contains(entry)) {
+                self.send_rpdo(idx).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send the RPDO at the given index of the mapping set with its current data, or hold it back
+    /// until the next flush if it is synchronous and those are deferred
+    async fn send_rpdo(&mut self, idx: usize) -> Result<(), DriveError> {
+        let transmission_type = &self.rpdo_mapping_set[idx].communication.transmission_type;
+        if self.defer_synchronous && transmission_type.is_synchronous() {
+            trace!(
+                "deferring {} until the next SYNC",
+                self.rpdo_mapping_set[idx].pdo.to_string_pretty()
+            );
+            self.rpdo_pending[idx] = true;
+            return Ok(());
+        }
 
-        trace!("sending RPDO #{num} - getting cob_id");
+        self.transmit_rpdo(idx).await
+    }
 
-        let cob_id =
-            pdo_mapping
-                .pdo
-                .get_pdo_cob_id(self.node_id)
-                .ok_or(DriveError::ViolatedInvariant(
-                    "Asked for the cob_id for PDO number: {rpdo_num} > 4".to_string(),
-                ))?;
+    async fn transmit_rpdo(&mut self, idx: usize) -> Result<(), DriveError> {
+        let mapping = &self.rpdo_mapping_set[idx];
+        let pdo = &mapping.pdo;
+        let frame = &self.rpdo_frames[idx];
 
-        trace!(
-            "sending RPDO #{num} - cob_id: {cob_id:#0x} - updating rpdo_frames[{}]",
-            num - 1
-        );
+        let cob_id = mapping
+            .cob_id(self.node_id)
+            .ok_or(DriveError::ViolatedInvariant(format!(
+                "Asked for the cob_id of {pdo:?}"
+            )))?;
 
-        let idx = (num - 1) as usize;
         trace!(
-            "sending RPDO #{num} - Constructing TxPacket from data: {:?} - dlc {}",
-            self.rpdo_frames[idx].data, self.rpdo_frames[idx].dlc,
+            "sending {} - cob_id: {cob_id:#0x} - data: {:?} - dlc {}",
+            pdo.to_string_pretty(),
+            frame.data,
+            frame.dlc,
         );
 
-        let value = TxPacket::new(
-            cob_id,
-            &self.rpdo_frames[idx].data[..self.rpdo_frames[idx].dlc],
-        )
-        .map_err(DriveError::CANOpenError)?;
+        let value =
+            TxPacket::new(cob_id, &frame.data[..frame.dlc]).map_err(DriveError::CANOpenError)?;
 
-        trace!("sending RPDO #{num} - TxPacket: {value:?}");
+        trace!("sending {} - TxPacket: {value:?}", pdo.to_string_pretty());
 
         self.canopen
             .tx
             .send_timeout(value, Duration::from_millis(SEND_TIMOUT))
             .await
             .map_err(DriveError::CanOpenTimeout)?;
+        self.rpdo_sent[idx] = Some(Instant::now());
+        self.rpdo_pending[idx] = false;
 
         Ok(())
     }
 
     /// Gets current control word
     fn get_current_controlword(&self) -> ControlWord {
-        let PdoType::RPDO(num) = RPDO_CONTROL_OPMODE.pdo else {
-            panic!("Controlword is not mapped to RPDO");
-        };
-        let cw_idx = (num - 1) as usize;
-
-        let cw_bytes = [
-            self.rpdo_frames[cw_idx].data[0],
-            self.rpdo_frames[cw_idx].data[1],
-        ];
+        let cw = self
+            .get_mapped(typed::CONTROL_WORD)
+            .expect("unable to fetch current controlword from saved RPDOs");
 
-        ControlWord::from_bits(u16::from_le_bytes(cw_bytes)).expect(
-            "unable to fetch current controlword from saved RPDO1 in write_position_setpoint",
+        ControlWord::from_bits(cw).expect(
+            "unable to fetch current controlword from saved RPDOs in write_position_setpoint",
         )
     }
 
     /// Saves new controlword in the appropriate RPDO frame, to be sent later
-    fn set_controlword_rpdo(&mut self, cw: ControlWord) {
-        let PdoType::RPDO(num) = RPDO_CONTROL_OPMODE.pdo else {
-            panic!("Controlword is not mapped to RPDO");
-        };
-        let cw_idx = (num - 1) as usize;
-
-        let cw_bytes = cw.bits().to_le_bytes();
+    fn set_controlword_rpdo(&mut self, cw: ControlWord) -> Result<(), DriveError> {
+        info!("setting controlword rpdo to new cw: {cw:?}");
+        let before = self.get_current_controlword();
+        info!("Controlword before Set: {before:?}");
 
-        info!("setting controlword rpdo #{num} to new cw: {cw:?}");
-        let mut cw = self.get_current_controlword();
-        info!("Controlword before Set: {cw:?}");
+        self.set_mapped(typed::CONTROL_WORD, cw.bits())?;
 
-        self.rpdo_frames[cw_idx].set(
-            RPDO_CONTROL_OPMODE.sources[RPDO_IDX_CONTROL_WORD]
-                .bit_range
-                .start as usize,
-            &cw_bytes,
-        );
+        let after = self.get_current_controlword();
+        info!("Controlword after Set: {after:?}");
 
-        let mut cw = self.get_current_controlword();
-        info!("Controlword after Set: {cw:?}");
+        Ok(())
     }
 
-    fn set_operational_mode(&mut self, mode: OperationMode) {
+    fn set_operational_mode(&mut self, mode: OperationMode) -> Result<(), DriveError> {
         trace!("setting operational mode to {mode:?}");
 
-        const OPMODE_OFFSET: usize = 2;
+        self.set_mapped(typed::SET_OPERATION_MODE, mode as i8)?;
 
-        let PdoType::RPDO(num) = RPDO_CONTROL_OPMODE.pdo else {
-            panic!("OPMODE is not mapped to RPDO");
-        };
-        let idx = (num - 1) as usize;
+        trace!("Operational mode {mode:?} applied to rpdo_frames");
 
-        self.rpdo_frames[idx].set(OPMODE_OFFSET, &[mode as u8]);
+        Ok(())
+    }
 
-        trace!(
-            "Operational mode {mode:?} applied to rpdo_frame: {:?}",
-            self.rpdo_frames[idx]
-        );
+    /// Index of the RPDO the given entry is mapped in
+    fn locate_mapped(&self, entry: &ODEntry) -> Result<usize, DriveError> {
+        self.rpdo_mapping_set
+            .iter()
+            .position(|mapping| mapping.contains(entry))
+            .ok_or_else(|| {
+                DriveError::ViolatedInvariant(format!("{} is not mapped in any RPDO", entry.name))
+            })
+    }
+
+    /// Fails if the value lies outside of the limits of the entry, e.g. a profile velocity above
+    /// the max motor speed
+    fn check_limits<T: ODType>(&self, entry: TypedEntry<T>, value: T) -> Result<(), DriveError> {
+        self.known_values.check(entry.entry(), &value.into_value())
+    }
+
+    /// Encode the value of the given entry into the RPDO frame it is mapped in, to be sent later
+    fn set_mapped<T: ODType>(&mut self, entry: TypedEntry<T>, value: T) -> Result<(), DriveError> {
+        let idx = self.locate_mapped(entry.entry())?;
+        self.rpdo_mapping_set[idx].set(&mut self.rpdo_frames[idx], entry, value)
+    }
+
+    /// Decode the current value of the given entry from the RPDO frame it is mapped in
+    fn get_mapped<T: ODType>(&self, entry: TypedEntry<T>) -> Result<T, DriveError> {
+        let idx = self.locate_mapped(entry.entry())?;
+        self.rpdo_mapping_set[idx].get(&self.rpdo_frames[idx].data, entry)
     }
 }
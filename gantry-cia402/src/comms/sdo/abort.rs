@@ -0,0 +1,124 @@
+use std::fmt;
+
+macro_rules! abort_codes {
+    ($($(#[$meta:meta])* $variant:ident = $code:literal, $description:literal;)*) => {
+        /// SDO abort codes of CiA 301 table 22, codes outside of the table are vendor specific
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum SdoAbortCode {
+            $($(#[$meta])* $variant,)*
+            Vendor(u32),
+        }
+
+        impl SdoAbortCode {
+            pub const fn from_code(code: u32) -> Self {
+                match code {
+                    $($code => SdoAbortCode::$variant,)*
+                    code => SdoAbortCode::Vendor(code),
+                }
+            }
+
+            pub const fn code(self) -> u32 {
+                match self {
+                    $(SdoAbortCode::$variant => $code,)*
+                    SdoAbortCode::Vendor(code) => code,
+                }
+            }
+
+            pub const fn description(self) -> &'static str {
+                match self {
+                    $(SdoAbortCode::$variant => $description,)*
+                    SdoAbortCode::Vendor(_) => "Vendor specific abort code",
+                }
+            }
+        }
+    };
+}
+
+abort_codes! {
+    ToggleBit = 0x0503_0000, "Toggle bit not alternated";
+    Timeout = 0x0504_0000, "SDO protocol timed out";
+    InvalidCommand = 0x0504_0001, "Client/server command specifier not valid or unknown";
+    InvalidBlockSize = 0x0504_0002, "Invalid block size";
+    InvalidSequence = 0x0504_0003, "Invalid sequence number";
+    Crc = 0x0504_0004, "CRC error";
+    OutOfMemory = 0x0504_0005, "Out of memory";
+    UnsupportedAccess = 0x0601_0000, "Unsupported access to an object";
+    WriteOnly = 0x0601_0001, "Attempt to read a write only object";
+    ReadOnly = 0x0601_0002, "Attempt to write a read only object";
+    NoObject = 0x0602_0000, "Object does not exist in the object dictionary";
+    NotMappable = 0x0604_0041, "Object cannot be mapped to the PDO";
+    PdoLength = 0x0604_0042, "The number and length of the objects to be mapped would exceed the PDO length";
+    ParameterIncompatibility = 0x0604_0043, "General parameter incompatibility";
+    InternalIncompatibility = 0x0604_0047, "General internal incompatibility in the device";
+    HardwareError = 0x0606_0000, "Access failed due to a hardware error";
+    LengthMismatch = 0x0607_0010, "Data type does not match, length of service parameter does not match";
+    LengthTooHigh = 0x0607_0012, "Data type does not match, length of service parameter too high";
+    LengthTooLow = 0x0607_0013, "Data type does not match, length of service parameter too low";
+    NoSubIndex = 0x0609_0011, "Sub-index does not exist";
+    InvalidValue = 0x0609_0030, "Invalid value for parameter";
+    ValueTooHigh = 0x0609_0031, "Value of parameter written too high";
+    ValueTooLow = 0x0609_0032, "Value of parameter written too low";
+    MaxBelowMin = 0x0609_0036, "Maximum value is less than minimum value";
+    ConnectionUnavailable = 0x060A_0023, "Resource not available: SDO connection";
+    General = 0x0800_0000, "General error";
+    NotStored = 0x0800_0020, "Data cannot be transferred or stored to the application";
+    LocalControl = 0x0800_0021, "Data cannot be transferred or stored to the application because of local control";
+    /// E.g. writing a PDO mapping while the drive is operational
+    DeviceState = 0x0800_0022, "Data cannot be transferred or stored to the application because of the present device state";
+    NoDictionary = 0x0800_0023, "Object dictionary dynamic generation failed or no object dictionary is present";
+    NoData = 0x0800_0024, "No data available";
+}
+
+impl SdoAbortCode {
+    /// Whether the same transfer may succeed when tried again, e.g. after a lost frame or once
+    /// the drive left the state that blocked it. Permanent aborts need a different request
+    pub const fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            SdoAbortCode::ToggleBit
+                | SdoAbortCode::Timeout
+                | SdoAbortCode::InvalidSequence
+                | SdoAbortCode::Crc
+                | SdoAbortCode::OutOfMemory
+                | SdoAbortCode::HardwareError
+                | SdoAbortCode::ConnectionUnavailable
+                | SdoAbortCode::LocalControl
+                | SdoAbortCode::DeviceState
+                | SdoAbortCode::NoData
+        )
+    }
+}
+
+impl From<u32> for SdoAbortCode {
+    fn from(code: u32) -> Self {
+        Self::from_code(code)
+    }
+}
+
+/// Description followed by the raw code, e.g. `Sub-index does not exist (0x06090011)`
+impl fmt::Display for SdoAbortCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:#010x})", self.description(), self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_codes() {
+        let code = SdoAbortCode::from_code(0x0609_0011);
+        assert_eq!(code, SdoAbortCode::NoSubIndex);
+        assert_eq!(code.code(), 0x0609_0011);
+        assert_eq!(code.to_string(), "Sub-index does not exist (0x06090011)");
+        assert!(!code.is_retryable());
+
+        assert!(SdoAbortCode::from_code(0x0800_0022).is_retryable());
+
+        let vendor = SdoAbortCode::from_code(0x0F00_0001);
+        assert_eq!(vendor, SdoAbortCode::Vendor(0x0F00_0001));
+        assert_eq!(vendor.code(), 0x0F00_0001);
+        assert!(!vendor.is_retryable());
+    }
+}
@@ -0,0 +1,757 @@
+use std::time::Duration;
+
+use oze_canopen::{
+    canopen::RxMessage,
+    error::CoError,
+    interface::{CanOpenInterface, SEND_TIMOUT},
+    transmitter::TxPacket,
+};
+use thiserror::Error;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tracing::*;
+
+use crate::comms::sdo::{abort::SdoAbortCode, protocol::*};
+
+/// Time we wait for every response of the server
+pub const SDO_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum SdoTransferError {
+    #[error("SDO transfer of {index:#06x}:{sub_index} timed out")]
+    Timeout { index: u16, sub_index: u8 },
+    #[error("SDO transfer of {index:#06x}:{sub_index} aborted by the server: {code}")]
+    Aborted {
+        index: u16,
+        sub_index: u8,
+        code: SdoAbortCode,
+    },
+    #[error("SDO transfer of {index:#06x}:{sub_index} failed, {reason}: aborted with {code}")]
+    Protocol {
+        index: u16,
+        sub_index: u8,
+        code: SdoAbortCode,
+        reason: &'static str,
+    },
+    #[error("SDO transfer of {index:#06x}:{sub_index} missed its deadline")]
+    Deadline { index: u16, sub_index: u8 },
+    #[error("Unable to send SDO frame: {0:?}")]
+    Send(CoError),
+    #[error("Timeout sending SDO frame")]
+    SendTimeout,
+    #[error("CANopen receiver closed")]
+    Closed,
+}
+
+impl SdoTransferError {
+    /// Abort code of the transfer, whether aborted by the server or by us
+    pub fn abort_code(&self) -> Option<SdoAbortCode> {
+        match self {
+            SdoTransferError::Timeout { .. } => Some(SdoAbortCode::Timeout),
+            SdoTransferError::Aborted { code, .. } | SdoTransferError::Protocol { code, .. } => {
+                Some(*code)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether trying the same transfer again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SdoTransferError::Send(_) | SdoTransferError::SendTimeout => true,
+            SdoTransferError::Closed => false,
+            _ => self.abort_code().is_some_and(SdoAbortCode::is_retryable),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SdoClientConfig {
+    pub timeout: Duration,
+    /// Downloads of at least this many bytes use a block transfer, None always uses a segmented
+    /// transfer for data that does not fit an expedited one
+    pub block_download_threshold: Option<usize>,
+    /// Segments per sub-block we accept in block uploads, 1 to 127
+    pub block_size: u8,
+}
+
+impl Default for SdoClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: SDO_TIMEOUT,
+            block_download_threshold: None,
+            block_size: MAX_BLOCK_SIZE,
+        }
+    }
+}
+
+/// SDO client of a single server (node), doing expedited, segmented and block transfers over the
+/// default SDO channel. Only one transfer runs at a time, share it behind a mutex
+#[derive(Debug)]
+pub struct SdoClient {
+    node_id: u8,
+    tx: mpsc::Sender<TxPacket>,
+    rx: broadcast::Receiver<RxMessage>,
+    config: SdoClientConfig,
+}
+
+impl SdoClient {
+    pub fn new(canopen: &CanOpenInterface, node_id: u8) -> Self {
+        Self::from_channels(canopen.tx.clone(), canopen.rx.resubscribe(), node_id)
+    }
+
+    pub fn from_channels(
+        tx: mpsc::Sender<TxPacket>,
+        rx: broadcast::Receiver<RxMessage>,
+        node_id: u8,
+    ) -> Self {
+        Self {
+            node_id,
+            tx,
+            rx,
+            config: SdoClientConfig::default(),
+        }
+    }
+
+    pub fn with_config(self, config: SdoClientConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    /// Upload (read) an object, the server decides between an expedited and a segmented transfer
+    pub async fn upload(&mut self, index: u16, sub_index: u8) -> Result<Vec<u8>, SdoTransferError> {
+        let mut transfer = Transfer::start(self, index, sub_index);
+        transfer
+            .send(ClientFrame::InitiateUpload { index, sub_index })
+            .await?;
+
+        let size = match transfer.receive().await? {
+            ServerFrame::InitiateUpload {
+                index: response_index,
+                sub_index: response_sub_index,
+                data,
+            } if (response_index, response_sub_index) == (index, sub_index) => match data {
+                Initiate::Expedited { data, size } => return Ok(data[..size.unwrap_or(4)].to_vec()),
+                Initiate::Segmented { size } => size,
+            },
+            _ => {
+                return Err(transfer
+                    .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                    .await);
+            }
+        };
+
+        let mut data = Vec::with_capacity(size.unwrap_or(0) as usize);
+        let mut toggle = false;
+        loop {
+            transfer.send(ClientFrame::UploadSegment { toggle }).await?;
+            let segment = match transfer.receive().await? {
+                ServerFrame::UploadSegment(segment) => segment,
+                _ => {
+                    return Err(transfer
+                        .fail(SdoAbortCode::InvalidCommand, "expected a segment")
+                        .await);
+                }
+            };
+            if segment.toggle != toggle {
+                return Err(transfer.fail(SdoAbortCode::ToggleBit, "toggle bit").await);
+            }
+
+            data.extend_from_slice(segment.data());
+            if segment.last {
+                break;
+            }
+            toggle = !toggle;
+        }
+
+        transfer.check_size(size, data.len())?;
+        Ok(data)
+    }
+
+    /// Upload (read) an object using a block transfer, for large objects like domains
+    pub async fn upload_block(
+        &mut self,
+        index: u16,
+        sub_index: u8,
+    ) -> Result<Vec<u8>, SdoTransferError> {
+        let block_size = self.config.block_size.clamp(1, MAX_BLOCK_SIZE);
+        let mut transfer = Transfer::start(self, index, sub_index);
+        transfer
+            .send(ClientFrame::InitiateBlockUpload {
+                index,
+                sub_index,
+                crc: true,
+                block_size,
+                pst: 0,
+            })
+            .await?;
+
+        let (crc, size) = match transfer.receive().await? {
+            ServerFrame::InitiateBlockUpload { crc, size, .. } => (crc, size),
+            _ => {
+                return Err(transfer
+                    .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                    .await);
+            }
+        };
+        transfer.send(ClientFrame::StartBlockUpload).await?;
+
+        let mut data = Vec::with_capacity(size.unwrap_or(0) as usize);
+        let mut sub_block = SubBlock::default();
+        loop {
+            let frame = transfer.receive_raw().await?;
+            let segment = match BlockSegment::decode(&frame) {
+                Ok(segment) => segment,
+                Err(_) => {
+                    return Err(transfer
+                        .fail(SdoAbortCode::InvalidSequence, "sequence number")
+                        .await);
+                }
+            };
+            let end_of_sub_block = segment.last || segment.seqno >= block_size;
+            sub_block.push(segment);
+            if !end_of_sub_block {
+                continue;
+            }
+
+            // Segments after a lost one are dropped, the server repeats them in the next sub-block
+            let ackseq = sub_block.ackseq();
+            transfer
+                .send(ClientFrame::BlockUploadAck { ackseq, block_size })
+                .await?;
+            if sub_block.acknowledge(ackseq, &mut data) {
+                break;
+            }
+        }
+
+        match transfer.receive().await? {
+            ServerFrame::EndBlockUpload { unused, crc: check } => {
+                data.truncate(data.len().saturating_sub(unused.into()));
+                if crc && crc16(&data) != check {
+                    return Err(transfer.fail(SdoAbortCode::Crc, "CRC mismatch").await);
+                }
+            }
+            _ => {
+                return Err(transfer
+                    .fail(
+                        SdoAbortCode::InvalidCommand,
+                        "expected the end of the block",
+                    )
+                    .await);
+            }
+        }
+        transfer.send(ClientFrame::EndBlockUpload).await?;
+
+        transfer.check_size(size, data.len())?;
+        Ok(data)
+    }
+
+    /// Download (write) an object: expedited if it fits in a single frame, otherwise segmented
+    /// or as a block, see [`SdoClientConfig::block_download_threshold`]
+    pub async fn download(
+        &mut self,
+        index: u16,
+        sub_index: u8,
+        data: &[u8],
+    ) -> Result<(), SdoTransferError> {
+        let mut transfer = Transfer::start(self, index, sub_index);
+
+        if data.len() <= 4 {
+            transfer
+                .send(ClientFrame::InitiateDownload {
+                    index,
+                    sub_index,
+                    data: Initiate::expedited(data),
+                })
+                .await?;
+            return transfer.expect_initiate_download().await;
+        }
+
+        if transfer
+            .client
+            .config
+            .block_download_threshold
+            .is_some_and(|threshold| data.len() >= threshold)
+        {
+            return transfer.download_block(data).await;
+        }
+
+        transfer
+            .send(ClientFrame::InitiateDownload {
+                index,
+                sub_index,
+                data: Initiate::Segmented {
+                    size: Some(data.len() as u32),
+                },
+            })
+            .await?;
+        transfer.expect_initiate_download().await?;
+
+        let mut toggle = false;
+        let mut segments = data.chunks(SEGMENT_SIZE).peekable();
+        while let Some(chunk) = segments.next() {
+            let last = segments.peek().is_none();
+            transfer
+                .send(ClientFrame::DownloadSegment(Segment::new(
+                    toggle, last, chunk,
+                )))
+                .await?;
+            match transfer.receive().await? {
+                ServerFrame::DownloadSegment { toggle: confirmed } if confirmed == toggle => {}
+                ServerFrame::DownloadSegment { .. } => {
+                    return Err(transfer.fail(SdoAbortCode::ToggleBit, "toggle bit").await);
+                }
+                _ => {
+                    return Err(transfer
+                        .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                        .await);
+                }
+            }
+            toggle = !toggle;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single transfer of an object, aborts it on errors
+struct Transfer<'a> {
+    client: &'a mut SdoClient,
+    index: u16,
+    sub_index: u8,
+}
+
+impl<'a> Transfer<'a> {
+    fn start(client: &'a mut SdoClient, index: u16, sub_index: u8) -> Self {
+        // Drop whatever the server sent before this transfer
+        client.rx = client.rx.resubscribe();
+
+        Self {
+            client,
+            index,
+            sub_index,
+        }
+    }
+
+    async fn send_raw(&self, frame: [u8; 8]) -> Result<(), SdoTransferError> {
+        let packet = TxPacket::new(RSDO_BASE + u16::from(self.client.node_id), &frame)
+            .map_err(SdoTransferError::Send)?;
+
+        self.client
+            .tx
+            .send_timeout(packet, Duration::from_millis(SEND_TIMOUT))
+            .await
+            .map_err(|_| SdoTransferError::SendTimeout)
+    }
+
+    async fn send(&self, frame: ClientFrame) -> Result<(), SdoTransferError> {
+        trace!("SDO node {} <= {frame:?}", self.client.node_id);
+        self.send_raw(frame.encode()).await
+    }
+
+    /// Next frame of our server, aborts the transfer if it does not respond in time
+    async fn receive_raw(&mut self) -> Result<[u8; 8], SdoTransferError> {
+        let cob_id = TSDO_BASE + u16::from(self.client.node_id);
+        let receive = async {
+            loop {
+                match self.client.rx.recv().await {
+                    Ok(message) if message.cob_id == cob_id => return Ok(message.data),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("SDO client lagged behind, skipped {skipped} frames");
+                    }
+                    Err(RecvError::Closed) => return Err(SdoTransferError::Closed),
+                }
+            }
+        };
+
+        match tokio::time::timeout(self.client.config.timeout, receive).await {
+            Ok(received) => received,
+            Err(_) => {
+                self.abort(SdoAbortCode::Timeout).await;
+                Err(SdoTransferError::Timeout {
+                    index: self.index,
+                    sub_index: self.sub_index,
+                })
+            }
+        }
+    }
+
+    /// Next command of our server, an abort by the server ends the transfer
+    async fn receive(&mut self) -> Result<ServerFrame, SdoTransferError> {
+        let frame = self.receive_raw().await?;
+        match ServerFrame::decode(&frame) {
+            Ok(ServerFrame::Abort { code, .. }) => Err(SdoTransferError::Aborted {
+                index: self.index,
+                sub_index: self.sub_index,
+                code,
+            }),
+            Ok(frame) => {
+                trace!("SDO node {} => {frame:?}", self.client.node_id);
+                Ok(frame)
+            }
+            Err(_) => Err(self
+                .fail(SdoAbortCode::InvalidCommand, "unknown command")
+                .await),
+        }
+    }
+
+    async fn abort(&self, code: SdoAbortCode) {
+        let abort = ClientFrame::Abort {
+            index: self.index,
+            sub_index: self.sub_index,
+            code,
+        };
+        if let Err(err) = self.send(abort).await {
+            error!("Unable to abort SDO transfer: {err}");
+        }
+    }
+
+    /// Abort the transfer because of a protocol error
+    async fn fail(&self, code: SdoAbortCode, reason: &'static str) -> SdoTransferError {
+        self.abort(code).await;
+        SdoTransferError::Protocol {
+            index: self.index,
+            sub_index: self.sub_index,
+            code,
+            reason,
+        }
+    }
+
+    fn check_size(&self, size: Option<u32>, received: usize) -> Result<(), SdoTransferError> {
+        match size {
+            Some(size) if size as usize != received => Err(SdoTransferError::Protocol {
+                index: self.index,
+                sub_index: self.sub_index,
+                code: SdoAbortCode::LengthMismatch,
+                reason: "size does not match the indicated size",
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    async fn expect_initiate_download(&mut self) -> Result<(), SdoTransferError> {
+        match self.receive().await? {
+            ServerFrame::InitiateDownload { index, sub_index }
+                if (index, sub_index) == (self.index, self.sub_index) =>
+            {
+                Ok(())
+            }
+            _ => Err(self
+                .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                .await),
+        }
+    }
+
+    async fn download_block(&mut self, data: &[u8]) -> Result<(), SdoTransferError> {
+        self.send(ClientFrame::InitiateBlockDownload {
+            index: self.index,
+            sub_index: self.sub_index,
+            crc: true,
+            size: Some(data.len() as u32),
+        })
+        .await?;
+        let (crc, mut block_size) = match self.receive().await? {
+            ServerFrame::InitiateBlockDownload {
+                crc, block_size, ..
+            } => (crc, block_size),
+            _ => {
+                return Err(self
+                    .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                    .await);
+            }
+        };
+
+        let (segments, unused) = block_segments(data);
+        let mut acknowledged = 0;
+        while acknowledged < segments.len() {
+            if !(1..=MAX_BLOCK_SIZE).contains(&block_size) {
+                return Err(self
+                    .fail(SdoAbortCode::InvalidBlockSize, "block size")
+                    .await);
+            }
+
+            let sub_block = &segments[acknowledged..];
+            let sent = sub_block.len().min(block_size.into());
+            for (seqno, segment) in (1..).zip(&sub_block[..sent]) {
+                let segment = BlockSegment {
+                    seqno,
+                    last: acknowledged + usize::from(seqno) == segments.len(),
+                    data: *segment,
+                };
+                self.send_raw(segment.encode()).await?;
+            }
+
+            match self.receive().await? {
+                ServerFrame::BlockDownloadAck {
+                    ackseq,
+                    block_size: next,
+                } if usize::from(ackseq) <= sent => {
+                    // Unacknowledged segments are repeated in the next sub-block
+                    acknowledged += usize::from(ackseq);
+                    block_size = next;
+                }
+                ServerFrame::BlockDownloadAck { .. } => {
+                    return Err(self
+                        .fail(SdoAbortCode::InvalidSequence, "sequence number")
+                        .await);
+                }
+                _ => {
+                    return Err(self
+                        .fail(SdoAbortCode::InvalidCommand, "expected an acknowledge")
+                        .await);
+                }
+            }
+        }
+
+        self.send(ClientFrame::EndBlockDownload {
+            unused,
+            crc: if crc { crc16(data) } else { 0 },
+        })
+        .await?;
+        match self.receive().await? {
+            ServerFrame::EndBlockDownload => Ok(()),
+            _ => Err(self
+                .fail(SdoAbortCode::InvalidCommand, "unexpected response")
+                .await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::sdo::mock::client_with_server;
+
+    #[tokio::test]
+    async fn test_segmented_upload() {
+        let name = b"PD4-C6018L4204-E-08";
+        let mut segments = name
+            .chunks(SEGMENT_SIZE)
+            .enumerate()
+            .collect::<Vec<_>>()
+            .into_iter();
+        let count = name.len().div_ceil(SEGMENT_SIZE);
+
+        let (mut client, _server) = client_with_server(move |request, _| match request {
+            ClientFrame::InitiateUpload { index, sub_index } => vec![
+                ServerFrame::InitiateUpload {
+                    index,
+                    sub_index,
+                    data: Initiate::Segmented {
+                        size: Some(name.len() as u32),
+                    },
+                }
+                .encode(),
+            ],
+            ClientFrame::UploadSegment { toggle } => {
+                let (i, chunk) = segments.next().unwrap();
+                vec![
+                    ServerFrame::UploadSegment(Segment::new(toggle, i + 1 == count, chunk))
+                        .encode(),
+                ]
+            }
+            _ => vec![],
+        });
+
+        assert_eq!(client.upload(0x1008, 0).await.unwrap(), name);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_error_aborts() {
+        let (mut client, server) = client_with_server(|request, _| match request {
+            ClientFrame::InitiateUpload { index, sub_index } => vec![
+                ServerFrame::InitiateUpload {
+                    index,
+                    sub_index,
+                    data: Initiate::Segmented { size: None },
+                }
+                .encode(),
+            ],
+            // Never toggles
+            ClientFrame::UploadSegment { .. } => {
+                vec![ServerFrame::UploadSegment(Segment::new(false, false, b"abc")).encode()]
+            }
+            _ => vec![],
+        });
+
+        assert!(matches!(
+            client.upload(0x100A, 0).await,
+            Err(SdoTransferError::Protocol {
+                code: SdoAbortCode::ToggleBit,
+                ..
+            })
+        ));
+        drop(client);
+        let sent = server.await.unwrap();
+        assert_eq!(
+            ClientFrame::decode(sent.last().unwrap()),
+            Ok(ClientFrame::Abort {
+                index: 0x100A,
+                sub_index: 0,
+                code: SdoAbortCode::ToggleBit
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_segmented_download() {
+        let (mut client, server) = client_with_server(|request, _| match request {
+            ClientFrame::InitiateDownload {
+                index, sub_index, ..
+            } => {
+                vec![ServerFrame::InitiateDownload { index, sub_index }.encode()]
+            }
+            ClientFrame::DownloadSegment(segment) => vec![
+                ServerFrame::DownloadSegment {
+                    toggle: segment.toggle,
+                }
+                .encode(),
+            ],
+            _ => vec![],
+        });
+
+        let data = [0xAB; 10];
+        client.download(0x2000, 1, &data).await.unwrap();
+        drop(client);
+
+        let sent = server.await.unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(
+            ClientFrame::decode(&sent[2]),
+            Ok(ClientFrame::DownloadSegment(Segment::new(
+                true, true, &[0xAB; 3]
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_download_with_lost_segment() {
+        let data: Vec<u8> = (0..40).collect();
+        let mut received = Vec::new();
+        let mut sub_block = SubBlock::default();
+        let mut lost_one = false;
+        let expected = data.clone();
+
+        let (mut client, _server) = client_with_server(move |request, frame| {
+            if let ClientFrame::InitiateBlockDownload {
+                index, sub_index, ..
+            } = request
+            {
+                return vec![
+                    ServerFrame::InitiateBlockDownload {
+                        index,
+                        sub_index,
+                        crc: true,
+                        block_size: 4,
+                    }
+                    .encode(),
+                ];
+            }
+            // Segments of a sub-block of 4 never decode as the end of the block
+            if let ClientFrame::EndBlockDownload { unused, crc } = request {
+                received.truncate(received.len() - usize::from(unused));
+                assert_eq!(received, expected);
+                assert_eq!(crc, crc16(&received));
+                return vec![ServerFrame::EndBlockDownload.encode()];
+            }
+
+            let segment = BlockSegment::decode(&frame).unwrap();
+            // Drop the second segment of the first sub-block
+            if segment.seqno == 2 && !lost_one {
+                lost_one = true;
+            } else {
+                sub_block.push(segment.clone());
+            }
+            if segment.seqno == 4 || segment.last {
+                let ackseq = sub_block.ackseq();
+                sub_block.acknowledge(ackseq, &mut received);
+                return vec![
+                    ServerFrame::BlockDownloadAck {
+                        ackseq,
+                        block_size: 4,
+                    }
+                    .encode(),
+                ];
+            }
+            vec![]
+        });
+        client.config.block_download_threshold = Some(8);
+
+        client.download(0x1F50, 1, &data).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_block_upload() {
+        let data: Vec<u8> = (0..20).collect();
+        let (segments, unused) = block_segments(&data);
+        let crc = crc16(&data);
+
+        let (mut client, _server) = client_with_server(move |request, _| match request {
+            ClientFrame::InitiateBlockUpload {
+                index, sub_index, ..
+            } => vec![
+                ServerFrame::InitiateBlockUpload {
+                    index,
+                    sub_index,
+                    crc: true,
+                    size: Some(20),
+                }
+                .encode(),
+            ],
+            ClientFrame::StartBlockUpload => (1..)
+                .zip(&segments)
+                .map(|(seqno, segment)| {
+                    BlockSegment {
+                        seqno,
+                        last: usize::from(seqno) == segments.len(),
+                        data: *segment,
+                    }
+                    .encode()
+                })
+                .collect(),
+            ClientFrame::BlockUploadAck { ackseq, .. } => {
+                assert_eq!(ackseq, 3);
+                vec![ServerFrame::EndBlockUpload { unused, crc }.encode()]
+            }
+            _ => vec![],
+        });
+
+        assert_eq!(client.upload_block(0x100A, 0).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_abort() {
+        let (mut client, _server) = client_with_server(|request, _| match request {
+            ClientFrame::InitiateDownload {
+                index, sub_index, ..
+            } => vec![
+                ServerFrame::Abort {
+                    index,
+                    sub_index,
+                    code: SdoAbortCode::ReadOnly,
+                }
+                .encode(),
+            ],
+            _ => vec![],
+        });
+
+        assert!(matches!(
+            client.download(0x1000, 0, &[1, 2, 3, 4]).await,
+            Err(SdoTransferError::Aborted {
+                index: 0x1000,
+                code: SdoAbortCode::ReadOnly,
+                ..
+            })
+        ));
+        assert!(matches!(
+            client.upload(0x1000, 0).await,
+            Err(SdoTransferError::Timeout { index: 0x1000, .. })
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+//! Simulated SDO servers for unit tests
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use oze_canopen::{canopen::RxMessage, transmitter::TxPacket};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::comms::sdo::{
+    abort::SdoAbortCode,
+    client::{SdoClient, SdoClientConfig},
+    protocol::{ClientFrame, Initiate, ServerFrame, TSDO_BASE},
+    scheduler::{SdoHandle, SdoScheduler, TransferOptions},
+};
+
+pub const NODE_ID: u8 = 3;
+
+/// Client connected to a server that answers every request with the frames `respond` returns.
+/// The server task returns every frame it received once the client is dropped
+pub fn client_with_server(
+    mut respond: impl FnMut(ClientFrame, [u8; 8]) -> Vec<[u8; 8]> + Send + 'static,
+) -> (SdoClient, JoinHandle<Vec<[u8; 8]>>) {
+    let (tx, mut requests) = mpsc::channel::<TxPacket>(256);
+    let (responses, rx) = broadcast::channel(256);
+
+    let server = tokio::spawn(async move {
+        let mut received = Vec::new();
+        while let Some(request) = requests.recv().await {
+            let frame: [u8; 8] = request.data.as_slice().try_into().unwrap();
+            received.push(frame);
+            let decoded = ClientFrame::decode(&frame).unwrap_or(ClientFrame::StartBlockUpload);
+            for data in respond(decoded, frame) {
+                let message = RxMessage {
+                    timestamp: Instant::now(),
+                    cob_id: TSDO_BASE + u16::from(NODE_ID),
+                    data,
+                    dlc: 8,
+                };
+                responses.send(message).unwrap();
+            }
+        }
+        received
+    });
+
+    let client = SdoClient::from_channels(tx, rx, NODE_ID).with_config(SdoClientConfig {
+        timeout: Duration::from_millis(100),
+        ..SdoClientConfig::default()
+    });
+    (client, server)
+}
+
+/// Device serving expedited transfers of its objects
+#[derive(Debug, Default)]
+pub struct MockDevice {
+    pub objects: HashMap<(u16, u8), Vec<u8>>,
+    /// Remaining number of requests for an object to abort, and the code to abort them with
+    pub aborts: HashMap<(u16, u8), (SdoAbortCode, usize)>,
+    /// Downloads to these objects are confirmed, but do not change the value
+    pub ignored: HashSet<(u16, u8)>,
+    /// Every confirmed download, in order
+    pub downloads: Vec<(u16, u8)>,
+    /// Expedited uploads do not indicate their size, the data is padded to 4 bytes
+    pub unsized_uploads: bool,
+}
+
+impl MockDevice {
+    pub fn abort(&mut self, index: u16, sub_index: u8, code: SdoAbortCode, times: usize) {
+        self.aborts.insert((index, sub_index), (code, times));
+    }
+
+    fn respond(&mut self, request: ClientFrame) -> Vec<[u8; 8]> {
+        let (index, sub_index) = match request {
+            ClientFrame::InitiateDownload {
+                index, sub_index, ..
+            }
+            | ClientFrame::InitiateUpload { index, sub_index } => (index, sub_index),
+            _ => return vec![],
+        };
+
+        if let Some((code, remaining)) = self.aborts.get_mut(&(index, sub_index))
+            && *remaining > 0
+        {
+            *remaining -= 1;
+            let code = *code;
+            return vec![
+                ServerFrame::Abort {
+                    index,
+                    sub_index,
+                    code,
+                }
+                .encode(),
+            ];
+        }
+
+        let response = match request {
+            ClientFrame::InitiateDownload {
+                data: Initiate::Expedited { data, size },
+                ..
+            } => {
+                self.downloads.push((index, sub_index));
+                if !self.ignored.contains(&(index, sub_index)) {
+                    let size = size.unwrap_or(data.len());
+                    self.objects
+                        .insert((index, sub_index), data[..size].to_vec());
+                }
+                ServerFrame::InitiateDownload { index, sub_index }
+            }
+            _ => match self.objects.get(&(index, sub_index)) {
+                Some(value) if value.len() <= 4 => ServerFrame::InitiateUpload {
+                    index,
+                    sub_index,
+                    data: match (Initiate::expedited(value), self.unsized_uploads) {
+                        (Initiate::Expedited { data, .. }, true) => {
+                            Initiate::Expedited { data, size: None }
+                        }
+                        (data, _) => data,
+                    },
+                },
+                _ => ServerFrame::Abort {
+                    index,
+                    sub_index,
+                    code: SdoAbortCode::NoObject,
+                },
+            },
+        };
+        vec![response.encode()]
+    }
+}
+
+/// Handle to `device` through a scheduler, the device stays inspectable through the returned
+/// handle. The scheduler does not retry, so tests see every abort
+pub fn client_with_device(device: MockDevice) -> (SdoHandle, Arc<std::sync::Mutex<MockDevice>>) {
+    let device = Arc::new(std::sync::Mutex::new(device));
+    let server_device = device.clone();
+    let (scheduler, _) = SdoScheduler::with_clients(move |_| {
+        let device = server_device.clone();
+        client_with_server(move |request, _| device.lock().unwrap().respond(request)).0
+    });
+    let options = TransferOptions {
+        retries: 0,
+        ..TransferOptions::default()
+    };
+    (scheduler.handle(NODE_ID).with_options(options), device)
+}
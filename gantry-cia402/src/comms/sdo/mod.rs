@@ -0,0 +1,175 @@
+pub mod abort;
+pub mod client;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod protocol;
+pub mod scheduler;
+
+use std::time::Duration;
+
+use crate::{
+    comms::sdo::{abort::SdoAbortCode, scheduler::SdoHandle},
+    error::DriveError,
+    od::{dictionary::ObjectDictionary, entry::ODEntry, meta::KnownValues, value::ODValue},
+};
+
+pub const SDO_PROCESS_DURATION: Duration = Duration::from_millis(0); // Typical SDO round trip at 1mbit/s ~= 4ms, + engineering factor :)
+
+/// One CANopen SDO parameter write (or read).
+/// Prefer constructing these through [`crate::od::typed::TypedEntry`], which guarantees the value
+/// matches the type of the entry
+#[derive(Debug, Clone)]
+pub enum SdoAction {
+    /// Send value to device
+    Download {
+        entry: &'static ODEntry,
+        value: ODValue,
+    },
+    /// Fetch data from device
+    Upload { entry: &'static ODEntry },
+}
+
+#[derive(Debug)]
+pub enum SdoResult {
+    Error(SdoAbortCode),
+    None,
+    Data(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct SdoTransaction<'a> {
+    action: &'a SdoAction,
+    result: SdoResult,
+}
+
+impl SdoTransaction<'_> {
+    pub fn action(&self) -> &SdoAction {
+        self.action
+    }
+
+    pub fn result(&self) -> &SdoResult {
+        &self.result
+    }
+}
+
+impl SdoAction {
+    /// Check this action against the access type, data type and limits of its entry, so
+    /// requests the device would abort never reach the bus
+    pub fn validate(&self) -> Result<(), DriveError> {
+        match self {
+            SdoAction::Download { entry, value } => entry.check_download(value),
+            SdoAction::Upload { entry } => entry.check_readable(),
+        }
+    }
+
+    pub async fn run_on_sdo_client(
+        &self,
+        sdo: SdoHandle,
+    ) -> Result<SdoTransaction<'_>, DriveError> {
+        self.validate()?;
+
+        let result = match self {
+            SdoAction::Download { entry, value } => {
+                sdo.download(entry.index, entry.sub_index, &value.encode()?)
+                    .await?;
+                SdoResult::None
+            }
+            SdoAction::Upload { entry } => {
+                let data = sdo.upload(entry.index, entry.sub_index).await?;
+                SdoResult::Data(data)
+            }
+        };
+
+        Ok(SdoTransaction {
+            action: self,
+            result,
+        })
+    }
+}
+
+/// Validate a parametrisation as a whole, limits set by other objects are checked against the
+/// value the device holds at that point: its default, or the value of an earlier download.
+/// Returns the values the device holds once all actions ran
+pub fn validate_parameters(
+    dictionary: &ObjectDictionary,
+    parameters: &[SdoAction],
+) -> Result<KnownValues, DriveError> {
+    let mut known = KnownValues::from_dictionary(dictionary);
+
+    for action in parameters {
+        action.validate()?;
+        if let SdoAction::Download { entry, value } = action {
+            known.check(entry, value)?;
+            known.insert(entry, value.clone());
+        }
+    }
+
+    Ok(known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        driver::startup::params::PARAMS,
+        od::{self, typed},
+    };
+
+    #[test]
+    fn test_validate_access() {
+        // Statusword is read only
+        assert!(matches!(
+            typed::STATUS_WORD.write(0).validate(),
+            Err(DriveError::NotWritable { index: 0x6041, .. })
+        ));
+        assert!(typed::STATUS_WORD.read().validate().is_ok());
+
+        const MISMATCH: SdoAction = SdoAction::Download {
+            entry: &od::CONTROL_WORD,
+            value: ODValue::U32(0),
+        };
+        assert!(matches!(
+            MISMATCH.validate(),
+            Err(DriveError::ValueTypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_params_are_valid() {
+        for action in PARAMS {
+            assert!(action.validate().is_ok(), "{action:?}");
+        }
+
+        let known = validate_parameters(&ObjectDictionary::builtin(), PARAMS).unwrap();
+        assert_eq!(
+            known.get(od::MAX_MOTOR_SPEED.idx()),
+            Some(&ODValue::U32(2000))
+        );
+    }
+
+    #[test]
+    fn test_validate_limits() {
+        // Motion profile type is either trapezoidal (0) or sinusoidal (1)
+        assert!(matches!(
+            typed::MOTION_PROFILE_TYPE.write(2).validate(),
+            Err(DriveError::OutOfRange { index: 0x6086, .. })
+        ));
+
+        let dictionary = ObjectDictionary::builtin();
+        let too_fast = [
+            typed::MAX_MOTOR_SPEED.write(2000),
+            typed::PROFILE_VELOCITY.write(3000),
+        ];
+        assert!(matches!(
+            validate_parameters(&dictionary, &too_fast),
+            Err(DriveError::OutOfRange { index: 0x6081, .. })
+        ));
+
+        // Checked against the max motor speed at the time of the download, the default here
+        let lowered_later = [
+            typed::PROFILE_VELOCITY.write(3000),
+            typed::MAX_MOTOR_SPEED.write(2000),
+        ];
+        assert!(validate_parameters(&dictionary, &lowered_later).is_ok());
+    }
+}
@@ -0,0 +1,708 @@
+//! SDO frames of CiA 301 section 7.2.4.3: expedited, segmented and block transfers.
+//!
+//! The command specifiers of both directions overlap, so client (RSDO) and server (TSDO) frames
+//! are decoded separately. Block segments carry no command specifier at all, whether a frame is
+//! one depends on the state of the transfer, see [`SubBlock`].
+
+use thiserror::Error;
+
+use crate::comms::sdo::abort::SdoAbortCode;
+
+/// COB-ID of the SDO server → client channel, + node id
+pub const TSDO_BASE: u16 = 0x580;
+/// COB-ID of the SDO client → server channel, + node id
+pub const RSDO_BASE: u16 = 0x600;
+
+/// Payload bytes of a segment of a segmented or block transfer
+pub const SEGMENT_SIZE: usize = 7;
+/// Segments in a sub-block of a block transfer
+pub const MAX_BLOCK_SIZE: u8 = 127;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SdoProtocolError {
+    #[error("Unknown SDO command specifier in {0:02x?}")]
+    UnknownCommand([u8; 8]),
+    #[error("Invalid block segment sequence number {0}")]
+    InvalidSequence(u8),
+}
+
+/// Data of an initiate download request or an initiate upload response
+#[derive(Debug, Clone, PartialEq)]
+pub enum Initiate {
+    /// Up to 4 bytes in the frame itself, `size` is None if the sender did not indicate it
+    Expedited { data: [u8; 4], size: Option<usize> },
+    /// The data follows in segments
+    Segmented { size: Option<u32> },
+}
+
+impl Initiate {
+    /// Data of an expedited transfer, all 4 bytes if the size is not indicated
+    pub fn expedited(data: &[u8]) -> Self {
+        let mut bytes = [0; 4];
+        bytes[..data.len()].copy_from_slice(data);
+        Initiate::Expedited {
+            data: bytes,
+            size: Some(data.len()),
+        }
+    }
+}
+
+/// One segment of a segmented transfer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub toggle: bool,
+    /// c: no more segments to follow
+    pub last: bool,
+    data: [u8; SEGMENT_SIZE],
+    len: usize,
+}
+
+impl Segment {
+    /// Panics if the data does not fit a single segment
+    pub fn new(toggle: bool, last: bool, data: &[u8]) -> Self {
+        let mut bytes = [0; SEGMENT_SIZE];
+        bytes[..data.len()].copy_from_slice(data);
+        Self {
+            toggle,
+            last,
+            data: bytes,
+            len: data.len(),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    fn encode(&self, command: u8) -> [u8; 8] {
+        let unused = (SEGMENT_SIZE - self.len) as u8;
+        let mut frame = [0; 8];
+        frame[0] = command | (self.toggle as u8) << 4 | unused << 1 | self.last as u8;
+        frame[1..].copy_from_slice(&self.data);
+        frame
+    }
+
+    fn decode(frame: &[u8; 8]) -> Self {
+        let unused = ((frame[0] >> 1) & 0b111) as usize;
+        Self::new(
+            frame[0] & 0x10 != 0,
+            frame[0] & 0x01 != 0,
+            &frame[1..1 + SEGMENT_SIZE - unused],
+        )
+    }
+}
+
+/// One segment of a sub-block of a block transfer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSegment {
+    /// 1 to 127
+    pub seqno: u8,
+    /// c: no more segments to follow
+    pub last: bool,
+    /// The unused bytes of the last segment are only known from the end of the transfer
+    pub data: [u8; SEGMENT_SIZE],
+}
+
+impl BlockSegment {
+    pub fn encode(&self) -> [u8; 8] {
+        let mut frame = [0; 8];
+        frame[0] = (self.last as u8) << 7 | self.seqno;
+        frame[1..].copy_from_slice(&self.data);
+        frame
+    }
+
+    pub fn decode(frame: &[u8; 8]) -> Result<Self, SdoProtocolError> {
+        let seqno = frame[0] & 0x7F;
+        if seqno == 0 {
+            return Err(SdoProtocolError::InvalidSequence(seqno));
+        }
+
+        Ok(Self {
+            seqno,
+            last: frame[0] & 0x80 != 0,
+            data: frame[1..]
+                .try_into()
+                .expect("7 bytes after the command byte"),
+        })
+    }
+}
+
+/// Frames sent by the SDO client (RSDO), except the segments of a block download
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientFrame {
+    InitiateDownload {
+        index: u16,
+        sub_index: u8,
+        data: Initiate,
+    },
+    DownloadSegment(Segment),
+    InitiateUpload {
+        index: u16,
+        sub_index: u8,
+    },
+    UploadSegment {
+        toggle: bool,
+    },
+    Abort {
+        index: u16,
+        sub_index: u8,
+        code: SdoAbortCode,
+    },
+    InitiateBlockDownload {
+        index: u16,
+        sub_index: u8,
+        crc: bool,
+        size: Option<u32>,
+    },
+    /// `unused` bytes of the last segment do not contain data
+    EndBlockDownload {
+        unused: u8,
+        crc: u16,
+    },
+    InitiateBlockUpload {
+        index: u16,
+        sub_index: u8,
+        crc: bool,
+        block_size: u8,
+        /// Protocol switch threshold, 0 means the server may not switch to a segmented transfer
+        pst: u8,
+    },
+    StartBlockUpload,
+    BlockUploadAck {
+        ackseq: u8,
+        block_size: u8,
+    },
+    EndBlockUpload,
+}
+
+/// Frames sent by the SDO server (TSDO), except the segments of a block upload
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerFrame {
+    InitiateDownload {
+        index: u16,
+        sub_index: u8,
+    },
+    DownloadSegment {
+        toggle: bool,
+    },
+    InitiateUpload {
+        index: u16,
+        sub_index: u8,
+        data: Initiate,
+    },
+    UploadSegment(Segment),
+    Abort {
+        index: u16,
+        sub_index: u8,
+        code: SdoAbortCode,
+    },
+    InitiateBlockDownload {
+        index: u16,
+        sub_index: u8,
+        crc: bool,
+        block_size: u8,
+    },
+    BlockDownloadAck {
+        ackseq: u8,
+        block_size: u8,
+    },
+    EndBlockDownload,
+    InitiateBlockUpload {
+        index: u16,
+        sub_index: u8,
+        crc: bool,
+        size: Option<u32>,
+    },
+    /// `unused` bytes of the last segment do not contain data
+    EndBlockUpload {
+        unused: u8,
+        crc: u16,
+    },
+}
+
+fn multiplexed(command: u8, index: u16, sub_index: u8, data: [u8; 4]) -> [u8; 8] {
+    let [index_low, index_high] = index.to_le_bytes();
+    let [d0, d1, d2, d3] = data;
+    [command, index_low, index_high, sub_index, d0, d1, d2, d3]
+}
+
+fn index_of(frame: &[u8; 8]) -> (u16, u8) {
+    (u16::from_le_bytes([frame[1], frame[2]]), frame[3])
+}
+
+fn u32_of(frame: &[u8; 8]) -> u32 {
+    u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]])
+}
+
+fn size_of(frame: &[u8; 8], indicated: bool) -> Option<u32> {
+    indicated.then(|| u32_of(frame))
+}
+
+/// Initiate download request and initiate upload response share their layout
+fn encode_initiate(command: u8, index: u16, sub_index: u8, data: &Initiate) -> [u8; 8] {
+    match *data {
+        Initiate::Expedited { data, size } => {
+            let unused = size.map_or(0, |size| (4 - size) as u8);
+            let command = command | unused << 2 | 0b10 | size.is_some() as u8;
+            multiplexed(command, index, sub_index, data)
+        }
+        Initiate::Segmented { size } => {
+            let command = command | size.is_some() as u8;
+            multiplexed(command, index, sub_index, size.unwrap_or(0).to_le_bytes())
+        }
+    }
+}
+
+fn decode_initiate(frame: &[u8; 8]) -> Initiate {
+    let size_indicated = frame[0] & 0b01 != 0;
+    match frame[0] & 0b10 != 0 {
+        true => Initiate::Expedited {
+            data: [frame[4], frame[5], frame[6], frame[7]],
+            size: size_indicated.then(|| 4 - ((frame[0] >> 2) & 0b11) as usize),
+        },
+        false => Initiate::Segmented {
+            size: size_of(frame, size_indicated),
+        },
+    }
+}
+
+fn encode_abort(index: u16, sub_index: u8, code: SdoAbortCode) -> [u8; 8] {
+    multiplexed(0x80, index, sub_index, code.code().to_le_bytes())
+}
+
+impl ClientFrame {
+    pub fn encode(&self) -> [u8; 8] {
+        match self {
+            ClientFrame::InitiateDownload {
+                index,
+                sub_index,
+                data,
+            } => encode_initiate(0x20, *index, *sub_index, data),
+            ClientFrame::DownloadSegment(segment) => segment.encode(0x00),
+            ClientFrame::InitiateUpload { index, sub_index } => {
+                multiplexed(0x40, *index, *sub_index, [0; 4])
+            }
+            ClientFrame::UploadSegment { toggle } => {
+                [0x60 | (*toggle as u8) << 4, 0, 0, 0, 0, 0, 0, 0]
+            }
+            ClientFrame::Abort {
+                index,
+                sub_index,
+                code,
+            } => encode_abort(*index, *sub_index, *code),
+            ClientFrame::InitiateBlockDownload {
+                index,
+                sub_index,
+                crc,
+                size,
+            } => {
+                let command = 0xC0 | (*crc as u8) << 2 | (size.is_some() as u8) << 1;
+                multiplexed(command, *index, *sub_index, size.unwrap_or(0).to_le_bytes())
+            }
+            ClientFrame::EndBlockDownload { unused, crc } => {
+                let [crc_low, crc_high] = crc.to_le_bytes();
+                [0xC1 | unused << 2, crc_low, crc_high, 0, 0, 0, 0, 0]
+            }
+            ClientFrame::InitiateBlockUpload {
+                index,
+                sub_index,
+                crc,
+                block_size,
+                pst,
+            } => {
+                let command = 0xA0 | (*crc as u8) << 2;
+                multiplexed(command, *index, *sub_index, [*block_size, *pst, 0, 0])
+            }
+            ClientFrame::StartBlockUpload => [0xA3, 0, 0, 0, 0, 0, 0, 0],
+            ClientFrame::BlockUploadAck { ackseq, block_size } => {
+                [0xA2, *ackseq, *block_size, 0, 0, 0, 0, 0]
+            }
+            ClientFrame::EndBlockUpload => [0xA1, 0, 0, 0, 0, 0, 0, 0],
+        }
+    }
+
+    pub fn decode(frame: &[u8; 8]) -> Result<Self, SdoProtocolError> {
+        let (index, sub_index) = index_of(frame);
+        let command = frame[0];
+
+        Ok(match command >> 5 {
+            0 => ClientFrame::DownloadSegment(Segment::decode(frame)),
+            1 => ClientFrame::InitiateDownload {
+                index,
+                sub_index,
+                data: decode_initiate(frame),
+            },
+            2 => ClientFrame::InitiateUpload { index, sub_index },
+            3 => ClientFrame::UploadSegment {
+                toggle: command & 0x10 != 0,
+            },
+            4 => ClientFrame::Abort {
+                index,
+                sub_index,
+                code: SdoAbortCode::from_code(u32_of(frame)),
+            },
+            5 => match command & 0b11 {
+                0 => ClientFrame::InitiateBlockUpload {
+                    index,
+                    sub_index,
+                    crc: command & 0b100 != 0,
+                    block_size: frame[4],
+                    pst: frame[5],
+                },
+                1 => ClientFrame::EndBlockUpload,
+                2 => ClientFrame::BlockUploadAck {
+                    ackseq: frame[1],
+                    block_size: frame[2],
+                },
+                _ => ClientFrame::StartBlockUpload,
+            },
+            6 => match command & 0b01 {
+                0 => ClientFrame::InitiateBlockDownload {
+                    index,
+                    sub_index,
+                    crc: command & 0b100 != 0,
+                    size: size_of(frame, command & 0b10 != 0),
+                },
+                _ => ClientFrame::EndBlockDownload {
+                    unused: (command >> 2) & 0b111,
+                    crc: u16::from_le_bytes([frame[1], frame[2]]),
+                },
+            },
+            _ => return Err(SdoProtocolError::UnknownCommand(*frame)),
+        })
+    }
+}
+
+impl ServerFrame {
+    pub fn encode(&self) -> [u8; 8] {
+        match self {
+            ServerFrame::InitiateDownload { index, sub_index } => {
+                multiplexed(0x60, *index, *sub_index, [0; 4])
+            }
+            ServerFrame::DownloadSegment { toggle } => {
+                [0x20 | (*toggle as u8) << 4, 0, 0, 0, 0, 0, 0, 0]
+            }
+            ServerFrame::InitiateUpload {
+                index,
+                sub_index,
+                data,
+            } => encode_initiate(0x40, *index, *sub_index, data),
+            ServerFrame::UploadSegment(segment) => segment.encode(0x00),
+            ServerFrame::Abort {
+                index,
+                sub_index,
+                code,
+            } => encode_abort(*index, *sub_index, *code),
+            ServerFrame::InitiateBlockDownload {
+                index,
+                sub_index,
+                crc,
+                block_size,
+            } => multiplexed(
+                0xA0 | (*crc as u8) << 2,
+                *index,
+                *sub_index,
+                [*block_size, 0, 0, 0],
+            ),
+            ServerFrame::BlockDownloadAck { ackseq, block_size } => {
+                [0xA2, *ackseq, *block_size, 0, 0, 0, 0, 0]
+            }
+            ServerFrame::EndBlockDownload => [0xA1, 0, 0, 0, 0, 0, 0, 0],
+            ServerFrame::InitiateBlockUpload {
+                index,
+                sub_index,
+                crc,
+                size,
+            } => {
+                let command = 0xC0 | (*crc as u8) << 2 | (size.is_some() as u8) << 1;
+                multiplexed(command, *index, *sub_index, size.unwrap_or(0).to_le_bytes())
+            }
+            ServerFrame::EndBlockUpload { unused, crc } => {
+                let [crc_low, crc_high] = crc.to_le_bytes();
+                [0xC1 | unused << 2, crc_low, crc_high, 0, 0, 0, 0, 0]
+            }
+        }
+    }
+
+    pub fn decode(frame: &[u8; 8]) -> Result<Self, SdoProtocolError> {
+        let (index, sub_index) = index_of(frame);
+        let command = frame[0];
+
+        Ok(match command >> 5 {
+            0 => ServerFrame::UploadSegment(Segment::decode(frame)),
+            1 => ServerFrame::DownloadSegment {
+                toggle: command & 0x10 != 0,
+            },
+            2 => ServerFrame::InitiateUpload {
+                index,
+                sub_index,
+                data: decode_initiate(frame),
+            },
+            3 => ServerFrame::InitiateDownload { index, sub_index },
+            4 => ServerFrame::Abort {
+                index,
+                sub_index,
+                code: SdoAbortCode::from_code(u32_of(frame)),
+            },
+            5 => match command & 0b11 {
+                0 => ServerFrame::InitiateBlockDownload {
+                    index,
+                    sub_index,
+                    crc: command & 0b100 != 0,
+                    block_size: frame[4],
+                },
+                1 => ServerFrame::EndBlockDownload,
+                2 => ServerFrame::BlockDownloadAck {
+                    ackseq: frame[1],
+                    block_size: frame[2],
+                },
+                _ => return Err(SdoProtocolError::UnknownCommand(*frame)),
+            },
+            6 => match command & 0b01 {
+                0 => ServerFrame::InitiateBlockUpload {
+                    index,
+                    sub_index,
+                    crc: command & 0b100 != 0,
+                    size: size_of(frame, command & 0b10 != 0),
+                },
+                _ => ServerFrame::EndBlockUpload {
+                    unused: (command >> 2) & 0b111,
+                    crc: u16::from_le_bytes([frame[1], frame[2]]),
+                },
+            },
+            _ => return Err(SdoProtocolError::UnknownCommand(*frame)),
+        })
+    }
+}
+
+/// Receiving end of a sub-block, keeps the segments that arrived in sequence
+#[derive(Debug, Clone, Default)]
+pub struct SubBlock {
+    segments: Vec<BlockSegment>,
+}
+
+impl SubBlock {
+    /// Keep the segment if it is the next in sequence, returns whether it was
+    pub fn push(&mut self, segment: BlockSegment) -> bool {
+        let in_sequence = usize::from(segment.seqno) == self.segments.len() + 1;
+        if in_sequence {
+            self.segments.push(segment);
+        }
+
+        in_sequence
+    }
+
+    /// Sequence number of the last segment received in sequence, 0 if none
+    pub fn ackseq(&self) -> u8 {
+        self.segments.len() as u8
+    }
+
+    /// Whether the last segment of the transfer arrived in sequence
+    pub fn is_last(&self) -> bool {
+        self.segments.last().is_some_and(|segment| segment.last)
+    }
+
+    /// Move the data of the acknowledged segments to `data` and start a new sub-block. Returns
+    /// whether the last segment of the transfer was acknowledged
+    pub fn acknowledge(&mut self, ackseq: u8, data: &mut Vec<u8>) -> bool {
+        let acknowledged = self.segments.drain(..).take(ackseq.into());
+        let mut last = false;
+        for segment in acknowledged {
+            data.extend_from_slice(&segment.data);
+            last = segment.last;
+        }
+
+        last
+    }
+}
+
+/// Split data into the segments of a block download, `unused` bytes of the last do not contain
+/// data
+pub fn block_segments(data: &[u8]) -> (Vec<[u8; SEGMENT_SIZE]>, u8) {
+    let segments = data
+        .chunks(SEGMENT_SIZE)
+        .map(|chunk| {
+            let mut segment = [0; SEGMENT_SIZE];
+            segment[..chunk.len()].copy_from_slice(chunk);
+            segment
+        })
+        .collect();
+    let unused = match data.len() % SEGMENT_SIZE {
+        0 => 0,
+        remainder => (SEGMENT_SIZE - remainder) as u8,
+    };
+
+    (segments, unused)
+}
+
+/// CRC of block transfers: CRC-16-CCITT, polynomial x^16 + x^12 + x^5 + 1 and initial value 0
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            match crc & 0x8000 != 0 {
+                true => crc << 1 ^ 0x1021,
+                false => crc << 1,
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_frames() {
+        // Expedited download of a u16, 2 bytes do not contain data
+        let expedited = [0x2B, 0x17, 0x10, 0x00, 0xE8, 0x03, 0x00, 0x00];
+        let decoded = ClientFrame::decode(&expedited).unwrap();
+        assert_eq!(
+            decoded,
+            ClientFrame::InitiateDownload {
+                index: 0x1017,
+                sub_index: 0,
+                data: Initiate::expedited(&[0xE8, 0x03]),
+            }
+        );
+        assert_eq!(decoded.encode(), expedited);
+
+        let frames = [
+            ClientFrame::InitiateDownload {
+                index: 0x1008,
+                sub_index: 0,
+                data: Initiate::Segmented { size: Some(12) },
+            },
+            ClientFrame::DownloadSegment(Segment::new(true, true, b"PD4")),
+            ClientFrame::UploadSegment { toggle: true },
+            ClientFrame::InitiateBlockDownload {
+                index: 0x1F50,
+                sub_index: 1,
+                crc: true,
+                size: Some(1000),
+            },
+            ClientFrame::EndBlockDownload {
+                unused: 3,
+                crc: 0x31C3,
+            },
+            ClientFrame::InitiateBlockUpload {
+                index: 0x100A,
+                sub_index: 0,
+                crc: true,
+                block_size: 127,
+                pst: 0,
+            },
+            ClientFrame::StartBlockUpload,
+            ClientFrame::BlockUploadAck {
+                ackseq: 5,
+                block_size: 127,
+            },
+            ClientFrame::EndBlockUpload,
+            ClientFrame::Abort {
+                index: 0x6040,
+                sub_index: 0,
+                code: SdoAbortCode::Timeout,
+            },
+        ];
+        for frame in frames {
+            assert_eq!(ClientFrame::decode(&frame.encode()), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn test_server_frames() {
+        let frames = [
+            ServerFrame::InitiateDownload {
+                index: 0x1017,
+                sub_index: 0,
+            },
+            ServerFrame::DownloadSegment { toggle: false },
+            ServerFrame::InitiateUpload {
+                index: 0x6041,
+                sub_index: 0,
+                data: Initiate::expedited(&[0x37, 0x02]),
+            },
+            ServerFrame::InitiateUpload {
+                index: 0x1008,
+                sub_index: 0,
+                data: Initiate::Segmented { size: Some(8) },
+            },
+            ServerFrame::UploadSegment(Segment::new(false, false, b"Nanotec")),
+            ServerFrame::InitiateBlockDownload {
+                index: 0x1F50,
+                sub_index: 1,
+                crc: true,
+                block_size: 64,
+            },
+            ServerFrame::BlockDownloadAck {
+                ackseq: 64,
+                block_size: 32,
+            },
+            ServerFrame::EndBlockDownload,
+            ServerFrame::InitiateBlockUpload {
+                index: 0x100A,
+                sub_index: 0,
+                crc: false,
+                size: None,
+            },
+            ServerFrame::EndBlockUpload {
+                unused: 6,
+                crc: 0xBEEF,
+            },
+        ];
+        for frame in frames {
+            assert_eq!(ServerFrame::decode(&frame.encode()), Ok(frame));
+        }
+
+        // Upload segment response with the toggle bit set, 7 - 4 bytes of data
+        let segment = ServerFrame::decode(&[0x19, b'v', b'1', b'.', 0, 0, 0, 0]).unwrap();
+        assert_eq!(
+            segment,
+            ServerFrame::UploadSegment(Segment::new(true, true, b"v1."))
+        );
+    }
+
+    #[test]
+    fn test_sub_block() {
+        let segment = |seqno, last| BlockSegment {
+            seqno,
+            last,
+            data: [seqno; SEGMENT_SIZE],
+        };
+        let mut sub_block = SubBlock::default();
+        assert!(sub_block.push(segment(1, false)));
+        assert!(sub_block.push(segment(2, false)));
+        // Segment 3 got lost
+        assert!(!sub_block.push(segment(4, true)));
+        assert_eq!(sub_block.ackseq(), 2);
+        assert!(!sub_block.is_last());
+
+        let mut data = Vec::new();
+        assert!(!sub_block.acknowledge(sub_block.ackseq(), &mut data));
+        assert_eq!(data.len(), 2 * SEGMENT_SIZE);
+
+        // Retransmitted in the next sub-block
+        assert!(sub_block.push(segment(1, true)));
+        assert!(sub_block.is_last());
+        assert!(sub_block.acknowledge(1, &mut data));
+        assert_eq!(data[2 * SEGMENT_SIZE], 1);
+
+        assert_eq!(
+            BlockSegment::decode(&[0x80, 0, 0, 0, 0, 0, 0, 0]),
+            Err(SdoProtocolError::InvalidSequence(0))
+        );
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(&[]), 0);
+    }
+
+    #[test]
+    fn test_block_segments() {
+        let (segments, unused) = block_segments(&[1; 15]);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(unused, 6);
+        assert_eq!(block_segments(&[1; 14]).1, 0);
+    }
+}
@@ -0,0 +1,684 @@
+//! Bus-wide SDO scheduling: transfers of all drivers on a CAN interface are queued here, at most
+//! one transfer per SDO server is outstanding, higher priorities go first and transfers failing
+//! with a transient error are retried
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use oze_canopen::{interface::CanOpenInterface, transmitter::TxPacket};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::*;
+
+use crate::{
+    comms::sdo::client::{SdoClient, SdoTransferError},
+    error::DriveError,
+    od::{data_type::DataType, value::ODValue},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SdoPriority {
+    /// Bulk transfers, e.g. parametrisation and PDO mapping at startup
+    Low,
+    Normal,
+    /// Transfers a running application waits on
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferOptions {
+    pub priority: SdoPriority,
+    /// Time after queueing in which the transfer has to start, retries included. A transfer that
+    /// already started is not cancelled, the timeout of the SDO client bounds it
+    pub deadline: Option<Duration>,
+    /// Number of times a transfer failing with a retryable error is tried again
+    pub retries: usize,
+}
+
+impl TransferOptions {
+    pub const STARTUP: Self = Self {
+        priority: SdoPriority::Low,
+        deadline: None,
+        retries: 2,
+    };
+
+    pub const RUNTIME: Self = Self {
+        priority: SdoPriority::High,
+        deadline: Some(Duration::from_millis(250)),
+        retries: 1,
+    };
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            priority: SdoPriority::Normal,
+            deadline: None,
+            retries: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyStats {
+    pub samples: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    fn record(&mut self, latency: Duration) {
+        self.samples += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.samples > 0).then(|| self.total / self.samples as u32)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchedulerStats {
+    /// Transfers waiting for their server
+    pub queued: usize,
+    /// Highest number of waiting transfers seen
+    pub max_queued: usize,
+    /// Transfers on the bus, at most one per server
+    pub in_flight: usize,
+    pub completed: u64,
+    pub failed: u64,
+    pub retried: u64,
+    /// Transfers that missed their deadline before starting
+    pub expired: u64,
+    /// Time from queueing a transfer until it starts
+    pub wait: LatencyStats,
+    /// Time from queueing a transfer until it completes, retries included
+    pub latency: LatencyStats,
+}
+
+#[derive(Debug)]
+enum TransferKind {
+    Upload,
+    UploadBlock,
+    Download(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct Request {
+    node_id: u8,
+    index: u16,
+    sub_index: u8,
+    kind: TransferKind,
+    options: TransferOptions,
+    queued_at: Instant,
+    attempts: usize,
+    reply: oneshot::Sender<Result<Vec<u8>, SdoTransferError>>,
+}
+
+impl Request {
+    fn deadline(&self) -> Option<Instant> {
+        self.options
+            .deadline
+            .map(|deadline| self.queued_at + deadline)
+    }
+}
+
+/// Queue entry, ordered by priority and then by the time it got in line
+#[derive(Debug)]
+struct Queued {
+    key: (SdoPriority, Reverse<u64>),
+    request: Request,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Queue and client of a single SDO server, the client is lent out while a transfer runs
+#[derive(Default)]
+struct Server {
+    queue: BinaryHeap<Queued>,
+    client: Option<SdoClient>,
+    busy: bool,
+}
+
+struct Completion {
+    client: SdoClient,
+    request: Request,
+    result: Result<Vec<u8>, SdoTransferError>,
+}
+
+/// Schedulers handed out by [`SdoScheduler::shared`], weak so they still stop once unused
+static SHARED: Mutex<Vec<SharedScheduler>> = Mutex::new(Vec::new());
+
+struct SharedScheduler {
+    /// Transmit channel of the interface the scheduler belongs to
+    interface: mpsc::WeakSender<TxPacket>,
+    tx: mpsc::WeakUnboundedSender<Request>,
+    stats: Weak<Mutex<SchedulerStats>>,
+}
+
+/// Schedules the SDO transfers of all servers on one CAN interface, cheap to clone.
+/// The scheduling task stops once every clone and [`SdoHandle`] is dropped
+#[derive(Debug, Clone)]
+pub struct SdoScheduler {
+    tx: mpsc::UnboundedSender<Request>,
+    stats: Arc<Mutex<SchedulerStats>>,
+}
+
+impl SdoScheduler {
+    /// Start scheduling the SDO transfers of the given interface
+    pub fn start(canopen: &CanOpenInterface) -> (Self, JoinHandle<()>) {
+        let canopen = canopen.clone();
+        Self::with_clients(move |node_id| SdoClient::new(&canopen, node_id))
+    }
+
+    /// The scheduler of the given interface, shared by every driver on it so their transfers are
+    /// ordered by priority. Started on first use, it stops once every driver and handle using it
+    /// is dropped
+    pub fn shared(canopen: &CanOpenInterface) -> Self {
+        Self::shared_by(&canopen.tx, || Self::start(canopen).0)
+    }
+
+    /// The scheduler shared by the interface with the given transmit channel, or the one `start`
+    /// returns if there is none yet
+    fn shared_by(interface: &mpsc::Sender<TxPacket>, start: impl FnOnce() -> Self) -> Self {
+        let mut shared = SHARED.lock().unwrap();
+        shared.retain(|scheduler| scheduler.tx.upgrade().is_some());
+
+        let existing = shared.iter().find_map(|scheduler| {
+            let same_interface = scheduler
+                .interface
+                .upgrade()
+                .is_some_and(|tx| tx.same_channel(interface));
+            match (
+                same_interface,
+                scheduler.tx.upgrade(),
+                scheduler.stats.upgrade(),
+            ) {
+                (true, Some(tx), Some(stats)) => Some(Self { tx, stats }),
+                _ => None,
+            }
+        });
+        if let Some(scheduler) = existing {
+            return scheduler;
+        }
+
+        let scheduler = start();
+        shared.push(SharedScheduler {
+            interface: interface.downgrade(),
+            tx: scheduler.tx.downgrade(),
+            stats: Arc::downgrade(&scheduler.stats),
+        });
+        scheduler
+    }
+
+    /// Start scheduling, with `new_client` creating the client of a server on its first transfer
+    pub fn with_clients(
+        new_client: impl FnMut(u8) -> SdoClient + Send + 'static,
+    ) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stats = Arc::new(Mutex::new(SchedulerStats::default()));
+        let handle = tokio::spawn(schedule(rx, Box::new(new_client), stats.clone()));
+        (Self { tx, stats }, handle)
+    }
+
+    /// Handle to the SDO server of the given node, transferring with the default options
+    pub fn handle(&self, node_id: u8) -> SdoHandle {
+        SdoHandle {
+            scheduler: self.clone(),
+            node_id,
+            options: TransferOptions::default(),
+        }
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    async fn transfer(
+        &self,
+        node_id: u8,
+        index: u16,
+        sub_index: u8,
+        kind: TransferKind,
+        options: TransferOptions,
+    ) -> Result<Vec<u8>, SdoTransferError> {
+        let (reply, response) = oneshot::channel();
+        let request = Request {
+            node_id,
+            index,
+            sub_index,
+            kind,
+            options,
+            queued_at: Instant::now(),
+            attempts: 0,
+            reply,
+        };
+        self.tx
+            .send(request)
+            .map_err(|_| SdoTransferError::Closed)?;
+        response.await.map_err(|_| SdoTransferError::Closed)?
+    }
+}
+
+/// Transfers to the SDO server of a single node through an [`SdoScheduler`], cheap to clone
+#[derive(Debug, Clone)]
+pub struct SdoHandle {
+    scheduler: SdoScheduler,
+    node_id: u8,
+    options: TransferOptions,
+}
+
+impl SdoHandle {
+    /// The same server, transferring with other options
+    pub fn with_options(&self, options: TransferOptions) -> Self {
+        Self {
+            options,
+            ..self.clone()
+        }
+    }
+
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    pub fn options(&self) -> TransferOptions {
+        self.options
+    }
+
+    pub fn scheduler(&self) -> &SdoScheduler {
+        &self.scheduler
+    }
+
+    /// See [`SdoClient::upload`]
+    pub async fn upload(&self, index: u16, sub_index: u8) -> Result<Vec<u8>, SdoTransferError> {
+        self.scheduler
+            .transfer(
+                self.node_id,
+                index,
+                sub_index,
+                TransferKind::Upload,
+                self.options,
+            )
+            .await
+    }
+
+    /// Upload an object holding a value of the given data type, see [`DataType::trim_upload`]
+    pub async fn upload_value(
+        &self,
+        index: u16,
+        sub_index: u8,
+        data_type: DataType,
+    ) -> Result<ODValue, DriveError> {
+        let data = self.upload(index, sub_index).await?;
+        Ok(ODValue::decode(data_type, data_type.trim_upload(&data))?)
+    }
+
+    /// See [`SdoClient::upload_block`]
+    pub async fn upload_block(
+        &self,
+        index: u16,
+        sub_index: u8,
+    ) -> Result<Vec<u8>, SdoTransferError> {
+        self.scheduler
+            .transfer(
+                self.node_id,
+                index,
+                sub_index,
+                TransferKind::UploadBlock,
+                self.options,
+            )
+            .await
+    }
+
+    /// See [`SdoClient::download`]
+    pub async fn download(
+        &self,
+        index: u16,
+        sub_index: u8,
+        data: &[u8],
+    ) -> Result<(), SdoTransferError> {
+        self.scheduler
+            .transfer(
+                self.node_id,
+                index,
+                sub_index,
+                TransferKind::Download(data.to_vec()),
+                self.options,
+            )
+            .await
+            .map(|_| ())
+    }
+}
+
+async fn schedule(
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    mut new_client: Box<dyn FnMut(u8) -> SdoClient + Send>,
+    stats: Arc<Mutex<SchedulerStats>>,
+) {
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel::<Completion>();
+    let mut servers: HashMap<u8, Server> = HashMap::new();
+    let mut sequence = 0u64;
+
+    loop {
+        let node_id = tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    break;
+                };
+                let node_id = request.node_id;
+                sequence += 1;
+                servers.entry(node_id).or_default().queue.push(Queued {
+                    key: (request.options.priority, Reverse(sequence)),
+                    request,
+                });
+                node_id
+            }
+            Some(Completion { client, request, result }) = done_rx.recv() => {
+                let node_id = request.node_id;
+                let server = servers.entry(node_id).or_default();
+                server.client = Some(client);
+                server.busy = false;
+                stats.lock().unwrap().in_flight -= 1;
+
+                let expired = request.deadline().is_some_and(|deadline| Instant::now() >= deadline);
+                match result {
+                    Err(err)
+                        if err.is_retryable()
+                            && request.attempts <= request.options.retries
+                            && !expired =>
+                    {
+                        debug!("Retrying SDO transfer for node id {node_id}: {err}");
+                        stats.lock().unwrap().retried += 1;
+                        // Retries go ahead of the transfers of the same priority queued meanwhile
+                        let key = (request.options.priority, Reverse(0));
+                        server.queue.push(Queued { key, request });
+                    }
+                    result => {
+                        let mut stats = stats.lock().unwrap();
+                        match &result {
+                            Ok(_) => stats.completed += 1,
+                            Err(_) => stats.failed += 1,
+                        }
+                        stats.latency.record(request.queued_at.elapsed());
+                        let _ = request.reply.send(result);
+                    }
+                }
+                node_id
+            }
+        };
+
+        dispatch(node_id, &mut servers, new_client.as_mut(), &stats, &done_tx);
+    }
+
+    trace!("SDO scheduler stopped");
+}
+
+/// Start the next transfer of the server if it is idle
+fn dispatch(
+    node_id: u8,
+    servers: &mut HashMap<u8, Server>,
+    new_client: &mut (dyn FnMut(u8) -> SdoClient + Send),
+    stats: &Mutex<SchedulerStats>,
+    done: &mpsc::UnboundedSender<Completion>,
+) {
+    let server = servers.entry(node_id).or_default();
+    let mut stats = stats.lock().unwrap();
+
+    while !server.busy
+        && let Some(Queued { mut request, .. }) = server.queue.pop()
+    {
+        if request.reply.is_closed() {
+            continue;
+        }
+        if request
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            stats.expired += 1;
+            let _ = request.reply.send(Err(SdoTransferError::Deadline {
+                index: request.index,
+                sub_index: request.sub_index,
+            }));
+            continue;
+        }
+
+        if request.attempts == 0 {
+            stats.wait.record(request.queued_at.elapsed());
+        }
+        request.attempts += 1;
+        stats.in_flight += 1;
+        server.busy = true;
+
+        let mut client = server.client.take().unwrap_or_else(|| new_client(node_id));
+        let done = done.clone();
+        tokio::spawn(async move {
+            let result = match &request.kind {
+                TransferKind::Upload => client.upload(request.index, request.sub_index).await,
+                TransferKind::UploadBlock => {
+                    client.upload_block(request.index, request.sub_index).await
+                }
+                TransferKind::Download(data) => client
+                    .download(request.index, request.sub_index, data)
+                    .await
+                    .map(|_| Vec::new()),
+            };
+            let _ = done.send(Completion {
+                client,
+                request,
+                result,
+            });
+        });
+    }
+
+    stats.queued = servers.values().map(|server| server.queue.len()).sum();
+    stats.max_queued = stats.max_queued.max(stats.queued);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::sdo::{
+        abort::SdoAbortCode,
+        mock::{MockDevice, client_with_device, client_with_server},
+        protocol::{ClientFrame, Initiate, ServerFrame},
+    };
+
+    /// Server answering uploads of any object with its index, or aborting them while `aborts` lasts
+    fn server(
+        uploads: Arc<Mutex<Vec<u16>>>,
+        mut aborts: usize,
+    ) -> impl FnMut(ClientFrame, [u8; 8]) -> Vec<[u8; 8]> + Send + 'static {
+        move |request, _| match request {
+            ClientFrame::InitiateUpload { index, sub_index } => {
+                uploads.lock().unwrap().push(index);
+                let response = if aborts > 0 {
+                    aborts -= 1;
+                    ServerFrame::Abort {
+                        index,
+                        sub_index,
+                        code: SdoAbortCode::DeviceState,
+                    }
+                } else {
+                    ServerFrame::InitiateUpload {
+                        index,
+                        sub_index,
+                        data: Initiate::expedited(&index.to_le_bytes()),
+                    }
+                };
+                vec![response.encode()]
+            }
+            _ => vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priorities() {
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let server_uploads = uploads.clone();
+        let (scheduler, _) = SdoScheduler::with_clients(move |_| {
+            client_with_server(server(server_uploads.clone(), 0)).0
+        });
+
+        let node = scheduler.handle(3);
+        let low = node.with_options(TransferOptions::STARTUP);
+        let high = node.with_options(TransferOptions::RUNTIME);
+
+        // The first transfer starts right away, the others wait for the server
+        let (first, second, third, fourth) = tokio::join!(
+            low.upload(0x1000, 0),
+            low.upload(0x1001, 0),
+            node.upload(0x1002, 0),
+            high.upload(0x1003, 0),
+        );
+        assert_eq!(first.unwrap(), vec![0x00, 0x10]);
+        assert_eq!(second.unwrap(), vec![0x01, 0x10]);
+        assert_eq!(third.unwrap(), vec![0x02, 0x10]);
+        assert_eq!(fourth.unwrap(), vec![0x03, 0x10]);
+        assert_eq!(*uploads.lock().unwrap(), [0x1000, 0x1003, 0x1002, 0x1001]);
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.completed, 4);
+        assert_eq!(stats.max_queued, 3);
+        assert_eq!((stats.queued, stats.in_flight), (0, 0));
+        assert_eq!(stats.latency.samples, 4);
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let server_uploads = uploads.clone();
+        let (scheduler, _) = SdoScheduler::with_clients(move |_| {
+            client_with_server(server(server_uploads.clone(), 2)).0
+        });
+
+        let node = scheduler.handle(3);
+        assert_eq!(node.upload(0x6061, 0).await.unwrap(), vec![0x61, 0x60]);
+        assert_eq!(uploads.lock().unwrap().len(), 3);
+
+        let stats = scheduler.stats();
+        assert_eq!((stats.completed, stats.retried, stats.failed), (1, 2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_server() {
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let server_uploads = uploads.clone();
+        // Node 1 never answers, node 2 does
+        let (scheduler, _) = SdoScheduler::with_clients(move |node_id| match node_id {
+            1 => client_with_server(|_, _| vec![]).0,
+            _ => client_with_server(server(server_uploads.clone(), 0)).0,
+        });
+
+        let stuck = scheduler.handle(1).with_options(TransferOptions {
+            retries: 0,
+            ..TransferOptions::default()
+        });
+        let expiring = stuck.with_options(TransferOptions {
+            deadline: Some(Duration::from_millis(10)),
+            ..stuck.options()
+        });
+        let other = scheduler.handle(2);
+
+        let (timed_out, expired, answered) = tokio::join!(
+            stuck.upload(0x1000, 0),
+            expiring.upload(0x1001, 0),
+            other.upload(0x1002, 0),
+        );
+        assert!(matches!(
+            timed_out,
+            Err(SdoTransferError::Timeout { index: 0x1000, .. })
+        ));
+        assert!(matches!(
+            expired,
+            Err(SdoTransferError::Deadline { index: 0x1001, .. })
+        ));
+        assert_eq!(answered.unwrap(), vec![0x02, 0x10]);
+
+        let stats = scheduler.stats();
+        assert_eq!((stats.completed, stats.failed, stats.expired), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_unsized_upload() {
+        let mut device = MockDevice {
+            unsized_uploads: true,
+            ..MockDevice::default()
+        };
+        device.objects.insert((0x6060, 0), vec![0xFA]);
+        device
+            .objects
+            .insert((0x6086, 0), 3i16.to_le_bytes().to_vec());
+        let (sdo, _) = client_with_device(device);
+
+        // Only the leading bytes of the 4 uploaded ones hold the value
+        assert_eq!(sdo.upload(0x6060, 0).await.unwrap(), [0xFA, 0, 0, 0]);
+        assert_eq!(
+            sdo.upload_value(0x6060, 0, DataType::Integer8)
+                .await
+                .unwrap(),
+            ODValue::I8(-6)
+        );
+        assert_eq!(
+            sdo.upload_value(0x6086, 0, DataType::Integer16)
+                .await
+                .unwrap(),
+            ODValue::I16(3)
+        );
+        assert!(
+            sdo.upload_value(0x6060, 0, DataType::Unsigned32)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared() {
+        let start = || SdoScheduler::with_clients(|_| unreachable!("no transfers")).0;
+        let (interface, _rx) = mpsc::channel::<TxPacket>(1);
+        let (other, _other_rx) = mpsc::channel::<TxPacket>(1);
+
+        let scheduler = SdoScheduler::shared_by(&interface, start);
+        let same = SdoScheduler::shared_by(&interface, start);
+        assert!(scheduler.tx.same_channel(&same.tx));
+        let separate = SdoScheduler::shared_by(&other, start);
+        assert!(!scheduler.tx.same_channel(&separate.tx));
+
+        // Once unused the interface gets a new scheduler
+        let stopped = scheduler.tx.downgrade();
+        drop((scheduler, same));
+        assert!(stopped.upgrade().is_none());
+        let restarted = SdoScheduler::shared_by(&interface, start);
+        assert!(!restarted.tx.is_closed());
+        assert!(
+            restarted
+                .tx
+                .same_channel(&SdoScheduler::shared_by(&interface, start).tx)
+        );
+    }
+}
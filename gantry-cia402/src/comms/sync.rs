@@ -0,0 +1,306 @@
+use std::time::Duration;
+
+use oze_canopen::{
+    interface::{CanOpenInterface, SEND_TIMOUT},
+    transmitter::TxPacket,
+};
+use tokio::{
+    sync::{broadcast, mpsc, watch},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tracing::*;
+
+use crate::{comms::pdo::codec::PdoValue, error::DriveError};
+
+/// COB-ID of the SYNC object (1005h)
+pub const SYNC_COB_ID: u16 = 0x080;
+
+/// Highest synchronous counter overflow value (1019h), 0 and 1 disable the counter
+const MAX_COUNTER_OVERFLOW: u8 = 240;
+
+/// Configuration of the SYNC producer
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    /// Communication cycle period (1006h), the time between two SYNCs
+    pub period: Duration,
+    /// Synchronous counter overflow value (1019h), SYNCs carry a counter running from 1 up to it
+    pub counter_overflow: Option<u8>,
+    /// Time before every SYNC in which synchronous RPDOs are sent, the devices apply them at
+    /// that SYNC
+    pub window: Duration,
+}
+
+impl SyncConfig {
+    /// SYNCs without counter, synchronous RPDOs are sent in the last quarter of every cycle
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            counter_overflow: None,
+            window: period / 4,
+        }
+    }
+
+    pub fn with_counter(mut self, overflow: u8) -> Self {
+        self.counter_overflow = Some(overflow);
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), DriveError> {
+        if self.period.is_zero() {
+            return Err(DriveError::InvalidSync("the cycle period is zero"));
+        }
+        if self.window.is_zero() || self.window >= self.period {
+            return Err(DriveError::InvalidSync(
+                "the window has to lie within the cycle period",
+            ));
+        }
+        if let Some(overflow) = self.counter_overflow
+            && !(2..=MAX_COUNTER_OVERFLOW).contains(&overflow)
+        {
+            return Err(DriveError::InvalidSync(
+                "the counter overflow value has to lie between 2 and 240",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Progress of the SYNC producer through a cycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncEvent {
+    /// The window before the SYNC with the given counter opened, synchronous RPDOs go out now
+    Window { counter: Option<u8> },
+    /// The SYNC with the given counter was sent at the given time
+    Sent { counter: Option<u8>, at: Instant },
+}
+
+/// How late SYNCs were sent compared to their schedule
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncStatistics {
+    pub sent: u64,
+    /// SYNCs skipped because the producer fell more than a cycle behind
+    pub missed: u64,
+    pub min_jitter: Duration,
+    pub max_jitter: Duration,
+    total_jitter: Duration,
+}
+
+impl SyncStatistics {
+    pub fn mean_jitter(&self) -> Duration {
+        match self.sent {
+            0 => Duration::ZERO,
+            sent => self.total_jitter.div_f64(sent as f64),
+        }
+    }
+
+    fn record(&mut self, jitter: Duration) {
+        self.min_jitter = match self.sent {
+            0 => jitter,
+            _ => self.min_jitter.min(jitter),
+        };
+        self.max_jitter = self.max_jitter.max(jitter);
+        self.total_jitter += jitter;
+        self.sent += 1;
+    }
+}
+
+/// Values of the synchronous TPDOs a node sent in reply to a single SYNC
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncSnapshot {
+    pub counter: Option<u8>,
+    pub values: Vec<PdoValue>,
+}
+
+/// Sends SYNC every cycle period, the host is the SYNC producer of the bus. Drivers follow the
+/// cycles through [`SyncProducer::subscribe`], see [`crate::driver::Cia402Driver::attach_sync`]
+/// Dropping this stops sending SYNCs
+pub struct SyncProducer {
+    config: SyncConfig,
+    events: broadcast::Sender<SyncEvent>,
+    statistics: watch::Receiver<SyncStatistics>,
+    handle: JoinHandle<()>,
+}
+
+impl SyncProducer {
+    /// Start sending SYNCs on the given interface, the first one goes out a cycle period from now
+    pub fn start(canopen: &CanOpenInterface, config: SyncConfig) -> Result<Self, DriveError> {
+        Self::from_sender(canopen.tx.clone(), config)
+    }
+
+    /// Same as [`SyncProducer::start`], but sends SYNCs through the given channel
+    pub fn from_sender(tx: mpsc::Sender<TxPacket>, config: SyncConfig) -> Result<Self, DriveError> {
+        config.validate()?;
+        info!("Producing SYNC - {config:?}");
+
+        let (events, _) = broadcast::channel(16);
+        let (statistics_tx, statistics) = watch::channel(SyncStatistics::default());
+        let handle = tokio::spawn(produce_sync(
+            tx,
+            config.clone(),
+            events.clone(),
+            statistics_tx,
+        ));
+
+        Ok(Self {
+            config,
+            events,
+            statistics,
+            handle,
+        })
+    }
+
+    pub fn config(&self) -> &SyncConfig {
+        &self.config
+    }
+
+    /// Follow the cycles from the next window on
+    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn statistics(&self) -> SyncStatistics {
+        self.statistics.borrow().clone()
+    }
+}
+
+impl Drop for SyncProducer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Counter of the SYNC after the given one, running from 1 up to the overflow value
+fn next_counter(counter: u8, overflow: u8) -> u8 {
+    counter % overflow + 1
+}
+
+async fn produce_sync(
+    tx: mpsc::Sender<TxPacket>,
+    config: SyncConfig,
+    events: broadcast::Sender<SyncEvent>,
+    statistics: watch::Sender<SyncStatistics>,
+) {
+    let mut counter = 0;
+    let mut scheduled = Instant::now() + config.period;
+
+    loop {
+        let current = config.counter_overflow.map(|overflow| {
+            counter = next_counter(counter, overflow);
+            counter
+        });
+
+        time::sleep_until(scheduled - config.window).await;
+        // Nobody following the cycles is fine
+        let _ = events.send(SyncEvent::Window { counter: current });
+
+        time::sleep_until(scheduled).await;
+        let packet = match TxPacket::new(SYNC_COB_ID, current.as_slice()) {
+            Ok(packet) => packet,
+            Err(err) => {
+                error!("Unable to construct SYNC: {err:?}");
+                return;
+            }
+        };
+        if let Err(err) = tx
+            .send_timeout(packet, Duration::from_millis(SEND_TIMOUT))
+            .await
+        {
+            error!("Unable to send SYNC: {err:?}");
+        }
+
+        let at = Instant::now();
+        statistics.send_modify(|statistics| statistics.record(at - scheduled));
+        let _ = events.send(SyncEvent::Sent {
+            counter: current,
+            at,
+        });
+
+        // Skip the SYNCs that are due already, rather than sending them back to back
+        scheduled += config.period;
+        while scheduled - config.window <= Instant::now() {
+            warn!("SYNC producer fell behind, skipping a cycle");
+            statistics.send_modify(|statistics| statistics.missed += 1);
+            scheduled += config.period;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let period = Duration::from_millis(10);
+        let config = SyncConfig::new(period);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.window, Duration::from_micros(2500));
+        assert!(config.clone().with_counter(2).validate().is_ok());
+        assert!(config.clone().with_counter(240).validate().is_ok());
+
+        assert!(config.clone().with_counter(1).validate().is_err());
+        assert!(config.clone().with_counter(241).validate().is_err());
+        assert!(config.clone().with_window(period).validate().is_err());
+        assert!(config.with_window(Duration::ZERO).validate().is_err());
+        assert!(SyncConfig::new(Duration::ZERO).validate().is_err());
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut counter = 0;
+        let sequence: Vec<_> = (0..5)
+            .map(|_| {
+                counter = next_counter(counter, 3);
+                counter
+            })
+            .collect();
+        assert_eq!(sequence, [1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut statistics = SyncStatistics::default();
+        statistics.record(Duration::from_micros(300));
+        statistics.record(Duration::from_micros(100));
+        statistics.record(Duration::from_micros(200));
+        assert_eq!(statistics.sent, 3);
+        assert_eq!(statistics.min_jitter, Duration::from_micros(100));
+        assert_eq!(statistics.max_jitter, Duration::from_micros(300));
+        assert_eq!(statistics.mean_jitter(), Duration::from_micros(200));
+    }
+
+    #[tokio::test]
+    async fn test_produce() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let config = SyncConfig::new(Duration::from_millis(20)).with_counter(2);
+        let producer = SyncProducer::from_sender(tx, config).unwrap();
+        let mut events = producer.subscribe();
+
+        for counter in [1, 2, 1] {
+            assert_eq!(
+                events.recv().await.unwrap(),
+                SyncEvent::Window {
+                    counter: Some(counter)
+                }
+            );
+            let packet = rx.recv().await.unwrap();
+            assert_eq!(packet.cob_id, SYNC_COB_ID);
+            assert_eq!(packet.data, [counter]);
+            assert!(matches!(
+                events.recv().await.unwrap(),
+                SyncEvent::Sent { counter: Some(c), .. } if c == counter
+            ));
+        }
+        assert!(producer.statistics().sent >= 2);
+
+        drop(producer);
+        assert!(rx.recv().await.is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use oze_canopen::{
+    interface::{CanOpenInterface, SEND_TIMOUT},
+    transmitter::TxPacket,
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+use tracing::*;
+
+use crate::{
+    error::DriveError,
+    od::{
+        data_type::DataType,
+        value::{ODValue, TimeOfDay},
+    },
+};
+
+/// COB-ID of the TIME object (1012h)
+pub const TIME_COB_ID: u16 = 0x100;
+
+/// Number of TIME messages the offset to the device clock is estimated from
+const CORRELATION_SAMPLES: usize = 16;
+
+/// Decode the data of a TIME message, None if it does not hold a TIME_OF_DAY
+pub fn decode_time(data: &[u8]) -> Option<TimeOfDay> {
+    match ODValue::decode(DataType::TimeOfDay, data) {
+        Ok(ODValue::TimeOfDay(time)) => Some(time),
+        _ => None,
+    }
+}
+
+/// A TIME message and when it was sent or received by the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeSample {
+    pub time: TimeOfDay,
+    pub at: Instant,
+}
+
+/// Relates host instants, e.g. [`oze_canopen::canopen::RxMessage::timestamp`], to the wall-clock
+/// time of the devices on the bus, learned from the TIME messages on it
+/// TIME messages reach the host some time after they were stamped, the sample with the least
+/// delay of the last few is taken as the offset between both clocks
+#[derive(Debug, Clone)]
+pub struct TimeCorrelation {
+    base: Instant,
+    // Device time at the base instant according to every recent sample
+    samples: VecDeque<Duration>,
+}
+
+impl Default for TimeCorrelation {
+    fn default() -> Self {
+        Self {
+            base: Instant::now(),
+            samples: VecDeque::with_capacity(CORRELATION_SAMPLES),
+        }
+    }
+}
+
+impl TimeCorrelation {
+    pub fn observe(&mut self, sample: TimeSample) {
+        let device = sample
+            .time
+            .to_system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let at_base = match sample.at.checked_duration_since(self.base) {
+            Some(elapsed) => device.saturating_sub(elapsed),
+            None => device + (self.base - sample.at),
+        };
+
+        if self.samples.len() == CORRELATION_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(at_base);
+    }
+
+    /// Device time at the given host instant, None until a TIME message was observed
+    pub fn device_time(&self, at: Instant) -> Option<SystemTime> {
+        let at_base = UNIX_EPOCH + self.offset()?;
+        Some(match at.checked_duration_since(self.base) {
+            Some(elapsed) => at_base + elapsed,
+            None => at_base - (self.base - at),
+        })
+    }
+
+    /// Host instant at the given device time, e.g. of an event the device logged
+    pub fn instant(&self, device_time: SystemTime) -> Option<Instant> {
+        let at_base = UNIX_EPOCH + self.offset()?;
+        Some(match device_time.duration_since(at_base) {
+            Ok(elapsed) => self.base + elapsed,
+            Err(err) => self.base.checked_sub(err.duration())?,
+        })
+    }
+
+    // Delays only make a sample's device time at base earlier, so the largest is the most accurate
+    fn offset(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+}
+
+/// Sends the wall-clock time of the host as TIME every period, the host is the TIME producer of
+/// the bus. Dropping this stops sending TIME
+pub struct TimeProducer {
+    period: Duration,
+    samples: broadcast::Sender<TimeSample>,
+    handle: JoinHandle<()>,
+}
+
+impl TimeProducer {
+    /// Start sending TIME on the given interface, the first one goes out immediately
+    pub fn start(canopen: &CanOpenInterface, period: Duration) -> Result<Self, DriveError> {
+        Self::from_sender(canopen.tx.clone(), period)
+    }
+
+    /// Same as [`TimeProducer::start`], but sends TIME through the given channel
+    pub fn from_sender(tx: mpsc::Sender<TxPacket>, period: Duration) -> Result<Self, DriveError> {
+        if period.is_zero() {
+            return Err(DriveError::InvalidTime("the period is zero"));
+        }
+        info!("Producing TIME every {period:?}");
+
+        let (samples, _) = broadcast::channel(16);
+        let handle = tokio::spawn(produce_time(tx, period, samples.clone()));
+
+        Ok(Self {
+            period,
+            samples,
+            handle,
+        })
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Every TIME sent from now on, the host does not receive the TIME messages it sends itself
+    pub fn subscribe(&self) -> broadcast::Receiver<TimeSample> {
+        self.samples.subscribe()
+    }
+}
+
+impl Drop for TimeProducer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn produce_time(
+    tx: mpsc::Sender<TxPacket>,
+    period: Duration,
+    samples: broadcast::Sender<TimeSample>,
+) {
+    let mut interval = time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let Some(time) = TimeOfDay::from_system_time(SystemTime::now()) else {
+            error!("The host clock lies outside of the CANopen time scale, not sending TIME");
+            continue;
+        };
+        let data = match ODValue::TimeOfDay(time).encode() {
+            Ok(data) => data,
+            Err(err) => {
+                error!("Unable to encode TIME: {err:?}");
+                continue;
+            }
+        };
+        let packet = match TxPacket::new(TIME_COB_ID, &data) {
+            Ok(packet) => packet,
+            Err(err) => {
+                error!("Unable to construct TIME: {err:?}");
+                return;
+            }
+        };
+        if let Err(err) = tx
+            .send_timeout(packet, Duration::from_millis(SEND_TIMOUT))
+            .await
+        {
+            error!("Unable to send TIME: {err:?}");
+            continue;
+        }
+
+        // Nobody following the TIME messages is fine
+        let _ = samples.send(TimeSample {
+            time,
+            at: Instant::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_epoch(ms: u64) -> SystemTime {
+        TimeOfDay { ms: 0, days: 0 }.to_system_time() + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_decode_time() {
+        let time = TimeOfDay { ms: 1500, days: 1 };
+        let data = ODValue::TimeOfDay(time).encode().unwrap();
+        assert_eq!(decode_time(&data), Some(time));
+        assert_eq!(decode_time(&[0; 5]), None);
+    }
+
+    #[test]
+    fn test_correlation() {
+        let mut correlation = TimeCorrelation::default();
+        let base = correlation.base;
+        assert_eq!(correlation.device_time(base), None);
+
+        let sample = |device_ms, host_ms| TimeSample {
+            time: TimeOfDay::from_system_time(at_epoch(device_ms)).unwrap(),
+            at: base + Duration::from_millis(host_ms),
+        };
+        // Received 3ms, 1ms and 5ms after they were stamped
+        correlation.observe(sample(1000, 3));
+        correlation.observe(sample(2000, 1001));
+        correlation.observe(sample(3000, 2005));
+
+        let host = base + Duration::from_millis(5001);
+        assert_eq!(correlation.device_time(host), Some(at_epoch(6000)));
+        assert_eq!(correlation.instant(at_epoch(6000)), Some(host));
+    }
+
+    #[tokio::test]
+    async fn test_produce() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let producer = TimeProducer::from_sender(tx, Duration::from_millis(10)).unwrap();
+        let mut samples = producer.subscribe();
+
+        let packet = rx.recv().await.unwrap();
+        assert_eq!(packet.cob_id, TIME_COB_ID);
+        let sample = samples.recv().await.unwrap();
+        assert_eq!(decode_time(&packet.data), Some(sample.time));
+        assert!(TimeProducer::from_sender(mpsc::channel(1).0, Duration::ZERO).is_err());
+
+        drop(producer);
+        while rx.recv().await.is_some() {}
+    }
+}
@@ -6,7 +6,8 @@ pub enum MotorCommand {
     /// Set continuous velocity
     Home,
 
-    /// Move to an absolute position (in device units, e.g. encoder ticks)
+    /// Move to an absolute position, in device position units (0x60A8) and velocity units (0x60A9),
+    /// see [`crate::driver::units`] for physical units
     MoveAbsolute { target: i32, profile_velocity: u32 },
 
     /// Move relative to current position
@@ -1,11 +1,14 @@
-use crate::driver::{
-    nmt::NmtState,
-    oms::OperationMode,
-    receiver::{
-        StatusWord,
-        parse::{self, sdo_response::SdoResponse},
+use crate::{
+    comms::sync::SyncSnapshot,
+    driver::{
+        nmt::NmtState,
+        oms::OperationMode,
+        receiver::{
+            StatusWord,
+            parse::{self, sdo_response::SdoResponse},
+        },
+        state::Cia402State,
     },
-    state::Cia402State,
 };
 
 /// Events broadcast by a motor driver (status updates, transitions, errors).
@@ -23,10 +26,10 @@ pub enum MotorEvent {
     /// New statusword received from device
     StatusWord(StatusWord),
 
-    /// Position feedback [counts]
+    /// Position feedback in device position units (0x60A8), see [`crate::driver::units`]
     PositionFeedback { actual_position: i32 },
 
-    /// Velocity feedback [counts/min]
+    /// Velocity feedback in device velocity units (0x60A9), see [`crate::driver::units`]
     VelocityFeedback { actual_velocity: i32 },
 
     /// Torque feedback
@@ -63,6 +66,10 @@ pub enum MotorEvent {
     /// EMCY message from motor driver
     EMCY(parse::EMCY),
 
+    /// Synchronous TPDOs received in reply to a SYNC of the host, see
+    /// [`crate::driver::Cia402Driver::attach_sync`]
+    SyncSnapshot(SyncSnapshot),
+
     /// SDO response received
     SdoResponse(SdoResponse),
 
@@ -5,45 +5,144 @@ pub mod oms;
 pub mod receiver;
 pub mod startup;
 pub mod state;
+pub mod units;
 pub mod update;
 
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc, time::SystemTime};
 
 use crate::{
     comms::{
-        pdo::{Pdo, mapping::PdoMapping},
-        sdo::SdoAction,
+        pdo::{
+            Pdo,
+            mapping::{
+                PdoMapping, PdoType,
+                profile::{PdoProfile, RemapStrategy},
+                registry::MappingRegistry,
+            },
+        },
+        sdo::{
+            SdoAction,
+            scheduler::{SdoHandle, SdoScheduler, TransferOptions},
+            validate_parameters,
+        },
+        sync::{SyncEvent, SyncProducer},
+        time::{TimeCorrelation, TimeProducer},
     },
     driver::{
         command::MotorCommand,
         event::MotorEvent,
-        nmt::{NmtState, nmt_task},
-        receiver::{setpoint_manager::SetpointManager, subscriber::handle_feedback},
-        startup::motor_startup_task,
+        nmt::{NmtState, nmt_task, switch_nmt_state, track_nmt_state},
+        receiver::{
+            setpoint_manager::SetpointManager,
+            subscriber::{collect_sync_snapshots, handle_feedback},
+        },
+        startup::{
+            MotorStartup, motor_startup_task,
+            parametrise::ParametrisationOptions,
+            pdo_mapping::{PdoSetup, configure_profile},
+        },
         state::{orchestrator::cia402_orchestrator_task, state_machine::cia402_state_machine_task},
-        update::publisher::publish_updates,
+        units::{AxisScaling, PhysicalCommand, PhysicalFeedback},
+        update::publisher::{publish_updates, refresh_rpdos},
     },
     error::DriveError,
     log::log_events,
+    od::{
+        dictionary::ObjectDictionary,
+        entry::ODEntry,
+        lint::lint,
+        value::{CodecError, ODValue},
+    },
 };
 
 use anyhow::Result;
-use oze_canopen::{interface::CanOpenInterface, sdo_client::SdoClient};
+use oze_canopen::interface::CanOpenInterface;
 use tokio::{
-    sync::{Mutex, broadcast, mpsc},
+    sync::{Mutex, broadcast, mpsc, watch},
     task::{self, JoinHandle},
+    time::Instant,
 };
 use tracing::*;
 
+/// How [`Cia402Driver::init_with_options`] sets up a driver, the defaults are those of
+/// [`Cia402Driver::init`]
+#[derive(Debug, Clone)]
+pub struct DriverOptions {
+    /// Object dictionary of the device, e.g. loaded from its EDS/DCF file, used to decode SDO
+    /// traffic and adopted PDO mappings. The parametrisation and PDO mappings are checked against
+    /// it, any disagreement is logged
+    pub dictionary: ObjectDictionary,
+    /// Whether parametrisation is read back, and how failed parametrisation actions affect the
+    /// startup
+    pub parametrisation: ParametrisationOptions,
+    /// Whether the given PDO mappings are applied to the device, or the mappings the device
+    /// already holds are adopted instead
+    pub pdo_setup: PdoSetup,
+    /// Scheduler SDO transfers go through. None uses the scheduler every driver on the CAN
+    /// interface shares, see [`SdoScheduler::shared`], so their transfers are ordered by priority
+    /// instead of contending for the bus
+    pub scheduler: Option<SdoScheduler>,
+    /// Conversion between millimetres and device units of the axis. Its unit, gear ratio and
+    /// feed constant parameters are downloaded ahead of the given parameters, which stay in
+    /// device units, see [`Cia402Driver::send_physical`]
+    pub scaling: Option<AxisScaling>,
+}
+
+impl Default for DriverOptions {
+    fn default() -> Self {
+        Self {
+            dictionary: ObjectDictionary::builtin(),
+            parametrisation: ParametrisationOptions::default(),
+            pdo_setup: PdoSetup::default(),
+            scheduler: None,
+            scaling: None,
+        }
+    }
+}
+
+impl DriverOptions {
+    pub fn with_dictionary(mut self, dictionary: ObjectDictionary) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    pub fn with_parametrisation(mut self, parametrisation: ParametrisationOptions) -> Self {
+        self.parametrisation = parametrisation;
+        self
+    }
+
+    pub fn with_pdo_setup(mut self, pdo_setup: PdoSetup) -> Self {
+        self.pdo_setup = pdo_setup;
+        self
+    }
+
+    pub fn with_scheduler(mut self, scheduler: SdoScheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn with_scaling(mut self, scaling: AxisScaling) -> Self {
+        self.scaling = Some(scaling);
+        self
+    }
+}
+
 /// CiA-402 driver built on top of a CANopen protocol manager
 pub struct Cia402Driver {
     pub node_id: u8,
     pub cmd_tx: broadcast::Sender<MotorCommand>,
     pub nmt_tx: mpsc::Sender<NmtState>,
     pub event_rx: broadcast::Receiver<MotorEvent>,
+    event_tx: broadcast::Sender<MotorEvent>,
     canopen: CanOpenInterface,
     _handles: Vec<JoinHandle<()>>,
-    sdo: Arc<Mutex<SdoClient>>,
+    sdo: SdoHandle,
+    dictionary: Arc<ObjectDictionary>,
+    nmt_state: watch::Receiver<NmtState>,
+    pdo: Arc<Mutex<Pdo>>,
+    mappings_tx: watch::Sender<MappingRegistry>,
+    time: watch::Sender<TimeCorrelation>,
+    scaling: Option<AxisScaling>,
 }
 
 impl Cia402Driver {
@@ -59,10 +158,66 @@ impl Cia402Driver {
     pub async fn init(
         node_id: u8,
         canopen: CanOpenInterface,
-        parameters: &'static [SdoAction<'_>],
+        parameters: &'static [SdoAction],
         rpdo_mapping_set: &'static [PdoMapping],
         tpdo_mapping_set: &'static [PdoMapping],
     ) -> Result<Self, DriveError> {
+        Self::init_with_options(
+            node_id,
+            canopen,
+            parameters,
+            rpdo_mapping_set,
+            tpdo_mapping_set,
+            DriverOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as [`Cia402Driver::init`], but with the given object dictionary, SDO scheduler and
+    /// startup behaviour, see [`DriverOptions`]
+    pub async fn init_with_options(
+        node_id: u8,
+        canopen: CanOpenInterface,
+        parameters: &'static [SdoAction],
+        rpdo_mapping_set: &'static [PdoMapping],
+        tpdo_mapping_set: &'static [PdoMapping],
+        options: DriverOptions,
+    ) -> Result<Self, DriveError> {
+        let DriverOptions {
+            dictionary,
+            parametrisation,
+            pdo_setup,
+            scheduler,
+            scaling,
+        } = options;
+        let sdo = scheduler.unwrap_or_else(|| SdoScheduler::shared(&canopen));
+
+        // The units have to be set before any parameter in those units is written
+        let parameters: Cow<'static, [SdoAction]> = match &scaling {
+            Some(scaling) => {
+                let mut scaled = scaling.parameters()?;
+                scaled.extend(parameters.iter().cloned());
+                Cow::Owned(scaled)
+            }
+            None => Cow::Borrowed(parameters),
+        };
+
+        let dictionary = Arc::new(dictionary);
+        check_against_dictionary(&dictionary, &parameters, rpdo_mapping_set, tpdo_mapping_set);
+
+        // Refuse parameters the device would abort, the values they leave on the device bound the
+        // motion commands
+        let known_values = validate_parameters(&dictionary, &parameters)?;
+
+        // Get the PDO client for this node id, we use this to manage R/TPDOs. This refuses RPDO
+        // mappings the device would not accept, before any task is spawned
+        let pdo = Arc::new(Mutex::new(Pdo::new(
+            canopen.clone(),
+            node_id,
+            rpdo_mapping_set,
+            known_values,
+        )?));
+
         // Track task handles that we are about to spawn
         let mut handles: Vec<JoinHandle<()>> = Vec::new();
 
@@ -79,7 +234,7 @@ impl Cia402Driver {
         // before they spawned
         let event_rx_logger = event_rx.resubscribe();
         let event_rx_nmt = event_rx.resubscribe();
-        let event_rx_startup = event_rx.resubscribe();
+        let event_rx_nmt_state = event_rx.resubscribe();
         let event_rx_cia402 = event_rx.resubscribe();
         let event_rx_setpoint_manager = event_rx.resubscribe();
         let event_tx_feedback = event_tx.clone();
@@ -89,8 +244,24 @@ impl Cia402Driver {
         let cmd_rx_publisher = cmd_rx.resubscribe();
 
         let canopen_feedback = canopen.clone();
+        let dictionary_feedback = dictionary.clone();
         let canopen_nmt = canopen.clone();
 
+        // PDOs are decoded with the requested mappings until startup reports the mappings in effect
+        let mut mappings = MappingRegistry::default();
+        mappings.insert(
+            node_id,
+            rpdo_mapping_set
+                .iter()
+                .chain(tpdo_mapping_set)
+                .map(Cow::Borrowed),
+        );
+        let (mappings_tx, mappings_rx) = watch::channel(mappings);
+
+        // Device time is unknown until a TIME message is seen on the bus
+        let time = watch::Sender::new(TimeCorrelation::default());
+        let time_feedback = time.clone();
+
         // Initialize the event_logger
         handles.push(task::spawn(async move {
             match log_events(event_rx_logger, node_id).await {
@@ -106,7 +277,9 @@ impl Cia402Driver {
             handle_feedback(
                 node_id,
                 canopen_feedback,
-                tpdo_mapping_set,
+                dictionary_feedback,
+                mappings_rx,
+                time_feedback,
                 event_tx_feedback,
             )
             .await;
@@ -124,23 +297,23 @@ impl Cia402Driver {
         // Initialize the NMT Task channel
         let (nmt_tx, nmt_rx) = tokio::sync::mpsc::channel(10);
 
-        // Get the SDO client for this node id, we use this to make SDO read/writes
-        let sdo = canopen
-            .clone()
-            .get_sdo_client(node_id)
-            .expect("Unable to construct SDO client for node id {node_id}");
-
-        // Get the PDO client for this node id, we use this to manage R/TPDOs
-        let pdo = Arc::new(Mutex::new(
-            Pdo::new(canopen.clone(), node_id, rpdo_mapping_set)
-                .expect("unable to construct PDO client for node id {node_id}"),
-        ));
+        // Get the SDO handle for this node id, we use this to make SDO read/writes
+        let sdo = sdo.handle(node_id);
 
         // Start the setpoint manager for this node, this encapsulates reactive setpoint logic by clearing CW bit 4 when device posts SW 12
         let (setpoint_manager_handle, new_setpoint_tx) =
             SetpointManager::init(event_rx_setpoint_manager, pdo.clone());
         handles.push(setpoint_manager_handle);
 
+        // Follow the NMT state of the device, SDO requests of the application depend on it
+        // Until the device reports its state it is assumed to be booting, so the startup waits for
+        // the device to report the states it requests
+        let (nmt_state_tx, nmt_state) = watch::channel(NmtState::Bootup);
+        handles.push(task::spawn(track_nmt_state(
+            event_rx_nmt_state,
+            nmt_state_tx,
+        )));
+
         // Start the NMT task
         trace!("Starting NMT State Machine task for motor with node id {node_id}");
         handles.push(task::spawn(async move {
@@ -171,9 +344,10 @@ impl Cia402Driver {
 
         // Start the publisher task, responsible for update aggregation and device communication
         trace!("Starting update publisher task for motor with node id {node_id}");
+        let pdo_publisher = pdo.clone();
         handles.push(tokio::task::spawn(async move {
             publish_updates(
-                pdo.clone(),
+                pdo_publisher,
                 state_update_rx,
                 cmd_rx_publisher,
                 new_setpoint_tx,
@@ -182,21 +356,48 @@ impl Cia402Driver {
             error!("Update Publisher task finished succesfully, this should never happen");
         }));
 
+        // Keep RPDOs with an event timer from timing out on the device
+        handles.push(task::spawn(refresh_rpdos(pdo.clone())));
+
         // Start the startup task for this motor, this does parametrisation and configures pdo mapping
         trace!("Performing Startup for motor at node id {node_id}");
-        if let Err(err) = motor_startup_task(
+        let startup = MotorStartup {
             node_id,
+            dictionary: &dictionary,
+            parameters: &parameters,
+            parametrisation,
+            rpdo_mapping: rpdo_mapping_set,
+            tpdo_mapping: tpdo_mapping_set,
+            pdo_setup,
+        };
+        let mappings = match motor_startup_task(
+            startup,
+            sdo.with_options(TransferOptions::STARTUP),
             nmt_tx.clone(),
-            sdo.clone(),
-            parameters,
-            rpdo_mapping_set,
-            tpdo_mapping_set,
-            event_rx_startup,
+            nmt_state.clone(),
         )
         .await
         {
-            error!("Unable to perform startup for motor at node id {node_id}: {err}");
-            return Err(err);
+            Ok(mappings) => mappings,
+            Err(err) => {
+                error!("Unable to perform startup for motor at node id {node_id}: {err}");
+                return Err(err);
+            }
+        };
+
+        // Adopted mappings replace the requested ones for both directions
+        if pdo_setup == PdoSetup::Adopt {
+            let rpdo_mapping_set = mappings
+                .iter()
+                .filter(|mapping| matches!(mapping.pdo, PdoType::RPDO(_)))
+                .cloned()
+                .collect();
+            pdo.lock().await.remap(rpdo_mapping_set)?;
+
+            mappings_tx.send_modify(|registry| {
+                registry.remove(node_id);
+                registry.insert(node_id, mappings);
+            });
         }
         trace!("Startup done for motor at node id {node_id}");
 
@@ -207,9 +408,249 @@ impl Cia402Driver {
             cmd_tx,
             nmt_tx,
             event_rx: event_rx.resubscribe(),
+            event_tx,
             canopen,
             _handles: handles,
             sdo,
+            dictionary,
+            nmt_state,
+            pdo,
+            mappings_tx,
+            time,
+            scaling,
         })
     }
+
+    /// Conversion between millimetres and the device units of this axis, see
+    /// [`DriverOptions::with_scaling`]
+    pub fn scaling(&self) -> Option<&AxisScaling> {
+        self.scaling.as_ref()
+    }
+
+    /// Send a command in millimetres, converted to device units with the scaling of this axis
+    pub fn send_physical(&self, command: &PhysicalCommand) -> Result<(), DriveError> {
+        let scaling = self.scaling.as_ref().ok_or(DriveError::NoScaling {
+            node_id: self.node_id,
+        })?;
+
+        self.cmd_tx
+            .send(scaling.command(command)?)
+            .map_err(DriveError::CommandError)?;
+        Ok(())
+    }
+
+    /// Position and velocity feedback of the given event in millimetres, None for every other
+    /// event or without a scaling
+    pub fn physical_feedback(&self, event: &MotorEvent) -> Option<PhysicalFeedback> {
+        self.scaling.as_ref()?.feedback(event)
+    }
+
+    /// Object dictionary of the managed device
+    pub fn dictionary(&self) -> &ObjectDictionary {
+        &self.dictionary
+    }
+
+    /// NMT state last reported by the device
+    pub fn nmt_state(&self) -> NmtState {
+        self.nmt_state.borrow().clone()
+    }
+
+    /// Wall-clock time of the device at the given host instant, e.g. the timestamp of a received
+    /// frame. None until a TIME message was seen on the bus or sent by an attached producer
+    pub fn device_time(&self, at: Instant) -> Option<SystemTime> {
+        self.time.borrow().device_time(at)
+    }
+
+    /// Host instant at the given wall-clock time of the device, e.g. of an event it logged
+    pub fn host_instant(&self, device_time: SystemTime) -> Option<Instant> {
+        self.time.borrow().instant(device_time)
+    }
+
+    /// Correlate the device clock with the TIME messages of the given producer, the host does
+    /// not receive the TIME messages it sends itself
+    pub fn attach_time(&mut self, producer: &TimeProducer) {
+        let time = self.time.clone();
+        let mut samples = producer.subscribe();
+        self._handles.push(task::spawn(async move {
+            loop {
+                match samples.recv().await {
+                    Ok(sample) => time.send_modify(|correlation| correlation.observe(sample)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Missed {skipped} TIME messages");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }));
+    }
+
+    /// Read an object from the device, e.g. `drive.read(&od::ERROR_CODE)`, decoded as the data
+    /// type of the entry. Entries of [`Cia402Driver::dictionary`] work as well
+    pub async fn read(&self, entry: &ODEntry) -> Result<ODValue, DriveError> {
+        entry.check_readable()?;
+        let data_type = entry.default.data_type().ok_or(CodecError::Container)?;
+
+        self.runtime_sdo()?
+            .upload_value(entry.index, entry.sub_index, data_type)
+            .await
+    }
+
+    /// Write a value to an object on the device, e.g.
+    /// `drive.write(&od::PROFILE_ACCELERATION, ODValue::U32(500))`. The value is checked against
+    /// the access type, data type and limits of the entry before anything is sent
+    pub async fn write(&self, entry: &ODEntry, value: ODValue) -> Result<(), DriveError> {
+        entry.check_download(&value)?;
+
+        self.runtime_sdo()?
+            .download(entry.index, entry.sub_index, &value.encode()?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remap the PDOs of the device to the given profile, e.g. before switching to an operation
+    /// mode the current mappings do not carry the objects of. The strategy decides whether the
+    /// device leaves NMT Operational for this, only switch while the drive is not moving
+    /// The PDO client and the feedback task change mappings together: nothing is sent while the
+    /// device is remapped, and PDOs received in the meantime are left undecoded. A failed switch
+    /// can leave the device partially remapped, switching again recovers from that
+    pub async fn switch_profile(
+        &self,
+        profile: &'static PdoProfile,
+        strategy: RemapStrategy,
+    ) -> Result<(), DriveError> {
+        profile.validate()?;
+        info!(
+            "Switching node id {} to PDO profile {} - {strategy:?}",
+            self.node_id, profile.name
+        );
+
+        // Hold the PDO client for the whole switch, so no RPDO goes out with the old mappings
+        let mut pdo = self.pdo.lock().await;
+
+        let mut nmt_state = self.nmt_state.clone();
+        if strategy == RemapStrategy::PreOperational {
+            switch_nmt_state(
+                self.node_id,
+                &self.nmt_tx,
+                &mut nmt_state,
+                NmtState::PreOperational,
+            )
+            .await?;
+        }
+
+        configure_profile(
+            self.node_id,
+            self.runtime_sdo()?,
+            &self.dictionary,
+            profile,
+            &self.mappings_tx,
+        )
+        .await?;
+        pdo.remap(profile.rpdos.iter().map(Cow::Borrowed).collect())?;
+
+        if strategy == RemapStrategy::PreOperational {
+            switch_nmt_state(
+                self.node_id,
+                &self.nmt_tx,
+                &mut nmt_state,
+                NmtState::Operational,
+            )
+            .await?;
+        }
+        info!("Node id {} uses PDO profile {}", self.node_id, profile.name);
+
+        Ok(())
+    }
+
+    /// Follow the cycles of the given SYNC producer: synchronous RPDOs are held back and sent in
+    /// the window before every SYNC, and the synchronous TPDOs the device replies with are
+    /// broadcast as one [`MotorEvent::SyncSnapshot`] per cycle
+    /// Drivers sharing a CAN interface should attach to the same producer
+    pub async fn attach_sync(&mut self, sync: &SyncProducer) -> Result<(), DriveError> {
+        info!(
+            "Node id {} follows SYNC - {:?}",
+            self.node_id,
+            sync.config()
+        );
+        self.pdo.lock().await.defer_synchronous(true).await?;
+
+        let pdo = self.pdo.clone();
+        let mut sync_rx = sync.subscribe();
+        self._handles.push(task::spawn(async move {
+            loop {
+                match sync_rx.recv().await {
+                    Ok(SyncEvent::Window { .. }) => {
+                        if let Err(err) = pdo.lock().await.flush_synchronous().await {
+                            error!("Unable to send synchronous RPDOs: {err}");
+                        }
+                    }
+                    Ok(SyncEvent::Sent { .. }) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Missed {skipped} SYNC events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+
+            // Without SYNCs nothing would flush the synchronous RPDOs anymore
+            if let Err(err) = pdo.lock().await.defer_synchronous(false).await {
+                error!("Unable to send synchronous RPDOs: {err}");
+            }
+        }));
+
+        self._handles.push(task::spawn(collect_sync_snapshots(
+            self.node_id,
+            self.canopen.rx.resubscribe(),
+            self.mappings_tx.subscribe(),
+            sync.subscribe(),
+            self.event_tx.clone(),
+        )));
+
+        Ok(())
+    }
+
+    /// SDO handle for requests of the application, these go ahead of bulk transfers
+    fn runtime_sdo(&self) -> Result<SdoHandle, DriveError> {
+        let state = self.nmt_state();
+        if !state.allows_sdo() {
+            return Err(DriveError::SdoUnavailable {
+                node_id: self.node_id,
+                state,
+            });
+        }
+
+        Ok(self.sdo.with_options(TransferOptions::RUNTIME))
+    }
+}
+
+/// Log every parameter and mapped PDO entry that disagrees with the given object dictionary, and
+/// every inconsistency within the dictionary itself
+fn check_against_dictionary(
+    dictionary: &ObjectDictionary,
+    parameters: &[SdoAction],
+    rpdo_mapping_set: &[PdoMapping],
+    tpdo_mapping_set: &[PdoMapping],
+) {
+    let parameter_entries = parameters.iter().map(|action| match action {
+        SdoAction::Download { entry, .. } => *entry,
+        SdoAction::Upload { entry } => *entry,
+    });
+    let mapped_entries = rpdo_mapping_set
+        .iter()
+        .chain(tpdo_mapping_set)
+        .flat_map(|mapping| mapping.sources.iter().map(|source| &*source.entry));
+
+    for entry in parameter_entries.chain(mapped_entries) {
+        if let Err(mismatch) = dictionary.check(entry) {
+            warn!(
+                "{} does not match the object dictionary: {mismatch}",
+                entry.name
+            );
+        }
+    }
+
+    for issue in lint(dictionary.iter()) {
+        warn!("Inconsistent object dictionary: {issue}");
+    }
 }
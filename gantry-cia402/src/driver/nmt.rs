@@ -3,11 +3,20 @@ use oze_canopen::{
     interface::CanOpenInterface,
     proto::nmt::{NmtCommand, NmtCommandSpecifier},
 };
-use tokio::sync::{broadcast, mpsc};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc, watch,
+    },
+    time::timeout,
+};
 use tracing::*;
 
 use crate::{
-    driver::event::MotorEvent,
+    driver::{
+        event::MotorEvent,
+        startup::{NMT_SWITCH_ATTEMPTS, NMT_SWITCH_TIMEOUT},
+    },
     error::DriveError,
 };
 
@@ -19,6 +28,13 @@ pub enum NmtState {
     Operational,
 }
 
+impl NmtState {
+    /// SDO transfers are only possible in NMT Pre-Operational and Operational (CiA 301 § 7.3.2.2)
+    pub fn allows_sdo(&self) -> bool {
+        matches!(self, NmtState::PreOperational | NmtState::Operational)
+    }
+}
+
 impl Into<NmtCommandSpecifier> for NmtState {
     fn into(self) -> NmtCommandSpecifier {
         match self {
@@ -30,6 +46,51 @@ impl Into<NmtCommandSpecifier> for NmtState {
     }
 }
 
+/// Follow the NMT state reported by the device
+pub async fn track_nmt_state(
+    mut event_rx: broadcast::Receiver<MotorEvent>,
+    state_tx: watch::Sender<NmtState>,
+) {
+    loop {
+        match event_rx.recv().await {
+            Ok(MotorEvent::NmtStateUpdate(state)) => {
+                state_tx.send_replace(state);
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Request the given NMT state through the NMT task and wait until the device reports it, the
+/// request is repeated every [`NMT_SWITCH_TIMEOUT`] up to [`NMT_SWITCH_ATTEMPTS`] times
+pub async fn switch_nmt_state(
+    node_id: u8,
+    nmt_tx: &mpsc::Sender<NmtState>,
+    nmt_state: &mut watch::Receiver<NmtState>,
+    state: NmtState,
+) -> Result<(), DriveError> {
+    for attempt in 1..=NMT_SWITCH_ATTEMPTS {
+        nmt_tx
+            .send(state.clone())
+            .await
+            .map_err(|err| DriveError::NMTSendError(state.clone(), err))?;
+
+        let reported = nmt_state.wait_for(|current| *current == state);
+        match timeout(NMT_SWITCH_TIMEOUT, reported).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(_)) => break,
+            Err(_) => warn!("Node id {node_id} is not in NMT {state:?} yet, attempt {attempt}"),
+        }
+    }
+
+    Err(DriveError::NmtSwitchFailed {
+        node_id,
+        state,
+        attempts: NMT_SWITCH_ATTEMPTS,
+    })
+}
+
 pub async fn nmt_task(
     node_id: u8,
     canopen: CanOpenInterface,
@@ -1,22 +1,43 @@
-use oze_canopen::canopen::RxMessage;
+use oze_canopen::canopen::{NodeId, RxMessage};
 use tracing::error;
 
 use crate::{
-    comms::pdo::mapping::PdoType,
+    comms::{
+        pdo::mapping::{PdoMapping, PdoType, registry::MappingRegistry},
+        time::decode_time,
+    },
     driver::{
         nmt::NmtState,
-        receiver::parse::{pdo_message::*, *},
+        receiver::parse::{pdo_message::*, sdo_transfer::SdoTransfers, *},
         *,
     },
+    od::registry::DictionaryRegistry,
 };
 
-impl TryFrom<RxMessage> for Frame {
-    type Error = ParseError;
-
-    fn try_from(frame: RxMessage) -> Result<Frame, ParseError> {
+impl Frame {
+    /// Parse a received message, SDO transfers are followed across frames and resolved against
+    /// the object dictionary of the node serving them, PDOs are decoded with the mapping of the
+    /// node sending or receiving them
+    /// PDOs are routed by the COB-IDs of the registered mappings first, so PDOs with an explicit
+    /// COB-ID are attributed to their node as well
+    pub fn from_message(
+        frame: RxMessage,
+        dictionaries: &DictionaryRegistry,
+        mappings: &MappingRegistry,
+        transfers: &mut SdoTransfers,
+    ) -> Result<Frame, ParseError> {
         let id = frame.cob_id;
         let timestamp = frame.timestamp;
 
+        if let Some((node, mapping)) = mappings.lookup(id) {
+            let parsed = parse_pdo(node, mapping.pdo.clone(), Some(mapping), &frame)?;
+            return Ok(Frame {
+                timestamp,
+                node_id: Some(node),
+                message: MessageType::PDO(parsed),
+            });
+        }
+
         let (node_id, message) = match id {
             // 0x000 → NMT Command
             0x000 => {
@@ -35,7 +56,12 @@ impl TryFrom<RxMessage> for Frame {
             }
 
             // 0x080 → SYNC
-            0x080 => (None, MessageType::Sync(SyncMessage)),
+            0x080 => (
+                None,
+                MessageType::Sync(SyncMessage {
+                    counter: (frame.dlc > 0).then_some(frame.data[0]),
+                }),
+            ),
 
             // 0x081–0x0FF → EMCY (Emergency)
             0x081..=0x0FF => {
@@ -75,75 +101,41 @@ impl TryFrom<RxMessage> for Frame {
                 (node_id, MessageType::EMCY(EmergencyMessage { error }))
             }
 
-            // T/RPDO1..4 (0x180 + n*0x200)
-            0x180..=0x57F => {
-                let (kind, base) = match id {
-                    0x180..=0x1FF => (PdoType::TPDO(1), 0x180),
-                    0x200..=0x21F => (PdoType::RPDO(1), 0x200),
-                    0x280..=0x2FF => (PdoType::TPDO(2), 0x280),
-                    0x300..=0x31F => (PdoType::RPDO(2), 0x300),
-                    0x380..=0x3FF => (PdoType::TPDO(3), 0x380),
-                    0x400..=0x41F => (PdoType::RPDO(3), 0x400),
-                    0x480..=0x4FF => (PdoType::TPDO(4), 0x480),
-                    0x500..=0x51F => (PdoType::RPDO(4), 0x500),
-                    _ => unreachable!(),
-                };
-                // let node_id = Some((id - base) as u8);
-                let node = (id - base) as u8;
-
-                let (num, message) = match kind {
-                    PdoType::RPDO(1) => (1, PDOMessage::RPDO1(frame.data.try_into()?)),
-                    PdoType::RPDO(2) => (2, PDOMessage::RPDO2(frame.data.try_into()?)),
-                    PdoType::RPDO(3) => (3, PDOMessage::RPDO3(frame.data.try_into()?)),
-                    PdoType::RPDO(4) => (4, PDOMessage::RPDO4(frame.data.try_into()?)),
-                    PdoType::TPDO(1) => (1, PDOMessage::TPDO1(frame.data.try_into()?)),
-                    PdoType::TPDO(2) => (2, PDOMessage::TPDO2(frame.data.try_into()?)),
-                    PdoType::TPDO(3) => (3, PDOMessage::TPDO3(frame.data.try_into()?)),
-                    PdoType::TPDO(4) => (4, PDOMessage::TPDO4(frame.data.try_into()?)),
-                    _ => (
-                        255,
-                        PDOMessage::Raw(RawPDOMessage {
-                            cob_id: id as usize,
-                            data: frame.data,
-                            dlc: frame.dlc,
-                        }),
-                    ),
-                };
-
-                let parsed = ParsedPDO {
-                    node,
-                    num,
-                    kind,
-                    message,
-                    raw_data: frame.data,
-                    raw_dlc: frame.dlc,
-                };
-
-                (Some(node), MessageType::PDO(parsed))
-            }
+            // 0x100 → TIME
+            0x100 => (
+                None,
+                MessageType::Time(TimeMessage {
+                    time: decode_time(&frame.data[..frame.dlc]),
+                }),
+            ),
+
+            // T/RPDO1..4 of the predefined connection set, the mapping is unknown
+            0x180..=0x57F => match PdoType::from_predefined_cob_id(id) {
+                Some((node, kind)) => {
+                    let parsed = parse_pdo(node, kind, None, &frame)?;
+                    (Some(node), MessageType::PDO(parsed))
+                }
+                None => (None, MessageType::Unknown(frame)),
+            },
 
             // 0x580–0x5FF → TSDO (Server→Client)
             0x580..=0x5FF => {
-                // let value = ODEntry::from_sdo_download(&frame.data, frame.dlc);
-                let node_id = Some((frame.cob_id - 0x580) as u8);
-                let response = SdoResponse::from_frame(&frame).map_err(ParseError)?;
+                let node = (frame.cob_id - 0x580) as u8;
+                let response = transfers
+                    .response(node, &frame, dictionaries.get(node))
+                    .map_err(ParseError)?;
+                let node_id = Some(node);
 
                 (node_id, MessageType::TSDO(response))
             }
 
             // 0x600–0x67F → RSDO (Client→Server)
             0x600..=0x67F => {
-                let node_id = Some((id - 0x600) as u8);
-                let value = ODEntry::from_sdo_download(&frame.data, frame.dlc);
+                let node = (id - 0x600) as u8;
+                let request = transfers.request(node, &frame, dictionaries.get(node));
+                let node_id = Some(node);
 
-                (
-                    node_id,
-                    MessageType::RSDO(SdoRequest {
-                        data: frame.data,
-                        dlc: frame.dlc,
-                        value,
-                    }),
-                )
+                (node_id, MessageType::RSDO(request))
             }
 
             // 0x700–0x77F → Heartbeat / Node Monitoring
@@ -172,3 +164,29 @@ impl TryFrom<RxMessage> for Frame {
         })
     }
 }
+
+/// PDO of the given node, decoded with its mapping if it is known
+fn parse_pdo(
+    node: NodeId,
+    kind: PdoType,
+    mapping: Option<&PdoMapping>,
+    frame: &RxMessage,
+) -> Result<ParsedPDO, ParseError> {
+    let num = match kind {
+        PdoType::RPDO(num) | PdoType::TPDO(num) => num,
+    };
+
+    let values = mapping
+        .map(|mapping| mapping.decode(&frame.data[..frame.dlc]))
+        .transpose()
+        .map_err(|err| ParseError(err.into()))?;
+
+    Ok(ParsedPDO {
+        node,
+        num,
+        kind,
+        values,
+        raw_data: frame.data,
+        raw_dlc: frame.dlc,
+    })
+}
@@ -2,7 +2,7 @@ use tracing::*;
 
 use crate::{
     comms::pdo::mapping::PdoType,
-    driver::receiver::parse::{MessageType, pdo_message::PrettyPdo, *},
+    driver::receiver::parse::{MessageType, pdo_message::PrettyPdo, sdo_response::SdoExchange, *},
 };
 
 // 1) change your Frame::log to emit structured fields (no ANSI)
@@ -19,7 +19,12 @@ impl Frame {
                     message = %format!("{:?}", msg.error)
                 );
             }
-            MessageType::Sync(_) => info!(target: "canopen", frame = "SYNC"),
+            MessageType::Sync(msg) => {
+                info!(target: "canopen", frame = "SYNC", data = %format!("{:?}", msg.counter))
+            }
+            MessageType::Time(msg) => {
+                info!(target: "canopen", frame = "TIME", data = %format!("{:?}", msg.time))
+            }
             MessageType::NmtMonitor(msg) => {
                 info!(
                     target: "canopen",
@@ -32,8 +37,9 @@ impl Frame {
                 info!(target: "canopen", frame = "NmtControl", node = self.node_id.unwrap_or(0) as u64, data =
                     %format!("{:?}", msg.requested_state));
             }
+            // Single frames of a transaction, see SdoExchange::log
             MessageType::TSDO(msg) => {
-                info!(
+                debug!(
                     target: "canopen",
                     frame = "TSDO",
                     node = self.node_id.unwrap_or(0) as u64,
@@ -41,12 +47,12 @@ impl Frame {
                 );
             }
             MessageType::RSDO(msg) => {
-                info!(
+                debug!(
                     target: "canopen",
                     frame = "RSDO",
                     node = self.node_id.unwrap_or(0) as u64,
                     data = %hex_dump(&msg.data[..msg.dlc]),
-                    parsed = ?msg.value,
+                    parsed = msg.fmt_pretty(),
                 );
             }
             MessageType::PDO(msg) => {
@@ -81,6 +87,28 @@ impl Frame {
     }
 }
 
+impl SdoExchange {
+    /// One line per transaction, failed transactions are logged as warnings
+    pub fn log(&self) {
+        match self.is_success() {
+            true => info!(
+                target: "canopen",
+                frame = "SDO",
+                node = self.node as u64,
+                failed = false,
+                parsed = self.fmt_pretty()
+            ),
+            false => warn!(
+                target: "canopen",
+                frame = "SDO",
+                node = self.node as u64,
+                failed = true,
+                parsed = self.fmt_pretty()
+            ),
+        }
+    }
+}
+
 pub fn hex_dump(data: &[u8]) -> String {
     data.iter()
         .map(|b| format!("{:#2X}", b))
@@ -1,8 +1,8 @@
 pub mod frame;
 pub mod log;
-pub mod pdo;
 pub mod pdo_message;
 pub mod sdo_response;
+pub mod sdo_transfer;
 
 use oze_canopen::canopen::{NodeId, RxMessage};
 use tokio::time::Instant;
@@ -15,7 +15,7 @@ use crate::{
             sdo_response::{SdoRequest, SdoResponse},
         },
     },
-    od::entry::ODEntry,
+    od::{entry::ODEntry, value::TimeOfDay},
 };
 
 #[derive(Debug)]
@@ -31,6 +31,7 @@ pub struct Frame {
 pub enum MessageType {
     NmtControl(NmtControlMessage),
     Sync(SyncMessage), // No node id
+    Time(TimeMessage), // No node id
     EMCY(EmergencyMessage),
     TSDO(SdoResponse),
     RSDO(SdoRequest),
@@ -50,7 +51,16 @@ pub struct NmtMonitorMessage {
 }
 
 #[derive(Debug)]
-pub struct SyncMessage;
+pub struct SyncMessage {
+    /// Synchronous counter, if the producer sends one (1019h)
+    pub counter: Option<u8>,
+}
+
+#[derive(Debug)]
+pub struct TimeMessage {
+    /// None if the frame is too short to hold a TIME_OF_DAY
+    pub time: Option<TimeOfDay>,
+}
 
 #[derive(Debug)]
 pub struct EmergencyMessage {
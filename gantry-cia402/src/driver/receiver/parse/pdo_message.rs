@@ -1,37 +1,32 @@
 use oze_canopen::canopen::NodeId;
 
 use crate::{
-    comms::pdo::mapping::PdoType,
-    driver::{
-        oms::{OMSFlagsSW, OperationMode},
-        receiver::StatusWord,
-        update::ControlWord,
-    },
+    comms::pdo::{codec::PdoValue, mapping::PdoType},
+    driver::{oms::OperationMode, receiver::StatusWord, update::ControlWord},
+    od::typed::{self, ODType, TypedEntry},
 };
 
-#[derive(Debug, Clone)]
-pub enum PDOMessage {
-    TPDO1(TPDO1Message),
-    TPDO2(TPDO2Message),
-    TPDO3(TPDO3Message),
-    TPDO4(TPDO4Message),
-    RPDO1(RPDO1Message),
-    RPDO2(RPDO2Message),
-    RPDO3(RPDO3Message),
-    RPDO4(RPDO4Message),
-    Raw(RawPDOMessage),
-}
-
 #[derive(Debug, Clone)]
 pub struct ParsedPDO {
     pub node: NodeId,
     pub num: u8,
     pub kind: PdoType,
-    pub message: PDOMessage,
+    /// Values of the mapped entries, None if the mapping of this PDO is unknown
+    pub values: Option<Vec<PdoValue>>,
     pub raw_data: [u8; 8],
     pub raw_dlc: usize,
 }
 
+impl ParsedPDO {
+    /// Value of the given entry, if it is mapped in this PDO
+    pub fn get<T: ODType>(&self, entry: TypedEntry<T>) -> Option<T> {
+        self.values
+            .iter()
+            .flatten()
+            .find_map(|value| value.get(entry))
+    }
+}
+
 pub struct PrettyPdo {
     pub header: String,
     pub raw: String,
@@ -43,22 +38,9 @@ impl From<ParsedPDO> for PrettyPdo {
         let header = value.kind.to_string_pretty();
         let raw = format!("{0:x?}", value.raw_data[..value.raw_dlc].to_vec());
 
-        let parsed = match &value.message {
-            PDOMessage::TPDO1(m) => format!(
-                "{0:?} - {1:?} => {2:?}",
-                m.statusword, m.actual_opmode, m.oms_flags
-            ),
-            PDOMessage::TPDO2(m) => format!("pos: {0:?} - vel: {1:?}", m.actual_pos, m.actual_vel),
-            PDOMessage::TPDO3(m) => format!("torque {0:?}", m.actual_torque),
-            PDOMessage::TPDO4(_) => String::new(),
-            PDOMessage::RPDO1(m) => format!("{0:?} - {1:?}", m.controlword, m.opmode),
-            PDOMessage::RPDO2(m) => format!(
-                "target pos: {0:?} - profile vel: {1:?}",
-                m.target_pos, m.profile_velocity
-            ),
-            PDOMessage::RPDO3(m) => format!("target vel: {0:?}", m.target_velocity),
-            PDOMessage::RPDO4(m) => format!("target torque: {0:?}", m.target_torque),
-            PDOMessage::Raw(_) => String::new(),
+        let parsed = match &value.values {
+            Some(values) => values.iter().map(fmt_value).collect::<Vec<_>>().join(" - "),
+            None => String::from("unknown mapping"),
         };
 
         PrettyPdo {
@@ -69,52 +51,19 @@ impl From<ParsedPDO> for PrettyPdo {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RawPDOMessage {
-    pub cob_id: usize,
-    pub data: [u8; 8],
-    pub dlc: usize,
-}
-
-#[derive(Debug, Clone)]
-pub struct TPDO1Message {
-    pub statusword: StatusWord,
-    pub actual_opmode: OperationMode,
-    pub oms_flags: OMSFlagsSW,
-}
-
-#[derive(Debug, Clone)]
-pub struct TPDO2Message {
-    pub actual_pos: i32,
-    pub actual_vel: i32,
-}
-
-#[derive(Debug, Clone)]
-pub struct TPDO3Message {
-    pub actual_torque: i16,
-}
-
-#[derive(Debug, Clone)]
-pub struct TPDO4Message;
-
-#[derive(Debug, Clone)]
-pub struct RPDO1Message {
-    pub controlword: ControlWord,
-    pub opmode: OperationMode,
-}
-
-#[derive(Debug, Clone)]
-pub struct RPDO2Message {
-    pub target_pos: i32,
-    pub profile_velocity: u32,
-}
-
-#[derive(Debug, Clone)]
-pub struct RPDO3Message {
-    pub target_velocity: i32,
-}
-
-#[derive(Debug, Clone)]
-pub struct RPDO4Message {
-    pub target_torque: i16,
+/// Bitfields and operation modes by name, everything else with the unit of its entry
+fn fmt_value(value: &PdoValue) -> String {
+    if let Some(statusword) = value.get(typed::STATUS_WORD) {
+        format!("{:?}", StatusWord::from_bits_truncate(statusword))
+    } else if let Some(controlword) = value.get(typed::CONTROL_WORD) {
+        format!("{:?}", ControlWord::from_bits_truncate(controlword))
+    } else if let Some(opmode) = value
+        .get(typed::GET_OPERATION_MODE)
+        .or_else(|| value.get(typed::SET_OPERATION_MODE))
+        .and_then(|opmode| OperationMode::try_from(opmode).ok())
+    {
+        format!("{opmode:?}")
+    } else {
+        value.fmt_pretty()
+    }
 }
@@ -1,29 +1,66 @@
-use oze_canopen::canopen::{NodeId, RxMessage};
+use std::time::Duration;
 
-use crate::{driver::receiver::parse::log::hex_dump, od::entry::ODEntry};
+use oze_canopen::canopen::NodeId;
 
+use crate::{
+    comms::sdo::{abort::SdoAbortCode, protocol::ClientFrame},
+    driver::receiver::parse::log::hex_dump,
+    od::entry::ODEntry,
+};
+
+/// RSDO frame, see [`super::sdo_transfer::SdoTransfers::request`]
 #[derive(Debug)]
 pub struct SdoRequest {
     pub data: [u8; 8],
     pub dlc: usize,
+    /// None for the segments of a block download and frames we can not decode
+    pub frame: Option<ClientFrame>,
+    /// Downloaded value, on the frame that completes the data of the download
     pub value: Option<ODEntry>,
 }
 
+impl SdoRequest {
+    /// The downloaded value with its unit, empty if the entry is unknown or the download
+    /// incomplete
+    pub fn fmt_pretty(&self) -> String {
+        if let Some(ClientFrame::Abort {
+            index,
+            sub_index,
+            code,
+        }) = &self.frame
+        {
+            return format!("SDO Abort for {index:#0x}:{sub_index} - {code}");
+        }
+        match &self.value {
+            Some(entry) => format!(
+                "SDO Download for {:#0x}:{} <= {}",
+                entry.index,
+                entry.sub_index,
+                entry.fmt_value()
+            ),
+            None => String::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SdoError {
     pub from: NodeId,
     pub index: u16,
     pub sub_index: u8,
-    pub code: u32,
+    pub code: SdoAbortCode,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SdoUploadResult {
     pub from: NodeId,
-    pub dlc: u8,
     pub index: u16,
     pub sub_index: u8,
-    pub data: [u8; 4],
+    /// All data of the transfer, expedited, segmented or block
+    pub data: Vec<u8>,
+    /// Uploaded value, if the entry is known to the dictionary of the sending node. Boxed, the
+    /// metadata of an entry would make every [`crate::driver::event::MotorEvent`] huge
+    pub value: Option<Box<ODEntry>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -33,68 +70,32 @@ pub struct SdoDownloadConfirmed {
     pub sub_index: u8,
 }
 
+/// Intermediate frame of a segmented or block transfer
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdoProgress {
+    pub from: NodeId,
+    pub index: u16,
+    pub sub_index: u8,
+    /// Bytes received so far, block segments count once their sub-block is acknowledged
+    pub transferred: usize,
+    pub size: Option<u32>,
+}
+
+/// TSDO frame, see [`super::sdo_transfer::SdoTransfers::response`]. Transfers only confirm once
+/// all their data is transferred
 #[derive(Debug, Clone, PartialEq)]
 pub enum SdoResponse {
     Error(SdoError),
     DownloadConfirm(SdoDownloadConfirmed),
     UploadConfirm(SdoUploadResult),
+    Progress(SdoProgress),
 }
 
 impl SdoResponse {
-    pub fn from_frame(frame: &RxMessage) -> anyhow::Result<Self> {
-        let from = (frame.cob_id - 0x580) as u8;
-        let payload = &frame.data[4..frame.dlc.min(8)];
-
-        match frame.data[0] {
-            0x80 => Ok(SdoResponse::Error(SdoError {
-                from,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-                code: u32::from_le_bytes(payload.try_into()?),
-            })),
-            0x60 => Ok(SdoResponse::DownloadConfirm(SdoDownloadConfirmed {
-                from,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-            })),
-            0x4F => Ok(SdoResponse::UploadConfirm(SdoUploadResult {
-                from,
-                dlc: 1,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-                data: payload.try_into()?,
-            })),
-            0x4B => Ok(SdoResponse::UploadConfirm(SdoUploadResult {
-                from,
-                dlc: 2,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-                data: payload.try_into()?,
-            })),
-            0x47 => Ok(SdoResponse::UploadConfirm(SdoUploadResult {
-                from,
-                dlc: 3,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-                data: payload.try_into()?,
-            })),
-            0x43 => Ok(SdoResponse::UploadConfirm(SdoUploadResult {
-                from,
-                dlc: 4,
-                index: u16::from_le_bytes(frame.data[1..3].try_into()?),
-                sub_index: frame.data[3],
-                data: payload.try_into()?,
-            })),
-            _ => {
-                anyhow::bail!("Unable to parse {frame:?} into SdoResponse");
-            }
-        }
-    }
-
     pub fn fmt_pretty(&self) -> String {
         match &self {
             SdoResponse::Error(sdo_error) => format!(
-                "SDO Error for {:#0x}:{} - code {:#0x}",
+                "SDO Error for {:#0x}:{} - {}",
                 sdo_error.index, sdo_error.sub_index, sdo_error.code
             )
             .to_string(),
@@ -103,6 +104,14 @@ impl SdoResponse {
                 sdo_download_result.index, sdo_download_result.sub_index
             )
             .to_string(),
+            SdoResponse::UploadConfirm(SdoUploadResult {
+                value: Some(entry), ..
+            }) => format!(
+                "SDO Upload Confirm for {:#0x}:{} => {}",
+                entry.index,
+                entry.sub_index,
+                entry.fmt_value()
+            ),
             SdoResponse::UploadConfirm(sdo_upload_result) => format!(
                 "SDO Upload Confirm for {:#0x}:{} => [{}]",
                 sdo_upload_result.index,
@@ -110,6 +119,89 @@ impl SdoResponse {
                 hex_dump(&sdo_upload_result.data)
             )
             .to_string(),
+            SdoResponse::Progress(progress) => match progress.size {
+                Some(size) => format!(
+                    "SDO Transfer of {:#0x}:{} - {}/{size} bytes",
+                    progress.index, progress.sub_index, progress.transferred
+                ),
+                None => format!(
+                    "SDO Transfer of {:#0x}:{} - {} bytes",
+                    progress.index, progress.sub_index, progress.transferred
+                ),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdoDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SdoOutcome {
+    Completed,
+    /// Aborted by the server, or by the client if `by_client`
+    Aborted {
+        code: SdoAbortCode,
+        by_client: bool,
+    },
+    /// The server did not finish the transfer within the SDO timeout
+    Unanswered,
+}
+
+/// An SDO transaction on the bus, the request of a client paired with the response of the server,
+/// see [`super::sdo_transfer::SdoTransfers::completed`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdoExchange {
+    pub node: NodeId,
+    pub index: u16,
+    pub sub_index: u8,
+    pub direction: SdoDirection,
+    /// Name of the object, if known to the dictionary of the node
+    pub name: Option<String>,
+    /// All data of the transfer, reassembled for segmented and block transfers
+    pub data: Vec<u8>,
+    /// Transferred value, if the object is known and the transfer completed
+    pub value: Option<Box<ODEntry>>,
+    /// Time from the first request until the last response, or until it was flagged unanswered
+    pub latency: Duration,
+    pub outcome: SdoOutcome,
+}
+
+impl SdoExchange {
+    pub fn is_success(&self) -> bool {
+        self.outcome == SdoOutcome::Completed
+    }
+
+    /// One line summary, e.g. `Upload 0x6041:0 Statusword = 567 (1.2ms)`
+    pub fn fmt_pretty(&self) -> String {
+        let direction = match self.direction {
+            SdoDirection::Upload => "Upload",
+            SdoDirection::Download => "Download",
+        };
+        let object = format!("{:#06x}:{}", self.index, self.sub_index);
+        let name = self.name.as_deref().unwrap_or("unknown object");
+
+        match &self.outcome {
+            SdoOutcome::Completed => {
+                let value = match (&self.value, &self.name) {
+                    (Some(entry), _) => entry.fmt_value(),
+                    (None, Some(name)) => format!("{name} = [{}]", hex_dump(&self.data)),
+                    (None, None) => format!("[{}]", hex_dump(&self.data)),
+                };
+                format!("{direction} {object} {value} ({:.1?})", self.latency)
+            }
+            SdoOutcome::Aborted { code, by_client } => format!(
+                "{direction} {object} {name} aborted by the {} after {:.1?}: {code}",
+                if *by_client { "client" } else { "server" },
+                self.latency
+            ),
+            SdoOutcome::Unanswered => format!(
+                "{direction} {object} {name} got no answer within {:.0?}",
+                self.latency
+            ),
         }
     }
 }
@@ -0,0 +1,793 @@
+use std::collections::BTreeMap;
+
+use oze_canopen::canopen::{NodeId, RxMessage};
+use tokio::time::Instant;
+use tracing::*;
+
+use crate::{
+    comms::sdo::{
+        client::SDO_TIMEOUT,
+        protocol::{BlockSegment, ClientFrame, Initiate, ServerFrame, SubBlock, crc16},
+    },
+    driver::receiver::parse::sdo_response::*,
+    od::{dictionary::ObjectDictionary, entry::ODEntry},
+};
+
+/// Follows the SDO transfers on the bus to reassemble segmented and block transfers and pair
+/// requests with their responses, there is at most one transfer per SDO server (node)
+#[derive(Debug, Default)]
+pub struct SdoTransfers {
+    ongoing: BTreeMap<NodeId, Transfer>,
+    completed: Vec<SdoExchange>,
+}
+
+#[derive(Debug)]
+struct Transfer {
+    index: u16,
+    sub_index: u8,
+    upload: bool,
+    kind: Kind,
+    size: Option<u32>,
+    data: Vec<u8>,
+    name: Option<String>,
+    started: Instant,
+    last_frame: Instant,
+}
+
+#[derive(Debug)]
+enum Kind {
+    /// Uploads start out as segmented, until the server answers with an expedited response
+    Segmented { toggle: bool, complete: bool },
+    Block {
+        /// Both client and server support the CRC
+        crc: bool,
+        sub_block: SubBlock,
+        in_sub_block: bool,
+    },
+}
+
+impl Transfer {
+    fn new(index: u16, sub_index: u8, upload: bool, kind: Kind, now: Instant) -> Self {
+        Self {
+            index,
+            sub_index,
+            upload,
+            kind,
+            size: None,
+            data: Vec::new(),
+            name: None,
+            started: now,
+            last_frame: now,
+        }
+    }
+
+    fn segmented(index: u16, sub_index: u8, upload: bool, now: Instant) -> Self {
+        let kind = Kind::Segmented {
+            toggle: false,
+            complete: false,
+        };
+        Self::new(index, sub_index, upload, kind, now)
+    }
+
+    /// Download with all data in the initiating request
+    fn expedited(index: u16, sub_index: u8, data: &[u8], now: Instant) -> Self {
+        let kind = Kind::Segmented {
+            toggle: false,
+            complete: true,
+        };
+        let mut transfer = Self::new(index, sub_index, false, kind, now);
+        transfer.data = data.to_vec();
+        transfer
+    }
+
+    fn block(index: u16, sub_index: u8, upload: bool, crc: bool, now: Instant) -> Self {
+        let kind = Kind::Block {
+            crc,
+            sub_block: SubBlock::default(),
+            in_sub_block: false,
+        };
+        Self::new(index, sub_index, upload, kind, now)
+    }
+
+    /// Whether the next frame of the sender of the block data is a block segment
+    fn in_sub_block(&self, upload: bool) -> bool {
+        self.upload == upload
+            && matches!(
+                self.kind,
+                Kind::Block {
+                    in_sub_block: true,
+                    ..
+                }
+            )
+    }
+
+    fn push_block_segment(&mut self, segment: BlockSegment) {
+        if let Kind::Block { sub_block, .. } = &mut self.kind {
+            sub_block.push(segment);
+        }
+    }
+
+    /// Append a segment of a segmented transfer, repeated segments are dropped
+    fn push_segment(&mut self, toggle: bool, data: &[u8], last: bool) {
+        if let Kind::Segmented {
+            toggle: expected,
+            complete,
+        } = &mut self.kind
+            && toggle == *expected
+        {
+            self.data.extend_from_slice(data);
+            *expected = !*expected;
+            *complete = last;
+        }
+    }
+
+    fn acknowledge(&mut self, ackseq: u8) {
+        if let Kind::Block {
+            sub_block,
+            in_sub_block,
+            ..
+        } = &mut self.kind
+            && sub_block.acknowledge(ackseq, &mut self.data)
+        {
+            *in_sub_block = false;
+        }
+    }
+
+    fn end_block(&mut self, unused: u8, crc: u16) {
+        self.data
+            .truncate(self.data.len().saturating_sub(unused.into()));
+        if matches!(self.kind, Kind::Block { crc: true, .. }) && crc16(&self.data) != crc {
+            warn!(
+                "CRC mismatch in block transfer of {:#06x}:{}",
+                self.index, self.sub_index
+            );
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        matches!(self.kind, Kind::Segmented { complete: true, .. })
+    }
+
+    fn progress(&self, from: NodeId) -> SdoResponse {
+        SdoResponse::Progress(SdoProgress {
+            from,
+            index: self.index,
+            sub_index: self.sub_index,
+            transferred: self.data.len(),
+            size: self.size,
+        })
+    }
+
+    fn value(&self, dictionary: &ObjectDictionary) -> Option<ODEntry> {
+        dictionary
+            .get(self.index, self.sub_index)
+            .and_then(|entry| entry.with_value_from(&self.data).ok())
+    }
+
+    fn uploaded(self, from: NodeId, dictionary: &ObjectDictionary) -> SdoResponse {
+        SdoResponse::UploadConfirm(SdoUploadResult {
+            from,
+            index: self.index,
+            sub_index: self.sub_index,
+            value: self.value(dictionary).map(Box::new),
+            data: self.data,
+        })
+    }
+
+    fn downloaded(&self, from: NodeId) -> SdoResponse {
+        SdoResponse::DownloadConfirm(SdoDownloadConfirmed {
+            from,
+            index: self.index,
+            sub_index: self.sub_index,
+        })
+    }
+
+    fn exchange(
+        self,
+        node: NodeId,
+        outcome: SdoOutcome,
+        now: Instant,
+        value: Option<ODEntry>,
+    ) -> SdoExchange {
+        SdoExchange {
+            node,
+            index: self.index,
+            sub_index: self.sub_index,
+            direction: match self.upload {
+                true => SdoDirection::Upload,
+                false => SdoDirection::Download,
+            },
+            name: self.name,
+            data: self.data,
+            value: value.map(Box::new),
+            latency: now.duration_since(self.started),
+            outcome,
+        }
+    }
+}
+
+impl SdoTransfers {
+    /// Transactions finished since the last call, in order. Transfers silent for longer than the
+    /// SDO timeout at `now` are flagged as unanswered
+    pub fn completed(&mut self, now: Instant) -> Vec<SdoExchange> {
+        let nodes: Vec<NodeId> = self.ongoing.keys().copied().collect();
+        for node in nodes {
+            self.expire(node, now);
+        }
+
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Drop a transfer that was silent for longer than the SDO timeout
+    fn expire(&mut self, node: NodeId, now: Instant) {
+        if let Some(transfer) = self.ongoing.get(&node)
+            && now.duration_since(transfer.last_frame) > SDO_TIMEOUT
+        {
+            warn!(
+                "SDO transfer of {:#06x}:{} with node {node} timed out",
+                transfer.index, transfer.sub_index
+            );
+            self.close(node, SdoOutcome::Unanswered, now);
+        }
+    }
+
+    /// End the ongoing transfer with the node without a value, if there is one
+    fn close(&mut self, node: NodeId, outcome: SdoOutcome, now: Instant) {
+        if let Some(transfer) = self.ongoing.remove(&node) {
+            let exchange = transfer.exchange(node, outcome, now, None);
+            self.completed.push(exchange);
+        }
+    }
+
+    fn ongoing(&mut self, node: NodeId, now: Instant) -> anyhow::Result<&mut Transfer> {
+        let transfer = self
+            .ongoing
+            .get_mut(&node)
+            .ok_or_else(|| anyhow::anyhow!("No SDO transfer in progress with node {node}"))?;
+        transfer.last_frame = now;
+
+        Ok(transfer)
+    }
+
+    fn start(&mut self, node: NodeId, mut transfer: Transfer, dictionary: &ObjectDictionary) {
+        transfer.name = dictionary
+            .get(transfer.index, transfer.sub_index)
+            .map(|entry| entry.name.to_string());
+
+        let now = transfer.started;
+        if let Some(previous) = self.ongoing.insert(node, transfer) {
+            warn!(
+                "SDO transfer of {:#06x}:{} with node {node} was never finished",
+                previous.index, previous.sub_index
+            );
+            let exchange = previous.exchange(node, SdoOutcome::Unanswered, now, None);
+            self.completed.push(exchange);
+        }
+    }
+
+    /// Parse an RSDO frame sent to `node`, a download is decoded once all of its data is known
+    pub fn request(
+        &mut self,
+        node: NodeId,
+        frame: &RxMessage,
+        dictionary: &ObjectDictionary,
+    ) -> SdoRequest {
+        let now = frame.timestamp;
+        self.expire(node, now);
+
+        let mut request = SdoRequest {
+            data: frame.data,
+            dlc: frame.dlc,
+            frame: None,
+            value: None,
+        };
+
+        // Segments of a block download carry no command specifier
+        if let Some(transfer) = self.ongoing.get_mut(&node)
+            && transfer.in_sub_block(false)
+            && let Ok(segment) = BlockSegment::decode(&frame.data)
+        {
+            transfer.last_frame = now;
+            transfer.push_block_segment(segment);
+            return request;
+        }
+
+        let Ok(decoded) = ClientFrame::decode(&frame.data) else {
+            return request;
+        };
+        match &decoded {
+            ClientFrame::InitiateDownload {
+                index,
+                sub_index,
+                data: Initiate::Expedited { data, size },
+            } => {
+                let data = &data[..size.unwrap_or(4)];
+                self.start(
+                    node,
+                    Transfer::expedited(*index, *sub_index, data, now),
+                    dictionary,
+                );
+                request.value = ODEntry::from_sdo_download(dictionary, &frame.data, frame.dlc);
+            }
+            ClientFrame::InitiateDownload {
+                index,
+                sub_index,
+                data: Initiate::Segmented { size },
+            } => {
+                let mut transfer = Transfer::segmented(*index, *sub_index, false, now);
+                transfer.size = *size;
+                self.start(node, transfer, dictionary);
+            }
+            ClientFrame::InitiateUpload { index, sub_index } => {
+                self.start(
+                    node,
+                    Transfer::segmented(*index, *sub_index, true, now),
+                    dictionary,
+                );
+            }
+            ClientFrame::InitiateBlockDownload {
+                index,
+                sub_index,
+                crc,
+                size,
+            } => {
+                let mut transfer = Transfer::block(*index, *sub_index, false, *crc, now);
+                transfer.size = *size;
+                self.start(node, transfer, dictionary);
+            }
+            ClientFrame::InitiateBlockUpload {
+                index,
+                sub_index,
+                crc,
+                ..
+            } => {
+                self.start(
+                    node,
+                    Transfer::block(*index, *sub_index, true, *crc, now),
+                    dictionary,
+                );
+            }
+            ClientFrame::Abort { code, .. } => {
+                let outcome = SdoOutcome::Aborted {
+                    code: *code,
+                    by_client: true,
+                };
+                self.close(node, outcome, now);
+            }
+            ClientFrame::EndBlockUpload => {
+                self.ongoing.remove(&node);
+            }
+            _ => {
+                if let Ok(transfer) = self.ongoing(node, now) {
+                    match &decoded {
+                        ClientFrame::DownloadSegment(segment) => {
+                            transfer.push_segment(segment.toggle, segment.data(), segment.last);
+                            if transfer.is_complete() {
+                                request.value = transfer.value(dictionary);
+                            }
+                        }
+                        ClientFrame::EndBlockDownload { unused, crc } => {
+                            transfer.end_block(*unused, *crc);
+                            request.value = transfer.value(dictionary);
+                        }
+                        ClientFrame::StartBlockUpload => {
+                            if let Kind::Block { in_sub_block, .. } = &mut transfer.kind {
+                                *in_sub_block = true;
+                            }
+                        }
+                        ClientFrame::BlockUploadAck { ackseq, .. } => transfer.acknowledge(*ackseq),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        request.frame = Some(decoded);
+        request
+    }
+
+    /// Parse a TSDO frame sent by `node`, uploaded values are decoded once all data is received
+    pub fn response(
+        &mut self,
+        node: NodeId,
+        frame: &RxMessage,
+        dictionary: &ObjectDictionary,
+    ) -> anyhow::Result<SdoResponse> {
+        let now = frame.timestamp;
+        self.expire(node, now);
+
+        // Segments of a block upload carry no command specifier
+        if let Some(transfer) = self.ongoing.get_mut(&node)
+            && transfer.in_sub_block(true)
+            && let Ok(segment) = BlockSegment::decode(&frame.data)
+        {
+            transfer.last_frame = now;
+            transfer.push_block_segment(segment);
+            return Ok(transfer.progress(node));
+        }
+
+        let response = match ServerFrame::decode(&frame.data)? {
+            ServerFrame::Abort {
+                index,
+                sub_index,
+                code,
+            } => {
+                let outcome = SdoOutcome::Aborted {
+                    code,
+                    by_client: false,
+                };
+                self.close(node, outcome, now);
+                SdoResponse::Error(SdoError {
+                    from: node,
+                    index,
+                    sub_index,
+                    code,
+                })
+            }
+            ServerFrame::InitiateUpload {
+                index,
+                sub_index,
+                data: Initiate::Expedited { data, size },
+            } => {
+                // Without the request (e.g. the sniffer started in between) the upload still
+                // decodes, but is not reported as a transaction
+                let transfer = match self.ongoing.get_mut(&node) {
+                    Some(transfer) if transfer.upload => transfer,
+                    _ => {
+                        let mut transfer = Transfer::segmented(index, sub_index, true, now);
+                        transfer.data = data[..size.unwrap_or(4)].to_vec();
+                        return Ok(transfer.uploaded(node, dictionary));
+                    }
+                };
+                transfer.data = data[..size.unwrap_or(4)].to_vec();
+                self.finish(node, dictionary, now)
+            }
+            ServerFrame::InitiateDownload { index, sub_index } => {
+                match self.ongoing.get_mut(&node) {
+                    Some(transfer) if !transfer.upload && transfer.is_complete() => {
+                        self.finish(node, dictionary, now)
+                    }
+                    // Segments follow
+                    Some(transfer) if !transfer.upload => {
+                        transfer.last_frame = now;
+                        transfer.progress(node)
+                    }
+                    _ => SdoResponse::DownloadConfirm(SdoDownloadConfirmed {
+                        from: node,
+                        index,
+                        sub_index,
+                    }),
+                }
+            }
+            frame => {
+                let transfer = self.ongoing(node, now)?;
+                match frame {
+                    ServerFrame::InitiateUpload {
+                        data: Initiate::Segmented { size },
+                        ..
+                    }
+                    | ServerFrame::InitiateBlockUpload { size, .. } => {
+                        transfer.size = size;
+                        transfer.progress(node)
+                    }
+                    ServerFrame::UploadSegment(segment) => {
+                        transfer.push_segment(segment.toggle, segment.data(), segment.last);
+                        match transfer.is_complete() {
+                            true => self.finish(node, dictionary, now),
+                            false => transfer.progress(node),
+                        }
+                    }
+                    ServerFrame::DownloadSegment { .. } => match transfer.is_complete() {
+                        true => self.finish(node, dictionary, now),
+                        false => transfer.progress(node),
+                    },
+                    ServerFrame::InitiateBlockDownload { crc, .. } => {
+                        if let Kind::Block {
+                            crc: both,
+                            in_sub_block,
+                            ..
+                        } = &mut transfer.kind
+                        {
+                            *both &= crc;
+                            *in_sub_block = true;
+                        }
+                        transfer.progress(node)
+                    }
+                    ServerFrame::BlockDownloadAck { ackseq, .. } => {
+                        transfer.acknowledge(ackseq);
+                        transfer.progress(node)
+                    }
+                    ServerFrame::EndBlockUpload { unused, crc } => {
+                        transfer.end_block(unused, crc);
+                        // The client confirms the end, but the data is complete
+                        self.finish(node, dictionary, now)
+                    }
+                    ServerFrame::EndBlockDownload => self.finish(node, dictionary, now),
+                    _ => unreachable!("handled above"),
+                }
+            }
+        };
+
+        Ok(response)
+    }
+
+    fn finish(&mut self, node: NodeId, dictionary: &ObjectDictionary, now: Instant) -> SdoResponse {
+        let transfer = self
+            .ongoing
+            .remove(&node)
+            .expect("finished transfers are ongoing");
+
+        let value = transfer.value(dictionary);
+        let response = match transfer.upload {
+            true => SdoResponse::UploadConfirm(SdoUploadResult {
+                from: node,
+                index: transfer.index,
+                sub_index: transfer.sub_index,
+                data: transfer.data.clone(),
+                value: value.clone().map(Box::new),
+            }),
+            false => transfer.downloaded(node),
+        };
+        self.completed
+            .push(transfer.exchange(node, SdoOutcome::Completed, now, value));
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        comms::sdo::{
+            abort::SdoAbortCode,
+            protocol::{Segment, block_segments},
+        },
+        od::{self, value::ODValue},
+    };
+
+    const NODE: NodeId = 3;
+
+    fn message(data: [u8; 8]) -> RxMessage {
+        RxMessage {
+            timestamp: Instant::now(),
+            cob_id: 0,
+            data,
+            dlc: 8,
+        }
+    }
+
+    #[test]
+    fn test_segmented_upload() {
+        let dictionary = ObjectDictionary::builtin();
+        let mut transfers = SdoTransfers::default();
+        let name = b"PD4-C6018L4204";
+
+        let request = |transfers: &mut SdoTransfers, frame: ClientFrame| {
+            transfers.request(NODE, &message(frame.encode()), &dictionary)
+        };
+        let response = |transfers: &mut SdoTransfers, frame: ServerFrame| {
+            transfers
+                .response(NODE, &message(frame.encode()), &dictionary)
+                .unwrap()
+        };
+
+        request(
+            &mut transfers,
+            ClientFrame::InitiateUpload {
+                index: 0x1008,
+                sub_index: 0,
+            },
+        );
+        let progress = response(
+            &mut transfers,
+            ServerFrame::InitiateUpload {
+                index: 0x1008,
+                sub_index: 0,
+                data: Initiate::Segmented {
+                    size: Some(name.len() as u32),
+                },
+            },
+        );
+        assert!(matches!(progress, SdoResponse::Progress(_)));
+
+        request(&mut transfers, ClientFrame::UploadSegment { toggle: false });
+        response(
+            &mut transfers,
+            ServerFrame::UploadSegment(Segment::new(false, false, &name[..7])),
+        );
+        request(&mut transfers, ClientFrame::UploadSegment { toggle: true });
+        let SdoResponse::UploadConfirm(result) = response(
+            &mut transfers,
+            ServerFrame::UploadSegment(Segment::new(true, true, &name[7..])),
+        ) else {
+            panic!("Expected the upload to complete");
+        };
+
+        assert_eq!(result.data, name);
+        assert_eq!(
+            result.value.unwrap().default,
+            ODValue::VisibleString(String::from("PD4-C6018L4204"))
+        );
+        assert!(transfers.ongoing.is_empty());
+    }
+
+    #[test]
+    fn test_block_download() {
+        let dictionary = ObjectDictionary::builtin();
+        let mut transfers = SdoTransfers::default();
+        let data = b"v2.3.1-gantry";
+        let (segments, unused) = block_segments(data);
+
+        let initiate = ClientFrame::InitiateBlockDownload {
+            index: od::SOFTWARE_VERSION.index,
+            sub_index: 0,
+            crc: true,
+            size: Some(data.len() as u32),
+        };
+        transfers.request(NODE, &message(initiate.encode()), &dictionary);
+        let initiated = ServerFrame::InitiateBlockDownload {
+            index: od::SOFTWARE_VERSION.index,
+            sub_index: 0,
+            crc: true,
+            block_size: 127,
+        };
+        transfers
+            .response(NODE, &message(initiated.encode()), &dictionary)
+            .unwrap();
+
+        for (seqno, segment) in (1..).zip(&segments) {
+            let segment = BlockSegment {
+                seqno,
+                last: usize::from(seqno) == segments.len(),
+                data: *segment,
+            };
+            let request = transfers.request(NODE, &message(segment.encode()), &dictionary);
+            assert!(request.frame.is_none());
+        }
+        let ack = ServerFrame::BlockDownloadAck {
+            ackseq: 2,
+            block_size: 127,
+        };
+        transfers
+            .response(NODE, &message(ack.encode()), &dictionary)
+            .unwrap();
+
+        let end = ClientFrame::EndBlockDownload {
+            unused,
+            crc: crc16(data),
+        };
+        let request = transfers.request(NODE, &message(end.encode()), &dictionary);
+        assert_eq!(
+            request.value.unwrap().default,
+            ODValue::VisibleString(String::from("v2.3.1-gantry"))
+        );
+
+        let confirm = transfers
+            .response(
+                NODE,
+                &message(ServerFrame::EndBlockDownload.encode()),
+                &dictionary,
+            )
+            .unwrap();
+        assert!(matches!(
+            confirm,
+            SdoResponse::DownloadConfirm(SdoDownloadConfirmed { index: 0x100A, .. })
+        ));
+    }
+
+    #[test]
+    fn test_abort_and_timeout() {
+        let dictionary = ObjectDictionary::builtin();
+        let mut transfers = SdoTransfers::default();
+
+        let initiate = ClientFrame::InitiateUpload {
+            index: 0x1008,
+            sub_index: 0,
+        };
+        transfers.request(NODE, &message(initiate.encode()), &dictionary);
+        let abort = ServerFrame::Abort {
+            index: 0x1008,
+            sub_index: 0,
+            code: SdoAbortCode::NoObject,
+        };
+        assert!(matches!(
+            transfers.response(NODE, &message(abort.encode()), &dictionary),
+            Ok(SdoResponse::Error(SdoError {
+                code: SdoAbortCode::NoObject,
+                ..
+            }))
+        ));
+        assert!(transfers.ongoing.is_empty());
+
+        // A segment long after its transfer started belongs to no transfer
+        transfers.request(NODE, &message(initiate.encode()), &dictionary);
+        let mut late =
+            message(ServerFrame::UploadSegment(Segment::new(false, true, b"x")).encode());
+        late.timestamp += SDO_TIMEOUT * 2;
+        assert!(transfers.response(NODE, &late, &dictionary).is_err());
+    }
+
+    #[test]
+    fn test_transactions() {
+        let dictionary = ObjectDictionary::builtin();
+        let mut transfers = SdoTransfers::default();
+        let start = Instant::now();
+        let at = |data: [u8; 8], after: Duration| {
+            let mut message = message(data);
+            message.timestamp = start + after;
+            message
+        };
+
+        let download = ClientFrame::InitiateDownload {
+            index: od::PROFILE_ACCELERATION.index,
+            sub_index: 0,
+            data: Initiate::expedited(&500u32.to_le_bytes()),
+        };
+        transfers.request(NODE, &at(download.encode(), Duration::ZERO), &dictionary);
+        assert!(transfers.completed(start).is_empty());
+
+        let confirm = ServerFrame::InitiateDownload {
+            index: od::PROFILE_ACCELERATION.index,
+            sub_index: 0,
+        };
+        let response = transfers
+            .response(
+                NODE,
+                &at(confirm.encode(), Duration::from_millis(2)),
+                &dictionary,
+            )
+            .unwrap();
+        assert!(matches!(response, SdoResponse::DownloadConfirm(_)));
+
+        let [exchange] = &transfers.completed(start)[..] else {
+            panic!("Expected the download to complete");
+        };
+        assert_eq!(exchange.outcome, SdoOutcome::Completed);
+        assert_eq!(exchange.direction, SdoDirection::Download);
+        assert_eq!(exchange.latency, Duration::from_millis(2));
+        assert_eq!(
+            exchange.name.as_deref(),
+            Some(&*od::PROFILE_ACCELERATION.name)
+        );
+        assert_eq!(exchange.value.as_ref().unwrap().default, ODValue::U32(500));
+
+        // Aborted by the client
+        let upload = ClientFrame::InitiateUpload {
+            index: 0x1008,
+            sub_index: 0,
+        };
+        transfers.request(NODE, &at(upload.encode(), Duration::ZERO), &dictionary);
+        let abort = ClientFrame::Abort {
+            index: 0x1008,
+            sub_index: 0,
+            code: SdoAbortCode::OutOfMemory,
+        };
+        transfers.request(
+            NODE,
+            &at(abort.encode(), Duration::from_millis(1)),
+            &dictionary,
+        );
+        let [exchange] = &transfers.completed(start)[..] else {
+            panic!("Expected the upload to be aborted");
+        };
+        assert_eq!(
+            exchange.outcome,
+            SdoOutcome::Aborted {
+                code: SdoAbortCode::OutOfMemory,
+                by_client: true
+            }
+        );
+
+        // Never answered
+        transfers.request(NODE, &at(upload.encode(), Duration::ZERO), &dictionary);
+        assert!(transfers.completed(start + SDO_TIMEOUT).is_empty());
+        let [exchange] = &transfers.completed(start + SDO_TIMEOUT * 2)[..] else {
+            panic!("Expected the upload to be flagged");
+        };
+        assert_eq!(exchange.outcome, SdoOutcome::Unanswered);
+        assert!(transfers.ongoing.is_empty());
+    }
+}
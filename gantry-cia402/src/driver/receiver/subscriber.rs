@@ -1,36 +1,55 @@
-use oze_canopen::interface::CanOpenInterface;
+use std::sync::Arc;
+
+use oze_canopen::{canopen::RxMessage, interface::CanOpenInterface};
 use tokio::{
-    sync::broadcast,
+    sync::{broadcast, watch},
     time::{self, Instant},
 };
 use tracing::*;
 
 use crate::{
-    comms::pdo::mapping::PdoMapping,
+    comms::{
+        pdo::{
+            codec::PdoValue,
+            mapping::{PdoType, registry::MappingRegistry},
+        },
+        sync::{SyncEvent, SyncSnapshot},
+        time::{TimeCorrelation, TimeSample},
+    },
     driver::{
         event::MotorEvent,
-        oms::{
-            OMSFlagsSW, OperationMode, home::HomeFlagsSW, position::PositionFlagsSW,
-            torque::TorqueFlagsSW, velocity::VelocityFlagsSW,
-        },
+        oms::{OMSFlagsSW, OperationMode},
         receiver::{
             error::ReceiverError,
-            parse::{Frame, MessageType, pdo_message::*},
+            parse::{Frame, MessageType, TimeMessage, sdo_transfer::SdoTransfers},
             *,
         },
     },
     error::DriveError,
     log::format_frame,
+    od::{dictionary::ObjectDictionary, registry::DictionaryRegistry, typed},
 };
 
+/// Parse every received frame and broadcast the feedback of the given node as events, PDOs are
+/// decoded with the latest mappings of the registry. TIME messages on the bus update the
+/// correlation to the device clock
 pub async fn handle_feedback(
     this_node_id: u8,
     mut canopen: CanOpenInterface,
-    tpdo_mapping: &'static [PdoMapping],
+    dictionary: Arc<ObjectDictionary>,
+    mappings: watch::Receiver<MappingRegistry>,
+    time: watch::Sender<TimeCorrelation>,
     event_tx: broadcast::Sender<MotorEvent>,
 ) {
     let mut last_seen = Instant::now();
 
+    // Frames of other nodes are resolved against the standard objects only
+    let mut dictionaries = DictionaryRegistry::default();
+    dictionaries.insert(this_node_id, dictionary);
+    let mut transfers = SdoTransfers::default();
+    // Operation mode specific statusword bits need the last reported operation mode
+    let mut opmode = None;
+
     trace!("Starting feedback handling loop");
 
     loop {
@@ -42,11 +61,25 @@ pub async fn handle_feedback(
                 trace!("Received frame: {}", format_frame(&message));
 
                 // Parse received frames
-                let Ok(parsed): Result<Frame, _> = message.try_into() else {
+                let Ok(parsed) =
+                    Frame::from_message(message, &dictionaries, &mappings.borrow(), &mut transfers)
+                else {
                     error!("Error parsing message: {message:?}");
                     continue;
                 };
                 parsed.log();
+                for exchange in transfers.completed(Instant::now()) {
+                    exchange.log();
+                }
+
+                // TIME is addressed to every node
+                if let MessageType::Time(TimeMessage { time: Some(device) }) = &parsed.message {
+                    let sample = TimeSample {
+                        time: *device,
+                        at: parsed.timestamp,
+                    };
+                    time.send_modify(|correlation| correlation.observe(sample));
+                }
 
                 // Skip messages that are not from the motor that we are managing
                 if parsed
@@ -58,8 +91,7 @@ pub async fn handle_feedback(
                     last_seen = Instant::now();
 
                     // Lets check what message we got
-                    if let Err(err) =
-                        handle_message(&parsed.message, &event_tx, &tpdo_mapping).await
+                    if let Err(err) = handle_message(&parsed.message, &event_tx, &mut opmode).await
                     {
                         error!(
                             "Error while handling this message: {:?} - {err}",
@@ -89,10 +121,94 @@ pub async fn handle_feedback(
     }
 }
 
+/// Collect the synchronous TPDOs the given node sends in reply to every SYNC of the host, and
+/// broadcast them as a single [`MotorEvent::SyncSnapshot`] once the window before the next SYNC
+/// opens. The values are broadcast as regular feedback by [`handle_feedback`] as well
+pub async fn collect_sync_snapshots(
+    this_node_id: u8,
+    mut rx: broadcast::Receiver<RxMessage>,
+    mappings: watch::Receiver<MappingRegistry>,
+    mut sync_rx: broadcast::Receiver<SyncEvent>,
+    event_tx: broadcast::Sender<MotorEvent>,
+) {
+    // When the SYNC of the current cycle was sent, and what the node replied so far
+    let mut cycle: Option<(Instant, SyncSnapshot)> = None;
+
+    loop {
+        tokio::select! {
+            event = sync_rx.recv() => match event {
+                Ok(SyncEvent::Window { .. }) => {
+                    // Nothing synchronous is mapped, or the node did not reply
+                    if let Some((_, snapshot)) = cycle.take()
+                        && !snapshot.values.is_empty()
+                    {
+                        send_update(MotorEvent::SyncSnapshot(snapshot), &event_tx);
+                    }
+                }
+                Ok(SyncEvent::Sent { counter, at }) => {
+                    let values = Vec::new();
+                    cycle = Some((at, SyncSnapshot { counter, values }));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Missed {skipped} SYNC events, dropping the current snapshot");
+                    cycle = None;
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    trace!("SYNC producer stopped, no more snapshots for node {this_node_id}");
+                    return;
+                }
+            },
+            message = rx.recv() => match message {
+                Ok(message) => {
+                    // Replies to an earlier SYNC are too late for this cycle
+                    if let Some((sent, snapshot)) = &mut cycle
+                        && message.timestamp >= *sent
+                        && let Some(values) =
+                            synchronous_tpdo_values(this_node_id, &message, &mappings.borrow())
+                    {
+                        snapshot.values.extend(values);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Missed {skipped} frames, dropping the current snapshot");
+                    cycle = None;
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("CAN interface closed, no more snapshots for node {this_node_id}");
+                    return;
+                }
+            },
+        }
+    }
+}
+
+/// Values of the message if it is a synchronous TPDO of the given node
+fn synchronous_tpdo_values(
+    node_id: u8,
+    message: &RxMessage,
+    mappings: &MappingRegistry,
+) -> Option<Vec<PdoValue>> {
+    let (node, mapping) = mappings.lookup(message.cob_id)?;
+    if node != node_id
+        || !matches!(mapping.pdo, PdoType::TPDO(_))
+        || !mapping.communication.transmission_type.is_synchronous()
+    {
+        return None;
+    }
+
+    match mapping.decode(&message.data[..message.dlc]) {
+        Ok(values) => Some(values),
+        Err(err) => {
+            warn!("Unable to decode {:?}: {err}", mapping.pdo);
+            None
+        }
+    }
+}
+
 async fn handle_message(
     message: &MessageType,
     event_tx: &broadcast::Sender<MotorEvent>,
-    tpdo_mapping: &&'static [PdoMapping],
+    opmode: &mut Option<OperationMode>,
 ) -> Result<(), ReceiverError> {
     match message {
         MessageType::NmtControl(_) => {
@@ -108,13 +224,13 @@ async fn handle_message(
             // We sent this: Ignore
         }
         MessageType::PDO(parsed_pdo) => {
-            handle_parsed_pdo(parsed_pdo, event_tx).await;
+            handle_parsed_pdo(parsed_pdo, event_tx, opmode).await;
         }
         MessageType::NmtMonitor(nmt_monitor_message) => {
             handle_nmt_monitor(nmt_monitor_message, event_tx).await;
         }
-        // SYNC and UNKNOWN are both not addressed to a single node, we not adress those here: Ignore
-        MessageType::Sync(_) | MessageType::Unknown(_) => {
+        // SYNC, TIME and UNKNOWN are all not addressed to a single node, we not adress those here: Ignore
+        MessageType::Sync(_) | MessageType::Time(_) | MessageType::Unknown(_) => {
             // Not for us: Ignore
         }
     };
@@ -122,33 +238,70 @@ async fn handle_message(
     Ok(())
 }
 
+/// Broadcast the feedback of every entry mapped in a TPDO
 async fn handle_parsed_pdo(
     parsed_pdo: &parse::pdo_message::ParsedPDO,
     event_tx: &broadcast::Sender<MotorEvent>,
+    opmode: &mut Option<OperationMode>,
 ) {
-    match &parsed_pdo.message {
-        parse::pdo_message::PDOMessage::TPDO1(tpdo1_message) => {
-            handle_parsed_tpdo1(tpdo1_message, event_tx).await;
-        }
-        parse::pdo_message::PDOMessage::TPDO2(tpdo2_message) => {
-            handle_parsed_tpdo2(tpdo2_message, event_tx).await;
-        }
-        parse::pdo_message::PDOMessage::TPDO3(tpdo3_message) => {
-            handle_parsed_tpdo3(tpdo3_message, event_tx).await;
-        }
-        parse::pdo_message::PDOMessage::TPDO4(tpdo4_message) => {
-            // TPDO4 is unmapped
-            warn!(
-                "Received TPDO4: {tpdo4_message:?}, however this should be unmapped 🤔, ignoring..."
-            );
-        }
-        parse::pdo_message::PDOMessage::Raw(raw_pdomessage) => {
-            warn!("Received weird parsed pdo: {raw_pdomessage:?}, ignoring...");
+    if matches!(parsed_pdo.kind, PdoType::RPDO(_)) {
+        // RPDO messages are sent by us, purposfully ignored here
+        return;
+    }
+    if parsed_pdo.values.is_none() {
+        warn!("Received {parsed_pdo:?} without a known mapping, ignoring...");
+        return;
+    }
+
+    let statusword = parsed_pdo
+        .get(typed::STATUS_WORD)
+        .map(StatusWord::from_bits_truncate);
+    // Send full statusword update to subscribers
+    if let Some(statusword) = statusword {
+        send_update(MotorEvent::StatusWord(statusword), event_tx);
+    }
+
+    // Send operational mode update
+    if let Some(actual) = parsed_pdo.get(typed::GET_OPERATION_MODE) {
+        match OperationMode::try_from(actual) {
+            Ok(actual) => {
+                *opmode = Some(actual);
+                send_update(MotorEvent::OperationModeUpdate(actual), event_tx);
+            }
+            Err(_) => warn!("Received unknown operation mode {actual} in {parsed_pdo:?}"),
         }
-        _ => {
-            // RPDO messages are sent by us, purposfully ignored here
+    }
+
+    // Parse Operational Mode Specific bits
+    if let (Some(statusword), Some(opmode)) = (statusword, *opmode) {
+        let event = match OMSFlagsSW::from_statusword_and_opmode(statusword, opmode) {
+            OMSFlagsSW::Homing(home_flags_sw) => Some(home_flags_sw.into_event()),
+            OMSFlagsSW::ProfilePosition(position_flags_sw) => Some(position_flags_sw.into_event()),
+            OMSFlagsSW::ProfileVelocity(velocity_flags_sw) => Some(velocity_flags_sw.into_event()),
+            OMSFlagsSW::ProfileTorque(torque_flags_sw) => Some(torque_flags_sw.into_event()),
+            OMSFlagsSW::None => None,
+        };
+        // Send anything interesting along
+        if let Some(event) = event {
+            trace!("Sending OMS event: {event:?}");
+            send_update(event, event_tx);
         }
     }
+
+    // Send actual position update
+    if let Some(actual_position) = parsed_pdo.get(typed::POSITION_ACTUAL_VALUE) {
+        send_update(MotorEvent::PositionFeedback { actual_position }, event_tx);
+    }
+
+    // Send actual velocity update
+    if let Some(actual_velocity) = parsed_pdo.get(typed::VELOCITY_ACTUAL_VALUE) {
+        send_update(MotorEvent::VelocityFeedback { actual_velocity }, event_tx);
+    }
+
+    // Send actual torque update
+    if let Some(actual_torque) = parsed_pdo.get(typed::TORQUE_ACTUAL_VALUE) {
+        send_update(MotorEvent::TorqueFeedback { actual_torque }, event_tx);
+    }
 }
 
 async fn handle_sdo_response(
@@ -175,68 +328,6 @@ async fn handle_nmt_monitor(
     );
 }
 
-async fn handle_parsed_tpdo1(
-    tpdo1_message: &TPDO1Message,
-    event_tx: &broadcast::Sender<MotorEvent>,
-) {
-    // Send full statusword update to subscribers
-    send_update(MotorEvent::StatusWord(tpdo1_message.statusword), event_tx);
-
-    // Send operational mode update
-    send_update(
-        MotorEvent::OperationModeUpdate(tpdo1_message.actual_opmode),
-        event_tx,
-    );
-
-    // Parse Operational Mode Specific bits
-    let event = match tpdo1_message.oms_flags {
-        OMSFlagsSW::Homing(home_flags_sw) => Some(home_flags_sw.into_event()),
-        OMSFlagsSW::ProfilePosition(position_flags_sw) => Some(position_flags_sw.into_event()),
-        OMSFlagsSW::ProfileVelocity(velocity_flags_sw) => Some(velocity_flags_sw.into_event()),
-        OMSFlagsSW::ProfileTorque(torque_flags_sw) => Some(torque_flags_sw.into_event()),
-        OMSFlagsSW::None => None,
-    };
-    // Send anything interesting along
-    if let Some(event) = event {
-        trace!("Sending OMS event: {event:?}");
-        send_update(event, event_tx);
-    }
-}
-
-async fn handle_parsed_tpdo2(
-    tpdo2_message: &TPDO2Message,
-    event_tx: &broadcast::Sender<MotorEvent>,
-) {
-    // Send actual position update
-    send_update(
-        MotorEvent::PositionFeedback {
-            actual_position: tpdo2_message.actual_pos,
-        },
-        event_tx,
-    );
-
-    // Send actual velocity update
-    send_update(
-        MotorEvent::VelocityFeedback {
-            actual_velocity: tpdo2_message.actual_vel,
-        },
-        event_tx,
-    );
-}
-
-async fn handle_parsed_tpdo3(
-    tpdo3_message: &TPDO3Message,
-    event_tx: &broadcast::Sender<MotorEvent>,
-) {
-    // Send actual torque update
-    // send_update(
-    //     MotorEvent::TorqueFeedback {
-    //         actual_torque: tpdo3_message.actual_torque,
-    //     },
-    //     event_tx,
-    // );
-}
-
 fn send_update(event: MotorEvent, event_tx: &broadcast::Sender<MotorEvent>) {
     match event_tx.send(event.clone()) {
         Ok(num_subscribers) => {
@@ -291,3 +382,113 @@ pub async fn wait_for_event(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::{
+        comms::pdo::mapping::custom,
+        od::{self, value::ODValue},
+    };
+
+    const NODE_ID: u8 = 3;
+
+    fn tpdo(cob_id: u16, data: [u8; 8]) -> RxMessage {
+        RxMessage {
+            timestamp: Instant::now(),
+            cob_id,
+            data,
+            dlc: 8,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_snapshots() {
+        let (rx_tx, rx) = broadcast::channel(8);
+        let (sync_tx, sync_rx) = broadcast::channel(8);
+        let (event_tx, mut event_rx) = broadcast::channel(8);
+        let mut registry = MappingRegistry::default();
+        registry.insert(
+            NODE_ID,
+            [
+                Cow::Owned(custom::TPDO_STATUS_OPMODE),
+                Cow::Owned(custom::CSP_TPDO_POS_VEL_ACTUAL),
+            ],
+        );
+        let (_mappings_tx, mappings) = watch::channel(registry);
+        tokio::spawn(collect_sync_snapshots(
+            NODE_ID, rx, mappings, sync_rx, event_tx,
+        ));
+        let settle = || time::sleep(Duration::from_millis(5));
+
+        // Replies before the first SYNC belong to no cycle
+        rx_tx.send(tpdo(0x283, [1, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        settle().await;
+        sync_tx
+            .send(SyncEvent::Sent {
+                counter: Some(1),
+                at: Instant::now(),
+            })
+            .unwrap();
+        settle().await;
+        // Asynchronous TPDOs are left out
+        rx_tx
+            .send(tpdo(0x183, [0x27, 0x06, 8, 0, 0, 0, 0, 0]))
+            .unwrap();
+        rx_tx
+            .send(tpdo(0x283, [100, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]))
+            .unwrap();
+        settle().await;
+        sync_tx
+            .send(SyncEvent::Window { counter: Some(2) })
+            .unwrap();
+
+        let event = time::timeout(Duration::from_secs(1), event_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let MotorEvent::SyncSnapshot(snapshot) = event else {
+            panic!("Expected a snapshot, got {event:?}");
+        };
+        assert_eq!(snapshot.counter, Some(1));
+        let values: Vec<_> = snapshot
+            .values
+            .iter()
+            .map(|value| (&*value.entry, value.value.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (&od::POSITION_ACTUAL_VALUE, ODValue::I32(100)),
+                (&od::VELOCITY_ACTUAL_VALUE, ODValue::I32(-1)),
+            ]
+        );
+    }
+    #[tokio::test]
+    async fn test_torque_feedback() {
+        let (event_tx, mut event_rx) = broadcast::channel(8);
+        let mut registry = MappingRegistry::default();
+        registry.insert(NODE_ID, [Cow::Owned(custom::TPDO_TORQUE_ACTUAL)]);
+
+        // -12.5 % of the nominal torque
+        let frame = Frame::from_message(
+            tpdo(0x383, [0x83, 0xFF, 0, 0, 0, 0, 0, 0]),
+            &DictionaryRegistry::default(),
+            &registry,
+            &mut SdoTransfers::default(),
+        )
+        .unwrap();
+        handle_message(&frame.message, &event_tx, &mut None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            MotorEvent::TorqueFeedback {
+                actual_torque: -125
+            }
+        );
+    }
+}
@@ -2,170 +2,118 @@ pub mod home;
 pub mod parametrise;
 pub mod params;
 pub mod pdo_mapping;
+pub mod snapshot;
 
-use std::{sync::Arc, time::Duration};
+use std::{borrow::Cow, time::Duration};
 
-use oze_canopen::sdo_client::SdoClient;
 use tokio::{
-    sync::{Mutex, broadcast, mpsc},
-    time::{sleep, timeout},
+    sync::{mpsc, watch},
+    time::sleep,
 };
 use tracing::*;
 
 use crate::{
-    comms::{pdo::mapping::PdoMapping, sdo::SdoAction},
+    comms::{
+        pdo::mapping::PdoMapping,
+        sdo::{SdoAction, scheduler::SdoHandle},
+    },
     driver::{
-        event::MotorEvent,
-        nmt::NmtState,
-        startup::{parametrise::parametrise_motor, pdo_mapping::configure_pdo_mappings},
+        nmt::{NmtState, switch_nmt_state},
+        startup::{
+            parametrise::{ParametrisationOptions, parametrise_motor},
+            pdo_mapping::{PdoSetup, configure_pdos},
+        },
     },
     error::DriveError,
+    od::dictionary::ObjectDictionary,
 };
 
 pub const RETRY_DURATION: Duration = Duration::from_secs(1);
 pub const NMT_SWITCH_TIMEOUT: Duration = Duration::from_secs(1);
 pub const NMT_SWITCH_ATTEMPTS: usize = 10;
+pub const PDO_CONFIGURATION_ATTEMPTS: usize = 10;
+
+/// What the startup of a single motor applies to the device
+#[derive(Debug, Clone, Copy)]
+pub struct MotorStartup<'a> {
+    pub node_id: u8,
+    pub dictionary: &'a ObjectDictionary,
+    pub parameters: &'a [SdoAction],
+    pub parametrisation: ParametrisationOptions,
+    pub rpdo_mapping: &'static [PdoMapping],
+    pub tpdo_mapping: &'static [PdoMapping],
+    pub pdo_setup: PdoSetup,
+}
 
 /// Parametrize & Set up PDO mapping for cia402 compliant motor at given node_id
+/// Returns the mappings of the PDOs enabled on the device, see [`configure_pdos`]
 pub async fn motor_startup_task(
-    node_id: u8,
+    startup: MotorStartup<'_>,
+    sdo: SdoHandle,
     nmt_tx: mpsc::Sender<NmtState>,
-    sdo: Arc<Mutex<SdoClient>>,
-    parameters: &[SdoAction<'_>],
-    rpdo_mapping: &'static [PdoMapping],
-    tpdo_mapping: &'static [PdoMapping],
-    event_rx: broadcast::Receiver<MotorEvent>,
-) -> Result<(), DriveError> {
+    mut nmt_state: watch::Receiver<NmtState>,
+) -> Result<Vec<Cow<'static, PdoMapping>>, DriveError> {
+    let MotorStartup {
+        node_id,
+        dictionary,
+        parameters,
+        parametrisation,
+        rpdo_mapping,
+        tpdo_mapping,
+        pdo_setup,
+    } = startup;
     trace!("Starting up motor at node id {node_id}");
 
     // Put the drive in NMT PreOperational, required for parametrisation & pdo mapping
-    let state = NmtState::PreOperational;
-    let mut attempt = 0;
-    let mut nmt_event_rx = event_rx.resubscribe();
-
-    loop {
-        // Put the device in PreOperational
-        nmt_tx
-            .send(state.clone())
-            .await
-            .map_err(|err| DriveError::NMTSendError(state.clone(), err))?;
-
-        // Wait for event indicating correct NMT state
-        match timeout(NMT_SWITCH_TIMEOUT, nmt_event_rx.recv()).await {
-            Ok(Ok(MotorEvent::NmtStateUpdate(new_state))) => {
-                trace!("new_state: {new_state:?}");
-                // Got an event within the timeout
-                if new_state == state {
-                    break;
-                }
-            }
-            Ok(Err(err)) => {
-                // The channel closed before we got an event
-                error!("Startup NMT PRE-OP: {err}");
-            }
-            Err(_) => {
-                // Timeout expired
-                warn!("Startup NMT PRE-OP: Timed out waiting for event");
-            }
-            Ok(Ok(event)) => {
-                // Non-NMT event
-                error!("Non NMT event : {event:?}");
-            }
-        }
-
-        attempt += 1;
-        if attempt >= NMT_SWITCH_ATTEMPTS {
-            panic!(
-                "Failed to switch device into NMT {state:?} after {NMT_SWITCH_ATTEMPTS} attempts, aborting"
-            );
-        }
-    }
-
-    // Parametrise this motor
-    loop {
-        trace!("Attempting to parametrise motor at node id {node_id}");
-        if let Err(err) = parametrise_motor(node_id, parameters, sdo.clone()).await {
-            warn!(
-                "Parametrisation failed of motor at node id {node_id}: {err}, retrying in {}s",
-                RETRY_DURATION.as_secs()
-            );
-            sleep(RETRY_DURATION).await;
-        } else {
-            info!("Succesful parametrisation of motor {node_id}");
-            break;
-        }
+    switch_nmt_state(node_id, &nmt_tx, &mut nmt_state, NmtState::PreOperational).await?;
+
+    // Parametrise this motor, the policy of the options decides whether failed actions are retried
+    // or stop the startup
+    trace!("Attempting to parametrise motor at node id {node_id}");
+    let report = parametrise_motor(node_id, parameters, sdo.clone(), parametrisation).await?;
+    if report.is_success() {
+        info!("Succesful parametrisation of motor {node_id}");
+    } else {
+        warn!(
+            "Continuing startup of motor {node_id} with {} failed parametrisation actions",
+            report.failures().count()
+        );
     }
 
-    // Configure RPDO mapping
-    trace!("Configuring RPDO_mapping of motor at node id {node_id}");
-    loop {
-        if let Err(err) = configure_pdo_mappings(node_id, sdo.clone(), rpdo_mapping).await {
-            warn!(
-                "RPDO mapping configuration failed of motor at node id {node_id}: {err}, retrying in {}s",
-                RETRY_DURATION.as_secs()
-            );
-            sleep(RETRY_DURATION).await;
-        } else {
-            info!("Succesful RPDO mapping for motor {node_id}");
-            break;
-        }
-    }
-
-    // Configure TPDO mapping
-    trace!("Configuring TPDO_mapping of motor at node id {node_id}");
-    loop {
-        if let Err(err) = configure_pdo_mappings(node_id, sdo.clone(), tpdo_mapping).await {
-            warn!(
-                "TPDO mapping configuration failed of motor at node id {node_id}: {err}, retrying in {}s",
-                RETRY_DURATION.as_secs()
-            );
-            sleep(RETRY_DURATION).await;
-        } else {
-            info!("Succesful TPDO mapping for motor {node_id}");
-            break;
-        }
-    }
-
-    // Put the drive in NMT Operational
-    let state = NmtState::Operational;
-    let mut attempt = 0;
-    let mut nmt_event_rx = event_rx.resubscribe();
-    loop {
-        // Put the device in Opertional
-        nmt_tx
-            .send(state.clone())
-            .await
-            .map_err(|err| DriveError::NMTSendError(state.clone(), err))?;
-
-        // Wait for event indicating correct NMT state
-        match timeout(NMT_SWITCH_TIMEOUT, nmt_event_rx.recv()).await {
-            Ok(Ok(MotorEvent::NmtStateUpdate(new_state))) => {
-                // Got an event within the timeout
-                if new_state == state {
-                    break;
-                }
-            }
-            Ok(Err(err)) => {
-                // The channel closed before we got an event
-                error!("Startup NMT PRE-OP: {err}");
-            }
-            Err(_) => {
-                // Timeout expired
-                warn!("Startup NMT PRE-OP: Timed out waiting for event");
+    // Configure the PDOs, this reads back what the device holds first so matching PDOs are left
+    // untouched. Only failed transfers are retried, invalid or missing PDOs stay that way
+    trace!("Configuring PDOs of motor at node id {node_id}");
+    let mut attempt = 1;
+    let mappings = loop {
+        match configure_pdos(
+            node_id,
+            sdo.clone(),
+            dictionary,
+            rpdo_mapping,
+            tpdo_mapping,
+            pdo_setup,
+        )
+        .await
+        {
+            Ok(mappings) => {
+                info!("Succesful PDO configuration for motor {node_id}");
+                break mappings;
             }
-            _ => {
-                // Non-NMT event
+            Err(err) if err.is_retryable() && attempt < PDO_CONFIGURATION_ATTEMPTS => {
+                warn!(
+                    "PDO configuration failed of motor at node id {node_id}: {err}, retrying in {}s",
+                    RETRY_DURATION.as_secs()
+                );
+                attempt += 1;
+                sleep(RETRY_DURATION).await;
             }
+            Err(err) => return Err(err),
         }
+    };
 
-        attempt += 1;
-        if attempt >= NMT_SWITCH_ATTEMPTS {
-            panic!(
-                "Failed to switch device into NMT {state:?} after {NMT_SWITCH_ATTEMPTS} attempts, aborting"
-            );
-        }
-    }
+    // Put the drive in NMT Operational
+    switch_nmt_state(node_id, &nmt_tx, &mut nmt_state, NmtState::Operational).await?;
     trace!("Device reporst NMT Opertional -> Startup Completed!");
 
-    Ok(())
+    Ok(mappings)
 }
@@ -1,33 +1,388 @@
-use std::sync::Arc;
+use std::{fmt, time::Duration};
 
-use anyhow::Result;
-use oze_canopen::sdo_client::SdoClient;
-use tokio::sync::Mutex;
 use tracing::*;
 
-use crate::comms::sdo::{SDO_PROCESS_DURATION, SdoAction};
+use crate::{
+    comms::sdo::{
+        SDO_PROCESS_DURATION, SdoAction, SdoResult, abort::SdoAbortCode, scheduler::SdoHandle,
+    },
+    driver::startup::RETRY_DURATION,
+    error::DriveError,
+    od::value::{CodecError, ODValue},
+};
+
+/// Decides whether the startup continues when parametrisation actions fail
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailurePolicy {
+    /// Stop the startup if any action failed
+    Fail,
+    /// Repeat actions that failed with a transient error (e.g. a timeout), stop the startup if
+    /// any action still failed after `attempts` tries
+    Retry { attempts: usize, delay: Duration },
+    /// Log the failed actions and continue the startup
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParametrisationOptions {
+    pub policy: FailurePolicy,
+    /// Upload every downloaded object afterwards and compare it with the written value, off by
+    /// default as it doubles the SDO transfers of the startup
+    pub read_back: bool,
+}
+
+impl Default for ParametrisationOptions {
+    fn default() -> Self {
+        Self {
+            policy: FailurePolicy::Retry {
+                attempts: 3,
+                delay: RETRY_DURATION,
+            },
+            read_back: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ActionOutcome {
+    /// Downloaded, the object is not read back
+    Written,
+    /// Downloaded, reading back the object returned the written value
+    Verified,
+    /// Uploaded data
+    Uploaded(Vec<u8>),
+    /// Downloaded, but reading back the object returned a different value
+    Mismatch {
+        read: ODValue,
+    },
+    Failed(DriveError),
+}
+
+impl ActionOutcome {
+    pub fn is_success(&self) -> bool {
+        !matches!(
+            self,
+            ActionOutcome::Mismatch { .. } | ActionOutcome::Failed(_)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ActionReport {
+    pub action: SdoAction,
+    pub outcome: ActionOutcome,
+    /// Number of times the action ran
+    pub attempts: usize,
+}
+
+impl ActionReport {
+    /// Abort code of the failed SDO transfer, if the device aborted it
+    pub fn abort_code(&self) -> Option<SdoAbortCode> {
+        match &self.outcome {
+            ActionOutcome::Failed(err) => err.sdo_abort_code(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ActionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            SdoAction::Download { entry, value } => write!(
+                f,
+                "{:#06x}:{} {} <= {value}: ",
+                entry.index, entry.sub_index, entry.name
+            )?,
+            SdoAction::Upload { entry } => write!(
+                f,
+                "{:#06x}:{} {}: ",
+                entry.index, entry.sub_index, entry.name
+            )?,
+        }
+
+        match &self.outcome {
+            ActionOutcome::Written => write!(f, "written"),
+            ActionOutcome::Verified => write!(f, "verified"),
+            ActionOutcome::Uploaded(data) => write!(f, "read {data:02x?}"),
+            ActionOutcome::Mismatch { read } => write!(f, "read back {read}"),
+            ActionOutcome::Failed(err) => {
+                write!(f, "failed after {} attempt(s): {err}", self.attempts)
+            }
+        }
+    }
+}
+
+/// Outcome of every action of a parametrisation, in order
+#[derive(Debug)]
+pub struct ParametrisationReport {
+    pub node_id: u8,
+    pub actions: Vec<ActionReport>,
+}
+
+impl ParametrisationReport {
+    pub fn is_success(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|report| report.outcome.is_success())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ActionReport> {
+        self.actions
+            .iter()
+            .filter(|report| !report.outcome.is_success())
+    }
+}
+
+impl fmt::Display for ParametrisationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Parametrisation of node id {}: {} of {} actions failed",
+            self.node_id,
+            self.failures().count(),
+            self.actions.len()
+        )?;
+        for report in &self.actions {
+            write!(f, "\n  {report}")?;
+        }
+        Ok(())
+    }
+}
 
 /// Parametrize the motor at given node id
 /// parametrisation is the process of setting important parameters like
 /// maximum velocity or torque to known values at boot
 /// The motor usually does not commit these changes to non-volatile memory,
 /// so this has to run on every new boot cycle of the device
+/// Every action runs, the policy of `options` decides if failed actions turn into an error, which
+/// carries the full report
 pub async fn parametrise_motor(
     node_id: u8,
-    parameters: &[SdoAction<'_>],
-    sdo: Arc<Mutex<SdoClient>>,
-) -> Result<()> {
+    parameters: &[SdoAction],
+    sdo: SdoHandle,
+    options: ParametrisationOptions,
+) -> Result<ParametrisationReport, DriveError> {
     trace!("Starting parametrisation of Motor with node id {}", node_id);
 
+    let max_attempts = match options.policy {
+        FailurePolicy::Retry { attempts, .. } => attempts.max(1),
+        FailurePolicy::Fail | FailurePolicy::Warn => 1,
+    };
+
     // parametrisation is done through a series of SDO calls, perform these in order
+    let mut actions = Vec::with_capacity(parameters.len());
     for action in parameters {
-        trace!("parametrizing node id {} with: {action:?}", node_id);
-        if let Err(err) = action.run_on_sdo_client(sdo.clone()).await {
-            error!("Error while parametrizing node id {}: {err}", node_id);
+        let mut attempts = 0;
+        let outcome = loop {
+            attempts += 1;
+            trace!("parametrizing node id {} with: {action:?}", node_id);
+            let outcome = run_action(action, sdo.clone(), options.read_back).await;
+            tokio::time::sleep(SDO_PROCESS_DURATION).await;
+
+            match (&outcome, options.policy) {
+                (ActionOutcome::Failed(err), FailurePolicy::Retry { delay, .. })
+                    if err.is_retryable() && attempts < max_attempts =>
+                {
+                    warn!("Error while parametrizing node id {node_id}: {err}, retrying");
+                    tokio::time::sleep(delay).await;
+                }
+                _ => break outcome,
+            }
+        };
+
+        actions.push(ActionReport {
+            action: action.clone(),
+            outcome,
+            attempts,
+        });
+    }
+
+    let report = ParametrisationReport { node_id, actions };
+    if report.is_success() {
+        debug!("{report}");
+        return Ok(report);
+    }
+
+    match options.policy {
+        FailurePolicy::Warn => {
+            warn!("{report}");
+            Ok(report)
         }
+        FailurePolicy::Fail | FailurePolicy::Retry { .. } => {
+            error!("{report}");
+            Err(DriveError::Parametrisation(Box::new(report)))
+        }
+    }
+}
+
+/// Run a single action, downloads are read back if asked for and the object is readable
+async fn run_action(action: &SdoAction, sdo: SdoHandle, read_back: bool) -> ActionOutcome {
+    let transaction = match action.run_on_sdo_client(sdo.clone()).await {
+        Ok(transaction) => transaction,
+        Err(err) => return ActionOutcome::Failed(err),
+    };
 
-        tokio::time::sleep(SDO_PROCESS_DURATION).await;
+    let (entry, value) = match (action, transaction.result()) {
+        (SdoAction::Upload { .. }, SdoResult::Data(data)) => {
+            return ActionOutcome::Uploaded(data.clone());
+        }
+        (SdoAction::Download { entry, value }, _) => (entry, value),
+        (SdoAction::Upload { .. }, _) => return ActionOutcome::Uploaded(Vec::new()),
+    };
+
+    if !read_back || entry.check_readable().is_err() {
+        return ActionOutcome::Written;
     }
 
-    Ok(())
+    let Some(data_type) = entry.default.data_type() else {
+        return ActionOutcome::Failed(CodecError::Container.into());
+    };
+    match sdo
+        .upload_value(entry.index, entry.sub_index, data_type)
+        .await
+    {
+        Ok(read) if read == *value => ActionOutcome::Verified,
+        Ok(read) => ActionOutcome::Mismatch { read },
+        Err(err) => ActionOutcome::Failed(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::sdo::mock::{MockDevice, client_with_device},
+        od::typed,
+    };
+
+    const RETRY: ParametrisationOptions = ParametrisationOptions {
+        policy: FailurePolicy::Retry {
+            attempts: 3,
+            delay: Duration::ZERO,
+        },
+        read_back: true,
+    };
+
+    #[tokio::test]
+    async fn test_report() {
+        let mut device = MockDevice::default();
+        // Motion profile type can not be written in this state, maximum motor speed is clamped
+        device.abort(0x6086, 0, SdoAbortCode::DeviceState, 1);
+        device.ignored.insert((0x6080, 0));
+        device
+            .objects
+            .insert((0x6080, 0), 1000u32.to_le_bytes().to_vec());
+        device
+            .objects
+            .insert((0x1000, 0), vec![0x92, 0x01, 0x02, 0x00]);
+        let (sdo, device) = client_with_device(device);
+
+        let parameters = [
+            typed::MOTION_PROFILE_TYPE.write(1),
+            typed::MAX_MOTOR_SPEED.write(2000),
+            typed::DEVICE_TYPE.read(),
+        ];
+
+        let Err(DriveError::Parametrisation(report)) =
+            parametrise_motor(3, &parameters, sdo.clone(), RETRY).await
+        else {
+            panic!("Expected the parametrisation to fail");
+        };
+        assert_eq!(report.node_id, 3);
+        assert_eq!(report.actions.len(), 3);
+        assert_eq!(report.failures().count(), 1);
+        // The transient abort is retried, only the clamped speed is left
+        assert!(matches!(
+            report.failures().next().unwrap().outcome,
+            ActionOutcome::Mismatch { .. }
+        ));
+
+        let options = ParametrisationOptions {
+            policy: FailurePolicy::Warn,
+            ..RETRY
+        };
+        device
+            .lock()
+            .unwrap()
+            .abort(0x6086, 0, SdoAbortCode::DeviceState, 1);
+        let report = parametrise_motor(3, &parameters, sdo, options)
+            .await
+            .unwrap();
+
+        // Transient aborts are not retried when only warning
+        assert!(!report.is_success());
+        assert_eq!(
+            report.actions[0].abort_code(),
+            Some(SdoAbortCode::DeviceState)
+        );
+        assert!(matches!(
+            &report.actions[1].outcome,
+            ActionOutcome::Mismatch { read } if read == &ODValue::U32(1000)
+        ));
+        assert!(matches!(
+            &report.actions[2].outcome,
+            ActionOutcome::Uploaded(data) if data == &[0x92, 0x01, 0x02, 0x00]
+        ));
+        assert_eq!(report.failures().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_read_back_unsized() {
+        let device = MockDevice {
+            unsized_uploads: true,
+            ..MockDevice::default()
+        };
+        let (sdo, _) = client_with_device(device);
+
+        // Narrow objects read back as 4 bytes still match the written value
+        let parameters = [
+            typed::MOTION_PROFILE_TYPE.write(1),
+            typed::MAX_MOTOR_SPEED.write(2000),
+        ];
+        let report = parametrise_motor(3, &parameters, sdo, RETRY).await.unwrap();
+        assert!(
+            report
+                .actions
+                .iter()
+                .all(|report| matches!(report.outcome, ActionOutcome::Verified))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let mut device = MockDevice::default();
+        device.abort(0x6086, 0, SdoAbortCode::DeviceState, 2);
+        device.abort(0x6080, 0, SdoAbortCode::ValueTooHigh, 1);
+        let (sdo, device) = client_with_device(device);
+
+        let parameters = [
+            typed::MOTION_PROFILE_TYPE.write(1),
+            typed::MAX_MOTOR_SPEED.write(2000),
+        ];
+
+        // Permanent aborts fail right away
+        let Err(DriveError::Parametrisation(report)) =
+            parametrise_motor(3, &parameters, sdo.clone(), RETRY).await
+        else {
+            panic!("Expected the parametrisation to fail");
+        };
+        assert_eq!(
+            report.actions[1].abort_code(),
+            Some(SdoAbortCode::ValueTooHigh)
+        );
+        assert_eq!(report.actions[1].attempts, 1);
+
+        device
+            .lock()
+            .unwrap()
+            .abort(0x6086, 0, SdoAbortCode::DeviceState, 2);
+        let report = parametrise_motor(3, &parameters, sdo, RETRY).await.unwrap();
+        assert_eq!(report.actions[0].attempts, 3);
+        assert!(matches!(report.actions[0].outcome, ActionOutcome::Verified));
+        assert_eq!(report.actions[1].attempts, 1);
+        assert_eq!(
+            device.lock().unwrap().objects.get(&(0x6080, 0)),
+            Some(&2000u32.to_le_bytes().to_vec())
+        );
+    }
 }
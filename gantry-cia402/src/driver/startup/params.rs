@@ -1,116 +1,43 @@
-use crate::{comms::sdo::SdoAction, driver::startup::home::HomingMethods, od::*};
+use crate::{comms::sdo::SdoAction, driver::startup::home::HomingMethods, od::typed::*};
 
 pub const PARAMS: &[SdoAction] = &[
     // Always good to upload device type for info
-    SdoAction::Upload {
-        entry: &DEVICE_TYPE,
-    },
+    DEVICE_TYPE.read(),
     // --- Profile Position ---
     // Set target position = 0 (we start from home or zero)
-    SdoAction::Download {
-        entry: &SET_TARGET_POSITION,
-        data: &0i32.to_le_bytes(),
-    },
+    SET_TARGET_POSITION.write(0),
     // Software position limits (disable by using min > max or wide range)
-    SdoAction::Download {
-        entry: &SOFTWARE_POSITION_RANGE_LIMIT_MIN,
-        data: &0i32.to_le_bytes(), // often used as "disable"
-    },
-    SdoAction::Download {
-        entry: &SOFTWARE_POSITION_RANGE_LIMIT_MAX,
-        data: &0i32.to_le_bytes(), // often used as "disable"
-    },
-    SdoAction::Download {
-        entry: &HOME_OFFSET,
-        data: &0i32.to_le_bytes(),
-    },
-    SdoAction::Download {
-        entry: &POSITION_RANGE_LIMIT_MIN,
-        data: &(-36000i32).to_le_bytes(), // 3600 counts = 1 rev
-    },
-    SdoAction::Download {
-        entry: &POSITION_RANGE_LIMIT_MAX,
-        data: &(36000i32).to_le_bytes(), // 3600 counts = 1 rev
-    },
-    SdoAction::Download {
-        entry: &POLARITY,
-        data: &0u8.to_le_bytes(), // normal direction
-    },
-    SdoAction::Download {
-        entry: &PROFILE_VELOCITY,
-        data: &(30u32).to_le_bytes(), // 30 revs/minute
-    },
-    SdoAction::Download {
-        entry: &END_VELOCITY,
-        data: &0u32.to_le_bytes(), // must be 0 for PP mode
-    },
-    SdoAction::Download {
-        entry: &PROFILE_ACCELERATION,
-        data: &(20_000u32).to_le_bytes(), // counts/s²
-    },
-    SdoAction::Download {
-        entry: &PROFILE_DECELERATION,
-        data: &(20_000u32).to_le_bytes(),
-    },
-    SdoAction::Download {
-        entry: &QUICK_STOP_DECELERATION,
-        data: &(30_000u32).to_le_bytes(),
-    },
-    SdoAction::Download {
-        entry: &MOTION_PROFILE_TYPE,
-        data: &1i16.to_le_bytes(), // 0 = trapezoidal, 1 = sinusoidal
-    },
-    SdoAction::Download {
-        entry: &MAX_ACCELERATION,
-        data: &(30_000u32).to_le_bytes(),
-    },
-    SdoAction::Download {
-        entry: &MAX_DECELERATION,
-        data: &(30_000u32).to_le_bytes(),
-    },
-    SdoAction::Download {
-        entry: &POSITIONING_OPTION_CODE,
-        data: &0u16.to_le_bytes(), // absolute positioning, immediate start
-    },
+    SOFTWARE_POSITION_RANGE_LIMIT_MIN.write(0), // often used as "disable"
+    SOFTWARE_POSITION_RANGE_LIMIT_MAX.write(0), // often used as "disable"
+    HOME_OFFSET.write(0),
+    POSITION_RANGE_LIMIT_MIN.write(-36000), // 3600 × 0.1° = 1 rev
+    POSITION_RANGE_LIMIT_MAX.write(36000),  // 3600 × 0.1° = 1 rev
+    POLARITY.write(0),                      // normal direction
+    PROFILE_VELOCITY.write(30),             // 30 revs/minute
+    END_VELOCITY.write(0),                  // must be 0 for PP mode
+    // The maximum acceleration limits the profile acceleration, so raise it first
+    MAX_ACCELERATION.write(30_000),
+    MAX_DECELERATION.write(30_000),
+    PROFILE_ACCELERATION.write(20_000), // rpm/s
+    PROFILE_DECELERATION.write(20_000),
+    QUICK_STOP_DECELERATION.write(30_000),
+    MOTION_PROFILE_TYPE.write(1),     // 0 = trapezoidal, 1 = sinusoidal
+    POSITIONING_OPTION_CODE.write(0), // absolute positioning, immediate start
     // --- Homing Mode Parameters (CiA 402 § 6.5.1.5) ---
     // 607Ch – Home Offset
-    SdoAction::Download {
-        entry: &HOME_OFFSET,
-        data: &0i32.to_le_bytes(), // controller zero aligns with machine zero
-    },
+    HOME_OFFSET.write(0), // controller zero aligns with machine zero
     // 6098h – Homing Method
-    SdoAction::Download {
-        entry: &HOMING_METHOD,
-        data: &HomingMethods::IndexOnly.as_i8().to_le_bytes(),
-    },
+    HOMING_METHOD.write(HomingMethods::IndexOnly.as_i8()),
     // 6099h:01h – Speed During Search For Switch
-    SdoAction::Download {
-        entry: &HOMING_SPEED_SWITCH_SEARCH,
-        data: &0x32u32.to_le_bytes(),
-    },
+    HOMING_SPEED_SWITCH_SEARCH.write(0x32),
     // 6099h:02h – Speed During Search For Zero
-    SdoAction::Download {
-        entry: &HOMING_SPEED_ZERO_SEARCH,
-        data: &0x0Au32.to_le_bytes(),
-    },
-    // 6080h – Max Motor Speed [counts/s]
-    SdoAction::Download {
-        entry: &MAX_MOTOR_SPEED,
-        data: &2000u32.to_le_bytes(),
-    },
+    HOMING_SPEED_ZERO_SEARCH.write(0x0A),
+    // 6080h – Max Motor Speed [rpm]
+    MAX_MOTOR_SPEED.write(2000),
     // 609Ah – Homing Acceleration
-    SdoAction::Download {
-        entry: &HOMING_ACCELERATION,
-        data: &0x1F4u32.to_le_bytes(),
-    },
+    HOMING_ACCELERATION.write(0x1F4),
     // 203Ah:01h – Minimum Current For Block Detection
-    SdoAction::Download {
-        entry: &BLOCK_DETECTION_MIN_CURRENT,
-        data: &0x41Ai32.to_le_bytes(),
-    },
+    BLOCK_DETECTION_MIN_CURRENT.write(0x41A),
     // 203Ah:02h – Period Of Blocking
-    SdoAction::Download {
-        entry: &BLOCK_DETECTION_PERIOD,
-        data: &0xC6i32.to_le_bytes(),
-    },
+    BLOCK_DETECTION_PERIOD.write(0xC6),
 ];
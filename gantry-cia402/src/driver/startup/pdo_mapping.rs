@@ -1,97 +1,557 @@
-use std::sync::Arc;
+use std::{borrow::Cow, collections::BTreeSet, time::Duration};
 
-use anyhow::Result;
-use oze_canopen::sdo_client::SdoClient;
-use tokio::sync::Mutex;
+use tokio::sync::watch;
 use tracing::*;
 
 use crate::{
     comms::{
-        pdo::mapping::{PdoMapping, PdoType},
-        sdo::SDO_PROCESS_DURATION,
+        pdo::mapping::{
+            BitRange, PdoMapping, PdoMappingSource, PdoType, profile::PdoProfile,
+            registry::MappingRegistry,
+        },
+        sdo::{SDO_PROCESS_DURATION, abort::SdoAbortCode, scheduler::SdoHandle},
     },
     error::DriveError,
     od::{
-        RPDO_COMMUNICATION_PARAMETER_BASE_INDEX, RPDO_MAPPING_PARAMETER_BASE_INDEX,
+        DUMMY_ENTRIES, RPDO_COMMUNICATION_PARAMETER_BASE_INDEX, RPDO_MAPPING_PARAMETER_BASE_INDEX,
         TPDO_COMMUNICATION_PARAMETER_BASE_INDEX, TPDO_MAPPING_PARAMETER_BASE_INDEX,
+        dictionary::ObjectDictionary, typed::ODType,
     },
 };
 
-#[derive(Debug, PartialEq)]
+/// Subindex 02h of a PDO communication parameter, see CiA 301 7.5.2.35
+/// RPDOs with a synchronous type are applied at the next SYNC, with an event driven type as soon
+/// as they are received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransmissionType {
-    OnSync,
+    /// Sent at the first SYNC after an event (00h)
+    SyncAcyclic,
+    /// Sent at every n-th SYNC, n between 1 and 240 (01h-F0h)
+    SyncCyclic(u8),
+    /// TPDO only, sampled at SYNC and sent on remote request (FCh)
+    RtrSync,
+    /// TPDO only, sent on remote request (FDh)
+    RtrEvent,
+    /// Sent on a manufacturer specific event (FEh)
+    EventManufacturer,
+    /// Sent on change, the event is defined by the device profile (FFh)
     OnChange,
 }
 
 impl TransmissionType {
+    /// Transmission at every SYNC
+    pub const ON_SYNC: Self = TransmissionType::SyncCyclic(1);
+
     pub fn od_value(&self) -> u8 {
         match self {
-            TransmissionType::OnSync => 0x1,
+            TransmissionType::SyncAcyclic => 0x00,
+            TransmissionType::SyncCyclic(every) => *every,
+            TransmissionType::RtrSync => 0xFC,
+            TransmissionType::RtrEvent => 0xFD,
+            TransmissionType::EventManufacturer => 0xFE,
             TransmissionType::OnChange => 0xFF,
         }
     }
+
+    /// Transmission type of the value of subindex 02h of a communication parameter, None for
+    /// reserved values
+    pub fn from_od_value(value: u8) -> Option<Self> {
+        Some(match value {
+            0x00 => TransmissionType::SyncAcyclic,
+            0x01..=0xF0 => TransmissionType::SyncCyclic(value),
+            0xFC => TransmissionType::RtrSync,
+            0xFD => TransmissionType::RtrEvent,
+            0xFE => TransmissionType::EventManufacturer,
+            0xFF => TransmissionType::OnChange,
+            _ => return None,
+        })
+    }
+
+    pub fn is_rtr(&self) -> bool {
+        matches!(self, TransmissionType::RtrSync | TransmissionType::RtrEvent)
+    }
+
+    /// Whether the PDO is sent or applied at a SYNC
+    pub fn is_synchronous(&self) -> bool {
+        matches!(
+            self,
+            TransmissionType::SyncAcyclic | TransmissionType::SyncCyclic(_)
+        )
+    }
+}
+
+/// Communication parameter of a PDO, optional parameters are left as the device has them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdoCommunication {
+    /// 11 bit COB-ID of subindex 01h, None for the COB-ID of the predefined connection set. A
+    /// TPDO of one node can be received as an RPDO by another node by giving both its COB-ID
+    pub cob_id: Option<u16>,
+    pub transmission_type: TransmissionType,
+    /// TPDO only, minimum time between two transmissions in multiples of 100µs (subindex 03h)
+    pub inhibit_time: Option<u16>,
+    /// Subindex 05h in ms, 0 disables it. TPDOs with an event driven transmission type are also
+    /// sent whenever it elapses. RPDOs that are not received within it raise an error on the
+    /// device, the [`crate::comms::pdo::Pdo`] keeps refreshing those
+    pub event_timer: Option<u16>,
+    /// TPDO only, SYNC counter value of the first SYNC of a cyclic synchronous TPDO (subindex
+    /// 06h), 0 ignores the SYNC counter
+    pub sync_start: Option<u8>,
 }
 
-/// Configure the devicde with the given set of PDO mappings
-pub async fn configure_pdo_mappings(
+impl PdoCommunication {
+    pub const fn new(transmission_type: TransmissionType) -> Self {
+        Self {
+            cob_id: None,
+            transmission_type,
+            inhibit_time: None,
+            event_timer: None,
+            sync_start: None,
+        }
+    }
+
+    pub const fn with_cob_id(mut self, cob_id: u16) -> Self {
+        self.cob_id = Some(cob_id);
+        self
+    }
+
+    pub const fn with_inhibit_time(mut self, inhibit_time: u16) -> Self {
+        self.inhibit_time = Some(inhibit_time);
+        self
+    }
+
+    pub const fn with_event_timer(mut self, event_timer: u16) -> Self {
+        self.event_timer = Some(event_timer);
+        self
+    }
+
+    pub const fn with_sync_start(mut self, sync_start: u8) -> Self {
+        self.sync_start = Some(sync_start);
+        self
+    }
+
+    /// Check that the parameters can be applied to the given PDO
+    pub fn validate(&self, pdo: &PdoType) -> Result<(), DriveError> {
+        let invalid = |reason| {
+            Err(DriveError::InvalidCommunication {
+                pdo: pdo.clone(),
+                reason,
+            })
+        };
+        let (PdoType::RPDO(num) | PdoType::TPDO(num)) = *pdo;
+        let is_rpdo = matches!(pdo, PdoType::RPDO(_));
+
+        if num == 0 {
+            return invalid("PDOs are numbered from 1");
+        }
+        if num > PREDEFINED_PDOS && self.cob_id.is_none() {
+            return invalid("PDOs beyond the predefined connection set need an explicit COB-ID");
+        }
+        match self.cob_id {
+            Some(0x800..) => return invalid("COB-IDs have 11 bits"),
+            Some(cob_id) if is_restricted(cob_id) => {
+                return invalid("the COB-ID is restricted by CiA 301");
+            }
+            _ => {}
+        }
+        if let TransmissionType::SyncCyclic(every) = self.transmission_type
+            && !(0x01..=0xF0).contains(&every)
+        {
+            return invalid("cyclic synchronous transmission is every 1 up to 240 SYNCs");
+        }
+        if is_rpdo && self.transmission_type.is_rtr() {
+            return invalid("RPDOs can not be requested remotely");
+        }
+        if is_rpdo && self.inhibit_time.is_some() {
+            return invalid("RPDOs have no inhibit time");
+        }
+        if self.sync_start.is_some_and(|start| start != 0)
+            && (is_rpdo || !matches!(self.transmission_type, TransmissionType::SyncCyclic(_)))
+        {
+            return invalid("a SYNC start value requires a cyclic synchronous TPDO");
+        }
+
+        Ok(())
+    }
+
+    /// Period at which an RPDO has to be sent to stay within its event timer, None if it has none
+    pub fn refresh_period(&self) -> Option<Duration> {
+        self.event_timer
+            .filter(|timer| *timer != 0)
+            .map(|timer| Duration::from_millis(timer as u64) / 2)
+    }
+}
+
+/// COB-IDs PDOs can not use, those of NMT, SYNC, TIME, SDO, LSS and the restricted ranges of
+/// CiA 301 7.3.5
+fn is_restricted(cob_id: u16) -> bool {
+    matches!(
+        cob_id,
+        0x000..=0x080 | 0x100..=0x180 | 0x581..=0x5FF | 0x601..=0x67F | 0x6E0..=0x7FF
+    )
+}
+
+/// Number of RPDOs and TPDOs of the predefined connection set, every one of these is checked at
+/// startup
+pub const PREDEFINED_PDOS: u8 = 4;
+
+/// Valid bit of subindex 01h of a communication parameter, set if the PDO is disabled
+pub const PDO_INVALID: u32 = 1 << 31;
+/// RTR bit of subindex 01h of a communication parameter, set if remote requests are not allowed
+const PDO_NO_RTR: u32 = 1 << 30;
+/// CAN-ID bits of subindex 01h of a communication parameter, 29 bits for extended frames
+pub const PDO_CAN_ID: u32 = (1 << 29) - 1;
+
+/// How the startup treats the PDO configuration found on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PdoSetup {
+    /// Apply the requested mappings, PDOs that already match are left untouched and PDOs outside
+    /// of the requested set are disabled
+    #[default]
+    Configure,
+    /// Leave the device as is, PDOs are decoded with the mappings the device reports
+    Adopt,
+}
+
+/// Configuration of a single PDO as uploaded from the device, optional subindices are None if
+/// the device does not have them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdoConfiguration {
+    pub pdo: PdoType,
+    /// Subindex 01h of the communication parameter, including the valid and RTR bit
+    pub cob_id: u32,
+    pub transmission_type: u8,
+    pub inhibit_time: Option<u16>,
+    pub event_timer: Option<u16>,
+    pub sync_start: Option<u8>,
+    /// Mapping parameter values of the mapped objects, see [`PdoMappingSource::mapping_value`]
+    pub mapped: Vec<u32>,
+}
+
+impl PdoConfiguration {
+    pub fn is_valid(&self) -> bool {
+        self.cob_id & PDO_INVALID == 0
+    }
+
+    /// Whether the device already holds the given mapping, as written by `set_pdo_mapping`
+    pub fn matches(&self, mapping: &PdoMapping) -> bool {
+        let communication = &mapping.communication;
+        let transmission_type = communication.transmission_type;
+
+        self.is_valid()
+            && self.pdo == mapping.pdo
+            && requested(
+                communication.cob_id.map(u32::from),
+                Some(self.cob_id & PDO_CAN_ID),
+            )
+            && self.transmission_type == transmission_type.od_value()
+            && (!transmission_type.is_rtr() || self.cob_id & PDO_NO_RTR == 0)
+            && requested(communication.inhibit_time, self.inhibit_time)
+            && requested(communication.event_timer, self.event_timer)
+            && requested(communication.sync_start, self.sync_start)
+            && self
+                .mapped
+                .iter()
+                .copied()
+                .eq(mapping.sources.iter().map(PdoMappingSource::mapping_value))
+    }
+
+    /// Mapping describing this configuration, the mapped objects are resolved against the
+    /// [`DUMMY_ENTRIES`] and the dictionary of the node, the mapping owns copies of its entries
+    /// The COB-ID is only kept if it differs from the one of the predefined connection set of
+    /// the given node
+    pub fn to_mapping(
+        &self,
+        node_id: u8,
+        dictionary: &ObjectDictionary,
+    ) -> Result<PdoMapping, DriveError> {
+        let mut start = 0u8;
+        let mut sources = Vec::with_capacity(self.mapped.len());
+        for &value in &self.mapped {
+            let (index, sub_index, len) = ((value >> 16) as u16, (value >> 8) as u8, value as u8);
+            let entry = DUMMY_ENTRIES
+                .iter()
+                .find(|entry| entry.index == index && entry.sub_index == sub_index)
+                .map(Cow::Borrowed)
+                .or_else(|| dictionary.get(index, sub_index).cloned().map(Cow::Owned))
+                .ok_or_else(|| {
+                    DriveError::ViolatedInvariant(format!(
+                        "{:?} maps {index:#06x}:{sub_index}, which is not in the object dictionary",
+                        self.pdo
+                    ))
+                })?;
+
+            sources.push(PdoMappingSource {
+                entry,
+                bit_range: BitRange { start, len },
+            });
+            start = start.checked_add(len).ok_or_else(|| {
+                DriveError::ViolatedInvariant(format!("{:?} maps more than 64 bits", self.pdo))
+            })?;
+        }
+
+        let transmission_type = TransmissionType::from_od_value(self.transmission_type)
+            .ok_or_else(|| DriveError::InvalidCommunication {
+                pdo: self.pdo.clone(),
+                reason: "reserved transmission type",
+            })?;
+        let cob_id = u16::try_from(self.cob_id & PDO_CAN_ID)
+            .ok()
+            .filter(|cob_id| *cob_id < 0x800)
+            .ok_or_else(|| DriveError::InvalidCommunication {
+                pdo: self.pdo.clone(),
+                reason: "extended frame COB-IDs are not supported",
+            })?;
+        let mapping = PdoMapping {
+            pdo: self.pdo.clone(),
+            sources: Cow::Owned(sources),
+            communication: PdoCommunication {
+                cob_id: Some(cob_id)
+                    .filter(|cob_id| self.pdo.get_pdo_cob_id(node_id) != Some(*cob_id)),
+                transmission_type,
+                inhibit_time: self.inhibit_time,
+                event_timer: self.event_timer,
+                // Unused unless the PDO is cyclic synchronous
+                sync_start: self
+                    .sync_start
+                    .filter(|_| matches!(transmission_type, TransmissionType::SyncCyclic(_))),
+            },
+        };
+        mapping.validate()?;
+
+        Ok(mapping)
+    }
+}
+
+/// Whether an optional communication parameter is either left to the device or set as requested
+fn requested<T: PartialEq>(requested: Option<T>, found: Option<T>) -> bool {
+    requested.is_none() || requested == found
+}
+
+/// Upload the configuration of the given PDO, None if the device does not have it
+pub async fn read_pdo_configuration(
+    sdo: SdoHandle,
+    pdo: &PdoType,
+) -> Result<Option<PdoConfiguration>, DriveError> {
+    let (communication_index, mapping_index) = pdo_indices(pdo);
+
+    let cob_id = match sdo.upload(communication_index, 0x1).await {
+        Ok(data) => u32::decode(u32::DATA_TYPE.trim_upload(&data))?,
+        Err(err) if err.abort_code() == Some(SdoAbortCode::NoObject) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let transmission_type = upload_as(&sdo, communication_index, 0x2).await?;
+    let event_timer = upload_optional(&sdo, communication_index, 0x5).await?;
+    let (inhibit_time, sync_start) = match pdo {
+        PdoType::TPDO(_) => (
+            upload_optional(&sdo, communication_index, 0x3).await?,
+            upload_optional(&sdo, communication_index, 0x6).await?,
+        ),
+        PdoType::RPDO(_) => (None, None),
+    };
+
+    let count: u8 = upload_as(&sdo, mapping_index, 0x0).await?;
+    let mut mapped = Vec::with_capacity(count as usize);
+    for sub_index in 1..=count {
+        mapped.push(upload_as(&sdo, mapping_index, sub_index).await?);
+    }
+
+    Ok(Some(PdoConfiguration {
+        pdo: pdo.clone(),
+        cob_id,
+        transmission_type,
+        inhibit_time,
+        event_timer,
+        sync_start,
+        mapped,
+    }))
+}
+
+/// Upload an object the device may not implement, e.g. the optional subindices of a
+/// communication parameter
+async fn upload_optional<T: ODType>(
+    sdo: &SdoHandle,
+    index: u16,
+    sub_index: u8,
+) -> Result<Option<T>, DriveError> {
+    match sdo.upload(index, sub_index).await {
+        Ok(data) => Ok(Some(T::decode(T::DATA_TYPE.trim_upload(&data))?)),
+        Err(err)
+            if matches!(
+                err.abort_code(),
+                Some(SdoAbortCode::NoSubIndex | SdoAbortCode::NoObject)
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Upload an object as the given type, the data of unsized expedited uploads is cut to its width
+async fn upload_as<T: ODType>(sdo: &SdoHandle, index: u16, sub_index: u8) -> Result<T, DriveError> {
+    let data = sdo.upload(index, sub_index).await?;
+    Ok(T::decode(T::DATA_TYPE.trim_upload(&data))?)
+}
+
+/// Bring the PDO configuration of the device in line with the requested mappings, or adopt the
+/// configuration of the device, see [`PdoSetup`]
+/// Adopted mappings are resolved against the given dictionary of the node
+/// Returns the mappings of every enabled PDO afterwards
+pub async fn configure_pdos(
     node_id: u8,
-    sdo: Arc<Mutex<SdoClient>>,
-    pdo_mapping: &'static [PdoMapping],
-) -> Result<()> {
-    trace!("configure_pdo_mappings for nodeId {}", node_id);
-    for mapping in pdo_mapping.iter() {
-        set_pdo_mapping(node_id, sdo.clone(), mapping).await?;
+    sdo: SdoHandle,
+    dictionary: &ObjectDictionary,
+    rpdo_mapping: &'static [PdoMapping],
+    tpdo_mapping: &'static [PdoMapping],
+    setup: PdoSetup,
+) -> Result<Vec<Cow<'static, PdoMapping>>, DriveError> {
+    trace!("configure_pdos for nodeId {node_id} - {setup:?}");
 
-        tokio::time::sleep(SDO_PROCESS_DURATION).await;
+    // Reject the whole set before touching the device
+    let requested: Vec<&'static PdoMapping> = rpdo_mapping.iter().chain(tpdo_mapping).collect();
+    for mapping in &requested {
+        mapping.validate()?;
     }
 
-    Ok(())
+    // Every PDO of the predefined connection set, and any requested beyond it
+    let mut pdos = BTreeSet::new();
+    for num in 1..=PREDEFINED_PDOS {
+        pdos.insert((false, num));
+        pdos.insert((true, num));
+    }
+    for mapping in &requested {
+        pdos.insert(match mapping.pdo {
+            PdoType::RPDO(num) => (false, num),
+            PdoType::TPDO(num) => (true, num),
+        });
+    }
+
+    let mut enabled = Vec::new();
+    for (transmit, num) in pdos {
+        let pdo = match transmit {
+            false => PdoType::RPDO(num),
+            true => PdoType::TPDO(num),
+        };
+        let mapping = requested.iter().find(|mapping| mapping.pdo == pdo).copied();
+
+        let Some(configuration) = read_pdo_configuration(sdo.clone(), &pdo).await? else {
+            if setup == PdoSetup::Configure && mapping.is_some() {
+                return Err(DriveError::ViolatedInvariant(format!(
+                    "Node id {node_id} has no {}",
+                    pdo.to_string_pretty()
+                )));
+            }
+            trace!("Node id {node_id} has no {}", pdo.to_string_pretty());
+            continue;
+        };
+        debug!("Node id {node_id} reports {configuration:?}");
+
+        match (setup, mapping) {
+            (PdoSetup::Adopt, _) => {
+                if configuration.is_valid() {
+                    enabled.push(Cow::Owned(configuration.to_mapping(node_id, dictionary)?));
+                }
+            }
+            (PdoSetup::Configure, Some(mapping)) if configuration.matches(mapping) => {
+                info!(
+                    "{} of node id {node_id} is already configured",
+                    pdo.to_string_pretty()
+                );
+                enabled.push(Cow::Borrowed(mapping));
+            }
+            (PdoSetup::Configure, Some(mapping)) => {
+                set_pdo_mapping(node_id, sdo.clone(), mapping, configuration.cob_id).await?;
+                tokio::time::sleep(SDO_PROCESS_DURATION).await;
+                enabled.push(Cow::Borrowed(mapping));
+            }
+            (PdoSetup::Configure, None) if configuration.is_valid() => {
+                info!(
+                    "Disabling {} of node id {node_id}, it is not in the requested set",
+                    pdo.to_string_pretty()
+                );
+                let (communication_index, _) = pdo_indices(&pdo);
+                sdo.download(
+                    communication_index,
+                    0x1,
+                    &(configuration.cob_id | PDO_INVALID).to_le_bytes(),
+                )
+                .await?;
+            }
+            (PdoSetup::Configure, None) => {}
+        }
+    }
+
+    Ok(enabled)
+}
+
+/// Configure the PDOs of a node for the given profile, see [`configure_pdos`]. The mappings of the
+/// node are unregistered meanwhile, so PDOs received during the remap are left undecoded, and
+/// replaced by those of the profile once the device holds them. A failure registers the previous
+/// mappings again
+pub async fn configure_profile(
+    node_id: u8,
+    sdo: SdoHandle,
+    dictionary: &ObjectDictionary,
+    profile: &'static PdoProfile,
+    mappings: &watch::Sender<MappingRegistry>,
+) -> Result<(), DriveError> {
+    let mut previous = None;
+    mappings.send_modify(|registry| previous = registry.remove(node_id));
+
+    let configured = configure_pdos(
+        node_id,
+        sdo,
+        dictionary,
+        profile.rpdos,
+        profile.tpdos,
+        PdoSetup::Configure,
+    )
+    .await;
+    match configured {
+        Ok(enabled) => {
+            mappings.send_modify(|registry| registry.insert(node_id, enabled));
+            Ok(())
+        }
+        Err(err) => {
+            warn!(
+                "Unable to configure PDO profile {} for node id {node_id}, decoding its PDOs with the previous mappings",
+                profile.name
+            );
+            mappings
+                .send_modify(|registry| registry.insert(node_id, previous.into_iter().flatten()));
+            Err(err)
+        }
+    }
 }
 
-/// Apply the given PDO mapping to the device
+/// Apply the given PDO mapping to the device, `cob_id` is the current value of subindex 01h of
+/// its communication parameter
 /// This follows steps listed at page 118 of PD4C_CANopen_Technical_Manual_v3.3
 async fn set_pdo_mapping(
     node_id: u8,
-    sdo: Arc<Mutex<SdoClient>>,
+    sdo: SdoHandle,
     pdo_mapping: &PdoMapping,
-) -> Result<()> {
+    validate_pdo: u32,
+) -> Result<(), DriveError> {
+    pdo_mapping.validate()?;
+
     // 1. Deactivate the PDO by setting the Valid Bit (bit 31) of subindex 01h of the corresponding communication parameter (e.g., 1400h:01h) to "1".
-    let (communication_index, num) = match pdo_mapping.pdo {
-        PdoType::RPDO(num) => (
-            calculate_pdo_index_offset(RPDO_COMMUNICATION_PARAMETER_BASE_INDEX, num),
-            num,
-        ),
-        PdoType::TPDO(num) => (
-            calculate_pdo_index_offset(TPDO_COMMUNICATION_PARAMETER_BASE_INDEX, num),
-            num,
-        ),
-    };
+    let (communication_index, mapping_index) = pdo_indices(&pdo_mapping.pdo);
     info!(
-        "Setting Pdo mapping {num}: {:?} for motor at node id {node_id}",
+        "Setting Pdo mapping {}: {:?} for motor at node id {node_id}",
+        pdo_mapping.pdo.to_string_pretty(),
         pdo_mapping
     );
 
-    let validate_bytes = sdo
-        .lock()
-        .await
-        .upload(communication_index, 0x1)
-        .await
-        .map_err(DriveError::CanOpen)?;
-
-    let validate_pdo = u32::from_le_bytes(
-        validate_bytes
-            .clone()
-            .try_into()
-            .map_err(DriveError::Conversion)?,
-    );
     trace!(
-        "0. Fetched current COB-ID: {:#0x} -> (node, RPDO base COB-ID): ({:#0x}, {:#0x})",
+        "0. Current COB-ID: {:#0x} -> (node, RPDO base COB-ID): ({:#0x}, {:#0x})",
         validate_pdo,
         validate_pdo as u8,
         (validate_pdo & !(u8::MAX as u32)) as u16,
     );
 
-    let invalidate_pdo = validate_pdo | (1 << 31);
+    let mut invalidate_pdo = validate_pdo | PDO_INVALID;
 
     trace!(
         "1. Deactivate the PDO by setting the Valid Bit (bit 31) of subindex 01h of the
@@ -99,117 +559,489 @@ async fn set_pdo_mapping(
         communication_index, invalidate_pdo
     );
     let invalidate_data = invalidate_pdo.to_le_bytes();
-    sdo.lock()
-        .await
-        .download(communication_index, 0x1, &invalidate_data)
-        .await
-        .map_err(DriveError::CanOpen)?;
+    sdo.download(communication_index, 0x1, &invalidate_data)
+        .await?;
+
+    let communication = &pdo_mapping.communication;
+    if let Some(cob_id) = communication.cob_id
+        && invalidate_pdo & PDO_CAN_ID != cob_id as u32
+    {
+        trace!("1.A Set the COB-ID to {cob_id:#0x}, only possible while the PDO is deactivated");
+        invalidate_pdo = (invalidate_pdo & !PDO_CAN_ID) | cob_id as u32;
+        sdo.download(communication_index, 0x1, &invalidate_pdo.to_le_bytes())
+            .await?;
+    }
 
     trace!(
         "1.B Set Transmission type to {:?}",
-        pdo_mapping.transmission_type
+        communication.transmission_type
     );
-    sdo.lock()
-        .await
-        .download(
-            communication_index,
-            0x2,
-            &[pdo_mapping.transmission_type.od_value()],
-        )
-        .await
-        .map_err(DriveError::CanOpen)?;
-
-    if let PdoType::TPDO(_) = pdo_mapping.pdo
-        && pdo_mapping.transmission_type == TransmissionType::OnChange
-    {
-        // Configure a periodic event to continously synchronise the driver with the latest device
-        // state
-        const SYNCHRONISATION_PERIOD_MS: u16 = 500;
-        const SYNCHRONISATION_SUB_IDX: u8 = 0x05;
-
-        trace!(
-            "1.C Transmission type is {:?} -> Configuring a periodic event to continously synchronise state in OD: {:#0x}:{} of val: {:x?}",
-            pdo_mapping.transmission_type,
-            communication_index,
-            SYNCHRONISATION_SUB_IDX,
-            SYNCHRONISATION_PERIOD_MS.to_le_bytes(),
-        );
+    sdo.download(
+        communication_index,
+        0x2,
+        &[communication.transmission_type.od_value()],
+    )
+    .await?;
 
-        sdo.lock()
-            .await
-            .download(
-                communication_index,
-                SYNCHRONISATION_SUB_IDX,
-                &SYNCHRONISATION_PERIOD_MS.to_le_bytes(),
-            )
-            .await
-            .map_err(DriveError::CanOpen)?;
+    trace!("1.C Set the optional communication parameters {communication:?}");
+    if let Some(inhibit_time) = communication.inhibit_time {
+        sdo.download(communication_index, 0x3, &inhibit_time.to_le_bytes())
+            .await?;
+    }
+    if let Some(event_timer) = communication.event_timer {
+        sdo.download(communication_index, 0x5, &event_timer.to_le_bytes())
+            .await?;
+    }
+    if let Some(sync_start) = communication.sync_start {
+        sdo.download(communication_index, 0x6, &[sync_start])
+            .await?;
     }
 
     // 2. Deactivate the mapping by setting subindex 00h of the corresponding mapping parameter to \"0\".,
-    let mapping_index = match pdo_mapping.pdo {
-        PdoType::RPDO(_) => calculate_pdo_index_offset(RPDO_MAPPING_PARAMETER_BASE_INDEX, num),
-        PdoType::TPDO(_) => calculate_pdo_index_offset(TPDO_MAPPING_PARAMETER_BASE_INDEX, num),
-    };
     trace!(
         "2. Deactivate the mapping by setting subindex 00h of the corresponding mapping parameter ({}) to \"0\".",
         mapping_index
     );
     let data = [0];
-    sdo.lock()
-        .await
-        .download(mapping_index, 0x0, &data)
-        .await
-        .map_err(DriveError::CanOpen)?;
+    sdo.download(mapping_index, 0x0, &data).await?;
 
     trace!("3. Change the mapping in the desired subindices.");
     for (number, source) in pdo_mapping.sources.iter().enumerate() {
         let number = number + 1;
 
         trace!("3. Mapping #{number} to {source:?}");
-        // Construct the payload: 2 bytes of OD entry to be mapped, 1 byte subindex, 1 byte with number of bits to be mapped
-        let index_bytes = source.entry.index.to_be_bytes();
-        let data: [u8; 4] = [
-            index_bytes[0],
-            index_bytes[1],
-            source.entry.sub_index,
-            source.bit_range.len,
-        ];
-        let vec: Vec<u8> = data.into_iter().rev().collect();
-
-        sdo.lock()
-            .await
-            .download(mapping_index, number as u8, &vec)
-            .await
-            .map_err(DriveError::CanOpen)?;
+        sdo.download(
+            mapping_index,
+            number as u8,
+            &source.mapping_value().to_le_bytes(),
+        )
+        .await?;
     }
 
     trace!(
         "4. Activate the mapping by writing the number of objects that are to be mapped in subindex 00h of the corresponding mapping parameter (e.g., 1600h:00h)."
     );
     let data = [pdo_mapping.sources.len() as u8];
-    sdo.lock()
-        .await
-        .download(mapping_index, 0x0, &data)
-        .await
-        .map_err(DriveError::CanOpen)?;
+    sdo.download(mapping_index, 0x0, &data).await?;
 
     trace!(
         "5. Activate the PDO by setting bit 31 of subindex 01h of the corresponding communication parameter (e.g., 1400h:01h) to \"0\"."
     );
-    let validate_pdo = invalidate_pdo & !(1 << 31);
-    sdo.lock()
-        .await
-        .download(communication_index, 0x1, &validate_pdo.to_le_bytes())
-        .await
-        .map_err(DriveError::CanOpen)?;
+    let mut validate_pdo = invalidate_pdo & !PDO_INVALID;
+    if communication.transmission_type.is_rtr() {
+        validate_pdo &= !PDO_NO_RTR;
+    }
+    sdo.download(communication_index, 0x1, &validate_pdo.to_le_bytes())
+        .await?;
 
     Ok(())
 }
 
+/// Indices of the communication and mapping parameter of the given PDO
+pub fn pdo_indices(pdo: &PdoType) -> (u16, u16) {
+    match *pdo {
+        PdoType::RPDO(num) => (
+            calculate_pdo_index_offset(RPDO_COMMUNICATION_PARAMETER_BASE_INDEX, num),
+            calculate_pdo_index_offset(RPDO_MAPPING_PARAMETER_BASE_INDEX, num),
+        ),
+        PdoType::TPDO(num) => (
+            calculate_pdo_index_offset(TPDO_COMMUNICATION_PARAMETER_BASE_INDEX, num),
+            calculate_pdo_index_offset(TPDO_MAPPING_PARAMETER_BASE_INDEX, num),
+        ),
+    }
+}
+
 /// Calculates pdo index offset from given base and pdo mapping number
 /// For example SDO for Node Id 3 = 0x500 + 3 = 0x503
 pub fn calculate_pdo_index_offset(base: u16, pdo_mapping_number: u8) -> u16 {
-    base.checked_add((pdo_mapping_number - 1).into())
+    pdo_mapping_number
+        .checked_sub(1)
+        .and_then(|offset| base.checked_add(offset.into()))
         .expect("Overflow in RPDO mapping parameter index calculation")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::{
+            pdo::mapping::custom::{self, SYNCHRONISATION_PERIOD_MS},
+            sdo::mock::{MockDevice, client_with_device},
+        },
+        od,
+    };
+
+    const NODE_ID: u8 = 3;
+
+    fn insert_pdo(
+        device: &mut MockDevice,
+        pdo: &PdoType,
+        cob_id: u32,
+        transmission_type: u8,
+        mapped: &[u32],
+    ) {
+        let (communication_index, mapping_index) = pdo_indices(pdo);
+        let objects = &mut device.objects;
+        objects.insert((communication_index, 0x1), cob_id.to_le_bytes().to_vec());
+        objects.insert((communication_index, 0x2), vec![transmission_type]);
+        if let PdoType::TPDO(_) = pdo {
+            objects.insert(
+                (communication_index, 0x5),
+                SYNCHRONISATION_PERIOD_MS.to_le_bytes().to_vec(),
+            );
+        }
+        objects.insert((mapping_index, 0x0), vec![mapped.len() as u8]);
+        for (sub_index, value) in mapped.iter().enumerate() {
+            objects.insert(
+                (mapping_index, sub_index as u8 + 1),
+                value.to_le_bytes().to_vec(),
+            );
+        }
+    }
+
+    fn mapped(mapping: &PdoMapping) -> Vec<u32> {
+        mapping
+            .sources
+            .iter()
+            .map(PdoMappingSource::mapping_value)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_configure() {
+        const RPDOS: &[PdoMapping] = &[custom::RPDO_CONTROL_OPMODE, custom::RPDO_TARGET_POS];
+
+        let mut device = MockDevice::default();
+        // RPDO1 is configured already, RPDO2 holds another mapping
+        let control = &custom::RPDO_CONTROL_OPMODE;
+        insert_pdo(&mut device, &control.pdo, 0x203, 0xFF, &mapped(control));
+        let other = mapped(&custom::RPDO_TARGET_VEL);
+        insert_pdo(&mut device, &PdoType::RPDO(2), 0x303, 0xFF, &other);
+        // TPDO1 is enabled but not requested, TPDO2 is disabled already
+        let status = mapped(&custom::TPDO_STATUS_OPMODE);
+        insert_pdo(&mut device, &PdoType::TPDO(1), 0x183, 0xFF, &status);
+        insert_pdo(&mut device, &PdoType::TPDO(2), 0x8000_0283, 0xFF, &[]);
+        let (sdo, device) = client_with_device(device);
+
+        let enabled = configure_pdos(
+            NODE_ID,
+            sdo.clone(),
+            &ObjectDictionary::builtin(),
+            RPDOS,
+            &[],
+            PdoSetup::Configure,
+        )
+        .await
+        .unwrap();
+        assert_eq!(enabled.len(), 2);
+
+        let device = device.lock().unwrap();
+        assert!(
+            device
+                .downloads
+                .iter()
+                .all(|(index, _)| ![0x1400, 0x1600, 0x1801].contains(index)),
+            "{:x?}",
+            device.downloads
+        );
+        assert_eq!(
+            device.objects[&(0x1601, 0x0)],
+            vec![RPDOS[1].sources.len() as u8]
+        );
+        assert_eq!(
+            device.objects[&(0x1601, 0x2)],
+            RPDOS[1].sources[1].mapping_value().to_le_bytes()
+        );
+        assert_eq!(device.objects[&(0x1401, 0x1)], 0x303u32.to_le_bytes());
+        assert_eq!(device.objects[&(0x1800, 0x1)], 0x8000_0183u32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_missing_pdo() {
+        const TPDOS: &[PdoMapping] = &[custom::TPDO_STATUS_OPMODE];
+
+        let (sdo, _) = client_with_device(MockDevice::default());
+        let result = configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &[],
+            TPDOS,
+            PdoSetup::Configure,
+        )
+        .await;
+        assert!(matches!(result, Err(DriveError::ViolatedInvariant(_))));
+    }
+
+    #[tokio::test]
+    async fn test_adopt_oversized() {
+        // Only CANopen FD devices map more than the 64 bits of a classic frame
+        let mut device = MockDevice::default();
+        let mut position = mapped(&custom::TPDO_POS_VEL_ACTUAL);
+        position.extend(mapped(&custom::TPDO_STATUS_OPMODE));
+        insert_pdo(&mut device, &PdoType::TPDO(1), 0x183, 0x01, &position);
+        let (sdo, _) = client_with_device(device);
+        let result = configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &[],
+            &[],
+            PdoSetup::Adopt,
+        )
+        .await;
+        assert!(matches!(result, Err(DriveError::InvalidMapping { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_adopt() {
+        let mut device = MockDevice::default();
+        let control = mapped(&custom::RPDO_CONTROL_OPMODE);
+        insert_pdo(&mut device, &PdoType::RPDO(1), 0x203, 0xFF, &control);
+        let position = mapped(&custom::TPDO_POS_VEL_ACTUAL);
+        insert_pdo(&mut device, &PdoType::TPDO(3), 0x383, 0x01, &position);
+        insert_pdo(&mut device, &PdoType::TPDO(1), 0x8000_0183, 0xFF, &[]);
+        let (sdo, device) = client_with_device(device);
+
+        // The requested mappings are ignored
+        let adopted = configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            custom::CUSTOM_RPDOS,
+            custom::CUSTOM_TPDOS,
+            PdoSetup::Adopt,
+        )
+        .await
+        .unwrap();
+        assert!(device.lock().unwrap().downloads.is_empty());
+
+        assert_eq!(adopted.len(), 2);
+        assert_eq!(adopted[0].pdo, PdoType::RPDO(1));
+        assert_eq!(
+            adopted[0].communication,
+            PdoCommunication::new(TransmissionType::OnChange)
+        );
+        assert_eq!(adopted[1].pdo, PdoType::TPDO(3));
+        assert_eq!(
+            adopted[1].communication,
+            PdoCommunication::new(TransmissionType::ON_SYNC)
+                .with_event_timer(SYNCHRONISATION_PERIOD_MS)
+        );
+        assert_eq!(adopted[1].dlc(), 8);
+
+        // The device mapping decodes the same as the mapping it was configured with
+        let data = [0x10, 0x27, 0x00, 0x00, 0xF4, 0x01, 0x00, 0x00];
+        assert_eq!(
+            adopted[1].decode(&data).unwrap(),
+            custom::TPDO_POS_VEL_ACTUAL.decode(&data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adopt_from_dictionary() {
+        let mut device = MockDevice {
+            unsized_uploads: true,
+            ..Default::default()
+        };
+        let status = mapped(&custom::TPDO_STATUS_OPMODE);
+        insert_pdo(&mut device, &PdoType::TPDO(1), 0x183, 0xFF, &status);
+        let (sdo, _) = client_with_device(device);
+
+        // The entries are those of the node, not the builtin ones
+        let mut dictionary = ObjectDictionary::builtin();
+        let mut status_word = od::STATUS_WORD;
+        status_word.name = "Device status".into();
+        dictionary.insert(status_word);
+
+        let adopted = configure_pdos(NODE_ID, sdo.clone(), &dictionary, &[], &[], PdoSetup::Adopt)
+            .await
+            .unwrap();
+        assert_eq!(adopted.len(), 1);
+        assert_eq!(adopted[0].sources[0].entry.name, "Device status");
+        assert_eq!(
+            adopted[0].communication.event_timer,
+            Some(SYNCHRONISATION_PERIOD_MS)
+        );
+        assert!(matches!(adopted[0], Cow::Owned(_)));
+
+        // Objects the node does not describe can not be decoded
+        let result = configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::new(),
+            &[],
+            &[],
+            PdoSetup::Adopt,
+        )
+        .await;
+        assert!(matches!(result, Err(DriveError::ViolatedInvariant(_))));
+    }
+
+    #[tokio::test]
+    async fn test_configure_profile() {
+        let mut registry = MappingRegistry::default();
+        registry.insert(NODE_ID, custom::PROFILE_MODES.mappings().map(Cow::Borrowed));
+        let mappings = watch::Sender::new(registry);
+        let tpdo2 = |mappings: &watch::Sender<MappingRegistry>| {
+            let registry = mappings.borrow();
+            mapped(registry.get(NODE_ID, &PdoType::TPDO(2)).unwrap())
+        };
+
+        // The device has no PDOs, so the switch fails and the previous mappings stay in use
+        let (sdo, _) = client_with_device(MockDevice::default());
+        let result = configure_profile(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &custom::CYCLIC_SYNCHRONOUS_POSITION,
+            &mappings,
+        )
+        .await;
+        assert!(matches!(result, Err(DriveError::ViolatedInvariant(_))));
+        assert_eq!(tpdo2(&mappings), mapped(&custom::TPDO_POS_VEL_ACTUAL));
+        assert!(mappings.borrow().get(NODE_ID, &PdoType::RPDO(4)).is_some());
+
+        let mut device = MockDevice::default();
+        for num in 1..=PREDEFINED_PDOS {
+            insert_pdo(&mut device, &PdoType::RPDO(num), 0x8000_0000, 0xFF, &[]);
+            insert_pdo(&mut device, &PdoType::TPDO(num), 0x8000_0000, 0xFF, &[]);
+        }
+        let (sdo, _) = client_with_device(device);
+        configure_profile(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &custom::CYCLIC_SYNCHRONOUS_POSITION,
+            &mappings,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tpdo2(&mappings), mapped(&custom::CSP_TPDO_POS_VEL_ACTUAL));
+        assert!(mappings.borrow().get(NODE_ID, &PdoType::RPDO(4)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_communication_parameters() {
+        const STATUS_OPMODE: &PdoMapping = &custom::TPDO_STATUS_OPMODE;
+        const POS_VEL_ACTUAL: &PdoMapping = &custom::TPDO_POS_VEL_ACTUAL;
+        const TPDOS: &[PdoMapping] = &[
+            PdoMapping {
+                pdo: PdoType::TPDO(1),
+                sources: Cow::Borrowed(STATUS_OPMODE.sources()),
+                communication: PdoCommunication::new(TransmissionType::RtrSync)
+                    .with_inhibit_time(20),
+            },
+            PdoMapping {
+                pdo: PdoType::TPDO(2),
+                sources: Cow::Borrowed(POS_VEL_ACTUAL.sources()),
+                communication: PdoCommunication::new(TransmissionType::SyncCyclic(4))
+                    .with_sync_start(2),
+            },
+        ];
+
+        let mut device = MockDevice::default();
+        // Remote requests are not allowed yet
+        insert_pdo(&mut device, &PdoType::TPDO(1), 0x4000_0183, 0xFF, &[]);
+        insert_pdo(&mut device, &PdoType::TPDO(2), 0x4000_0283, 0xFF, &[]);
+        let (sdo, device) = client_with_device(device);
+
+        configure_pdos(
+            NODE_ID,
+            sdo.clone(),
+            &ObjectDictionary::builtin(),
+            &[],
+            TPDOS,
+            PdoSetup::Configure,
+        )
+        .await
+        .unwrap();
+        {
+            let device = device.lock().unwrap();
+            assert_eq!(device.objects[&(0x1800, 0x1)], 0x183u32.to_le_bytes());
+            assert_eq!(device.objects[&(0x1800, 0x2)], [0xFC]);
+            assert_eq!(device.objects[&(0x1800, 0x3)], 20u16.to_le_bytes());
+            assert_eq!(device.objects[&(0x1801, 0x1)], 0x4000_0283u32.to_le_bytes());
+            assert_eq!(device.objects[&(0x1801, 0x2)], [4]);
+            assert_eq!(device.objects[&(0x1801, 0x6)], [2]);
+        }
+
+        // Read back, both match now
+        device.lock().unwrap().downloads.clear();
+        configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &[],
+            TPDOS,
+            PdoSetup::Configure,
+        )
+        .await
+        .unwrap();
+        assert!(
+            device
+                .lock()
+                .unwrap()
+                .downloads
+                .iter()
+                .all(|(index, _)| ![0x1800, 0x1801, 0x1A00, 0x1A01].contains(index))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cob_id() {
+        // RPDO3 receives the actual position of node 2 as target position
+        const TARGET_POS: &PdoMapping = &custom::CSP_RPDO_TARGET_POS;
+        const RPDOS: &[PdoMapping] = &[PdoMapping {
+            pdo: PdoType::RPDO(3),
+            sources: Cow::Borrowed(TARGET_POS.sources()),
+            communication: PdoCommunication::new(TransmissionType::OnChange).with_cob_id(0x282),
+        }];
+
+        let mut device = MockDevice::default();
+        insert_pdo(&mut device, &PdoType::RPDO(3), 0x403, 0xFF, &[]);
+        let (sdo, device) = client_with_device(device);
+
+        configure_pdos(
+            NODE_ID,
+            sdo.clone(),
+            &ObjectDictionary::builtin(),
+            RPDOS,
+            &[],
+            PdoSetup::Configure,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            device.lock().unwrap().objects[&(0x1402, 0x1)],
+            0x282u32.to_le_bytes()
+        );
+
+        // Matches now, and is adopted with its COB-ID
+        device.lock().unwrap().downloads.clear();
+        configure_pdos(
+            NODE_ID,
+            sdo.clone(),
+            &ObjectDictionary::builtin(),
+            RPDOS,
+            &[],
+            PdoSetup::Configure,
+        )
+        .await
+        .unwrap();
+        assert!(device.lock().unwrap().downloads.is_empty());
+
+        let adopted = configure_pdos(
+            NODE_ID,
+            sdo,
+            &ObjectDictionary::builtin(),
+            &[],
+            &[],
+            PdoSetup::Adopt,
+        )
+        .await
+        .unwrap();
+        assert_eq!(adopted[0].communication, RPDOS[0].communication);
+        assert_eq!(adopted[0].cob_id(NODE_ID), Some(0x282));
+    }
+}
@@ -0,0 +1,541 @@
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::*;
+
+use crate::{
+    comms::{
+        pdo::mapping::{PdoMapping, PdoType},
+        sdo::{SdoAction, SdoResult, scheduler::SdoHandle},
+    },
+    driver::startup::pdo_mapping::{PDO_CAN_ID, PDO_INVALID, PREDEFINED_PDOS, pdo_indices},
+    od::{
+        CONTROL_WORD, ODIdx, SET_OPERATION_MODE, SET_TARGET_POSITION, SET_TARGET_TORQUE,
+        SET_TARGET_VELOCITY, dictionary::ObjectDictionary, entry::ODEntry, typed::ODType,
+        value::ODValue,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Unable to read/write snapshot: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to serialize snapshot as TOML: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("Unable to parse TOML snapshot: {0}")]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[error("Unable to (de)serialize JSON snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Expected configuration refers to {0:#06x}:{1}, which is not in the object dictionary")]
+    UnknownObject(u16, u8),
+}
+
+/// Values of the objects of a node, as uploaded from the live device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub node_id: u8,
+    pub objects: Vec<SnapshotObject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotObject {
+    pub index: u16,
+    pub sub_index: u8,
+    pub name: String,
+    /// Uploaded bytes as hex, absent if the upload failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Decoded value, only there for the reader of the snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Reason the upload failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SnapshotObject {
+    /// Uploaded bytes, None if the upload failed or the data is not valid hex
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        let data = self.data.as_deref()?;
+        if !data.len().is_multiple_of(2) {
+            return None;
+        }
+
+        (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+impl Snapshot {
+    /// Upload every readable object of the given entries from the node
+    /// Failed uploads are recorded in the snapshot instead of aborting it, devices commonly lack
+    /// a few optional objects
+    pub async fn take(
+        node_id: u8,
+        sdo: SdoHandle,
+        entries: impl IntoIterator<Item = &'static ODEntry>,
+    ) -> Snapshot {
+        let mut objects = Vec::new();
+
+        for entry in entries {
+            if !entry.access.is_readable() || matches!(entry.default, ODValue::Array(_)) {
+                continue;
+            }
+
+            let mut object = SnapshotObject {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                name: entry.name.to_string(),
+                data: None,
+                value: None,
+                error: None,
+            };
+
+            let action = SdoAction::Upload { entry };
+            match action.run_on_sdo_client(sdo.clone()).await {
+                Ok(transaction) => match transaction.result() {
+                    SdoResult::Data(data) => {
+                        object.data = Some(hex(data));
+                        object.value = entry
+                            .with_value_from(data)
+                            .ok()
+                            .map(|entry| format!("{:?}", entry.default));
+                    }
+                    other => object.error = Some(format!("{other:?}")),
+                },
+                Err(err) => {
+                    warn!(
+                        "Unable to upload {} ({:#06x}:{}) from node {node_id}: {err}",
+                        entry.name, entry.index, entry.sub_index
+                    );
+                    object.error = Some(err.to_string());
+                }
+            }
+
+            objects.push(object);
+        }
+
+        Snapshot { node_id, objects }
+    }
+
+    pub fn get(&self, index: u16, sub_index: u8) -> Option<&SnapshotObject> {
+        self.objects
+            .iter()
+            .find(|object| object.index == index && object.sub_index == sub_index)
+    }
+
+    pub fn to_toml(&self) -> Result<String, SnapshotError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SnapshotError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, SnapshotError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    /// Write the snapshot as JSON if the path ends in `.json`, as TOML otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let contents = match is_json(path) {
+            true => self.to_json()?,
+            false => self.to_toml()?,
+        };
+
+        Ok(std::fs::write(path, contents)?)
+    }
+
+    /// Read a snapshot written by [`Snapshot::save`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        match is_json(path) {
+            true => Self::from_json(&contents),
+            false => Self::from_toml(&contents),
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// Objects the driver commands while running, their values after startup say nothing about the
+/// configuration of the device. The same holds for every object mapped into an RPDO
+const RUNTIME_SETPOINTS: &[ODEntry] = &[
+    CONTROL_WORD,
+    SET_OPERATION_MODE,
+    SET_TARGET_POSITION,
+    SET_TARGET_VELOCITY,
+    SET_TARGET_TORQUE,
+];
+
+/// Value an object should hold after startup, only the bits of the mask are compared if there is
+/// one, e.g. the valid bit of a PDO COB-ID
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedValue {
+    pub entry: ODEntry,
+    pub value: ODValue,
+    pub mask: Option<u32>,
+}
+
+/// Values the startup parametrisation and PDO configuration leave the device with, in index
+/// order. Later downloads of the same object replace earlier ones, runtime setpoints are left out
+/// PDO objects are looked up in the dictionary of the node
+pub fn expected_configuration<'a>(
+    dictionary: &ObjectDictionary,
+    parameters: &[SdoAction],
+    pdo_mappings: impl IntoIterator<Item = &'a PdoMapping>,
+) -> Result<Vec<ExpectedValue>, SnapshotError> {
+    let pdo_mappings: Vec<&PdoMapping> = pdo_mappings.into_iter().collect();
+    let is_setpoint = |entry: &ODEntry| {
+        let rpdo_mapped = pdo_mappings
+            .iter()
+            .filter(|mapping| matches!(mapping.pdo, PdoType::RPDO(_)))
+            .flat_map(|mapping| mapping.sources.iter())
+            .any(|source| source.entry.idx() == entry.idx());
+        rpdo_mapped
+            || RUNTIME_SETPOINTS
+                .iter()
+                .any(|setpoint| setpoint.idx() == entry.idx())
+    };
+
+    let mut expected = BTreeMap::new();
+    for action in parameters {
+        if let SdoAction::Download { entry, value } = action
+            && !is_setpoint(entry)
+        {
+            let expectation = ExpectedValue {
+                entry: (*entry).clone(),
+                value: value.clone(),
+                mask: None,
+            };
+            expected.insert(entry.idx(), expectation);
+        }
+    }
+
+    let mut expect = |index: u16, sub_index: u8, value: ODValue, mask: Option<u32>| {
+        let entry = dictionary
+            .get(index, sub_index)
+            .ok_or(SnapshotError::UnknownObject(index, sub_index))?
+            .clone();
+        expected.insert(
+            ODIdx { index, sub_index },
+            ExpectedValue { entry, value, mask },
+        );
+        Ok::<_, SnapshotError>(())
+    };
+
+    // Mirrors what `configure_pdos` writes: the requested PDOs are enabled, on their explicit
+    // COB-ID if they have one
+    for mapping in &pdo_mappings {
+        let (communication_index, mapping_index) = pdo_indices(&mapping.pdo);
+
+        let communication = &mapping.communication;
+        match communication.cob_id {
+            Some(cob_id) => expect(
+                communication_index,
+                0x1,
+                ODValue::U32(cob_id as u32),
+                Some(PDO_INVALID | PDO_CAN_ID),
+            )?,
+            None => expect(communication_index, 0x1, ODValue::U32(0), Some(PDO_INVALID))?,
+        }
+        expect(
+            communication_index,
+            0x2,
+            ODValue::U8(communication.transmission_type.od_value()),
+            None,
+        )?;
+        if let Some(inhibit_time) = communication.inhibit_time {
+            expect(communication_index, 0x3, ODValue::U16(inhibit_time), None)?;
+        }
+        if let Some(event_timer) = communication.event_timer {
+            expect(communication_index, 0x5, ODValue::U16(event_timer), None)?;
+        }
+        if let Some(sync_start) = communication.sync_start {
+            expect(communication_index, 0x6, ODValue::U8(sync_start), None)?;
+        }
+
+        expect(
+            mapping_index,
+            0x0,
+            ODValue::U8(mapping.sources.len() as u8),
+            None,
+        )?;
+        for (number, source) in mapping.sources.iter().enumerate() {
+            expect(
+                mapping_index,
+                number as u8 + 1,
+                ODValue::U32(source.mapping_value()),
+                None,
+            )?;
+        }
+    }
+
+    // The PDOs of the predefined connection set outside of the mappings are disabled, which
+    // leaves their remaining configuration up to the device. PDOs the node lacks stay unchecked
+    for num in 1..=PREDEFINED_PDOS {
+        for pdo in [PdoType::RPDO(num), PdoType::TPDO(num)] {
+            let (communication_index, _) = pdo_indices(&pdo);
+            let requested = pdo_mappings.iter().any(|mapping| mapping.pdo == pdo);
+            if !requested && dictionary.get(communication_index, 0x1).is_some() {
+                let disabled = ODValue::U32(PDO_INVALID);
+                expect(communication_index, 0x1, disabled, Some(PDO_INVALID))?;
+            }
+        }
+    }
+
+    Ok(expected.into_values().collect())
+}
+
+/// A way the snapshot of a device differs from the expected configuration
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Difference {
+    #[error("{name} ({index:#06x}:{sub_index}) is {found:?}, expected {expected:?}")]
+    Value {
+        index: u16,
+        sub_index: u8,
+        name: String,
+        expected: ODValue,
+        found: ODValue,
+    },
+    #[error(
+        "{name} ({index:#06x}:{sub_index}) is missing from the snapshot, expected {expected:?}"
+    )]
+    Missing {
+        index: u16,
+        sub_index: u8,
+        name: String,
+        expected: ODValue,
+    },
+    #[error(
+        "{name} ({index:#06x}:{sub_index}) is {found:#010x}, expected {expected:#010x} in the bits {mask:#010x}"
+    )]
+    Bits {
+        index: u16,
+        sub_index: u8,
+        name: String,
+        mask: u32,
+        expected: u32,
+        found: u32,
+    },
+    #[error("{name} ({index:#06x}:{sub_index}) holds undecodable data {data:02x?}")]
+    Undecodable {
+        index: u16,
+        sub_index: u8,
+        name: String,
+        data: Vec<u8>,
+    },
+}
+
+/// Compare a snapshot against the expected configuration, see [`expected_configuration`]
+pub fn diff(snapshot: &Snapshot, expected: &[ExpectedValue]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+
+    for ExpectedValue { entry, value, mask } in expected {
+        let (index, sub_index) = (entry.index, entry.sub_index);
+        let name = entry.name.to_string();
+
+        let Some(data) = snapshot
+            .get(index, sub_index)
+            .and_then(SnapshotObject::bytes)
+        else {
+            differences.push(Difference::Missing {
+                index,
+                sub_index,
+                name,
+                expected: value.clone(),
+            });
+            continue;
+        };
+
+        let found = entry.with_value_from(&data).map(|found| found.default);
+        match (found, mask) {
+            (Ok(found), Some(mask)) => {
+                let expected = u32::from_value(value).unwrap_or_default();
+                match u32::from_value(&found) {
+                    Some(found) if found & mask == expected & mask => {}
+                    Some(found) => differences.push(Difference::Bits {
+                        index,
+                        sub_index,
+                        name,
+                        mask: *mask,
+                        expected,
+                        found,
+                    }),
+                    None => differences.push(Difference::Undecodable {
+                        index,
+                        sub_index,
+                        name,
+                        data,
+                    }),
+                }
+            }
+            (Ok(found), None) if found == *value => {}
+            (Ok(found), None) => differences.push(Difference::Value {
+                index,
+                sub_index,
+                name,
+                expected: value.clone(),
+                found,
+            }),
+            (Err(_), _) => differences.push(Difference::Undecodable {
+                index,
+                sub_index,
+                name,
+                data,
+            }),
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        comms::pdo::mapping::custom::{CUSTOM_RPDOS, CUSTOM_TPDOS},
+        driver::startup::params::PARAMS,
+        od::{self, typed},
+    };
+
+    /// Snapshot of a device configured exactly as expected
+    fn configured_snapshot(expected: &[ExpectedValue]) -> Snapshot {
+        let objects = expected
+            .iter()
+            .map(|ExpectedValue { entry, value, .. }| SnapshotObject {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                name: entry.name.to_string(),
+                data: Some(hex(&value.encode().unwrap())),
+                value: Some(format!("{value:?}")),
+                error: None,
+            })
+            .collect();
+
+        Snapshot {
+            node_id: 3,
+            objects,
+        }
+    }
+
+    fn set_cob_id(snapshot: &mut Snapshot, communication_index: u16, cob_id: u32) {
+        let object = snapshot
+            .objects
+            .iter_mut()
+            .find(|object| (object.index, object.sub_index) == (communication_index, 0x1))
+            .unwrap();
+        object.data = Some(hex(&cob_id.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_expected_configuration() {
+        let dictionary = ObjectDictionary::builtin();
+        let expected = expected_configuration(&dictionary, PARAMS, CUSTOM_RPDOS).unwrap();
+        let find = |expected: &[ExpectedValue], entry: &ODEntry| {
+            expected
+                .iter()
+                .find(|expected| expected.entry.idx() == entry.idx())
+                .cloned()
+        };
+
+        // RPDO1 maps the controlword first
+        let controlword = find(&expected, &od::RPDO1_MAPPING_1).unwrap();
+        assert_eq!(controlword.value, ODValue::U32(0x6040_0010));
+        // The target position is a setpoint, the parameters only write it once
+        assert_eq!(find(&expected, &od::SET_TARGET_POSITION), None);
+        // Requested PDOs are enabled, the others disabled
+        let rpdo1 = find(&expected, &od::RPDO1_COB_ID).unwrap();
+        assert_eq!(
+            (rpdo1.value, rpdo1.mask),
+            (ODValue::U32(0), Some(PDO_INVALID))
+        );
+        let tpdo1 = find(&expected, &od::TPDO1_COB_ID).unwrap();
+        assert_eq!(tpdo1.value, ODValue::U32(PDO_INVALID));
+
+        // Later downloads replace earlier ones
+        const PARAMETERS: &[SdoAction] = &[
+            typed::PROFILE_VELOCITY.write(1),
+            typed::PROFILE_VELOCITY.write(2),
+        ];
+        let expected = expected_configuration(&dictionary, PARAMETERS, []).unwrap();
+        let velocity = find(&expected, &od::PROFILE_VELOCITY).unwrap();
+        assert_eq!(velocity.value, ODValue::U32(2));
+        assert_eq!(expected.len(), 1 + 2 * PREDEFINED_PDOS as usize);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mappings = CUSTOM_RPDOS.iter().chain(CUSTOM_TPDOS);
+        let expected =
+            expected_configuration(&ObjectDictionary::builtin(), PARAMS, mappings).unwrap();
+        let mut snapshot = configured_snapshot(&expected);
+        assert_eq!(diff(&snapshot, &expected), vec![]);
+
+        // Survives a round trip through both formats
+        let toml = snapshot.to_toml().unwrap();
+        assert_eq!(Snapshot::from_toml(&toml).unwrap(), snapshot);
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(Snapshot::from_json(&json).unwrap(), snapshot);
+
+        // Someone changed the max acceleration
+        let acceleration = snapshot
+            .objects
+            .iter_mut()
+            .find(|object| object.index == od::MAX_ACCELERATION.index)
+            .unwrap();
+        acceleration.data = Some(hex(&1234u32.to_le_bytes()));
+        snapshot.objects.retain(|object| object.index != 0x1A00);
+        // and enabled TPDO4 again, which is not requested
+        set_cob_id(&mut snapshot, 0x1803, 0x483);
+
+        let differences = diff(&snapshot, &expected);
+        assert!(differences.iter().any(|difference| matches!(
+            difference,
+            Difference::Value {
+                found: ODValue::U32(1234),
+                ..
+            }
+        )));
+        assert!(
+            differences
+                .iter()
+                .any(|difference| matches!(difference, Difference::Missing { index: 0x1A00, .. }))
+        );
+        assert!(differences.iter().any(|difference| matches!(
+            difference,
+            Difference::Bits {
+                index: 0x1803,
+                found: 0x483,
+                ..
+            }
+        )));
+
+        // A COB-ID other than the predefined one is fine, as long as the PDO is enabled
+        let mut snapshot = configured_snapshot(&expected);
+        set_cob_id(&mut snapshot, 0x1400, 0x203);
+        assert_eq!(diff(&snapshot, &expected), vec![]);
+        set_cob_id(&mut snapshot, 0x1400, PDO_INVALID | 0x203);
+        assert_eq!(diff(&snapshot, &expected).len(), 1);
+    }
+}
@@ -0,0 +1,571 @@
+use std::{f64::consts::TAU, fmt};
+
+use thiserror::Error;
+
+use crate::{
+    comms::sdo::{SdoAction, scheduler::SdoHandle},
+    driver::{command::MotorCommand, event::MotorEvent},
+    error::DriveError,
+    od::typed,
+};
+
+/// Unit codes of CiA 303-2 as used by the SI unit objects 0x60A8/0x60A9
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiUnitCode {
+    Dimensionless,
+    Metre,
+    Second,
+    Radian,
+    Degree,
+    Minute,
+    Hour,
+    Day,
+    Revolution,
+    Other(u8),
+}
+
+impl SiUnitCode {
+    pub const fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => SiUnitCode::Dimensionless,
+            0x01 => SiUnitCode::Metre,
+            0x03 => SiUnitCode::Second,
+            0x10 => SiUnitCode::Radian,
+            0x41 => SiUnitCode::Degree,
+            0x47 => SiUnitCode::Minute,
+            0x48 => SiUnitCode::Hour,
+            0x49 => SiUnitCode::Day,
+            0xB4 => SiUnitCode::Revolution,
+            code => SiUnitCode::Other(code),
+        }
+    }
+
+    pub const fn code(self) -> u8 {
+        match self {
+            SiUnitCode::Dimensionless => 0x00,
+            SiUnitCode::Metre => 0x01,
+            SiUnitCode::Second => 0x03,
+            SiUnitCode::Radian => 0x10,
+            SiUnitCode::Degree => 0x41,
+            SiUnitCode::Minute => 0x47,
+            SiUnitCode::Hour => 0x48,
+            SiUnitCode::Day => 0x49,
+            SiUnitCode::Revolution => 0xB4,
+            SiUnitCode::Other(code) => code,
+        }
+    }
+
+    /// Revolutions per unit, for rotary units
+    fn revolutions(self) -> Option<f64> {
+        match self {
+            SiUnitCode::Revolution => Some(1.0),
+            SiUnitCode::Degree => Some(1.0 / 360.0),
+            SiUnitCode::Radian => Some(1.0 / TAU),
+            _ => None,
+        }
+    }
+
+    /// Seconds per unit, for time units
+    fn seconds(self) -> Option<f64> {
+        match self {
+            SiUnitCode::Second => Some(1.0),
+            SiUnitCode::Minute => Some(60.0),
+            SiUnitCode::Hour => Some(3600.0),
+            SiUnitCode::Day => Some(86400.0),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> String {
+        match self {
+            SiUnitCode::Dimensionless => String::new(),
+            SiUnitCode::Metre => String::from("m"),
+            SiUnitCode::Second => String::from("s"),
+            SiUnitCode::Radian => String::from("rad"),
+            SiUnitCode::Degree => String::from("°"),
+            SiUnitCode::Minute => String::from("min"),
+            SiUnitCode::Hour => String::from("h"),
+            SiUnitCode::Day => String::from("d"),
+            SiUnitCode::Revolution => String::from("rev"),
+            SiUnitCode::Other(code) => format!("<{code:#04x}>"),
+        }
+    }
+}
+
+/// Decoded SI_UNIT_POSITION (0x60A8) or SI_UNIT_SPEED (0x60A9): a power of ten prefix in the
+/// highest byte, then the numerator and the denominator unit, the lowest byte is reserved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiUnit {
+    pub exponent: i8,
+    pub numerator: SiUnitCode,
+    pub denominator: SiUnitCode,
+}
+
+impl SiUnit {
+    /// Default position unit of the PD4-C, 3600 = 1 revolution
+    pub const TENTH_DEGREE: SiUnit = SiUnit::from_raw(0xFF410000);
+    /// Default velocity unit of the PD4-C
+    pub const RPM: SiUnit = SiUnit::from_raw(0x00B44700);
+
+    pub const fn from_raw(raw: u32) -> Self {
+        let [_, denominator, numerator, exponent] = raw.to_le_bytes();
+        Self {
+            exponent: exponent as i8,
+            numerator: SiUnitCode::from_code(numerator),
+            denominator: SiUnitCode::from_code(denominator),
+        }
+    }
+
+    pub const fn raw(self) -> u32 {
+        u32::from_le_bytes([
+            0,
+            self.denominator.code(),
+            self.numerator.code(),
+            self.exponent as u8,
+        ])
+    }
+
+    fn factor(self) -> f64 {
+        10f64.powi(self.exponent.into())
+    }
+}
+
+impl fmt::Display for SiUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exponent != 0 {
+            write!(f, "10^{} ", self.exponent)?;
+        }
+        write!(f, "{}", self.numerator.symbol())?;
+        if self.denominator != SiUnitCode::Dimensionless {
+            write!(f, "/{}", self.denominator.symbol())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the gear ratio and feed constant of an axis are applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingLocation {
+    /// Written to 0x6091/0x6092, the device position unit is linear (e.g. mm)
+    Device,
+    /// Device units stay rotary and refer to the motor shaft, the host does the scaling
+    Host,
+}
+
+/// Motor revolutions per revolutions of the driving shaft, e.g. of a gearbox between the motor
+/// and the belt pulley
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GearRatio {
+    pub motor_revolutions: u32,
+    pub shaft_revolutions: u32,
+}
+
+impl GearRatio {
+    pub const DIRECT: GearRatio = GearRatio {
+        motor_revolutions: 1,
+        shaft_revolutions: 1,
+    };
+}
+
+/// Linear travel per revolutions of the driving shaft, e.g. the circumference of a belt pulley or
+/// the pitch of a lead screw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedConstant {
+    pub feed_mm: f64,
+    pub shaft_revolutions: u32,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UnitError {
+    #[error("{0} can not be converted to millimetres")]
+    NotLinear(SiUnit),
+    #[error("{0} is not a velocity unit")]
+    NotVelocity(SiUnit),
+    #[error("Invalid axis scaling: {0}")]
+    InvalidScaling(&'static str),
+    #[error("{value} {unit} does not fit in the range of the device")]
+    OutOfRange { value: f64, unit: &'static str },
+}
+
+/// Commands of a gantry axis in millimetres, see [`AxisScaling::command`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhysicalCommand {
+    MoveAbsolute { target_mm: f64, velocity_mm_s: f64 },
+    MoveRelative { delta_mm: f64, velocity_mm_s: f64 },
+    SetVelocity { velocity_mm_s: f64 },
+}
+
+/// Feedback of a gantry axis in millimetres, see [`AxisScaling::feedback`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhysicalFeedback {
+    Position { actual_mm: f64 },
+    Velocity { actual_mm_s: f64 },
+}
+
+/// Conversion between millimetres and the device units of a single belt or lead screw driven
+/// axis. Accelerations are in velocity units per second on the device, mm/s² on the host
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisScaling {
+    position_unit: SiUnit,
+    velocity_unit: SiUnit,
+    gear_ratio: GearRatio,
+    feed_constant: FeedConstant,
+    location: ScalingLocation,
+    mm_per_position_unit: f64,
+    mm_s_per_velocity_unit: f64,
+}
+
+impl AxisScaling {
+    pub fn new(
+        position_unit: SiUnit,
+        velocity_unit: SiUnit,
+        gear_ratio: GearRatio,
+        feed_constant: FeedConstant,
+        location: ScalingLocation,
+    ) -> Result<Self, UnitError> {
+        if gear_ratio.motor_revolutions == 0 || gear_ratio.shaft_revolutions == 0 {
+            return Err(UnitError::InvalidScaling(
+                "gear ratio with zero revolutions",
+            ));
+        }
+        if feed_constant.feed_mm.is_nan()
+            || feed_constant.feed_mm <= 0.0
+            || feed_constant.shaft_revolutions == 0
+        {
+            return Err(UnitError::InvalidScaling(
+                "feed constant has to be positive",
+            ));
+        }
+
+        let mut scaling = Self {
+            position_unit,
+            velocity_unit,
+            gear_ratio,
+            feed_constant,
+            location,
+            mm_per_position_unit: 0.0,
+            mm_s_per_velocity_unit: 0.0,
+        };
+
+        scaling.mm_per_position_unit = position_unit.factor() * scaling.mm_per(position_unit)?;
+        let seconds = velocity_unit
+            .denominator
+            .seconds()
+            .ok_or(UnitError::NotVelocity(velocity_unit))?;
+        scaling.mm_s_per_velocity_unit =
+            velocity_unit.factor() * scaling.mm_per(velocity_unit)? / seconds;
+
+        Ok(scaling)
+    }
+
+    /// Scaling using the units currently configured on the device
+    pub async fn from_device(
+        sdo: SdoHandle,
+        gear_ratio: GearRatio,
+        feed_constant: FeedConstant,
+        location: ScalingLocation,
+    ) -> Result<Self, DriveError> {
+        let position_unit = SiUnit::from_raw(typed::SI_UNIT_POSITION.upload(sdo.clone()).await?);
+        let velocity_unit = SiUnit::from_raw(typed::SI_UNIT_SPEED.upload(sdo).await?);
+
+        Ok(Self::new(
+            position_unit,
+            velocity_unit,
+            gear_ratio,
+            feed_constant,
+            location,
+        )?)
+    }
+
+    /// Millimetres per numerator unit (without prefix) of the given device unit
+    fn mm_per(&self, unit: SiUnit) -> Result<f64, UnitError> {
+        // Rotary device units refer to the motor when the device does not apply the gear ratio
+        let shaft_revolutions = match (unit.numerator, self.location) {
+            (SiUnitCode::Metre, ScalingLocation::Device) => return Ok(1000.0),
+            (numerator, ScalingLocation::Device) => numerator.revolutions(),
+            (numerator, ScalingLocation::Host) => numerator.revolutions().map(|revolutions| {
+                revolutions * self.gear_ratio.shaft_revolutions as f64
+                    / self.gear_ratio.motor_revolutions as f64
+            }),
+        }
+        .ok_or(UnitError::NotLinear(unit))?;
+
+        Ok(shaft_revolutions * self.feed_constant.feed_mm
+            / self.feed_constant.shaft_revolutions as f64)
+    }
+
+    pub fn position_unit(&self) -> SiUnit {
+        self.position_unit
+    }
+
+    pub fn velocity_unit(&self) -> SiUnit {
+        self.velocity_unit
+    }
+
+    /// Startup parameters of this scaling, the gear ratio and feed constant on the device are
+    /// reset to 1:1 when the host does the scaling
+    pub fn parameters(&self) -> Result<Vec<SdoAction>, UnitError> {
+        let (gear_ratio, feed, shaft_revolutions) = match self.location {
+            ScalingLocation::Device => {
+                if self.position_unit.numerator != SiUnitCode::Metre {
+                    return Err(UnitError::NotLinear(self.position_unit));
+                }
+                let feed = self.feed_constant.feed_mm / self.mm_per_position_unit;
+
+                (
+                    self.gear_ratio,
+                    to_device::<u32>(feed, "position units")?,
+                    self.feed_constant.shaft_revolutions,
+                )
+            }
+            ScalingLocation::Host => (GearRatio::DIRECT, 1, 1),
+        };
+
+        Ok(vec![
+            typed::SI_UNIT_POSITION.write(self.position_unit.raw()),
+            typed::SI_UNIT_SPEED.write(self.velocity_unit.raw()),
+            typed::GEAR_RATIO_MOTOR_REVOLUTIONS.write(gear_ratio.motor_revolutions),
+            typed::GEAR_RATIO_SHAFT_REVOLUTIONS.write(gear_ratio.shaft_revolutions),
+            typed::FEED_CONSTANT_FEED.write(feed),
+            typed::FEED_CONSTANT_SHAFT_REVOLUTIONS.write(shaft_revolutions),
+        ])
+    }
+
+    /// Profile acceleration and deceleration parameters
+    pub fn acceleration_parameters(
+        &self,
+        acceleration_mm_s2: f64,
+        deceleration_mm_s2: f64,
+    ) -> Result<[SdoAction; 2], UnitError> {
+        Ok([
+            typed::PROFILE_ACCELERATION.write(self.acceleration_to_device(acceleration_mm_s2)?),
+            typed::PROFILE_DECELERATION.write(self.acceleration_to_device(deceleration_mm_s2)?),
+        ])
+    }
+
+    pub fn position_to_device(&self, mm: f64) -> Result<i32, UnitError> {
+        to_device(mm / self.mm_per_position_unit, "mm")
+    }
+
+    pub fn position_from_device(&self, position: i32) -> f64 {
+        position as f64 * self.mm_per_position_unit
+    }
+
+    pub fn velocity_to_device(&self, mm_s: f64) -> Result<i32, UnitError> {
+        to_device(mm_s / self.mm_s_per_velocity_unit, "mm/s")
+    }
+
+    pub fn velocity_from_device(&self, velocity: i32) -> f64 {
+        velocity as f64 * self.mm_s_per_velocity_unit
+    }
+
+    /// Unsigned velocity, e.g. the profile velocity
+    pub fn speed_to_device(&self, mm_s: f64) -> Result<u32, UnitError> {
+        to_device(mm_s / self.mm_s_per_velocity_unit, "mm/s")
+    }
+
+    pub fn acceleration_to_device(&self, mm_s2: f64) -> Result<u32, UnitError> {
+        to_device(mm_s2 / self.mm_s_per_velocity_unit, "mm/s²")
+    }
+
+    pub fn command(&self, command: &PhysicalCommand) -> Result<MotorCommand, UnitError> {
+        Ok(match *command {
+            PhysicalCommand::MoveAbsolute {
+                target_mm,
+                velocity_mm_s,
+            } => MotorCommand::MoveAbsolute {
+                target: self.position_to_device(target_mm)?,
+                profile_velocity: self.speed_to_device(velocity_mm_s)?,
+            },
+            PhysicalCommand::MoveRelative {
+                delta_mm,
+                velocity_mm_s,
+            } => MotorCommand::MoveRelative {
+                delta: self.position_to_device(delta_mm)?,
+                profile_velocity: self.speed_to_device(velocity_mm_s)?,
+            },
+            PhysicalCommand::SetVelocity { velocity_mm_s } => MotorCommand::SetVelocity {
+                target_velocity: self.velocity_to_device(velocity_mm_s)?,
+            },
+        })
+    }
+
+    /// Position and velocity feedback in millimetres, None for every other event
+    pub fn feedback(&self, event: &MotorEvent) -> Option<PhysicalFeedback> {
+        match *event {
+            MotorEvent::PositionFeedback { actual_position } => Some(PhysicalFeedback::Position {
+                actual_mm: self.position_from_device(actual_position),
+            }),
+            MotorEvent::VelocityFeedback { actual_velocity } => Some(PhysicalFeedback::Velocity {
+                actual_mm_s: self.velocity_from_device(actual_velocity),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Round to the nearest device value, failing if it does not fit
+fn to_device<T: TryFrom<i64>>(value: f64, unit: &'static str) -> Result<T, UnitError> {
+    let rounded = value.round();
+    let out_of_range = UnitError::OutOfRange { value, unit };
+    if !rounded.is_finite() || rounded.abs() > i64::MAX as f64 {
+        return Err(out_of_range);
+    }
+
+    T::try_from(rounded as i64).map_err(|_| out_of_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: FeedConstant = FeedConstant {
+        feed_mm: 40.0,
+        shaft_revolutions: 1,
+    };
+
+    #[test]
+    fn test_decode_si_units() {
+        assert_eq!(
+            SiUnit::TENTH_DEGREE,
+            SiUnit {
+                exponent: -1,
+                numerator: SiUnitCode::Degree,
+                denominator: SiUnitCode::Dimensionless,
+            }
+        );
+        assert_eq!(SiUnit::TENTH_DEGREE.raw(), 0xFF410000);
+        assert_eq!(SiUnit::RPM.numerator, SiUnitCode::Revolution);
+        assert_eq!(SiUnit::RPM.denominator, SiUnitCode::Minute);
+        assert_eq!(SiUnit::RPM.to_string(), "rev/min");
+        assert_eq!(SiUnit::TENTH_DEGREE.to_string(), "10^-1 °");
+    }
+
+    #[test]
+    fn test_host_scaling() {
+        // 2:1 gearbox in front of a 40 mm belt pulley
+        let gear_ratio = GearRatio {
+            motor_revolutions: 2,
+            shaft_revolutions: 1,
+        };
+        let scaling = AxisScaling::new(
+            SiUnit::TENTH_DEGREE,
+            SiUnit::RPM,
+            gear_ratio,
+            FEED,
+            ScalingLocation::Host,
+        )
+        .unwrap();
+
+        // One shaft revolution is 2 motor revolutions of 3600 units
+        assert_eq!(scaling.position_to_device(40.0), Ok(7200));
+        assert_eq!(scaling.position_to_device(-20.0), Ok(-3600));
+        assert!((scaling.position_from_device(7200) - 40.0).abs() < 1e-9);
+
+        // 1 rpm is 20 mm/min
+        assert_eq!(scaling.velocity_to_device(10.0), Ok(30));
+        assert!(matches!(
+            scaling.command(&PhysicalCommand::MoveAbsolute {
+                target_mm: 40.0,
+                velocity_mm_s: 10.0
+            }),
+            Ok(MotorCommand::MoveAbsolute {
+                target: 7200,
+                profile_velocity: 30
+            })
+        ));
+        assert_eq!(
+            scaling.feedback(&MotorEvent::VelocityFeedback {
+                actual_velocity: 30
+            }),
+            Some(PhysicalFeedback::Velocity { actual_mm_s: 10.0 })
+        );
+
+        assert!(matches!(
+            scaling.speed_to_device(-1.0),
+            Err(UnitError::OutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_device_scaling() {
+        // Micrometres and mm/s, the device applies gear ratio and feed constant
+        let micrometre = SiUnit {
+            exponent: -6,
+            numerator: SiUnitCode::Metre,
+            denominator: SiUnitCode::Dimensionless,
+        };
+        let mm_s = SiUnit {
+            exponent: -3,
+            numerator: SiUnitCode::Metre,
+            denominator: SiUnitCode::Second,
+        };
+        let scaling = AxisScaling::new(
+            micrometre,
+            mm_s,
+            GearRatio::DIRECT,
+            FEED,
+            ScalingLocation::Device,
+        )
+        .unwrap();
+
+        assert_eq!(scaling.position_to_device(12.5), Ok(12500));
+        assert_eq!(scaling.velocity_to_device(100.0), Ok(100));
+
+        let parameters = scaling.parameters().unwrap();
+        let SdoAction::Download { value, .. } = &parameters[4] else {
+            panic!("Expected the feed constant download");
+        };
+        assert_eq!(*value, crate::od::value::ODValue::U32(40000));
+    }
+
+    #[test]
+    fn test_scaling_parameters() {
+        // The driver downloads these ahead of its parameters, so the dictionary has to accept them
+        let scaling = AxisScaling::new(
+            SiUnit::TENTH_DEGREE,
+            SiUnit::RPM,
+            GearRatio::DIRECT,
+            FEED,
+            ScalingLocation::Host,
+        )
+        .unwrap();
+        let parameters = scaling.parameters().unwrap();
+        assert!(
+            crate::comms::sdo::validate_parameters(
+                &crate::od::dictionary::ObjectDictionary::builtin(),
+                &parameters
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_invalid_scaling() {
+        // Rotary units can not be scaled by the device
+        assert!(matches!(
+            AxisScaling::new(
+                SiUnit::TENTH_DEGREE,
+                SiUnit::RPM,
+                GearRatio::DIRECT,
+                FEED,
+                ScalingLocation::Device,
+            )
+            .unwrap()
+            .parameters(),
+            Err(UnitError::NotLinear(SiUnit::TENTH_DEGREE))
+        ));
+        // A position unit is no velocity unit
+        assert_eq!(
+            AxisScaling::new(
+                SiUnit::TENTH_DEGREE,
+                SiUnit::TENTH_DEGREE,
+                GearRatio::DIRECT,
+                FEED,
+                ScalingLocation::Host,
+            ),
+            Err(UnitError::NotVelocity(SiUnit::TENTH_DEGREE))
+        );
+    }
+}
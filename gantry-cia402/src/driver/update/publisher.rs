@@ -1,8 +1,11 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use tokio::sync::{
-    Mutex, broadcast,
-    mpsc::{self},
+use tokio::{
+    sync::{
+        Mutex, broadcast,
+        mpsc::{self},
+    },
+    time::{self, Instant},
 };
 use tracing::*;
 
@@ -22,6 +25,9 @@ use crate::{
     },
 };
 
+/// How often to check for RPDOs to refresh while none with an event timer was sent
+pub const RPDO_REFRESH_POLL: Duration = Duration::from_millis(100);
+
 /// Responsible for all CANopen communication to the drive
 /// Receives updates from the Cia402 state machine and operational mode specific handler
 /// It encodes these changes into the appropriate controlword bits or OD object
@@ -113,3 +119,15 @@ pub async fn publish_updates(
         }
     }
 }
+
+/// Keep sending the RPDOs that have an event timer, the device uses it as a receive timeout
+pub async fn refresh_rpdos(pdo: Arc<Mutex<Pdo>>) {
+    loop {
+        let next_refresh = pdo.lock().await.next_refresh();
+        time::sleep_until(next_refresh.unwrap_or_else(|| Instant::now() + RPDO_REFRESH_POLL)).await;
+
+        if let Err(err) = pdo.lock().await.refresh().await {
+            error!("Unable to refresh RPDOs: {err}");
+        }
+    }
+}
@@ -11,9 +11,21 @@ use tokio::{
     time::error::Elapsed,
 };
 
-use crate::driver::{
-    command::MotorCommand, event::MotorEvent, nmt::NmtState, oms::setpoint::Setpoint,
-    receiver::StatusWord, state::Cia402State,
+use crate::{
+    comms::{
+        pdo::mapping::PdoType,
+        sdo::{abort::SdoAbortCode, client::SdoTransferError},
+    },
+    driver::{
+        command::MotorCommand, event::MotorEvent, nmt::NmtState, oms::setpoint::Setpoint,
+        receiver::StatusWord, startup::parametrise::ParametrisationReport, state::Cia402State,
+        units::UnitError,
+    },
+    od::{
+        access::AccessType,
+        mappable::MappableType,
+        value::{CodecError, ODValue},
+    },
 };
 
 #[derive(Debug, Error)]
@@ -24,10 +36,72 @@ pub enum DriveError {
     OperationModeSpecific(String),
     #[error("CANopen communication error: {0:?}")]
     CanOpen(CoError),
+    #[error(transparent)]
+    SdoTransfer(#[from] SdoTransferError),
+    #[error("No SDO transfers with node id {node_id} possible in NMT state {state:?}")]
+    SdoUnavailable { node_id: u8, state: NmtState },
+    #[error("Node id {node_id} has no axis scaling to convert physical units with")]
+    NoScaling { node_id: u8 },
     #[error("Timeout Sending CANopen packet {0:?}")]
     CanOpenTimeout(SendTimeoutError<TxPacket>),
     #[error("Invalid conversion of {0:?} into integer")]
     Conversion(Vec<u8>),
+    #[error("Value {value:?} does not match the data type of {index:#06x}:{sub_index}")]
+    ValueTypeMismatch {
+        index: u16,
+        sub_index: u8,
+        value: ODValue,
+    },
+    #[error("{index:#06x}:{sub_index} is {access:?} and can not be written")]
+    NotWritable {
+        index: u16,
+        sub_index: u8,
+        access: AccessType,
+    },
+    #[error("{index:#06x}:{sub_index} is {access:?} and can not be read")]
+    NotReadable {
+        index: u16,
+        sub_index: u8,
+        access: AccessType,
+    },
+    #[error("{index:#06x}:{sub_index} is PDO mappable as {mappable:?}, not in {pdo:?}")]
+    NotPdoMappable {
+        index: u16,
+        sub_index: u8,
+        pdo: PdoType,
+        mappable: MappableType,
+    },
+    #[error("{index:#06x}:{sub_index} can not be mapped in {pdo:?}: {reason}")]
+    InvalidMapping {
+        index: u16,
+        sub_index: u8,
+        pdo: PdoType,
+        reason: &'static str,
+    },
+    #[error("Invalid communication parameters for {pdo:?}: {reason}")]
+    InvalidCommunication { pdo: PdoType, reason: &'static str },
+    #[error("Invalid SYNC producer configuration: {0}")]
+    InvalidSync(&'static str),
+    #[error("Invalid TIME producer configuration: {0}")]
+    InvalidTime(&'static str),
+    #[error("{value} is out of range for {index:#06x}:{sub_index}, limit is {limit}")]
+    OutOfRange {
+        index: u16,
+        sub_index: u8,
+        value: String,
+        limit: String,
+    },
+    #[error(
+        "Parametrisation of node id {} failed for {} of {} actions",
+        .0.node_id,
+        .0.failures().count(),
+        .0.actions.len()
+    )]
+    Parametrisation(Box<ParametrisationReport>),
+    #[error("Unable to encode/decode object dictionary value: {0}")]
+    Codec(#[from] CodecError),
+    #[error("Unable to convert physical units: {0}")]
+    Units(#[from] UnitError),
     #[error("Invariant violated: {0}")]
     ViolatedInvariant(String),
     #[error("Error from CANOpen: {0:?}")]
@@ -42,6 +116,12 @@ pub enum DriveError {
     BroadcastClosed(MotorEvent, RecvError),
     #[error("Error switching to NMT state: {0:?}: {1:?}")]
     NMTSendError(NmtState, SendError<NmtState>),
+    #[error("Node id {node_id} did not switch to NMT {state:?} after {attempts} attempts")]
+    NmtSwitchFailed {
+        node_id: u8,
+        state: NmtState,
+        attempts: usize,
+    },
     #[error("Error sending new setpoint do setpoint manager: {0:?}: {1:?}")]
     NewSetpointSendError(Setpoint, SendError<Setpoint>),
     #[error("Unable to decode {0:?} into Cia402State")]
@@ -55,3 +135,22 @@ pub enum DriveError {
     #[error("Timeout asking cia402 SM to transition from {0:?} to {1:?}")]
     Cia402TransitionTimeout(Cia402State, Cia402State),
 }
+
+impl DriveError {
+    /// Abort code of a failed SDO transfer
+    pub fn sdo_abort_code(&self) -> Option<SdoAbortCode> {
+        match self {
+            DriveError::SdoTransfer(err) => err.abort_code(),
+            _ => None,
+        }
+    }
+
+    /// Whether the failed operation may succeed when tried again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DriveError::SdoTransfer(err) => err.is_retryable(),
+            DriveError::CanOpenTimeout(_) => true,
+            _ => false,
+        }
+    }
+}
@@ -1,10 +1,17 @@
 use oze_canopen::{canopen::RxMessage, interface::CanOpenInterface};
-use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{self, Instant},
+};
 use tracing::{instrument, *};
 
-use crate::driver::{
-    event::MotorEvent,
-    receiver::parse::Frame,
+use crate::{
+    comms::{pdo::mapping::registry::MappingRegistry, sdo::client::SDO_TIMEOUT},
+    driver::{
+        event::MotorEvent,
+        receiver::parse::{Frame, sdo_transfer::SdoTransfers},
+    },
+    od::registry::DictionaryRegistry,
 };
 
 #[instrument(skip(event_rx))]
@@ -26,7 +33,26 @@ pub async fn log_events(
 }
 
 #[instrument(skip(canopen))]
-pub async fn log_canopen_pretty(mut canopen: CanOpenInterface) -> Result<(), RecvError> {
+pub async fn log_canopen_pretty(canopen: CanOpenInterface) -> Result<(), RecvError> {
+    log_canopen_pretty_with_dictionaries(
+        canopen,
+        DictionaryRegistry::default(),
+        MappingRegistry::default(),
+    )
+    .await
+}
+
+/// Pretty print all canopen traffic, SDO transfers are decoded using the object dictionary
+/// registered for the node (e.g. loaded from its EDS), PDOs using the mappings registered for it
+#[instrument(skip(canopen, dictionaries, mappings))]
+pub async fn log_canopen_pretty_with_dictionaries(
+    mut canopen: CanOpenInterface,
+    dictionaries: DictionaryRegistry,
+    mappings: MappingRegistry,
+) -> Result<(), RecvError> {
+    let mut transfers = SdoTransfers::default();
+    let mut unanswered = time::interval(SDO_TIMEOUT);
+
     loop {
         tokio::select! {
             message = canopen.rx.recv() => {
@@ -35,17 +61,26 @@ pub async fn log_canopen_pretty(mut canopen: CanOpenInterface) -> Result<(), Rec
 
                 match message {
                     Ok(message) => {
-                        let Ok(parsed): Result<Frame, _> = message.try_into() else {
+                        let Ok(parsed) = Frame::from_message(message, &dictionaries, &mappings, &mut transfers) else {
                             error!("Error parsing message: {message:?}");
                             continue;
                         };
                         parsed.log();
+                        for exchange in transfers.completed(Instant::now()) {
+                            exchange.log();
+                        }
                     }
                     Err(err) => {
                         error!("Error logging canopen traffic: {err}");
                     }
                 }
             },
+            // Flag transfers that are not answered while the bus is quiet
+            _ = unanswered.tick() => {
+                for exchange in transfers.completed(Instant::now()) {
+                    exchange.log();
+                }
+            },
             _ = tokio::signal::ctrl_c() => return Ok(()),
         };
     }
@@ -5,3 +5,15 @@ pub enum AccessType {
     ReadWrite,
     Const,
 }
+
+impl AccessType {
+    /// Can the object be uploaded (read) by a client
+    pub const fn is_readable(&self) -> bool {
+        !matches!(self, AccessType::WriteOnly)
+    }
+
+    /// Can the object be downloaded (written) by a client
+    pub const fn is_writable(&self) -> bool {
+        matches!(self, AccessType::WriteOnly | AccessType::ReadWrite)
+    }
+}
@@ -0,0 +1,123 @@
+/// Data bytes of an expedited SDO transfer
+const EXPEDITED_SIZE: usize = 4;
+
+/// CiA 301 basic data types, as referenced by the `DataType` key of EDS/DCF files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Boolean,
+    Integer8,
+    Integer16,
+    Integer32,
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Real32,
+    VisibleString,
+    OctetString,
+    UnicodeString,
+    TimeOfDay,
+    TimeDifference,
+    Domain,
+    Integer24,
+    Real64,
+    Integer40,
+    Integer48,
+    Integer56,
+    Integer64,
+    Unsigned24,
+    Unsigned40,
+    Unsigned48,
+    Unsigned56,
+    Unsigned64,
+}
+
+impl DataType {
+    /// Data type from its CiA 301 object index (0x0001..=0x001B)
+    pub const fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            0x0001 => Self::Boolean,
+            0x0002 => Self::Integer8,
+            0x0003 => Self::Integer16,
+            0x0004 => Self::Integer32,
+            0x0005 => Self::Unsigned8,
+            0x0006 => Self::Unsigned16,
+            0x0007 => Self::Unsigned32,
+            0x0008 => Self::Real32,
+            0x0009 => Self::VisibleString,
+            0x000A => Self::OctetString,
+            0x000B => Self::UnicodeString,
+            0x000C => Self::TimeOfDay,
+            0x000D => Self::TimeDifference,
+            0x000F => Self::Domain,
+            0x0010 => Self::Integer24,
+            0x0011 => Self::Real64,
+            0x0012 => Self::Integer40,
+            0x0013 => Self::Integer48,
+            0x0014 => Self::Integer56,
+            0x0015 => Self::Integer64,
+            0x0016 => Self::Unsigned24,
+            0x0018 => Self::Unsigned40,
+            0x0019 => Self::Unsigned48,
+            0x001A => Self::Unsigned56,
+            0x001B => Self::Unsigned64,
+            _ => return None,
+        })
+    }
+
+    /// CiA 301 object index of this data type
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::Boolean => 0x0001,
+            Self::Integer8 => 0x0002,
+            Self::Integer16 => 0x0003,
+            Self::Integer32 => 0x0004,
+            Self::Unsigned8 => 0x0005,
+            Self::Unsigned16 => 0x0006,
+            Self::Unsigned32 => 0x0007,
+            Self::Real32 => 0x0008,
+            Self::VisibleString => 0x0009,
+            Self::OctetString => 0x000A,
+            Self::UnicodeString => 0x000B,
+            Self::TimeOfDay => 0x000C,
+            Self::TimeDifference => 0x000D,
+            Self::Domain => 0x000F,
+            Self::Integer24 => 0x0010,
+            Self::Real64 => 0x0011,
+            Self::Integer40 => 0x0012,
+            Self::Integer48 => 0x0013,
+            Self::Integer56 => 0x0014,
+            Self::Integer64 => 0x0015,
+            Self::Unsigned24 => 0x0016,
+            Self::Unsigned40 => 0x0018,
+            Self::Unsigned48 => 0x0019,
+            Self::Unsigned56 => 0x001A,
+            Self::Unsigned64 => 0x001B,
+        }
+    }
+
+    /// Leading bytes of uploaded data holding a value of this type. Expedited uploads that do not
+    /// indicate their size always carry 4 bytes, narrower values only fill the first of those
+    pub fn trim_upload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match self.size() {
+            Some(size) if size < data.len() && data.len() == EXPEDITED_SIZE => &data[..size],
+            _ => data,
+        }
+    }
+
+    /// Size in bytes of this data type on the wire, None for variable length types
+    pub const fn size(&self) -> Option<usize> {
+        Some(match self {
+            Self::Boolean | Self::Integer8 | Self::Unsigned8 => 1,
+            Self::Integer16 | Self::Unsigned16 => 2,
+            Self::Integer24 | Self::Unsigned24 => 3,
+            Self::Integer32 | Self::Unsigned32 | Self::Real32 => 4,
+            Self::Integer40 | Self::Unsigned40 => 5,
+            Self::Integer48 | Self::Unsigned48 | Self::TimeOfDay | Self::TimeDifference => 6,
+            Self::Integer56 | Self::Unsigned56 => 7,
+            Self::Integer64 | Self::Unsigned64 | Self::Real64 => 8,
+            Self::VisibleString | Self::OctetString | Self::UnicodeString | Self::Domain => {
+                return None;
+            }
+        })
+    }
+}
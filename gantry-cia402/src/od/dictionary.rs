@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, mem::discriminant, path::Path};
+
+use thiserror::Error;
+
+use crate::od::{
+    FULL_OBJECT_DICTIONARY, MANUFACTURER_SPECIFIC_INDICES, ODIdx,
+    access::AccessType,
+    eds::{self, EdsError},
+    entry::ODEntry,
+    mappable::MappableType,
+    value::ODValue,
+};
+
+/// Ways a compile time [`ODEntry`] can disagree with the dictionary of the device
+#[derive(Debug, Error, PartialEq)]
+pub enum EntryMismatch {
+    #[error("{0:#06x}:{1} is not present in the object dictionary")]
+    Missing(u16, u8),
+    #[error("{index:#06x}:{sub_index} holds a {expected:?}, not a {found:?}")]
+    DataType {
+        index: u16,
+        sub_index: u8,
+        expected: ODValue,
+        found: ODValue,
+    },
+    #[error("{index:#06x}:{sub_index} has access {expected:?}, not {found:?}")]
+    Access {
+        index: u16,
+        sub_index: u8,
+        expected: AccessType,
+        found: AccessType,
+    },
+    #[error("{index:#06x}:{sub_index} is PDO mappable as {expected:?}, not {found:?}")]
+    PdoMappable {
+        index: u16,
+        sub_index: u8,
+        expected: MappableType,
+        found: MappableType,
+    },
+}
+
+/// Runtime object dictionary of a single device
+/// Usually loaded from the EDS/DCF file the vendor ships with the device
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectDictionary {
+    entries: BTreeMap<ODIdx, ODEntry>,
+}
+
+impl ObjectDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a dictionary from the given entries, later duplicates replace earlier ones
+    pub fn from_entries(entries: impl IntoIterator<Item = ODEntry>) -> Self {
+        let mut dictionary = Self::new();
+        for entry in entries {
+            dictionary.insert(entry);
+        }
+        dictionary
+    }
+
+    /// Dictionary holding the objects this crate defines in [`crate::od`]
+    /// These are the standard objects plus the Nanotec PD4-C manufacturer specific ones
+    pub fn builtin() -> Self {
+        Self::from_entries(FULL_OBJECT_DICTIONARY.iter().cloned())
+    }
+
+    /// Dictionary holding only the standard CiA 301/402 objects of [`crate::od`], vendor objects
+    /// can be added using [`ObjectDictionary::extend`] or [`ObjectDictionary::extend_from_eds`]
+    pub fn standard() -> Self {
+        Self::from_entries(
+            FULL_OBJECT_DICTIONARY
+                .iter()
+                .filter(|entry| !MANUFACTURER_SPECIFIC_INDICES.contains(&entry.index))
+                .cloned(),
+        )
+    }
+
+    /// Parse an EDS/DCF file (CiA 306) into an object dictionary
+    pub fn from_eds(source: &str) -> Result<Self, EdsError> {
+        Ok(Self::from_entries(eds::parse_entries(source)?))
+    }
+
+    /// Read and parse an EDS/DCF file (CiA 306) into an object dictionary
+    pub fn from_eds_file(path: impl AsRef<Path>) -> Result<Self, EdsError> {
+        let source = std::fs::read_to_string(path.as_ref()).map_err(EdsError::Io)?;
+        Self::from_eds(&source)
+    }
+
+    /// Add an entry, returns the entry it replaced if any
+    pub fn insert(&mut self, entry: ODEntry) -> Option<ODEntry> {
+        self.entries.insert(entry.idx(), entry)
+    }
+
+    /// Add the given entries, e.g. the objects of a vendor profile, replacing existing ones
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = ODEntry>) {
+        for entry in entries {
+            self.insert(entry);
+        }
+    }
+
+    /// Add the objects described by an EDS/DCF file, replacing existing ones
+    pub fn extend_from_eds(&mut self, source: &str) -> Result<(), EdsError> {
+        self.extend(eds::parse_entries(source)?);
+        Ok(())
+    }
+
+    /// Remove an entry, returns it if it was present
+    pub fn remove(&mut self, index: u16, sub_index: u8) -> Option<ODEntry> {
+        self.entries.remove(&ODIdx { index, sub_index })
+    }
+
+    pub fn get(&self, index: u16, sub_index: u8) -> Option<&ODEntry> {
+        self.entries.get(&ODIdx { index, sub_index })
+    }
+
+    /// Find an entry by its parameter name, ignoring ASCII case
+    /// If several entries share a name the one with the lowest index/subindex is returned
+    pub fn get_by_name(&self, name: &str) -> Option<&ODEntry> {
+        self.iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn contains(&self, index: u16, sub_index: u8) -> bool {
+        self.get(index, sub_index).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over all entries, ordered by index and subindex
+    pub fn iter(&self) -> impl Iterator<Item = &ODEntry> {
+        self.entries.values()
+    }
+
+    /// Check that the given entry agrees with the one in this dictionary on data type, access
+    /// and PDO mappability
+    pub fn check(&self, entry: &ODEntry) -> Result<(), EntryMismatch> {
+        let Some(known) = self.get(entry.index, entry.sub_index) else {
+            return Err(EntryMismatch::Missing(entry.index, entry.sub_index));
+        };
+
+        if discriminant(&known.default) != discriminant(&entry.default) {
+            return Err(EntryMismatch::DataType {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                expected: known.default.clone(),
+                found: entry.default.clone(),
+            });
+        }
+
+        if known.access != entry.access {
+            return Err(EntryMismatch::Access {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                expected: known.access,
+                found: entry.access,
+            });
+        }
+
+        if known.pdo_mappable != entry.pdo_mappable {
+            return Err(EntryMismatch::PdoMappable {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                expected: known.pdo_mappable.clone(),
+                found: entry.pdo_mappable.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::od;
+
+    #[test]
+    fn test_standard_and_vendor_objects() {
+        let mut dictionary = ObjectDictionary::standard();
+        assert!(dictionary.contains(od::CONTROL_WORD.index, od::CONTROL_WORD.sub_index));
+        assert!(!dictionary.contains(od::BLOCK_DETECTION_PERIOD.index, 2));
+
+        // More objects than the old fixed size lookup table could hold
+        let vendor = (0..200).map(|sub_index| {
+            ODEntry::new(
+                0x3000,
+                sub_index,
+                "Vendor object",
+                AccessType::ReadWrite,
+                MappableType::None,
+                ODValue::U16(sub_index.into()),
+            )
+        });
+        dictionary.extend(vendor);
+        assert_eq!(
+            dictionary.get(0x3000, 199).unwrap().default,
+            ODValue::U16(199)
+        );
+
+        dictionary.extend(od::FULL_OBJECT_DICTIONARY.iter().cloned());
+        assert_eq!(dictionary.get(0x203A, 2), Some(&od::BLOCK_DETECTION_PERIOD));
+    }
+
+    #[test]
+    fn test_get_by_name() {
+        let dictionary = ObjectDictionary::builtin();
+        let name = od::STATUS_WORD.name.to_ascii_uppercase();
+        assert_eq!(dictionary.get_by_name(&name), Some(&od::STATUS_WORD));
+        assert!(dictionary.get_by_name("Does not exist").is_none());
+    }
+}
@@ -0,0 +1,385 @@
+pub mod parser;
+
+use std::{borrow::Cow, collections::BTreeSet};
+
+use thiserror::Error;
+use tracing::*;
+
+use crate::od::{
+    ODIdx,
+    access::AccessType,
+    data_type::DataType,
+    eds::parser::{EdsObject, EdsParseError, parse_integer_with_node_id, parse_object_reference},
+    entry::ODEntry,
+    mappable::MappableType,
+    meta::ODMeta,
+    value::{ODValue, TimeOfDay},
+};
+
+#[derive(Debug, Error)]
+pub enum EdsError {
+    #[error("Unable to read EDS file: {0}")]
+    Io(std::io::Error),
+    #[error("Unable to parse EDS file: {0}")]
+    Parse(#[from] EdsParseError),
+    #[error("Invalid {key} {value:?} for object {index:#06x}:{sub_index}")]
+    InvalidValue {
+        index: u16,
+        sub_index: u8,
+        key: &'static str,
+        value: String,
+    },
+}
+
+/// Parse an EDS/DCF file (CiA 306) into object dictionary entries
+/// Objects with a default value we can not represent as [`ODValue`] are skipped
+pub fn parse_entries(source: &str) -> Result<Vec<ODEntry>, EdsError> {
+    let file = parser::parse(source)?;
+
+    // Containers are only represented by an entry if the file does not describe sub-index 0
+    let described_sub_zero: BTreeSet<u16> = file
+        .objects
+        .iter()
+        .filter(|object| !object.is_container() && object.sub_index == 0)
+        .map(|object| object.index)
+        .collect();
+
+    let mut entries = Vec::with_capacity(file.objects.len());
+    for object in file.objects.iter() {
+        if object.is_container() && described_sub_zero.contains(&object.index) {
+            continue;
+        }
+
+        match object_to_entry(object, file.node_id)? {
+            Some(entry) => entries.push(entry),
+            None => warn!(
+                "Skipping EDS object {:#06x}:{} ({}) with unsupported default of type {:?}",
+                object.index, object.sub_index, object.name, object.data_type
+            ),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Convert a single EDS object into an [`ODEntry`], returns None for unsupported default values
+pub fn object_to_entry(
+    object: &EdsObject,
+    node_id: Option<u8>,
+) -> Result<Option<ODEntry>, EdsError> {
+    let invalid = |key: &'static str, value: &str| EdsError::InvalidValue {
+        index: object.index,
+        sub_index: object.sub_index,
+        key,
+        value: value.to_string(),
+    };
+
+    let access = match object.access_type.as_deref() {
+        None => AccessType::ReadOnly,
+        Some(raw) => parse_access_type(raw).ok_or_else(|| invalid("AccessType", raw))?,
+    };
+
+    let pdo_mappable = match (object.pdo_mapping, object.access_type.as_deref()) {
+        (false, _) => MappableType::None,
+        // rww: written by the network (RPDO), rwr: read by the network (TPDO)
+        (true, Some("rww") | Some("wo")) => MappableType::RPDO,
+        (true, Some("rwr") | Some("ro") | Some("const")) => MappableType::TPDO,
+        (true, _) => MappableType::Both,
+    };
+
+    let mut meta = ODMeta {
+        max_object: object
+            .high_limit_object
+            .as_deref()
+            .map(|raw| {
+                parse_object_reference(raw)
+                    .map(|(index, sub_index)| ODIdx { index, sub_index })
+                    .ok_or_else(|| invalid("HighLimitObject", raw))
+            })
+            .transpose()?,
+        unit: object.unit.clone().map(Cow::Owned),
+        description: object.description.clone().map(Cow::Owned),
+        ..ODMeta::NONE
+    };
+
+    let default = if object.is_container() {
+        ODValue::Array(object.sub_number.unwrap_or(0) as usize)
+    } else {
+        let code = object
+            .data_type
+            .ok_or_else(|| invalid("DataType", "<missing>"))?;
+        let Some(data_type) = DataType::from_code(code) else {
+            return Err(invalid("DataType", &format!("{code:#06x}")));
+        };
+
+        // Limits share the notation and data type of the value
+        let limit = |key: &'static str, raw: Option<&str>| match raw {
+            None => Ok(None),
+            Some(raw) => parse_value(data_type, Some(raw), node_id).map_err(|_| invalid(key, raw)),
+        };
+        meta.min = limit("LowLimit", object.low_limit.as_deref())?;
+        meta.max = limit("HighLimit", object.high_limit.as_deref())?;
+
+        match parse_value(data_type, object.value(), node_id) {
+            Ok(Some(value)) => value,
+            Ok(None) => return Ok(None),
+            Err(raw) => return Err(invalid("DefaultValue", &raw)),
+        }
+    };
+
+    Ok(Some(ODEntry {
+        index: object.index,
+        sub_index: object.sub_index,
+        name: Cow::Owned(object.name.clone()),
+        default,
+        access,
+        pdo_mappable,
+        meta,
+    }))
+}
+
+pub fn parse_access_type(raw: &str) -> Option<AccessType> {
+    Some(match raw.trim().to_ascii_lowercase().as_str() {
+        "ro" => AccessType::ReadOnly,
+        "wo" => AccessType::WriteOnly,
+        "rw" | "rwr" | "rww" => AccessType::ReadWrite,
+        "const" => AccessType::Const,
+        _ => return None,
+    })
+}
+
+/// Parse a raw EDS value into the [`ODValue`] matching the data type
+/// Missing values become zero/empty, unsupported values return Ok(None)
+fn parse_value(
+    data_type: DataType,
+    raw: Option<&str>,
+    node_id: Option<u8>,
+) -> Result<Option<ODValue>, String> {
+    let int = |raw: Option<&str>| -> Result<i128, String> {
+        match raw {
+            None => Ok(0),
+            Some(raw) => parse_integer_with_node_id(raw, node_id).ok_or(raw.to_string()),
+        }
+    };
+    // Narrow a parsed integer to the target width, negative values of unsigned types are
+    // rejected. Encoding checks the widths without a Rust type of their own, e.g. UNSIGNED24
+    macro_rules! narrow {
+        ($variant:ident, $ty:ty) => {{
+            let value = int(raw)?;
+            let narrowed =
+                ODValue::$variant(<$ty>::try_from(value).map_err(|_| value.to_string())?);
+            narrowed.encode().map_err(|_| value.to_string())?;
+            narrowed
+        }};
+    }
+    let float = |raw: Option<&str>| -> Result<f64, String> {
+        match raw {
+            None => Ok(0.0),
+            Some(raw) => raw.trim().parse::<f64>().map_err(|_| raw.to_string()),
+        }
+    };
+
+    Ok(Some(match data_type {
+        DataType::Boolean => ODValue::Bool(int(raw)? != 0),
+        DataType::Integer8 => narrow!(I8, i8),
+        DataType::Integer16 => narrow!(I16, i16),
+        DataType::Integer24 => narrow!(I24, i32),
+        DataType::Integer32 => narrow!(I32, i32),
+        DataType::Integer40 => narrow!(I40, i64),
+        DataType::Integer48 => narrow!(I48, i64),
+        DataType::Integer56 => narrow!(I56, i64),
+        DataType::Integer64 => narrow!(I64, i64),
+        DataType::Unsigned8 => narrow!(U8, u8),
+        DataType::Unsigned16 => narrow!(U16, u16),
+        DataType::Unsigned24 => narrow!(U24, u32),
+        DataType::Unsigned32 => narrow!(U32, u32),
+        DataType::Unsigned40 => narrow!(U40, u64),
+        DataType::Unsigned48 => narrow!(U48, u64),
+        DataType::Unsigned56 => narrow!(U56, u64),
+        DataType::Unsigned64 => narrow!(U64, u64),
+        DataType::Real32 => ODValue::F32(float(raw)? as f32),
+        DataType::Real64 => ODValue::F64(float(raw)?),
+        DataType::VisibleString => ODValue::VisibleString(raw.unwrap_or_default().to_string()),
+        DataType::OctetString => ODValue::OctetString(parse_hex_bytes(raw.unwrap_or_default())?),
+        DataType::Domain => ODValue::Domain(parse_hex_bytes(raw.unwrap_or_default())?),
+        DataType::UnicodeString => {
+            ODValue::UnicodeString(raw.unwrap_or_default().encode_utf16().collect())
+        }
+        // There is no textual representation of times, only missing values are supported
+        DataType::TimeOfDay if raw.is_none() => ODValue::TimeOfDay(TimeOfDay::default()),
+        DataType::TimeDifference if raw.is_none() => ODValue::TimeDifference(TimeOfDay::default()),
+        DataType::TimeOfDay | DataType::TimeDifference => return Ok(None),
+    }))
+}
+
+/// Octet strings are written as hex digits, optionally separated by whitespace
+fn parse_hex_bytes(raw: &str) -> Result<Vec<u8>, String> {
+    let digits: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(raw.to_string());
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| raw.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::od::dictionary::ObjectDictionary;
+
+    const EDS: &str = "
+[1018]
+ParameterName=Identity object
+ObjectType=0x9
+SubNumber=2
+
+[1018sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=ro
+DefaultValue=1
+
+[1018sub1]
+ParameterName=Vendor-ID
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x0000026C
+
+[1800]
+ParameterName=Transmit PDO 1 parameter
+ObjectType=0x9
+SubNumber=2
+
+[1800sub1]
+ParameterName=COB-ID used by TPDO
+DataType=0x0007
+AccessType=rw
+DefaultValue=$NODEID+0x180
+
+[6041]
+ParameterName=Statusword
+DataType=0x0006
+AccessType=ro
+PDOMapping=1
+
+[607A]
+ParameterName=Target position
+DataType=0x0004
+AccessType=rww
+PDOMapping=1
+DefaultValue=-100
+LowLimit=-36000
+HighLimit=0x8CA0
+Unit=counts
+
+[6064]
+ParameterName=Position actual value
+DataType=0x0004
+AccessType=rw
+PDOMapping=1
+
+[1008]
+ParameterName=Manufacturer device name
+DataType=0x0009
+AccessType=const
+DefaultValue=PD4-C
+
+[2000]
+ParameterName=Unsupported
+DataType=0x000C
+AccessType=rw
+DefaultValue=1
+
+[2001]
+ParameterName=Wide counter
+DataType=0x0016
+AccessType=rw
+DefaultValue=0xFFFFFF
+";
+
+    #[test]
+    fn test_eds_to_dictionary() {
+        let od = ObjectDictionary::from_eds(EDS).unwrap();
+
+        // 1018 describes sub 0 itself, so no container entry
+        let sub0 = od.get(0x1018, 0).unwrap();
+        assert_eq!(sub0.default, ODValue::U8(1));
+        assert_eq!(od.get(0x1018, 1).unwrap().default, ODValue::U32(0x26C));
+
+        // 1800 has no sub 0, so the container is kept
+        assert_eq!(od.get(0x1800, 0).unwrap().default, ODValue::Array(2));
+        // No node id known in a plain EDS
+        assert_eq!(od.get(0x1800, 1).unwrap().default, ODValue::U32(0x180));
+
+        let status = od.get(0x6041, 0).unwrap();
+        assert_eq!(status.name, "Statusword");
+        assert_eq!(status.access, AccessType::ReadOnly);
+        assert_eq!(status.pdo_mappable, MappableType::TPDO);
+        assert_eq!(status.default, ODValue::U16(0));
+
+        let target = od.get(0x607A, 0).unwrap();
+        assert_eq!(target.pdo_mappable, MappableType::RPDO);
+        assert_eq!(target.default, ODValue::I32(-100));
+        assert_eq!(target.meta.min, Some(ODValue::I32(-36000)));
+        assert_eq!(target.meta.max, Some(ODValue::I32(36000)));
+        assert_eq!(target.meta.format_value(&target.default), "-100 counts");
+
+        assert_eq!(od.get(0x6064, 0).unwrap().pdo_mappable, MappableType::Both);
+        assert_eq!(
+            od.get(0x1008, 0).unwrap().default,
+            ODValue::VisibleString("PD4-C".to_string())
+        );
+
+        assert_eq!(od.get(0x2001, 0).unwrap().default, ODValue::U24(0xFFFFFF));
+        // TIME_OF_DAY values can not be parsed, it is skipped
+        assert!(!od.contains(0x2000, 0));
+    }
+
+    #[test]
+    fn test_out_of_range_default() {
+        let eds = "[2001]\nParameterName=Wide counter\nDataType=0x0016\nDefaultValue=0x1000000\n";
+        assert!(matches!(
+            parse_entries(eds),
+            Err(EdsError::InvalidValue { index: 0x2001, .. })
+        ));
+
+        let eds = "[6060]\nParameterName=Modes of operation\nDataType=0x0002\nAccessType=rw\nDefaultValue=300\n";
+        assert!(matches!(
+            parse_entries(eds),
+            Err(EdsError::InvalidValue { index: 0x6060, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sdo_download_lookup() {
+        let od = ObjectDictionary::from_eds(EDS).unwrap();
+
+        // Expedited download of 4 bytes to 607A:00
+        let frame = [0x23, 0x7A, 0x60, 0x00, 0x10, 0x00, 0x00, 0x00];
+        let entry = ODEntry::from_sdo_download(&od, &frame, 8).unwrap();
+        assert_eq!(entry.name, "Target position");
+        assert_eq!(entry.default, ODValue::I32(16));
+
+        let unknown = [0x23, 0x00, 0x30, 0x00, 0x10, 0x00, 0x00, 0x00];
+        assert!(ODEntry::from_sdo_download(&od, &unknown, 8).is_none());
+    }
+
+    #[test]
+    fn test_generated_entries_match_eds() {
+        // build.rs and the runtime parser must agree on the reviewed EDS
+        let od = ObjectDictionary::from_eds(include_str!("../../../eds/pd4c.eds")).unwrap();
+        assert_eq!(od, ObjectDictionary::builtin());
+
+        assert_eq!(
+            crate::od::SET_TARGET_POSITION.pdo_mappable,
+            MappableType::RPDO
+        );
+        assert_eq!(
+            crate::od::GET_OPERATION_MODE.pdo_mappable,
+            MappableType::TPDO
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Raw CiA 306 EDS/DCF parser
+//!
+//! Only depends on `std` so the build script can reuse it through `#[path]`, it turns the INI
+//! style file into flat [`EdsObject`]s and leaves the typing to the caller.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Object type of a plain variable
+pub const OBJECT_TYPE_VAR: u8 = 0x7;
+/// Object type of an array, all sub-indices share a data type
+pub const OBJECT_TYPE_ARRAY: u8 = 0x8;
+/// Object type of a record, sub-indices can have different data types
+pub const OBJECT_TYPE_RECORD: u8 = 0x9;
+
+/// Error while parsing an EDS/DCF file
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdsParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for EdsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for EdsParseError {}
+
+/// A single object (or sub-object) as described by an EDS/DCF section
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EdsObject {
+    pub index: u16,
+    pub sub_index: u8,
+    pub name: String,
+    pub object_type: u8,
+    /// Number of sub-indices for ARRAY/RECORD objects
+    pub sub_number: Option<u8>,
+    /// CiA 301 data type code, e.g. 0x0007 = UNSIGNED32
+    pub data_type: Option<u16>,
+    /// Raw access type, one of ro, wo, rw, rwr, rww, const
+    pub access_type: Option<String>,
+    pub pdo_mapping: bool,
+    pub default_value: Option<String>,
+    /// DCF only: the value configured for this device
+    pub parameter_value: Option<String>,
+    /// DCF only: application specific name of the object
+    pub denotation: Option<String>,
+    pub low_limit: Option<String>,
+    pub high_limit: Option<String>,
+    /// Not part of CiA 306: the object whose value is the high limit, named like a section, e.g.
+    /// `6080` or `6099sub1`
+    pub high_limit_object: Option<String>,
+    /// Not part of CiA 306: physical unit of the value
+    pub unit: Option<String>,
+    /// Not part of CiA 306: what the object does
+    pub description: Option<String>,
+}
+
+impl EdsObject {
+    /// Value that should be used for this object, a DCF `ParameterValue` wins over the EDS
+    /// `DefaultValue`
+    pub fn value(&self) -> Option<&str> {
+        self.parameter_value
+            .as_deref()
+            .or(self.default_value.as_deref())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Does this section describe a container (ARRAY/RECORD) instead of a value
+    pub fn is_container(&self) -> bool {
+        matches!(self.object_type, OBJECT_TYPE_ARRAY | OBJECT_TYPE_RECORD)
+    }
+}
+
+/// Parsed contents of an EDS/DCF file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EdsFile {
+    /// Node id from the `[DeviceComissioning]` section, only present in DCF files
+    pub node_id: Option<u8>,
+    /// All object sections in file order
+    pub objects: Vec<EdsObject>,
+}
+
+/// Parse an EDS/DCF file into its object sections
+pub fn parse(source: &str) -> Result<EdsFile, EdsParseError> {
+    let sections = split_sections(source)?;
+
+    let node_id = sections
+        .iter()
+        .find(|section| section.name.eq_ignore_ascii_case("DeviceComissioning"))
+        .and_then(|section| section.get("NodeID"))
+        .map(|value| {
+            parse_integer(value)
+                .and_then(|id| u8::try_from(id).ok())
+                .ok_or_else(|| EdsParseError {
+                    line: 0,
+                    message: format!("Invalid NodeID {value:?}"),
+                })
+        })
+        .transpose()?;
+
+    let mut objects = Vec::new();
+    for section in sections.iter() {
+        let Some((index, sub_index)) = parse_section_name(&section.name) else {
+            // FileInfo, DeviceInfo, object lists, comments, ... we only care about objects
+            continue;
+        };
+
+        let object = section_to_object(section, index, sub_index)?;
+
+        // Compact arrays only describe their sub-indices implicitly
+        if sub_index.is_none()
+            && object.object_type == OBJECT_TYPE_ARRAY
+            && let Some(count) = section.get("CompactSubObj")
+        {
+            let count = parse_integer(count)
+                .and_then(|count| u8::try_from(count).ok())
+                .ok_or_else(|| section.error(format!("Invalid CompactSubObj {count:?}")))?;
+
+            objects.extend(expand_compact_array(&sections, &object, count));
+        }
+
+        objects.push(object);
+    }
+
+    Ok(EdsFile { node_id, objects })
+}
+
+/// Parse an EDS integer literal, accepting decimal, `0x` hexadecimal and leading zero octal
+pub fn parse_integer(value: &str) -> Option<i128> {
+    let value = value.trim();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let magnitude = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<i128>().ok()?
+    };
+
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// Evaluate a value that may reference the node id, e.g. `$NODEID+0x180`
+pub fn parse_integer_with_node_id(value: &str, node_id: Option<u8>) -> Option<i128> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    if !upper.contains("$NODEID") {
+        return parse_integer(value);
+    }
+
+    let node_id = node_id.unwrap_or(0) as i128;
+    upper
+        .split('+')
+        .map(|term| match term.trim() {
+            "$NODEID" => Some(node_id),
+            term => parse_integer(term),
+        })
+        .sum()
+}
+
+/// Parse a reference to an object written like a section name, e.g. `6080` or `6099sub1`
+pub fn parse_object_reference(value: &str) -> Option<(u16, u8)> {
+    let (index, sub_index) = parse_section_name(value.trim())?;
+    Some((index, sub_index.unwrap_or(0)))
+}
+
+struct Section {
+    name: String,
+    line: usize,
+    keys: BTreeMap<String, String>,
+}
+
+impl Section {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.keys
+            .get(&key.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    fn error(&self, message: String) -> EdsParseError {
+        EdsParseError {
+            line: self.line,
+            message: format!("[{}] {message}", self.name),
+        }
+    }
+}
+
+fn split_sections(source: &str) -> Result<Vec<Section>, EdsParseError> {
+    let mut sections: Vec<Section> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or_else(|| EdsParseError {
+                line: number,
+                message: format!("Unterminated section header {line:?}"),
+            })?;
+            sections.push(Section {
+                name: name.trim().to_string(),
+                line: number,
+                keys: BTreeMap::new(),
+            });
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(EdsParseError {
+                line: number,
+                message: format!("Expected key=value, got {line:?}"),
+            });
+        };
+
+        let Some(section) = sections.last_mut() else {
+            return Err(EdsParseError {
+                line: number,
+                message: format!("Key {key:?} outside of a section"),
+            });
+        };
+
+        section
+            .keys
+            .insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    Ok(sections)
+}
+
+/// Object sections are named `1018` or `1018sub2`, everything else is metadata
+fn parse_section_name(name: &str) -> Option<(u16, Option<u8>)> {
+    let lower = name.to_ascii_lowercase();
+    let (index, sub_index) = match lower.split_once("sub") {
+        Some((index, sub_index)) => (index, Some(sub_index)),
+        None => (lower.as_str(), None),
+    };
+
+    if index.len() != 4 {
+        return None;
+    }
+
+    let index = u16::from_str_radix(index, 16).ok()?;
+    let sub_index = match sub_index {
+        Some(sub_index) => Some(u8::from_str_radix(sub_index, 16).ok()?),
+        None => None,
+    };
+
+    Some((index, sub_index))
+}
+
+fn section_to_object(
+    section: &Section,
+    index: u16,
+    sub_index: Option<u8>,
+) -> Result<EdsObject, EdsParseError> {
+    let parse_u8 = |key: &str| -> Result<Option<u8>, EdsParseError> {
+        section
+            .get(key)
+            .map(|value| {
+                parse_integer(value)
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or_else(|| section.error(format!("Invalid {key} {value:?}")))
+            })
+            .transpose()
+    };
+
+    let non_empty = |key: &str| {
+        section
+            .get(key)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let data_type = section
+        .get("DataType")
+        .map(|value| {
+            parse_integer(value)
+                .and_then(|value| u16::try_from(value).ok())
+                .ok_or_else(|| section.error(format!("Invalid DataType {value:?}")))
+        })
+        .transpose()?;
+
+    let pdo_mapping = match section.get("PDOMapping") {
+        None => false,
+        Some(value) => {
+            parse_integer(value)
+                .ok_or_else(|| section.error(format!("Invalid PDOMapping {value:?}")))?
+                != 0
+        }
+    };
+
+    Ok(EdsObject {
+        index,
+        sub_index: sub_index.unwrap_or(0),
+        name: section.get("ParameterName").unwrap_or_default().to_string(),
+        object_type: parse_u8("ObjectType")?.unwrap_or(OBJECT_TYPE_VAR),
+        sub_number: parse_u8("SubNumber")?,
+        data_type,
+        access_type: section
+            .get("AccessType")
+            .map(|value| value.to_ascii_lowercase()),
+        pdo_mapping,
+        default_value: section.get("DefaultValue").map(str::to_string),
+        parameter_value: section.get("ParameterValue").map(str::to_string),
+        denotation: non_empty("Denotation"),
+        low_limit: non_empty("LowLimit"),
+        high_limit: non_empty("HighLimit"),
+        high_limit_object: non_empty("HighLimitObject"),
+        unit: non_empty("Unit"),
+        description: non_empty("Description"),
+    })
+}
+
+/// Expand a `CompactSubObj` array into explicit sub-objects, names come from the optional
+/// `[<index>Name]` section and values from the optional `[<index>Value]` section
+fn expand_compact_array(sections: &[Section], array: &EdsObject, count: u8) -> Vec<EdsObject> {
+    let lookup = |suffix: &str, sub_index: u8| {
+        let name = format!("{:04X}{suffix}", array.index);
+        sections
+            .iter()
+            .find(|section| section.name.eq_ignore_ascii_case(&name))
+            .and_then(|section| section.get(&sub_index.to_string()))
+            .map(str::to_string)
+    };
+
+    let mut objects = vec![EdsObject {
+        index: array.index,
+        sub_index: 0,
+        name: String::from("Number of entries"),
+        object_type: OBJECT_TYPE_VAR,
+        data_type: Some(0x0005),
+        access_type: Some(String::from("ro")),
+        default_value: Some(count.to_string()),
+        ..Default::default()
+    }];
+
+    for sub_index in 1..=count {
+        objects.push(EdsObject {
+            index: array.index,
+            sub_index,
+            name: lookup("Name", sub_index).unwrap_or_else(|| format!("{}{sub_index}", array.name)),
+            object_type: OBJECT_TYPE_VAR,
+            sub_number: None,
+            parameter_value: lookup("Value", sub_index),
+            denotation: None,
+            ..array.clone()
+        });
+    }
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDS: &str = "
+[FileInfo]
+FileName=test.eds
+
+[DeviceComissioning]
+NodeID=3
+
+[1000]
+ParameterName=Device type
+ObjectType=0x7
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x00040192
+PDOMapping=0
+
+[1400]
+ParameterName=Receive PDO 1 parameter
+ObjectType=0x9
+SubNumber=2
+
+[1400sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=ro
+DefaultValue=2
+
+[1400sub1]
+ParameterName=COB-ID used by RPDO
+DataType=0x0007
+AccessType=rw
+DefaultValue=$NODEID+0x200
+ParameterValue=0x80000203
+
+[6060]
+ParameterName=Modes of operation
+DataType=0x0002
+AccessType=rww
+PDOMapping=1
+DefaultValue=1
+Denotation=SET_OPERATION_MODE
+LowLimit=-1
+HighLimit=10
+Description=Requested operation mode
+
+[1F80]
+ParameterName=Compact
+ObjectType=0x8
+DataType=0x0006
+AccessType=rw
+CompactSubObj=2
+
+[1F80Name]
+NrOfEntries=1
+2=Second
+";
+
+    #[test]
+    fn test_parse_objects() {
+        let file = parse(EDS).unwrap();
+        assert_eq!(file.node_id, Some(3));
+
+        let device_type = &file.objects[0];
+        assert_eq!(device_type.index, 0x1000);
+        assert_eq!(device_type.sub_index, 0);
+        assert_eq!(device_type.name, "Device type");
+        assert_eq!(device_type.data_type, Some(0x0007));
+        assert_eq!(device_type.access_type.as_deref(), Some("ro"));
+        assert_eq!(device_type.value(), Some("0x00040192"));
+
+        let cob_id = file
+            .objects
+            .iter()
+            .find(|o| o.index == 0x1400 && o.sub_index == 1)
+            .unwrap();
+        // ParameterValue overrides DefaultValue
+        assert_eq!(cob_id.value(), Some("0x80000203"));
+
+        let opmode = file.objects.iter().find(|o| o.index == 0x6060).unwrap();
+        assert!(opmode.pdo_mapping);
+        assert_eq!(opmode.denotation.as_deref(), Some("SET_OPERATION_MODE"));
+        assert_eq!(opmode.low_limit.as_deref(), Some("-1"));
+        assert_eq!(opmode.high_limit.as_deref(), Some("10"));
+        assert_eq!(
+            opmode.description.as_deref(),
+            Some("Requested operation mode")
+        );
+    }
+
+    #[test]
+    fn test_compact_array() {
+        let file = parse(EDS).unwrap();
+        let subs: Vec<_> = file
+            .objects
+            .iter()
+            .filter(|o| o.index == 0x1F80 && !o.is_container())
+            .collect();
+
+        assert_eq!(subs.len(), 3);
+        assert_eq!(subs[0].data_type, Some(0x0005));
+        assert_eq!(subs[1].name, "Compact1");
+        assert_eq!(subs[2].name, "Second");
+        assert_eq!(subs[2].data_type, Some(0x0006));
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer("42"), Some(42));
+        assert_eq!(parse_integer("-42"), Some(-42));
+        assert_eq!(parse_integer("0x2A"), Some(42));
+        assert_eq!(parse_integer("052"), Some(42));
+        assert_eq!(parse_integer("0"), Some(0));
+        assert_eq!(parse_integer("nope"), None);
+        assert_eq!(
+            parse_integer_with_node_id("$NODEID+0x180", Some(3)),
+            Some(0x183)
+        );
+        assert_eq!(
+            parse_integer_with_node_id("0x600+$NodeId", Some(3)),
+            Some(0x603)
+        );
+        assert_eq!(parse_object_reference("6080"), Some((0x6080, 0)));
+        assert_eq!(parse_object_reference("6099sub1"), Some((0x6099, 1)));
+        assert_eq!(parse_object_reference("0x6080"), None);
+    }
+
+    #[test]
+    fn test_reject_garbage() {
+        assert!(parse("[1000]\nnot a key value pair").is_err());
+        assert!(parse("DataType=0x7").is_err());
+        assert!(parse("[1000\nDataType=0x7").is_err());
+    }
+}
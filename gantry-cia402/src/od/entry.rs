@@ -1,102 +1,205 @@
+use std::borrow::Cow;
+
 use tracing::*;
 
-use crate::od::{OD_LOOKUP, ODIdx, mappable::MappableType};
+use crate::{
+    error::DriveError,
+    od::{ODIdx, dictionary::ObjectDictionary, mappable::MappableType, meta::ODMeta},
+};
 
-use super::{access::AccessType, value::ODValue};
+use super::{
+    access::AccessType,
+    value::{CodecError, ODValue},
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ODEntry {
     // Index of the OD entry
     pub index: u16,
     // Subindex of the OD entry
     pub sub_index: u8,
+    // Parameter name, as listed in the datasheet / EDS
+    pub name: Cow<'static, str>,
     // Indicates both the type and default value
     pub default: ODValue,
     pub access: AccessType,
     pub pdo_mappable: MappableType,
+    // Limits, unit and description, if known
+    pub meta: ODMeta,
 }
 
 impl ODEntry {
     pub const fn new(
         index: u16,
         sub_index: u8,
+        name: &'static str,
+        access: AccessType,
+        pdo_mappable: MappableType,
+        default: ODValue,
+    ) -> Self {
+        Self::new_with_meta(
+            index,
+            sub_index,
+            name,
+            access,
+            pdo_mappable,
+            default,
+            ODMeta::NONE,
+        )
+    }
+
+    pub const fn new_with_meta(
+        index: u16,
+        sub_index: u8,
+        name: &'static str,
         access: AccessType,
         pdo_mappable: MappableType,
         default: ODValue,
+        meta: ODMeta,
     ) -> Self {
         Self {
             index,
             sub_index,
+            name: Cow::Borrowed(name),
             access,
             pdo_mappable,
             default,
+            meta,
+        }
+    }
+
+    /// Whether this is one of the dummy entries, see [`crate::od::DUMMY_ENTRIES`]
+    pub fn is_dummy(&self) -> bool {
+        (0x0001..=0x0007).contains(&self.index)
+    }
+
+    pub fn idx(&self) -> ODIdx {
+        ODIdx {
+            index: self.index,
+            sub_index: self.sub_index,
+        }
+    }
+
+    // Attempt to parse received data payload into SDO, using the object dictionary of the
+    // addressed node. Only expedited downloads carry a value
+    pub fn from_sdo_download(
+        dictionary: &ObjectDictionary,
+        data: &[u8; 8],
+        dlc: usize,
+    ) -> Option<Self> {
+        let idx = Self::sdo_download_idx(data);
+        let entry = dictionary.get(idx.index, idx.sub_index)?;
+
+        let command = data[0];
+        // Client command specifier 1: initiate download, e: expedited
+        if command >> 5 != 1 || command & 0b10 == 0 {
+            return None;
+        }
+        let size = match command & 0b01 {
+            // s: size indicated, n is the number of bytes that do not contain data
+            1 => 4 - ((command >> 2) & 0b11) as usize,
+            _ => entry.default.data_type()?.size()?.min(4),
+        };
+
+        // Extract payload (after command specifier + index/subindex)
+        let payload = data.get(4..(4 + size).min(dlc))?;
+        match entry.with_value_from(payload) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                error!("Unable to parse {:?} into SDO: {err}", data);
+                None
+            }
         }
     }
 
-    // Attempt to parse received data payload into SDO
-    pub fn from_sdo_download(data: &[u8; 8], dlc: usize) -> Option<Self> {
+    fn sdo_download_idx(data: &[u8; 8]) -> ODIdx {
         // Extract index/subindex
         let index = u16::from_le_bytes([data[1], data[2]]);
         let sub_index = data[3];
-        let idx = ODIdx { index, sub_index };
+        ODIdx { index, sub_index }
+    }
 
-        // Lookup OD entry
-        let entry = OD_LOOKUP.get(&idx)?;
-        let expected = &entry.default;
+    /// Copy of this entry holding the value decoded from the given little endian payload
+    pub fn with_value_from(&self, payload: &[u8]) -> Result<Self, CodecError> {
+        let data_type = self.default.data_type().ok_or(CodecError::Container)?;
 
-        // Extract payload (after command specifier + index/subindex)
-        let payload = &data[4..dlc.min(8)];
-
-        // Try to interpret the bytes into the right ODValue
-        let parsed_value = match expected {
-            ODValue::Bool(_) => ODValue::Bool(payload[0] != 0),
-            ODValue::I8(_) => ODValue::I8(payload[0] as i8),
-            ODValue::U8(_) => ODValue::U8(payload[0]),
-            ODValue::I16(_) => ODValue::I16(i16::from_le_bytes(payload[..2].try_into().ok()?)),
-            ODValue::U16(_) => ODValue::U16(u16::from_le_bytes(payload[..2].try_into().ok()?)),
-            ODValue::I32(_) => ODValue::I32(i32::from_le_bytes(payload[..4].try_into().ok()?)),
-            ODValue::U32(_) => ODValue::U32(u32::from_le_bytes(payload[..4].try_into().ok()?)),
-            ODValue::I64(_) => ODValue::I64(i64::from_le_bytes(
-                payload[..8.min(payload.len())].try_into().ok()?,
-            )),
-            ODValue::U64(_) => ODValue::U64(u64::from_le_bytes(
-                payload[..8.min(payload.len())].try_into().ok()?,
-            )),
-            ODValue::F32(_) => ODValue::F32(f32::from_le_bytes(payload[..4].try_into().ok()?)),
-            ODValue::F64(_) => ODValue::F64(f64::from_le_bytes(
-                payload[..8.min(payload.len())].try_into().ok()?,
-            )),
-            _ => {
-                error!("Unable to parse {:?} into SDO", data);
-                return None;
-            }
+        Ok(Self {
+            default: ODValue::decode(data_type, payload)?,
+            ..self.clone()
+        })
+    }
+
+    /// Fails if the access type of this entry does not allow a download (write)
+    pub fn check_writable(&self) -> Result<(), DriveError> {
+        if !self.access.is_writable() {
+            return Err(DriveError::NotWritable {
+                index: self.index,
+                sub_index: self.sub_index,
+                access: self.access,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the access type of this entry does not allow an upload (read)
+    pub fn check_readable(&self) -> Result<(), DriveError> {
+        if !self.access.is_readable() {
+            return Err(DriveError::NotReadable {
+                index: self.index,
+                sub_index: self.sub_index,
+                access: self.access,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the value lies outside of the static limits of this entry, see
+    /// [`crate::od::meta::KnownValues::check`] for limits set by other objects
+    pub fn check_range(&self, value: &ODValue) -> Result<(), DriveError> {
+        let out_of_range = |limit: String| DriveError::OutOfRange {
+            index: self.index,
+            sub_index: self.sub_index,
+            value: self.meta.format_value(value),
+            limit,
         };
 
-        Some(Self {
-            index,
-            sub_index,
-            default: parsed_value,
-            access: entry.access,
-            pdo_mappable: entry.pdo_mappable.clone(),
-        })
+        if let Some(min) = &self.meta.min
+            && value < min
+        {
+            return Err(out_of_range(format!("min {}", self.meta.format_value(min))));
+        }
+        if let Some(max) = &self.meta.max
+            && value > max
+        {
+            return Err(out_of_range(format!("max {}", self.meta.format_value(max))));
+        }
+
+        Ok(())
     }
 
-    pub fn get_num_bytes(&self) -> usize {
-        match &self.default {
-            ODValue::Bool(_) => 1,
-            ODValue::I8(_) => 1,
-            ODValue::U8(_) => 1,
-            ODValue::I16(_) => 2,
-            ODValue::U16(_) => 2,
-            ODValue::I32(_) => 4,
-            ODValue::U32(_) => 4,
-            ODValue::I64(_) => 8,
-            ODValue::U64(_) => 8,
-            ODValue::F32(_) => 4,
-            ODValue::F64(_) => 8,
-            ODValue::VisibleString(s) => s.len(),
-            ODValue::OctetString(items) => items.len(),
-            ODValue::Array(bytes) => *bytes,
+    /// Fails if the value can not be downloaded to this entry: the entry is not writable, the
+    /// value is of another data type or outside of the static limits
+    pub fn check_download(&self, value: &ODValue) -> Result<(), DriveError> {
+        self.check_writable()?;
+        if !value.same_type(&self.default) {
+            return Err(DriveError::ValueTypeMismatch {
+                index: self.index,
+                sub_index: self.sub_index,
+                value: value.clone(),
+            });
         }
+
+        self.check_range(value)
+    }
+
+    /// Name and value of this entry including its unit, e.g. `Profile velocity = 30 rpm`
+    pub fn fmt_value(&self) -> String {
+        format!("{} = {}", self.name, self.meta.format_value(&self.default))
+    }
+
+    pub fn get_num_bytes(&self) -> usize {
+        self.default.encoded_len()
     }
 }
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use crate::od::{
+    access::AccessType,
+    entry::ODEntry,
+    mappable::MappableType,
+    value::{CodecError, ODValue},
+};
+
+/// Inconsistencies between the flags and default value of an [`ODEntry`]
+#[derive(Debug, Error, PartialEq)]
+pub enum LintIssue {
+    #[error("{index:#06x}:{sub_index} is listed more than once")]
+    Duplicate { index: u16, sub_index: u8 },
+    #[error("{index:#06x}:{sub_index} is {access:?} but PDO mappable as {mappable:?}")]
+    MappingAccess {
+        index: u16,
+        sub_index: u8,
+        access: AccessType,
+        mappable: MappableType,
+    },
+    #[error("{index:#06x}:{sub_index} is PDO mappable but has no fixed size")]
+    MappingSize { index: u16, sub_index: u8 },
+    #[error("{index:#06x}:{sub_index} has a default that can not be encoded: {error}")]
+    Default {
+        index: u16,
+        sub_index: u8,
+        error: CodecError,
+    },
+}
+
+/// Check the given entries for inconsistent flags:
+/// - RPDO mappable entries have to be writable and TPDO mappable entries readable
+/// - PDO mappable entries need a fixed size
+/// - Defaults have to fit their data type
+/// - Every index/subindex is listed only once
+pub fn lint<'a>(entries: impl IntoIterator<Item = &'a ODEntry>) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for entry in entries {
+        let (index, sub_index) = (entry.index, entry.sub_index);
+
+        if !seen.insert(entry.idx()) {
+            issues.push(LintIssue::Duplicate { index, sub_index });
+        }
+
+        let mappable = &entry.pdo_mappable;
+        if (mappable.allows_rpdo() && !entry.access.is_writable())
+            || (mappable.allows_tpdo() && !entry.access.is_readable())
+        {
+            issues.push(LintIssue::MappingAccess {
+                index,
+                sub_index,
+                access: entry.access,
+                mappable: mappable.clone(),
+            });
+        }
+
+        let fixed_size = entry.default.data_type().and_then(|t| t.size()).is_some();
+        if *mappable != MappableType::None && !fixed_size {
+            issues.push(LintIssue::MappingSize { index, sub_index });
+        }
+
+        if !matches!(entry.default, ODValue::Array(_))
+            && let Err(error) = entry.default.encode()
+        {
+            issues.push(LintIssue::Default {
+                index,
+                sub_index,
+                error,
+            });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::od::FULL_OBJECT_DICTIONARY;
+
+    #[test]
+    fn test_full_object_dictionary_is_consistent() {
+        assert_eq!(lint(FULL_OBJECT_DICTIONARY), vec![]);
+    }
+
+    #[test]
+    fn test_inconsistent_entries() {
+        let read_only_rpdo = ODEntry::new(
+            0x2000,
+            0,
+            "Read only RPDO",
+            AccessType::ReadOnly,
+            MappableType::RPDO,
+            ODValue::U8(0),
+        );
+        let mapped_string = ODEntry::new(
+            0x2001,
+            0,
+            "Mapped string",
+            AccessType::ReadWrite,
+            MappableType::Both,
+            ODValue::VisibleString(String::new()),
+        );
+        let issues = lint([&read_only_rpdo, &mapped_string, &read_only_rpdo]);
+
+        assert_eq!(
+            issues,
+            vec![
+                LintIssue::MappingAccess {
+                    index: 0x2000,
+                    sub_index: 0,
+                    access: AccessType::ReadOnly,
+                    mappable: MappableType::RPDO,
+                },
+                LintIssue::MappingSize {
+                    index: 0x2001,
+                    sub_index: 0
+                },
+                LintIssue::Duplicate {
+                    index: 0x2000,
+                    sub_index: 0
+                },
+                LintIssue::MappingAccess {
+                    index: 0x2000,
+                    sub_index: 0,
+                    access: AccessType::ReadOnly,
+                    mappable: MappableType::RPDO,
+                },
+            ]
+        );
+    }
+}
@@ -3,4 +3,18 @@ pub enum MappableType {
     None,
     RPDO,
     TPDO,
+    // Can be mapped in either direction, e.g. an EDS `rw` object with PDOMapping=1
+    Both,
+}
+
+impl MappableType {
+    /// Can the object be mapped into a RPDO, i.e. be written by the network
+    pub const fn allows_rpdo(&self) -> bool {
+        matches!(self, MappableType::RPDO | MappableType::Both)
+    }
+
+    /// Can the object be mapped into a TPDO, i.e. be read by the network
+    pub const fn allows_tpdo(&self) -> bool {
+        matches!(self, MappableType::TPDO | MappableType::Both)
+    }
 }
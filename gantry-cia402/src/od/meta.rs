@@ -0,0 +1,142 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use crate::{
+    error::DriveError,
+    od::{ODIdx, dictionary::ObjectDictionary, entry::ODEntry, value::ODValue},
+};
+
+/// Optional metadata of an [`ODEntry`], see the `LowLimit`, `HighLimit`, `HighLimitObject`,
+/// `Unit` and `Description` keys of the EDS file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ODMeta {
+    /// Lowest value the device accepts
+    pub min: Option<ODValue>,
+    /// Highest value the device accepts
+    pub max: Option<ODValue>,
+    /// Object whose current value is the highest value the device accepts, e.g. the max motor
+    /// speed for the profile velocity
+    pub max_object: Option<ODIdx>,
+    /// Physical unit of the value, e.g. `rpm`
+    pub unit: Option<Cow<'static, str>>,
+    pub description: Option<Cow<'static, str>>,
+}
+
+impl ODMeta {
+    pub const NONE: ODMeta = ODMeta {
+        min: None,
+        max: None,
+        max_object: None,
+        unit: None,
+        description: None,
+    };
+
+    /// The value followed by its unit, if known
+    pub fn format_value(&self, value: &ODValue) -> String {
+        match &self.unit {
+            Some(unit) => format!("{value} {unit}"),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// Values of the objects of a single device: the defaults of its dictionary, overridden by
+/// every value we wrote to it. Resolves the limits that refer to other objects
+#[derive(Debug, Clone, Default)]
+pub struct KnownValues {
+    // Entries holding the current value as their default, like the entries of upload results
+    entries: BTreeMap<ODIdx, ODEntry>,
+}
+
+impl KnownValues {
+    pub fn from_dictionary(dictionary: &ObjectDictionary) -> Self {
+        Self {
+            entries: dictionary
+                .iter()
+                .filter(|entry| !matches!(entry.default, ODValue::Array(_)))
+                .map(|entry| (entry.idx(), entry.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, idx: ODIdx) -> Option<&ODValue> {
+        self.entries.get(&idx).map(|entry| &entry.default)
+    }
+
+    /// Record a value written to the device
+    pub fn insert(&mut self, entry: &ODEntry, value: ODValue) {
+        self.entries.insert(
+            entry.idx(),
+            ODEntry {
+                default: value,
+                ..entry.clone()
+            },
+        );
+    }
+
+    /// Check the value against the static limits of the entry and the current value of its
+    /// `max_object`. A `max_object` we know nothing about is not checked
+    pub fn check(&self, entry: &ODEntry, value: &ODValue) -> Result<(), DriveError> {
+        entry.check_range(value)?;
+
+        let Some(bound) = entry.meta.max_object.and_then(|idx| self.entries.get(&idx)) else {
+            return Ok(());
+        };
+
+        // The bounding object may have a different data type, e.g. an unsigned maximum for a
+        // signed value, so compare the plain numbers
+        match (value.as_i128(), bound.default.as_i128()) {
+            (Some(number), Some(max)) if number > max => Err(DriveError::OutOfRange {
+                index: entry.index,
+                sub_index: entry.sub_index,
+                value: entry.meta.format_value(value),
+                limit: format!(
+                    "{} ({})",
+                    bound.name,
+                    bound.meta.format_value(&bound.default)
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::od::{self, typed};
+
+    #[test]
+    fn test_static_limits() {
+        let meta = ODMeta {
+            min: Some(ODValue::U16(1)),
+            max: Some(ODValue::U16(10)),
+            unit: Some(Cow::Borrowed("ms")),
+            ..ODMeta::NONE
+        };
+        let entry = ODEntry {
+            meta,
+            ..od::PRODUCER_HEARTBEAT_TIME.clone()
+        };
+
+        assert!(entry.check_range(&ODValue::U16(1)).is_ok());
+        assert!(entry.check_range(&ODValue::U16(10)).is_ok());
+        assert!(matches!(
+            entry.check_range(&ODValue::U16(11)),
+            Err(DriveError::OutOfRange { value, .. }) if value == "11 ms"
+        ));
+        assert!(entry.check_range(&ODValue::U16(0)).is_err());
+    }
+
+    #[test]
+    fn test_profile_velocity_below_max_motor_speed() {
+        let mut known = KnownValues::from_dictionary(&ObjectDictionary::builtin());
+        known.insert(&od::MAX_MOTOR_SPEED, ODValue::U32(2000));
+
+        let velocity = typed::PROFILE_VELOCITY.entry();
+        assert!(known.check(velocity, &ODValue::U32(2000)).is_ok());
+        assert!(matches!(
+            known.check(velocity, &ODValue::U32(2001)),
+            Err(DriveError::OutOfRange { index: 0x6081, .. })
+        ));
+    }
+}
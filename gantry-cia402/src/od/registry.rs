@@ -0,0 +1,65 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use oze_canopen::canopen::NodeId;
+
+use crate::od::dictionary::ObjectDictionary;
+
+/// Object dictionaries of the nodes on a bus, used to resolve received frames against the
+/// dictionary of the node that sent or is addressed by them
+#[derive(Debug, Clone)]
+pub struct DictionaryRegistry {
+    nodes: BTreeMap<NodeId, Arc<ObjectDictionary>>,
+    /// Used for nodes without a registered dictionary
+    fallback: Arc<ObjectDictionary>,
+}
+
+impl Default for DictionaryRegistry {
+    fn default() -> Self {
+        Self::new(Arc::new(ObjectDictionary::standard()))
+    }
+}
+
+impl DictionaryRegistry {
+    pub fn new(fallback: Arc<ObjectDictionary>) -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            fallback,
+        }
+    }
+
+    /// Register the dictionary of a node, returns the dictionary it replaced if any
+    pub fn insert(
+        &mut self,
+        node_id: NodeId,
+        dictionary: Arc<ObjectDictionary>,
+    ) -> Option<Arc<ObjectDictionary>> {
+        self.nodes.insert(node_id, dictionary)
+    }
+
+    pub fn remove(&mut self, node_id: NodeId) -> Option<Arc<ObjectDictionary>> {
+        self.nodes.remove(&node_id)
+    }
+
+    /// Dictionary of the given node, or the fallback dictionary if it has none registered
+    pub fn get(&self, node_id: NodeId) -> &ObjectDictionary {
+        self.nodes.get(&node_id).unwrap_or(&self.fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::od;
+
+    #[test]
+    fn test_per_node_lookup() {
+        let mut registry = DictionaryRegistry::default();
+        registry.insert(3, Arc::new(ObjectDictionary::builtin()));
+
+        let block = &od::BLOCK_DETECTION_PERIOD;
+        assert!(registry.get(3).contains(block.index, block.sub_index));
+        // Other nodes only know the standard objects
+        assert!(!registry.get(4).contains(block.index, block.sub_index));
+        assert!(registry.get(4).contains(0x6040, 0));
+    }
+}
@@ -0,0 +1,236 @@
+use std::marker::PhantomData;
+
+use crate::{
+    comms::sdo::{SdoAction, scheduler::SdoHandle},
+    error::DriveError,
+    od::{
+        data_type::DataType,
+        entry::ODEntry,
+        value::{CodecError, ODValue},
+    },
+};
+
+/// Rust types that map onto a fixed width CiA 301 data type
+pub trait ODType: Copy + Sized {
+    const DATA_TYPE: DataType;
+    /// Encoded width in bytes
+    const SIZE: usize;
+
+    fn into_value(self) -> ODValue;
+    fn from_value(value: &ODValue) -> Option<Self>;
+
+    /// Little endian encoding, using the [`ODValue`] codec
+    fn encode(self) -> Vec<u8> {
+        self.into_value()
+            .encode()
+            .expect("Fixed width values always fit their data type")
+    }
+
+    /// Decode from exactly [`ODType::SIZE`] little endian bytes, using the [`ODValue`] codec
+    fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+        let value = ODValue::decode(Self::DATA_TYPE, bytes)?;
+        Ok(Self::from_value(&value).expect("Decoded value matches the requested data type"))
+    }
+}
+
+/// An [`ODEntry`] together with the Rust type of its value
+/// Constructing one for an entry holding a different type panics, which is a compile error when
+/// done in a const context:
+/// ```compile_fail
+/// use gantry_cia402::{od, od::typed::TypedEntry, typed_entry};
+///
+/// // Period of blocking is an INTEGER32
+/// const PERIOD: TypedEntry<u16> = typed_entry!(u16, od::BLOCK_DETECTION_PERIOD);
+/// let _ = PERIOD;
+/// ```
+#[derive(Debug)]
+pub struct TypedEntry<T> {
+    entry: &'static ODEntry,
+    _type: PhantomData<T>,
+}
+
+// Manual impls, derives would require T: Clone/Copy
+impl<T> Clone for TypedEntry<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedEntry<T> {}
+
+/// Construct a [`TypedEntry`] for a const [`ODEntry`], e.g.
+/// `const TARGET: TypedEntry<i32> = typed_entry!(i32, od::SET_TARGET_POSITION);`
+/// Borrowing the const through an inner const gives the `'static` reference `TypedEntry::new`
+/// needs, a plain `&od::SET_TARGET_POSITION` argument would be a temporary
+#[macro_export]
+macro_rules! typed_entry {
+    ($ty:ty, $entry:path) => {
+        $crate::od::typed::TypedEntry::<$ty>::new({
+            const ENTRY: &$crate::od::entry::ODEntry = &$entry;
+            ENTRY
+        })
+    };
+}
+
+impl<T: ODType> TypedEntry<T> {
+    pub const fn new(entry: &'static ODEntry) -> Self {
+        match entry.default.data_type() {
+            Some(data_type) if data_type.code() == T::DATA_TYPE.code() => {}
+            _ => panic!("TypedEntry type does not match the data type of the ODEntry"),
+        }
+
+        Self {
+            entry,
+            _type: PhantomData,
+        }
+    }
+
+    pub const fn entry(&self) -> &'static ODEntry {
+        self.entry
+    }
+
+    /// Upload (read) this entry from the device
+    pub const fn read(self) -> SdoAction {
+        SdoAction::Upload { entry: self.entry }
+    }
+
+    pub fn encode(&self, value: T) -> Vec<u8> {
+        value.encode()
+    }
+
+    /// Decode a value of this entry, the data has to be exactly as wide as the entry
+    pub fn decode(&self, data: &[u8]) -> Result<T, DriveError> {
+        Ok(T::decode(data)?)
+    }
+
+    /// Read this entry from the device using the given SDO handle
+    pub async fn upload(&self, sdo: SdoHandle) -> Result<T, DriveError> {
+        self.entry.check_readable()?;
+
+        let data = sdo.upload(self.entry.index, self.entry.sub_index).await?;
+
+        self.decode(&data)
+    }
+
+    /// Write this entry on the device using the given SDO handle
+    pub async fn download(&self, sdo: SdoHandle, value: T) -> Result<(), DriveError> {
+        self.entry.check_writable()?;
+        self.entry.check_range(&value.into_value())?;
+
+        sdo.download(self.entry.index, self.entry.sub_index, &value.encode())
+            .await?;
+
+        Ok(())
+    }
+}
+
+macro_rules! impl_od_type {
+    ($($ty:ty => $variant:ident, $data_type:ident;)*) => {
+        $(
+            impl ODType for $ty {
+                const DATA_TYPE: DataType = DataType::$data_type;
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn into_value(self) -> ODValue {
+                    ODValue::$variant(self)
+                }
+
+                fn from_value(value: &ODValue) -> Option<Self> {
+                    match value {
+                        ODValue::$variant(value) => Some(*value),
+                        _ => None,
+                    }
+                }
+            }
+
+            // Generic trait methods can not be called in const fn, so the const SdoAction
+            // constructor is implemented per type
+            impl TypedEntry<$ty> {
+                /// Download (write) the given value to this entry on the device
+                pub const fn write(self, value: $ty) -> SdoAction {
+                    SdoAction::Download {
+                        entry: self.entry,
+                        value: ODValue::$variant(value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_od_type! {
+    i8 => I8, Integer8;
+    u8 => U8, Unsigned8;
+    i16 => I16, Integer16;
+    u16 => U16, Unsigned16;
+    i32 => I32, Integer32;
+    u32 => U32, Unsigned32;
+    i64 => I64, Integer64;
+    u64 => U64, Unsigned64;
+    f32 => F32, Real32;
+    f64 => F64, Real64;
+}
+
+impl ODType for bool {
+    const DATA_TYPE: DataType = DataType::Boolean;
+    const SIZE: usize = 1;
+
+    fn into_value(self) -> ODValue {
+        ODValue::Bool(self)
+    }
+
+    fn from_value(value: &ODValue) -> Option<Self> {
+        match value {
+            ODValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl TypedEntry<bool> {
+    /// Download (write) the given value to this entry on the device
+    pub const fn write(self, value: bool) -> SdoAction {
+        SdoAction::Download {
+            entry: self.entry,
+            value: ODValue::Bool(value),
+        }
+    }
+}
+
+// Typed views of the generated entries in [`crate::od`], e.g. `typed::CONTROL_WORD.write(0x0F)`
+include!(concat!(env!("OUT_DIR"), "/od_typed_entries.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_write() {
+        let SdoAction::Download { entry, value } = MOTION_PROFILE_TYPE.write(1) else {
+            panic!("Expected a download");
+        };
+        assert_eq!(entry, &crate::od::MOTION_PROFILE_TYPE);
+        assert_eq!(value, ODValue::I16(1));
+        assert_eq!(value.encode(), Ok(vec![1, 0]));
+    }
+
+    #[test]
+    fn test_typed_decode() {
+        assert_eq!(
+            POSITION_ACTUAL_VALUE
+                .decode(&(-5i32).to_le_bytes())
+                .unwrap(),
+            -5
+        );
+        // Width mismatch
+        assert!(POSITION_ACTUAL_VALUE.decode(&[0, 0]).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_type_mismatch() {
+        // Block detection period is an INTEGER32, not an UNSIGNED16
+        let entry = Box::leak(Box::new(crate::od::BLOCK_DETECTION_PERIOD));
+        let _ = TypedEntry::<u16>::new(entry);
+    }
+}
@@ -1,3 +1,13 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+use crate::od::data_type::DataType;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ODValue {
     Bool(bool),
@@ -5,13 +15,475 @@ pub enum ODValue {
     U8(u8),
     I16(i16),
     U16(u16),
+    I24(i32),
+    U24(u32),
     I32(i32),
     U32(u32),
+    I40(i64),
+    U40(u64),
+    I48(i64),
+    U48(u64),
+    I56(i64),
+    U56(u64),
     I64(i64),
     U64(u64),
     F32(f32),
     F64(f64),
     VisibleString(String),
     OctetString(Vec<u8>),
+    // UTF-16 code units
+    UnicodeString(Vec<u16>),
+    TimeOfDay(TimeOfDay),
+    TimeDifference(TimeOfDay),
+    Domain(Vec<u8>),
     Array(usize), // Indicates the presence of sub-indices
 }
+
+/// CiA 301 TIME_OF_DAY / TIME_DIFFERENCE
+/// For TIME_OF_DAY the days count from January 1, 1984
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeOfDay {
+    /// Milliseconds after midnight, 28 bits
+    pub ms: u32,
+    pub days: u16,
+}
+
+/// Start of the CANopen time scale, 1984-01-01 00:00 UTC, in seconds after the unix epoch
+const TIME_EPOCH_SECS: u64 = 441_763_200;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+impl TimeOfDay {
+    pub const MAX_MS: u32 = (1 << 28) - 1;
+
+    /// None before 1984 or after the 65535 days the time scale spans
+    pub fn from_system_time(time: SystemTime) -> Option<Self> {
+        let since_epoch = time
+            .duration_since(UNIX_EPOCH + Duration::from_secs(TIME_EPOCH_SECS))
+            .ok()?;
+        let ms = since_epoch.as_millis() as u64;
+
+        Some(Self {
+            ms: (ms % MS_PER_DAY) as u32,
+            days: u16::try_from(ms / MS_PER_DAY).ok()?,
+        })
+    }
+
+    /// Only meaningful for a TIME_OF_DAY, a TIME_DIFFERENCE has no epoch
+    pub fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH
+            + Duration::from_secs(TIME_EPOCH_SECS)
+            + Duration::from_millis(self.days as u64 * MS_PER_DAY + self.ms as u64)
+    }
+}
+
+/// Errors encoding or decoding an [`ODValue`]
+#[derive(Debug, Error, PartialEq)]
+pub enum CodecError {
+    #[error("{data_type:?} takes {expected} bytes, got {found}")]
+    Length {
+        data_type: DataType,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{0:?} does not fit in its data type")]
+    OutOfRange(ODValue),
+    #[error("{0:?} is not a valid VISIBLE_STRING")]
+    InvalidString(Vec<u8>),
+    #[error("UNICODE_STRING of {0} bytes is not a whole number of code units")]
+    UnicodeLength(usize),
+    #[error("Arrays and records have no encoding of their own")]
+    Container,
+}
+
+impl ODValue {
+    /// CiA 301 data type of this value, None for arrays/records
+    pub const fn data_type(&self) -> Option<DataType> {
+        Some(match self {
+            ODValue::Bool(_) => DataType::Boolean,
+            ODValue::I8(_) => DataType::Integer8,
+            ODValue::U8(_) => DataType::Unsigned8,
+            ODValue::I16(_) => DataType::Integer16,
+            ODValue::U16(_) => DataType::Unsigned16,
+            ODValue::I24(_) => DataType::Integer24,
+            ODValue::U24(_) => DataType::Unsigned24,
+            ODValue::I32(_) => DataType::Integer32,
+            ODValue::U32(_) => DataType::Unsigned32,
+            ODValue::I40(_) => DataType::Integer40,
+            ODValue::U40(_) => DataType::Unsigned40,
+            ODValue::I48(_) => DataType::Integer48,
+            ODValue::U48(_) => DataType::Unsigned48,
+            ODValue::I56(_) => DataType::Integer56,
+            ODValue::U56(_) => DataType::Unsigned56,
+            ODValue::I64(_) => DataType::Integer64,
+            ODValue::U64(_) => DataType::Unsigned64,
+            ODValue::F32(_) => DataType::Real32,
+            ODValue::F64(_) => DataType::Real64,
+            ODValue::VisibleString(_) => DataType::VisibleString,
+            ODValue::OctetString(_) => DataType::OctetString,
+            ODValue::UnicodeString(_) => DataType::UnicodeString,
+            ODValue::TimeOfDay(_) => DataType::TimeOfDay,
+            ODValue::TimeDifference(_) => DataType::TimeDifference,
+            ODValue::Domain(_) => DataType::Domain,
+            ODValue::Array(_) => return None,
+        })
+    }
+
+    /// Do both values hold the same data type
+    pub fn same_type(&self, other: &ODValue) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Integer value of boolean and integer types
+    pub fn as_i128(&self) -> Option<i128> {
+        Some(match *self {
+            ODValue::Bool(value) => value.into(),
+            ODValue::I8(value) => value.into(),
+            ODValue::U8(value) => value.into(),
+            ODValue::I16(value) => value.into(),
+            ODValue::U16(value) => value.into(),
+            ODValue::I24(value) | ODValue::I32(value) => value.into(),
+            ODValue::U24(value) | ODValue::U32(value) => value.into(),
+            ODValue::I40(value)
+            | ODValue::I48(value)
+            | ODValue::I56(value)
+            | ODValue::I64(value) => value.into(),
+            ODValue::U40(value)
+            | ODValue::U48(value)
+            | ODValue::U56(value)
+            | ODValue::U64(value) => value.into(),
+            _ => return None,
+        })
+    }
+
+    /// Value of real types
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            ODValue::F32(value) => Some(value.into()),
+            ODValue::F64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes this value takes on the wire, the number of sub-indices for arrays
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            ODValue::VisibleString(value) => value.len(),
+            ODValue::OctetString(value) | ODValue::Domain(value) => value.len(),
+            ODValue::UnicodeString(value) => value.len() * 2,
+            ODValue::Array(sub_indices) => *sub_indices,
+            // Everything else has a fixed size
+            value => value.data_type().and_then(|t| t.size()).unwrap_or(0),
+        }
+    }
+
+    /// Little endian encoding as sent over the wire
+    /// Fails for values that do not fit their data type (e.g. an UNSIGNED24 above 0xFFFFFF) and
+    /// for arrays/records
+    pub fn encode(&self) -> Result<Vec<u8>, CodecError> {
+        let out_of_range = || CodecError::OutOfRange(self.clone());
+
+        Ok(match self {
+            ODValue::Bool(value) => vec![*value as u8],
+            ODValue::I8(value) => value.to_le_bytes().to_vec(),
+            ODValue::U8(value) => value.to_le_bytes().to_vec(),
+            ODValue::I16(value) => value.to_le_bytes().to_vec(),
+            ODValue::U16(value) => value.to_le_bytes().to_vec(),
+            ODValue::I24(value) => encode_int(*value as i64, 3).ok_or_else(out_of_range)?,
+            ODValue::U24(value) => encode_uint(*value as u64, 3).ok_or_else(out_of_range)?,
+            ODValue::I32(value) => value.to_le_bytes().to_vec(),
+            ODValue::U32(value) => value.to_le_bytes().to_vec(),
+            ODValue::I40(value) => encode_int(*value, 5).ok_or_else(out_of_range)?,
+            ODValue::U40(value) => encode_uint(*value, 5).ok_or_else(out_of_range)?,
+            ODValue::I48(value) => encode_int(*value, 6).ok_or_else(out_of_range)?,
+            ODValue::U48(value) => encode_uint(*value, 6).ok_or_else(out_of_range)?,
+            ODValue::I56(value) => encode_int(*value, 7).ok_or_else(out_of_range)?,
+            ODValue::U56(value) => encode_uint(*value, 7).ok_or_else(out_of_range)?,
+            ODValue::I64(value) => value.to_le_bytes().to_vec(),
+            ODValue::U64(value) => value.to_le_bytes().to_vec(),
+            ODValue::F32(value) => value.to_le_bytes().to_vec(),
+            ODValue::F64(value) => value.to_le_bytes().to_vec(),
+            ODValue::VisibleString(value) => {
+                if !value.is_ascii() {
+                    return Err(CodecError::InvalidString(value.as_bytes().to_vec()));
+                }
+                value.as_bytes().to_vec()
+            }
+            ODValue::OctetString(value) | ODValue::Domain(value) => value.clone(),
+            ODValue::UnicodeString(value) => value.iter().flat_map(|c| c.to_le_bytes()).collect(),
+            ODValue::TimeOfDay(time) | ODValue::TimeDifference(time) => {
+                if time.ms > TimeOfDay::MAX_MS {
+                    return Err(out_of_range());
+                }
+                let mut bytes = time.ms.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&time.days.to_le_bytes());
+                bytes
+            }
+            ODValue::Array(_) => return Err(CodecError::Container),
+        })
+    }
+
+    /// Decode a value of the given data type, fixed size types need exactly as many bytes as
+    /// the type is wide
+    pub fn decode(data_type: DataType, bytes: &[u8]) -> Result<ODValue, CodecError> {
+        if let Some(expected) = data_type.size()
+            && bytes.len() != expected
+        {
+            return Err(CodecError::Length {
+                data_type,
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        // The length is checked above, so these conversions can not fail
+        macro_rules! le {
+            ($ty:ty) => {
+                <$ty>::from_le_bytes(bytes.try_into().expect("length checked"))
+            };
+        }
+
+        Ok(match data_type {
+            DataType::Boolean => ODValue::Bool(bytes[0] != 0),
+            DataType::Integer8 => ODValue::I8(le!(i8)),
+            DataType::Unsigned8 => ODValue::U8(le!(u8)),
+            DataType::Integer16 => ODValue::I16(le!(i16)),
+            DataType::Unsigned16 => ODValue::U16(le!(u16)),
+            DataType::Integer24 => ODValue::I24(decode_int(bytes) as i32),
+            DataType::Unsigned24 => ODValue::U24(decode_uint(bytes) as u32),
+            DataType::Integer32 => ODValue::I32(le!(i32)),
+            DataType::Unsigned32 => ODValue::U32(le!(u32)),
+            DataType::Integer40 => ODValue::I40(decode_int(bytes)),
+            DataType::Unsigned40 => ODValue::U40(decode_uint(bytes)),
+            DataType::Integer48 => ODValue::I48(decode_int(bytes)),
+            DataType::Unsigned48 => ODValue::U48(decode_uint(bytes)),
+            DataType::Integer56 => ODValue::I56(decode_int(bytes)),
+            DataType::Unsigned56 => ODValue::U56(decode_uint(bytes)),
+            DataType::Integer64 => ODValue::I64(le!(i64)),
+            DataType::Unsigned64 => ODValue::U64(le!(u64)),
+            DataType::Real32 => ODValue::F32(le!(f32)),
+            DataType::Real64 => ODValue::F64(le!(f64)),
+            DataType::VisibleString => {
+                if !bytes.is_ascii() {
+                    return Err(CodecError::InvalidString(bytes.to_vec()));
+                }
+                ODValue::VisibleString(String::from_utf8_lossy(bytes).into_owned())
+            }
+            DataType::OctetString => ODValue::OctetString(bytes.to_vec()),
+            DataType::Domain => ODValue::Domain(bytes.to_vec()),
+            DataType::UnicodeString => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(CodecError::UnicodeLength(bytes.len()));
+                }
+                ODValue::UnicodeString(
+                    bytes
+                        .chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect(),
+                )
+            }
+            DataType::TimeOfDay => ODValue::TimeOfDay(decode_time(bytes)),
+            DataType::TimeDifference => ODValue::TimeDifference(decode_time(bytes)),
+        })
+    }
+}
+
+/// Only numeric values of the same data type are ordered
+impl PartialOrd for ODValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if !self.same_type(other) {
+            return None;
+        }
+
+        match (self.as_i128(), other.as_i128()) {
+            (Some(value), Some(other)) => Some(value.cmp(&other)),
+            _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+        }
+    }
+}
+
+/// Plain value without the data type, e.g. `30` instead of `U32(30)`
+impl fmt::Display for ODValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ODValue::F32(value) => write!(f, "{value}"),
+            ODValue::F64(value) => write!(f, "{value}"),
+            ODValue::VisibleString(value) => write!(f, "{value:?}"),
+            ODValue::UnicodeString(value) => write!(f, "{:?}", String::from_utf16_lossy(value)),
+            ODValue::OctetString(value) | ODValue::Domain(value) => write!(f, "{value:02x?}"),
+            ODValue::TimeOfDay(time) | ODValue::TimeDifference(time) => {
+                write!(f, "{} days {} ms", time.days, time.ms)
+            }
+            ODValue::Array(sub_indices) => write!(f, "{sub_indices} sub-indices"),
+            value => match value.as_i128() {
+                Some(value) => write!(f, "{value}"),
+                None => write!(f, "{value:?}"),
+            },
+        }
+    }
+}
+
+/// Lowest `width` bytes of the value, if it fits in them
+fn encode_uint(value: u64, width: usize) -> Option<Vec<u8>> {
+    (value >> (8 * width) == 0).then(|| value.to_le_bytes()[..width].to_vec())
+}
+
+/// Lowest `width` bytes of the two's complement value, if it fits in them
+fn encode_int(value: i64, width: usize) -> Option<Vec<u8>> {
+    let shift = 64 - 8 * width;
+    ((value << shift) >> shift == value).then(|| value.to_le_bytes()[..width].to_vec())
+}
+
+fn decode_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+/// Sign extend a two's complement value of up to 8 bytes
+fn decode_int(bytes: &[u8]) -> i64 {
+    let shift = 64 - 8 * bytes.len();
+    ((decode_uint(bytes) << shift) as i64) >> shift
+}
+
+fn decode_time(bytes: &[u8]) -> TimeOfDay {
+    TimeOfDay {
+        // The upper 4 bits are reserved
+        ms: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) & TimeOfDay::MAX_MS,
+        days: u16::from_le_bytes([bytes[4], bytes[5]]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn time() -> impl Strategy<Value = TimeOfDay> {
+        (0..=TimeOfDay::MAX_MS, any::<u16>()).prop_map(|(ms, days)| TimeOfDay { ms, days })
+    }
+
+    fn value() -> impl Strategy<Value = ODValue> {
+        use proptest::num::{f32, f64};
+
+        prop_oneof![
+            any::<bool>().prop_map(ODValue::Bool),
+            any::<i8>().prop_map(ODValue::I8),
+            any::<u8>().prop_map(ODValue::U8),
+            any::<i16>().prop_map(ODValue::I16),
+            any::<u16>().prop_map(ODValue::U16),
+            (-(1 << 23)..(1 << 23)).prop_map(ODValue::I24),
+            (0..(1u32 << 24)).prop_map(ODValue::U24),
+            any::<i32>().prop_map(ODValue::I32),
+            any::<u32>().prop_map(ODValue::U32),
+            (-(1i64 << 39)..(1 << 39)).prop_map(ODValue::I40),
+            (0..(1u64 << 40)).prop_map(ODValue::U40),
+            (-(1i64 << 47)..(1 << 47)).prop_map(ODValue::I48),
+            (0..(1u64 << 48)).prop_map(ODValue::U48),
+            (-(1i64 << 55)..(1 << 55)).prop_map(ODValue::I56),
+            (0..(1u64 << 56)).prop_map(ODValue::U56),
+            any::<i64>().prop_map(ODValue::I64),
+            any::<u64>().prop_map(ODValue::U64),
+            // NaN never compares equal, so leave it out
+            (f32::NORMAL | f32::SUBNORMAL | f32::ZERO | f32::INFINITE).prop_map(ODValue::F32),
+            (f64::NORMAL | f64::SUBNORMAL | f64::ZERO | f64::INFINITE).prop_map(ODValue::F64),
+            "[ -~]{0,32}".prop_map(ODValue::VisibleString),
+            prop::collection::vec(any::<u8>(), 0..32).prop_map(ODValue::OctetString),
+            prop::collection::vec(any::<u16>(), 0..16).prop_map(ODValue::UnicodeString),
+            time().prop_map(ODValue::TimeOfDay),
+            time().prop_map(ODValue::TimeDifference),
+            prop::collection::vec(any::<u8>(), 0..64).prop_map(ODValue::Domain),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(value in value()) {
+            let data_type = value.data_type().unwrap();
+            let bytes = value.encode().unwrap();
+
+            prop_assert_eq!(bytes.len(), value.encoded_len());
+            if let Some(size) = data_type.size() {
+                prop_assert_eq!(bytes.len(), size);
+            }
+            prop_assert_eq!(ODValue::decode(data_type, &bytes).unwrap(), value);
+        }
+
+        #[test]
+        fn test_wrong_length(value in value(), extra in 1usize..4) {
+            let data_type = value.data_type().unwrap();
+            prop_assume!(data_type.size().is_some());
+
+            let mut bytes = value.encode().unwrap();
+            bytes.extend(std::iter::repeat_n(0, extra));
+            let is_length_error = matches!(
+                ODValue::decode(data_type, &bytes),
+                Err(CodecError::Length { .. })
+            );
+            prop_assert!(is_length_error);
+        }
+
+        #[test]
+        fn test_out_of_range(value in (1u32 << 24)..) {
+            prop_assert!(ODValue::U24(value).encode().is_err());
+        }
+    }
+
+    #[test]
+    fn test_system_time() {
+        let at_epoch =
+            |ms| UNIX_EPOCH + Duration::from_secs(TIME_EPOCH_SECS) + Duration::from_millis(ms);
+
+        let time = TimeOfDay::from_system_time(at_epoch(MS_PER_DAY + 1500)).unwrap();
+        assert_eq!(time, TimeOfDay { ms: 1500, days: 1 });
+        assert_eq!(time.to_system_time(), at_epoch(MS_PER_DAY + 1500));
+        assert_eq!(
+            ODValue::TimeOfDay(time).encode(),
+            Ok(vec![0xDC, 0x05, 0, 0, 1, 0])
+        );
+        // The upper 4 bits of the milliseconds are reserved
+        assert_eq!(
+            ODValue::decode(DataType::TimeOfDay, &[0, 0, 0, 0xF0, 2, 0]),
+            Ok(ODValue::TimeOfDay(TimeOfDay { ms: 0, days: 2 }))
+        );
+
+        assert_eq!(TimeOfDay::from_system_time(UNIX_EPOCH), None);
+        assert_eq!(
+            TimeOfDay::from_system_time(at_epoch(u16::MAX as u64 * MS_PER_DAY + MS_PER_DAY)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sign_extension() {
+        assert_eq!(
+            ODValue::decode(DataType::Integer24, &[0xFF, 0xFF, 0xFF]),
+            Ok(ODValue::I24(-1))
+        );
+        assert_eq!(
+            ODValue::I40(-2).encode(),
+            Ok(vec![0xFE, 0xFF, 0xFF, 0xFF, 0xFF])
+        );
+        assert!(ODValue::I24(1 << 23).encode().is_err());
+        assert!(ODValue::I24(-(1 << 23)).encode().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(ODValue::VisibleString("µ".into()).encode().is_err());
+        assert!(ODValue::decode(DataType::VisibleString, &[0xB5]).is_err());
+        assert!(ODValue::decode(DataType::UnicodeString, &[0, 0, 0]).is_err());
+        assert_eq!(ODValue::Array(3).encode(), Err(CodecError::Container));
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(ODValue::U32(30) < ODValue::U32(2000));
+        assert!(ODValue::I32(-1) < ODValue::I32(0));
+        assert!(ODValue::F32(0.5) > ODValue::F32(-0.5));
+        // Different data types are not comparable
+        assert_eq!(ODValue::U32(1).partial_cmp(&ODValue::I32(1)), None);
+        assert_eq!(ODValue::U8(1).to_string(), "1");
+    }
+}
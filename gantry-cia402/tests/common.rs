@@ -1,15 +1,18 @@
-use std::time::Duration;
+use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use gantry_cia402::{
     comms::{
         pdo::mapping::{
             PdoMapping,
             custom::{CUSTOM_RPDOS, CUSTOM_TPDOS},
+            registry::MappingRegistry,
         },
         sdo::SdoAction,
+        time::TimeCorrelation,
     },
     driver::{event::MotorEvent, nmt::NmtState, receiver::subscriber::handle_feedback, startup},
     log::{log_canopen_pretty, log_events},
+    od::dictionary::ObjectDictionary,
 };
 use oze_canopen::{error::CoError, interface::CanOpenInterface};
 use thiserror::Error;
@@ -17,6 +20,7 @@ use tokio::{
     sync::{
         broadcast::{self, error::RecvError},
         mpsc::{self, error::SendError},
+        watch,
     },
     task::{self, JoinHandle},
     time::{self, Instant, error::Elapsed},
@@ -29,7 +33,7 @@ pub const CAN_BITRATE: u32 = 1_000_000;
 pub const NODE_ID: u8 = 3;
 pub const PARAMS: &[SdoAction] = startup::params::PARAMS;
 pub const TIMEOUT: Duration = Duration::from_secs(5);
-pub const TPDOS: &[PdoMapping; 4] = CUSTOM_TPDOS;
+pub const TPDOS: &[PdoMapping; 3] = CUSTOM_TPDOS;
 pub const RPDOS: &[PdoMapping; 4] = CUSTOM_RPDOS;
 
 #[derive(Debug, Error)]
@@ -62,12 +66,22 @@ pub fn start_feedback_task(
         broadcast::Receiver<MotorEvent>,
     ) = tokio::sync::broadcast::channel(10);
 
+    // The mappings never change during these tests, dropping the sender keeps the last value
+    let mut mappings = MappingRegistry::default();
+    mappings.insert(
+        node_id,
+        RPDOS.iter().chain(tpdo_mapping_set).map(Cow::Borrowed),
+    );
+    let (_, mappings) = watch::channel(mappings);
+
     trace!("Starting device feedback handler for motor with node id {node_id}");
     (
         task::spawn(handle_feedback(
             node_id,
             canopen,
-            tpdo_mapping_set,
+            Arc::new(ObjectDictionary::builtin()),
+            mappings,
+            watch::channel(TimeCorrelation::default()).0,
             event_tx,
         )),
         event_rx,
@@ -1,14 +1,18 @@
 pub mod common;
 
-use std::time::Duration;
+use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use gantry_cia402::{
-    comms::pdo::mapping::PdoMapping,
+    comms::{
+        pdo::mapping::{PdoMapping, registry::MappingRegistry},
+        time::TimeCorrelation,
+    },
     driver::{event::MotorEvent, receiver::subscriber::handle_feedback},
+    od::dictionary::ObjectDictionary,
 };
 use oze_canopen::interface::CanOpenInterface;
 use tokio::{
-    sync::broadcast,
+    sync::{broadcast, watch},
     task::{self, JoinHandle},
 };
 use tracing::*;
@@ -25,12 +29,19 @@ fn start_feedback_task(
         broadcast::Receiver<MotorEvent>,
     ) = tokio::sync::broadcast::channel(10);
 
+    // The mappings never change during these tests, dropping the sender keeps the last value
+    let mut mappings = MappingRegistry::default();
+    mappings.insert(node_id, tpdo_mapping_set.iter().map(Cow::Borrowed));
+    let (_, mappings) = watch::channel(mappings);
+
     trace!("Starting device feedback handler for motor with node id {node_id}");
     (
         task::spawn(handle_feedback(
             node_id,
             canopen,
-            tpdo_mapping_set,
+            Arc::new(ObjectDictionary::builtin()),
+            mappings,
+            watch::channel(TimeCorrelation::default()).0,
             event_tx,
         )),
         event_rx,
@@ -41,7 +52,7 @@ fn start_feedback_task(
 mod tests {
 
     use gantry_cia402::{
-        comms::pdo::mapping::custom::CUSTOM_TPDOS,
+        comms::{pdo::mapping::custom::CUSTOM_TPDOS, sdo::scheduler::SdoScheduler},
         driver::{
             nmt::{NmtState, nmt_task},
             receiver::subscriber::wait_for_event,
@@ -102,12 +113,10 @@ mod tests {
         })?;
 
         info!("Start SDO client");
-        // Get the SDO client for this node id, we use this to make SDO read/writes
-        let sdo = canopen
-            .get_sdo_client(node_id)
-            .unwrap_or_else(|| panic!("Unable to construct SDO client for node id {node_id}"));
+        // Get the SDO handle for this node id, we use this to make SDO read/writes
+        let sdo = SdoScheduler::shared(&canopen).handle(node_id);
 
-        parametrise_motor(node_id, PARAMS, sdo.clone())
+        parametrise_motor(node_id, PARAMS, sdo.clone(), Default::default())
             .await
             .map_err(|err| format!("Error during motor parametrisation: {err}").to_string())?;
 
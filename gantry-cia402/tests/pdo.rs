@@ -9,20 +9,22 @@ use tracing::*;
 #[cfg(test)]
 mod tests {
 
+    use crate::common::*;
     use gantry_cia402::{
-        comms::pdo::mapping::custom::CUSTOM_TPDOS,
+        comms::{pdo::mapping::custom::CUSTOM_TPDOS, sdo::scheduler::SdoScheduler},
         driver::{
             nmt::nmt_task,
             receiver::subscriber::wait_for_event,
             startup::{
-                parametrise::parametrise_motor, params::PARAMS, pdo_mapping::configure_pdo_mappings,
+                parametrise::parametrise_motor,
+                params::PARAMS,
+                pdo_mapping::{PdoSetup, configure_pdos},
             },
         },
         log::log_events,
+        od::dictionary::ObjectDictionary,
     };
 
-    use crate::common::*;
-
     use super::*;
 
     #[tokio::test]
@@ -74,25 +76,39 @@ mod tests {
         })?;
 
         info!("Starting SDO client");
-        // Get the SDO client for this node id, we use this to make SDO read/writes
-        let sdo = canopen
-            .get_sdo_client(node_id)
-            .unwrap_or_else(|| panic!("Unable to construct SDO client for node id {node_id}"));
+        // Get the SDO handle for this node id, we use this to make SDO read/writes
+        let sdo = SdoScheduler::shared(&canopen).handle(node_id);
 
         info!("Starting Parametrisation of motor at node id {node_id}");
-        parametrise_motor(node_id, PARAMS, sdo.clone())
+        parametrise_motor(node_id, PARAMS, sdo.clone(), Default::default())
             .await
             .map_err(|err| format!("Error during motor parametrisation: {err}").to_string())?;
 
-        info!("Configuring RPDO_mapping of motor at node id {node_id}");
-        configure_pdo_mappings(node_id, sdo.clone(), RPDOS)
-            .await
-            .map_err(|err| format!("Error during RPDO mapping configuration: {err}").to_string())?;
+        info!("Configuring PDOs of motor at node id {node_id}");
+        let dictionary = ObjectDictionary::builtin();
+        configure_pdos(
+            node_id,
+            sdo.clone(),
+            &dictionary,
+            RPDOS,
+            TPDOS,
+            PdoSetup::Configure,
+        )
+        .await
+        .map_err(|err| format!("Error during PDO configuration: {err}").to_string())?;
 
-        info!("Configuring TPDO_mapping of motor at node id {node_id}");
-        configure_pdo_mappings(node_id, sdo.clone(), TPDOS)
-            .await
-            .map_err(|err| format!("Error during TPDO mapping configuration: {err}").to_string())?;
+        // Everything matches now, adopting yields the requested mappings
+        let adopted = configure_pdos(
+            node_id,
+            sdo.clone(),
+            &dictionary,
+            RPDOS,
+            TPDOS,
+            PdoSetup::Adopt,
+        )
+        .await
+        .map_err(|err| format!("Error adopting PDO configuration: {err}").to_string())?;
+        assert_eq!(adopted.len(), RPDOS.len() + TPDOS.len());
 
         info!("Requesting NMT Operational");
         nmt_tx
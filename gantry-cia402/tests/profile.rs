@@ -0,0 +1,50 @@
+pub mod common;
+
+use tracing::*;
+
+#[cfg(test)]
+mod tests {
+
+    use gantry_cia402::{
+        comms::pdo::mapping::{
+            custom::{CYCLIC_SYNCHRONOUS_POSITION, PROFILE_MODES},
+            profile::RemapStrategy,
+        },
+        driver::{Cia402Driver, nmt::NmtState},
+        error::DriveError,
+        od::{self, value::ODValue},
+    };
+
+    use crate::common::{NODE_ID, PARAMS, RPDOS, TPDOS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_switch_profile() -> Result<(), DriveError> {
+        gantry_demo::setup_tracing();
+
+        let node_id = NODE_ID;
+
+        info!("Starting can interface");
+        let (canopen, _) = oze_canopen::canopen::start(String::from("can0"), Some(1000000));
+
+        info!("Initializing Cia402Driver for motor driver at node id {node_id}");
+        let drive = Cia402Driver::init(node_id, canopen, PARAMS, RPDOS, TPDOS).await?;
+
+        info!("Switching to the cyclic synchronous position profile through PreOperational");
+        drive
+            .switch_profile(&CYCLIC_SYNCHRONOUS_POSITION, RemapStrategy::PreOperational)
+            .await?;
+        assert_eq!(drive.nmt_state(), NmtState::Operational);
+        assert_eq!(drive.read(&od::RPDO2_MAPPING_COUNT).await?, ODValue::U8(1));
+
+        info!("Switching back to the profile modes while Operational");
+        drive
+            .switch_profile(&PROFILE_MODES, RemapStrategy::Operational)
+            .await?;
+        assert_eq!(drive.nmt_state(), NmtState::Operational);
+        assert_eq!(drive.read(&od::RPDO2_MAPPING_COUNT).await?, ODValue::U8(2));
+
+        Ok(())
+    }
+}
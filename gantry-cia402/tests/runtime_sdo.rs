@@ -0,0 +1,50 @@
+pub mod common;
+
+use tracing::*;
+
+#[cfg(test)]
+mod tests {
+
+    use gantry_cia402::{
+        driver::{Cia402Driver, nmt::NmtState},
+        error::DriveError,
+        od::{self, value::ODValue},
+    };
+
+    use crate::common::{NODE_ID, PARAMS, RPDOS, TPDOS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_runtime_sdo() -> Result<(), DriveError> {
+        gantry_demo::setup_tracing();
+
+        let node_id = NODE_ID;
+
+        info!("Starting can interface");
+        let (canopen, _) = oze_canopen::canopen::start(String::from("can0"), Some(1000000));
+
+        info!("Initializing Cia402Driver for motor driver at node id {node_id}");
+        let drive = Cia402Driver::init(node_id, canopen, PARAMS, RPDOS, TPDOS).await?;
+        assert_eq!(drive.nmt_state(), NmtState::Operational);
+
+        info!("Reading the error code");
+        let error_code = drive.read(&od::ERROR_CODE).await?;
+        info!("Error code: {error_code}");
+
+        info!("Changing the profile acceleration");
+        let acceleration = ODValue::U32(750);
+        drive
+            .write(&od::PROFILE_ACCELERATION, acceleration.clone())
+            .await?;
+        assert_eq!(drive.read(&od::PROFILE_ACCELERATION).await?, acceleration);
+
+        // The statusword is read only, nothing is sent
+        assert!(matches!(
+            drive.write(&od::STATUS_WORD, ODValue::U16(0)).await,
+            Err(DriveError::NotWritable { index: 0x6041, .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+pub mod common;
+
+use tracing::*;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use gantry_cia402::{
+        comms::{
+            pdo::mapping::{custom::CYCLIC_SYNCHRONOUS_POSITION, profile::RemapStrategy},
+            sync::{SyncConfig, SyncProducer},
+        },
+        driver::{Cia402Driver, event::MotorEvent},
+        error::DriveError,
+    };
+    use tokio::time;
+
+    use crate::common::{NODE_ID, PARAMS, RPDOS, TPDOS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_snapshots() -> Result<(), DriveError> {
+        gantry_demo::setup_tracing();
+
+        let node_id = NODE_ID;
+
+        info!("Starting can interface");
+        let (canopen, _) = oze_canopen::canopen::start(String::from("can0"), Some(1000000));
+
+        info!("Initializing Cia402Driver for motor driver at node id {node_id}");
+        let mut drive = Cia402Driver::init(node_id, canopen.clone(), PARAMS, RPDOS, TPDOS).await?;
+        drive
+            .switch_profile(&CYCLIC_SYNCHRONOUS_POSITION, RemapStrategy::PreOperational)
+            .await?;
+
+        info!("Producing SYNC every 10ms");
+        let sync = SyncProducer::start(
+            &canopen,
+            SyncConfig::new(Duration::from_millis(10)).with_counter(16),
+        )?;
+        drive.attach_sync(&sync).await?;
+
+        let mut event_rx = drive.event_rx.resubscribe();
+        let mut counters = Vec::new();
+        while counters.len() < 3 {
+            match time::timeout(Duration::from_secs(1), event_rx.recv()).await {
+                Ok(Ok(MotorEvent::SyncSnapshot(snapshot))) => {
+                    info!("Received snapshot {snapshot:?}");
+                    assert!(!snapshot.values.is_empty());
+                    counters.push(snapshot.counter);
+                }
+                Ok(_) => {}
+                Err(_) => panic!("No snapshot received within a second"),
+            }
+        }
+        assert!(counters.iter().all(Option::is_some));
+
+        let statistics = sync.statistics();
+        info!("SYNC statistics: {statistics:?}");
+        assert!(statistics.sent >= 3);
+
+        Ok(())
+    }
+}
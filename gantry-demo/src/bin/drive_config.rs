@@ -0,0 +1,146 @@
+//! Dump the configuration of a live drive, and diff it against our startup configuration
+//!
+//! ```text
+//! drive_config dump <snapshot.toml|snapshot.json> [--node <id>] [--eds <file>]
+//! drive_config diff [snapshot.toml|snapshot.json] [--node <id>] [--eds <file>]
+//! ```
+//!
+//! `diff` compares against `PARAMS` and the custom PDO mappings, using a fresh upload from the
+//! drive when no snapshot file is given. Setpoints the driver commands while running, e.g. the
+//! target position, are not compared. It exits with status 1 when the drive differs, e.g.
+//! because someone reconfigured it with Plug & Drive Studio, and with status 2 on errors.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::{Context, bail};
+use gantry_cia402::{
+    comms::{
+        pdo::mapping::custom::{CUSTOM_RPDOS, CUSTOM_TPDOS},
+        sdo::scheduler::SdoScheduler,
+    },
+    driver::startup::{
+        params::PARAMS,
+        snapshot::{Snapshot, diff, expected_configuration},
+    },
+    od::{dictionary::ObjectDictionary, entry::ODEntry},
+};
+use gantry_demo::setup_tracing;
+use oze_canopen::canopen;
+use tracing::*;
+
+const DEFAULT_NODE_ID: u8 = 3;
+const INTERFACE: &str = "can0";
+const BITRATE: u32 = 1000000;
+
+enum Command {
+    Dump,
+    Diff,
+}
+
+struct Args {
+    command: Command,
+    file: Option<PathBuf>,
+    node_id: u8,
+    eds: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let command = match args.next().as_deref() {
+        Some("dump") => Command::Dump,
+        Some("diff") => Command::Diff,
+        _ => bail!("Usage: drive_config <dump|diff> [file] [--node <id>] [--eds <file>]"),
+    };
+
+    let mut parsed = Args {
+        command,
+        file: None,
+        node_id: DEFAULT_NODE_ID,
+        eds: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--node" => {
+                let node_id = args.next().context("--node needs a node id")?;
+                parsed.node_id = node_id.parse().context("Invalid node id")?;
+            }
+            "--eds" => parsed.eds = Some(args.next().context("--eds needs a file")?.into()),
+            _ if parsed.file.is_none() => parsed.file = Some(arg.into()),
+            _ => bail!("Unexpected argument {arg}"),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Upload every object of the dictionary from the drive
+async fn upload(node_id: u8, entries: Vec<&'static ODEntry>) -> anyhow::Result<Snapshot> {
+    let (canopen, _handles) = canopen::start(String::from(INTERFACE), Some(BITRATE));
+    let sdo = SdoScheduler::shared(&canopen).handle(node_id);
+
+    info!("Uploading {} objects from node {node_id}", entries.len());
+    Ok(Snapshot::take(node_id, sdo, entries).await)
+}
+
+async fn run() -> anyhow::Result<ExitCode> {
+    let args = parse_args(std::env::args().skip(1))?;
+
+    let dictionary = match &args.eds {
+        Some(path) => ObjectDictionary::from_eds_file(path)?,
+        None => ObjectDictionary::builtin(),
+    };
+    // Upload actions need 'static entries, fine for a process this short lived
+    let dictionary: &'static ObjectDictionary = Box::leak(Box::new(dictionary));
+    let entries: Vec<&'static ODEntry> = dictionary.iter().collect();
+
+    match args.command {
+        Command::Dump => {
+            let file = args.file.context("dump needs a file to write to")?;
+            let snapshot = upload(args.node_id, entries).await?;
+            snapshot.save(&file)?;
+            info!("Wrote snapshot of node {} to {file:?}", args.node_id);
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Diff => {
+            let snapshot = match &args.file {
+                Some(file) => Snapshot::load(file)?,
+                None => upload(args.node_id, entries).await?,
+            };
+            let mappings = CUSTOM_RPDOS.iter().chain(CUSTOM_TPDOS);
+            let expected = expected_configuration(dictionary, PARAMS, mappings)?;
+
+            let differences = diff(&snapshot, &expected);
+            for difference in differences.iter() {
+                println!("{difference}");
+            }
+
+            if differences.is_empty() {
+                info!(
+                    "Node {} matches the startup configuration",
+                    snapshot.node_id
+                );
+                Ok(ExitCode::SUCCESS)
+            } else {
+                error!(
+                    "Node {} differs from the startup configuration in {} objects",
+                    snapshot.node_id,
+                    differences.len()
+                );
+                Ok(ExitCode::FAILURE)
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    setup_tracing();
+
+    match run().await {
+        Ok(code) => code,
+        Err(err) => {
+            error!("{err:#}");
+            ExitCode::from(2)
+        }
+    }
+}
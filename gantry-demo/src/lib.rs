@@ -16,7 +16,10 @@ pub fn setup_tracing() {
 
     let frame_fmt_layer = tracing_subscriber::fmt::layer()
         .event_format(FrameFormatter)
-        .with_filter(filter_fn(|meta| meta.target() == "canopen"));
+        .with_filter(filter_fn(|meta| {
+            // Single SDO frames are logged at debug, their transactions at info
+            meta.target() == "canopen" && *meta.level() <= Level::INFO
+        }));
 
     let default_layer =
         tracing_subscriber::fmt::layer().with_filter(filter_fn(|meta| meta.target() != "canopen"));
@@ -41,6 +44,7 @@ struct FieldExtractor {
     index: Option<String>,
     sub_index: Option<String>,
     num: Option<u64>,
+    failed: bool,
 }
 
 impl Visit for FieldExtractor {
@@ -57,6 +61,12 @@ impl Visit for FieldExtractor {
         }
     }
 
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "failed" {
+            self.failed = value;
+        }
+    }
+
     fn record_u64(&mut self, field: &Field, value: u64) {
         if field.name() == "node" {
             self.node = Some(value);
@@ -166,6 +176,20 @@ where
                     format!("Node {}", node).cyan(),
                     parsed.cyan(),
                 )?,
+                "SDO" if ex.failed => write!(
+                    writer,
+                    "{} <> {} {}",
+                    "SDO".red().bold(),
+                    format!("Node {}", node).red(),
+                    parsed.red(),
+                )?,
+                "SDO" => write!(
+                    writer,
+                    "{} <> {} {}",
+                    "SDO".cyan().bold(),
+                    format!("Node {}", node).cyan(),
+                    parsed.cyan(),
+                )?,
                 "SYNC" => write!(writer, "{}", "SYNC".white().bold())?,
                 "NmtControl" => write!(
                     writer,
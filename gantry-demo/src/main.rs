@@ -1,5 +1,14 @@
 use ::tracing::info;
-use gantry_cia402::log::log_canopen_pretty;
+use std::{borrow::Cow, sync::Arc};
+
+use gantry_cia402::{
+    comms::pdo::mapping::{
+        custom::{CUSTOM_RPDOS, CUSTOM_TPDOS},
+        registry::MappingRegistry,
+    },
+    log::log_canopen_pretty_with_dictionaries,
+    od::{dictionary::ObjectDictionary, registry::DictionaryRegistry},
+};
 use gantry_demo::setup_tracing;
 use oze_canopen::canopen;
 use tracing::*;
@@ -10,8 +19,23 @@ const NODE_ID: u8 = 3;
 async fn main() {
     setup_tracing();
 
+    // Optionally decode SDO traffic of the device using its EDS/DCF
+    let mut dictionaries = DictionaryRegistry::default();
+    if let Some(path) = std::env::args().nth(1) {
+        info!("Loading object dictionary of node {NODE_ID} from {path}");
+        let dictionary = ObjectDictionary::from_eds_file(&path).expect("Unable to load EDS file");
+        dictionaries.insert(NODE_ID, Arc::new(dictionary));
+    }
+
+    // The demo configures the device with the custom PDO mappings
+    let mut mappings = MappingRegistry::default();
+    mappings.insert(
+        NODE_ID,
+        CUSTOM_RPDOS.iter().chain(CUSTOM_TPDOS).map(Cow::Borrowed),
+    );
+
     info!("Starting can interface");
     let (canopen, handles) = canopen::start(String::from("can0"), Some(1000000));
 
-    let _ = log_canopen_pretty(canopen).await;
+    let _ = log_canopen_pretty_with_dictionaries(canopen, dictionaries, mappings).await;
 }